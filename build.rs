@@ -0,0 +1,78 @@
+//! Records what the results of a build were produced by: the commit of the
+//! crate, the version of the vendored HAMT and the compiler, see
+//! src/environment.rs.
+
+use std::env;
+use std::fs;
+use std::process::Command;
+
+const VENDORED_HAMT: &str = "vendor/fvm_ipld_hamt";
+
+fn main() {
+    // Whatever changes the commit or the sources, not only Rust files.
+    for path in [
+        ".git/HEAD",
+        ".git/index",
+        "Cargo.toml",
+        "src",
+        VENDORED_HAMT,
+    ] {
+        println!("cargo:rerun-if-changed={path}");
+    }
+
+    let commit = git(&["rev-parse", "--short=12", "HEAD"])
+        .map(|commit| commit + dirty("."))
+        .unwrap_or_else(|| "unknown".to_string());
+    println!("cargo:rustc-env=BUILD_COMMIT={commit}");
+
+    // The version alone stays the same while the vendored code is changed,
+    // its tree in the commit doesn't.
+    let version = package_version(&format!("{VENDORED_HAMT}/Cargo.toml"))
+        .unwrap_or_else(|| "unknown".to_string());
+    let hamt = match git(&["rev-parse", "--short=12", &format!("HEAD:{VENDORED_HAMT}")]) {
+        Some(tree) => format!("{version} (tree {tree}{})", dirty(VENDORED_HAMT)),
+        None => version,
+    };
+    println!("cargo:rustc-env=BUILD_HAMT_VERSION={hamt}");
+
+    let rustc = env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+    let rustc = Command::new(rustc)
+        .arg("--version")
+        .output()
+        .ok()
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|version| version.trim().to_string())
+        .unwrap_or_else(|| "unknown".to_string());
+    println!("cargo:rustc-env=BUILD_RUSTC_VERSION={rustc}");
+}
+
+fn git(args: &[&str]) -> Option<String> {
+    let output = Command::new("git").args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    String::from_utf8(output.stdout)
+        .ok()
+        .map(|out| out.trim().to_string())
+}
+
+/// `-dirty` if files under `path` differ from the commit.
+fn dirty(path: &str) -> &'static str {
+    match git(&["status", "--porcelain", "--untracked-files=no", "--", path]) {
+        Some(status) if !status.is_empty() => "-dirty",
+        _ => "",
+    }
+}
+
+/// The `version` of the `[package]` in a manifest.
+fn package_version(manifest: &str) -> Option<String> {
+    let manifest = fs::read_to_string(manifest).ok()?;
+    let package = manifest.split("[package]").nth(1)?;
+    package
+        .lines()
+        .take_while(|line| !line.starts_with('['))
+        .find_map(|line| {
+            let (key, value) = line.split_once('=')?;
+            (key.trim() == "version").then(|| value.trim().trim_matches('"').to_string())
+        })
+}
@@ -0,0 +1,415 @@
+//! A minimal Array Mapped Trie, the structure `fvm_ipld_amt` implements, as
+//! a baseline for dense integer keys.
+//!
+//! Blocks are laid out like those of `fvm_ipld_amt` v3: the root block is
+//! `[bit_width, height, count, node]` with the top node inline, and every
+//! node is `[bitmap, links, values]`, where the bitmap marks which of the
+//! `2^bit_width` slots are used. Only nodes at height 0 hold values, so the
+//! path to an index is given by its digits in base `2^bit_width`, without
+//! hashing. Unlike the real crate, trees can't be loaded back from a store;
+//! they're only built and flushed to measure them.
+
+use anyhow::{bail, Result};
+use cid::multihash::Code;
+use cid::Cid;
+use fvm_ipld_blockstore::{Block, Blockstore};
+use fvm_ipld_encoding::serde_bytes::ByteBuf;
+use fvm_ipld_encoding::{to_vec, DAG_CBOR};
+use serde::Serialize;
+
+use crate::experiments::proof::merkle_proof_bytes_experiment;
+use crate::experiments::sizes::experiment;
+use crate::experiments::ExperimentContext;
+use crate::memorydb::MemoryDB;
+use crate::workload::Workload;
+
+pub struct Amt<'a, BS, V> {
+    store: &'a BS,
+    bit_width: u32,
+    height: u32,
+    count: u64,
+    root: Node<V>,
+    /// Length of the root block as of the last flush.
+    root_size: Option<u64>,
+    /// Length of all blocks put so far.
+    bytes_written: u64,
+}
+
+enum Node<V> {
+    Leaf(Vec<Option<V>>),
+    Internal(Vec<Option<Child<V>>>),
+}
+
+struct Child<V> {
+    node: Node<V>,
+    /// CID and length of the block, unless the node changed since it was
+    /// last flushed.
+    block: Option<(Cid, u64)>,
+}
+
+impl<'a, BS: Blockstore, V: Serialize> Amt<'a, BS, V> {
+    pub fn new_with_bit_width(store: &'a BS, bit_width: u32) -> Self {
+        assert!(
+            (1..=8).contains(&bit_width),
+            "unsupported bit width {bit_width}"
+        );
+        Amt {
+            store,
+            bit_width,
+            height: 0,
+            count: 0,
+            root: Node::leaf(bit_width),
+            root_size: None,
+            bytes_written: 0,
+        }
+    }
+
+    /// Number of values set.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Length of all blocks flushed so far, counting blocks with the same
+    /// contents, e.g. leaves of equal values, once per flush of them.
+    pub fn bytes_written(&self) -> u64 {
+        self.bytes_written
+    }
+
+    /// Length of the blocks of the flushed tree, counting every node even if
+    /// the store deduplicates equal ones. `None` if there are unflushed
+    /// changes.
+    pub fn total_bytes(&self) -> Option<u64> {
+        Some(self.root_size? + self.root.children_bytes())
+    }
+
+    /// Sets the value at `index`, returning the previous one.
+    pub fn set(&mut self, index: u64, value: V) -> Option<V> {
+        while index >= self.capacity(self.height) {
+            let old = std::mem::replace(&mut self.root, Node::internal(self.bit_width));
+            if !old.is_empty() {
+                let Node::Internal(children) = &mut self.root else {
+                    unreachable!("just created");
+                };
+                children[0] = Some(Child {
+                    node: old,
+                    block: None,
+                });
+            }
+            self.height += 1;
+        }
+        self.root_size = None;
+
+        let bit_width = self.bit_width;
+        let mut node = &mut self.root;
+        let mut height = self.height;
+        loop {
+            let slot = slot(bit_width, index, height);
+            match node {
+                Node::Leaf(values) => {
+                    let old = values[slot].replace(value);
+                    if old.is_none() {
+                        self.count += 1;
+                    }
+                    return old;
+                }
+                Node::Internal(children) => {
+                    height -= 1;
+                    let child = children[slot].get_or_insert_with(|| Child {
+                        node: if height == 0 {
+                            Node::leaf(bit_width)
+                        } else {
+                            Node::internal(bit_width)
+                        },
+                        block: None,
+                    });
+                    child.block = None;
+                    node = &mut child.node;
+                }
+            }
+        }
+    }
+
+    pub fn get(&self, index: u64) -> Option<&V> {
+        if index >= self.capacity(self.height) {
+            return None;
+        }
+        let mut node = &self.root;
+        let mut height = self.height;
+        loop {
+            let slot = slot(self.bit_width, index, height);
+            match node {
+                Node::Leaf(values) => return values[slot].as_ref(),
+                Node::Internal(children) => {
+                    node = &children[slot].as_ref()?.node;
+                    height -= 1;
+                }
+            }
+        }
+    }
+
+    /// Writes every changed node and the root to the store.
+    pub fn flush(&mut self) -> Result<Cid> {
+        self.bytes_written += flush_children(&mut self.root, self.store)?;
+        let root = (
+            self.bit_width,
+            self.height,
+            self.count,
+            self.root.encoding(),
+        );
+        let (cid, size) = put(self.store, &root)?;
+        self.root_size = Some(size);
+        self.bytes_written += size;
+        Ok(cid)
+    }
+
+    /// Total length of the blocks from the root to the value at `index`,
+    /// which proves the value. Fails if the AMT wasn't flushed since it was
+    /// last changed.
+    pub fn proof_bytes(&self, index: u64) -> Result<Option<u64>> {
+        let Some(mut bytes) = self.root_size else {
+            bail!("the AMT has to be flushed first");
+        };
+        if index >= self.capacity(self.height) {
+            return Ok(None);
+        }
+        let mut node = &self.root;
+        let mut height = self.height;
+        loop {
+            let slot = slot(self.bit_width, index, height);
+            match node {
+                Node::Leaf(values) => return Ok(values[slot].as_ref().map(|_| bytes)),
+                Node::Internal(children) => {
+                    let Some(child) = &children[slot] else {
+                        return Ok(None);
+                    };
+                    let (_, size) = child.block.expect("flushed above");
+                    bytes += size;
+                    node = &child.node;
+                    height -= 1;
+                }
+            }
+        }
+    }
+
+    /// Number of indices below a node at `height`.
+    fn capacity(&self, height: u32) -> u64 {
+        1 << (self.bit_width * (height + 1))
+    }
+}
+
+/// Slot of `index` in its node at `height`.
+fn slot(bit_width: u32, index: u64, height: u32) -> usize {
+    ((index >> (bit_width * height)) % (1 << bit_width)) as usize
+}
+
+impl<V> Node<V> {
+    fn leaf(bit_width: u32) -> Self {
+        Node::Leaf((0..1 << bit_width).map(|_| None).collect())
+    }
+
+    fn internal(bit_width: u32) -> Self {
+        Node::Internal((0..1 << bit_width).map(|_| None).collect())
+    }
+
+    fn is_empty(&self) -> bool {
+        match self {
+            Node::Leaf(values) => values.iter().all(Option::is_none),
+            Node::Internal(children) => children.iter().all(Option::is_none),
+        }
+    }
+
+    fn children_bytes(&self) -> u64 {
+        match self {
+            Node::Leaf(_) => 0,
+            Node::Internal(children) => children
+                .iter()
+                .flatten()
+                .map(|child| {
+                    let (_, size) = child.block.expect("flushed with the root");
+                    size + child.node.children_bytes()
+                })
+                .sum(),
+        }
+    }
+
+    /// `[bitmap, links, values]`, children have to be flushed.
+    fn encoding(&self) -> (ByteBuf, Vec<Cid>, Vec<&V>) {
+        let (used, links, values): (Vec<bool>, Vec<Cid>, Vec<&V>) = match self {
+            Node::Leaf(values) => (
+                values.iter().map(Option::is_some).collect(),
+                Vec::new(),
+                values.iter().flatten().collect(),
+            ),
+            Node::Internal(children) => (
+                children.iter().map(Option::is_some).collect(),
+                children
+                    .iter()
+                    .flatten()
+                    .map(|child| child.block.expect("children are flushed first").0)
+                    .collect(),
+                Vec::new(),
+            ),
+        };
+        let mut bitmap = vec![0u8; used.len().div_ceil(8)];
+        for (i, _) in used.iter().enumerate().filter(|(_, used)| **used) {
+            bitmap[i / 8] |= 1 << (i % 8);
+        }
+        (ByteBuf::from(bitmap), links, values)
+    }
+}
+
+/// Puts the changed descendants of `node` and returns their total length.
+fn flush_children<V: Serialize>(node: &mut Node<V>, store: &impl Blockstore) -> Result<u64> {
+    let mut written = 0;
+    if let Node::Internal(children) = node {
+        for child in children.iter_mut().flatten() {
+            if child.block.is_none() {
+                written += flush_children(&mut child.node, store)?;
+                let block = put(store, &child.node.encoding())?;
+                written += block.1;
+                child.block = Some(block);
+            }
+        }
+    }
+    Ok(written)
+}
+
+fn put(store: &impl Blockstore, value: &impl Serialize) -> Result<(Cid, u64)> {
+    let bytes = to_vec(value)?;
+    let cid = store.put(Code::Blake2b256, &Block::new(DAG_CBOR, &bytes))?;
+    Ok((cid, bytes.len() as u64))
+}
+
+#[derive(Debug, Serialize)]
+pub struct AmtResult {
+    n: usize,
+    m: usize,
+    bucket_size: usize,
+    bit_width: u32,
+    hamt_total_bytes: u64,
+    amt_total_bytes: u64,
+    hamt_byte_diff: u64,
+    amt_byte_diff: u64,
+    /// Of key `0`.
+    hamt_proof_bytes: u64,
+    amt_proof_bytes: u64,
+    /// `hamt_total_bytes / amt_total_bytes`
+    total_bytes_overhead: f64,
+}
+
+/// The measurements of [`experiment`] and [`merkle_proof_bytes_experiment`]
+/// for the keys `0..n`, in a HAMT and in an AMT of the same bit width.
+pub fn amt_experiment<const BUCKET_SIZE: usize>(
+    ctx: &ExperimentContext,
+    bit_width: u32,
+    n: usize,
+    m: usize,
+) -> Result<AmtResult> {
+    let hamt = experiment::<BUCKET_SIZE>(ctx, bit_width, n, m, &Workload::Sequential);
+    let hamt_proof_bytes = merkle_proof_bytes_experiment::<BUCKET_SIZE>(bit_width, n);
+    let amt = amt_sizes(bit_width, n, m)?;
+    Ok(AmtResult {
+        n,
+        m,
+        bucket_size: BUCKET_SIZE,
+        bit_width,
+        hamt_total_bytes: hamt.total_bytes,
+        amt_total_bytes: amt.total_bytes,
+        hamt_byte_diff: hamt.byte_difference,
+        amt_byte_diff: amt.byte_difference,
+        hamt_proof_bytes,
+        amt_proof_bytes: amt.proof_bytes,
+        total_bytes_overhead: hamt.total_bytes as f64 / amt.total_bytes as f64,
+    })
+}
+
+struct AmtSizes {
+    total_bytes: u64,
+    byte_difference: u64,
+    proof_bytes: u64,
+}
+
+/// The sizes [`amt_experiment`] compares, of an AMT with the keys `0..n`.
+fn amt_sizes(bit_width: u32, n: usize, m: usize) -> Result<AmtSizes> {
+    let store = MemoryDB::default();
+    let mut amt = Amt::new_with_bit_width(&store, bit_width);
+    for key in 0..n {
+        amt.set(key as u64, "F".to_string());
+    }
+    amt.flush()?;
+    // Leaves holding the same values are equal blocks, but unlike HAMT
+    // buckets that's only because every key has the same value, so they're
+    // counted separately.
+    let total_bytes = amt.total_bytes().expect("flushed");
+    let proof_bytes = amt.proof_bytes(0)?.unwrap_or_default();
+    let written = amt.bytes_written();
+
+    for key in 0..m {
+        amt.set(key as u64, ".".to_string());
+    }
+    amt.flush()?;
+    Ok(AmtSizes {
+        total_bytes,
+        byte_difference: amt.bytes_written() - written,
+        proof_bytes,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memorydb::MemoryDB;
+
+    #[test]
+    fn sets_and_gets_values() -> Result<()> {
+        let store = MemoryDB::default();
+        let mut amt = Amt::new_with_bit_width(&store, 3);
+        for index in (0..1000).step_by(7) {
+            assert_eq!(amt.set(index, index * 2), None);
+        }
+        assert_eq!(amt.set(7, 0), Some(14));
+        assert_eq!(amt.count(), 143);
+        // 8^4 = 4096 indices fit below a root at height 3.
+        assert_eq!(amt.height(), 3);
+        assert_eq!(amt.get(14), Some(&28));
+        assert_eq!(amt.get(7), Some(&0));
+        assert_eq!(amt.get(15), None);
+        assert_eq!(amt.get(1 << 20), None);
+
+        amt.flush()?;
+        // The root, and nodes covering 512, 64 and 8 indices.
+        assert_eq!(store.blocks(), 1 + 2 + 16 + 125);
+        let total = amt.total_bytes().unwrap();
+        assert_eq!(total, amt.bytes_written());
+        let proof = amt.proof_bytes(14)?.unwrap();
+        assert!(proof > 0 && proof < total);
+        assert_eq!(amt.proof_bytes(15)?, None);
+        amt.set(15, 1);
+        assert!(amt.proof_bytes(15).is_err());
+        assert_eq!(amt.total_bytes(), None);
+        amt.flush()?;
+        // Only the path to index 15 is rewritten.
+        assert!(amt.bytes_written() - total < 4 * proof);
+        Ok(())
+    }
+
+    #[test]
+    fn encodes_nodes_like_fvm_ipld_amt() -> Result<()> {
+        let store = MemoryDB::default();
+        let mut amt = Amt::new_with_bit_width(&store, 3);
+        amt.set(1, 5u8);
+        amt.set(3, 6u8);
+        amt.flush()?;
+        let root = (3u32, 0u32, 2u64, amt.root.encoding());
+        // [3, 0, 2, [h'0a', [], [5, 6]]]
+        assert_eq!(
+            to_vec(&root)?,
+            [0x84, 0x03, 0x00, 0x02, 0x83, 0x41, 0x0a, 0x80, 0x82, 0x05, 0x06]
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,300 @@
+//! Statistics for HAMTs found in arbitrary DAG-CBOR data, such as CAR
+//! exports of Filecoin state trees.
+//!
+//! Nodes are read as plain IPLD, so neither the key and value types nor the
+//! bucket size have to be known up front. HAMTs with an [`Envelope`] are
+//! reported by it, along with the parameters it records.
+
+use std::collections::{HashMap, HashSet};
+
+use anyhow::{anyhow, bail, Result};
+use cid::Cid;
+use fvm_ipld_blockstore::Blockstore;
+use fvm_ipld_encoding::{from_slice, DAG_CBOR};
+use fvm_ipld_hamt::bitfield::Bitfield;
+use fvm_ipld_hamt::Envelope;
+use libipld_core::ipld::Ipld;
+use serde::Serialize;
+
+use crate::stats::Histogram;
+
+/// Finds the roots of all HAMTs reachable from `roots`, in the order they are
+/// first reached.
+///
+/// A block counts as a HAMT node if it has the `[bitfield, pointers]` shape
+/// with one pointer per set bit. Nodes linked from another HAMT node aren't
+/// roots, and roots linked from an envelope are replaced by the envelope.
+/// Links to blocks missing from `store` are ignored, since exports are often
+/// pruned.
+pub fn find_hamt_roots<S: Blockstore>(store: &S, roots: &[Cid]) -> Result<Vec<Cid>> {
+    let mut seen = HashSet::new();
+    let mut stack: Vec<Cid> = roots.iter().rev().copied().collect();
+    let mut hamt_nodes = Vec::new();
+    let mut children = HashSet::new();
+    let mut envelopes = HashMap::new();
+
+    while let Some(cid) = stack.pop() {
+        if cid.codec() != DAG_CBOR || !seen.insert(cid) {
+            continue;
+        }
+        let block = match store.get(&cid)? {
+            Some(block) => block,
+            None => continue,
+        };
+        let ipld: Ipld = from_slice(&block)?;
+        if let Some(root) = envelope_root(&ipld) {
+            envelopes.insert(root, cid);
+        }
+        if let Some((_, pointers)) = as_node(&ipld) {
+            hamt_nodes.push(cid);
+            children.extend(pointers.iter().filter_map(|pointer| match pointer {
+                Ipld::Link(child) => Some(*child),
+                _ => None,
+            }));
+        }
+
+        let mut links = Vec::new();
+        ipld.references(&mut links);
+        stack.extend(links.into_iter().rev());
+    }
+
+    Ok(hamt_nodes
+        .into_iter()
+        .filter(|cid| !children.contains(cid))
+        .map(|cid| envelopes.get(&cid).copied().unwrap_or(cid))
+        .collect())
+}
+
+#[derive(Debug, Serialize)]
+pub struct HamtSummary {
+    pub root: String,
+    pub nodes: u64,
+    pub total_bytes: u64,
+    pub avg_node_bytes: f64,
+    pub entries: u64,
+    /// Largest bucket seen, a lower bound for the bucket size.
+    pub max_bucket: usize,
+    /// Bits needed for the highest slot seen, a lower bound for the bit width.
+    pub min_bit_width: u32,
+    /// Parameters recorded in the envelope, if the HAMT has one.
+    pub bit_width: Option<u32>,
+    pub bucket_size: Option<usize>,
+    pub hash: Option<String>,
+    pub links_per_node: f64,
+    pub values_per_node: f64,
+    pub median_degree: Option<usize>,
+    pub max_degree: Option<usize>,
+    pub mean_depth: f64,
+    pub max_depth: Option<usize>,
+}
+
+/// Walks the HAMT at `root`, which has to be complete in `store`. `root` may
+/// also be an envelope linking to the root node.
+pub fn analyze_hamt<S: Blockstore>(store: &S, root: &Cid) -> Result<HamtSummary> {
+    let envelope = Envelope::load(store, root)?;
+    let mut nodes = 0;
+    let mut total_bytes = 0;
+    let mut links = 0;
+    let mut entries = 0;
+    let mut max_bucket = 0;
+    let mut max_slot = 0;
+    let mut degrees = Histogram::default();
+    let mut depths = Histogram::default();
+    let mut stack = vec![(envelope.as_ref().map_or(*root, |envelope| envelope.root), 0)];
+
+    while let Some((cid, depth)) = stack.pop() {
+        let block = store
+            .get(&cid)?
+            .ok_or_else(|| anyhow!("block {cid} not found"))?;
+        let ipld: Ipld = from_slice(&block)?;
+        let (bitfield, pointers) = match as_node(&ipld) {
+            Some(node) => node,
+            None => bail!("block {cid} isn't a HAMT node"),
+        };
+
+        nodes += 1;
+        total_bytes += block.len() as u64;
+        if let Some(slot) = (0..256).rev().find(|&idx| bitfield.test_bit(idx)) {
+            max_slot = max_slot.max(slot);
+        }
+        let mut degree = 0;
+        for pointer in pointers {
+            match pointer {
+                Ipld::Link(child) => {
+                    degree += 1;
+                    stack.push((*child, depth + 1));
+                }
+                Ipld::List(bucket) => {
+                    entries += bucket.len() as u64;
+                    max_bucket = max_bucket.max(bucket.len());
+                    for _ in bucket {
+                        depths.add(depth);
+                    }
+                }
+                _ => unreachable!("checked by as_node"),
+            }
+        }
+        links += degree;
+        degrees.add(degree);
+    }
+
+    Ok(HamtSummary {
+        root: root.to_string(),
+        nodes,
+        total_bytes,
+        avg_node_bytes: total_bytes as f64 / nodes as f64,
+        entries,
+        max_bucket,
+        min_bit_width: max_slot.checked_ilog2().map_or(1, |bits| bits + 1),
+        bit_width: envelope.as_ref().map(|envelope| envelope.bit_width),
+        bucket_size: envelope.as_ref().map(|envelope| envelope.bucket_size),
+        hash: envelope.map(|envelope| envelope.hash),
+        links_per_node: links as f64 / nodes as f64,
+        values_per_node: entries as f64 / nodes as f64,
+        median_degree: degrees.median(),
+        max_degree: degrees.max(),
+        mean_depth: depths.mean(),
+        max_depth: depths.max(),
+    })
+}
+
+/// The root node `ipld` links to, if it looks like an [`Envelope`].
+fn envelope_root(ipld: &Ipld) -> Option<Cid> {
+    match ipld {
+        Ipld::Map(fields) if fields.contains_key("version") => match fields.get("root") {
+            Some(Ipld::Link(root)) => Some(*root),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// The bitfield and pointers of `ipld`, if it looks like a HAMT node, as a
+/// bitfield and a list of pointers or as a map of its slots.
+fn as_node(ipld: &Ipld) -> Option<(Bitfield, Vec<&Ipld>)> {
+    let (bitfield, pointers): (_, Vec<_>) = match ipld {
+        Ipld::List(fields) => match fields.as_slice() {
+            // Compressed nodes end with the slots they skip.
+            [Ipld::Bytes(bitfield), Ipld::List(pointers)]
+            | [Ipld::Bytes(bitfield), Ipld::List(pointers), Ipld::Bytes(_)] => {
+                // In either encoding, the bytes or the compact one.
+                let (bitfield, _) = Bitfield::from_bytes(bitfield).ok()?;
+                (bitfield, pointers.iter().collect())
+            }
+            _ => return None,
+        },
+        Ipld::Map(fields) => {
+            let mut bitfield = Bitfield::zero();
+            let mut slots = Vec::new();
+            for (key, pointer) in fields {
+                if key == "skip" || key == "len" {
+                    continue;
+                }
+                let slot: u32 = key.parse().ok().filter(|&slot| slot < 256)?;
+                bitfield.set_bit(slot);
+                slots.push((slot, pointer));
+            }
+            slots.sort_by_key(|&(slot, _)| slot);
+            (
+                bitfield,
+                slots.into_iter().map(|(_, pointer)| pointer).collect(),
+            )
+        }
+        _ => return None,
+    };
+    if pointers.is_empty() || bitfield.count_ones() != pointers.len() {
+        return None;
+    }
+    let valid_pointer = |pointer: &Ipld| match pointer {
+        Ipld::Link(_) => true,
+        Ipld::List(bucket) => {
+            !bucket.is_empty()
+                && bucket
+                    .iter()
+                    .all(|entry| matches!(entry, Ipld::List(kv) if kv.len() == 2))
+        }
+        _ => false,
+    };
+    pointers
+        .iter()
+        .all(|pointer| valid_pointer(pointer))
+        .then_some((bitfield, pointers))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memorydb::MemoryDB;
+    use crate::stats::TreeStats;
+    use cid::multihash::Code;
+    use fvm_ipld_encoding::CborStore;
+    use fvm_ipld_hamt::{Hamt, Sha256};
+
+    #[test]
+    fn finds_nested_hamts() -> Result<()> {
+        let store = MemoryDB::default();
+        let mut map: Hamt<_, String, usize, Sha256, 3> = Hamt::new_with_bit_width(&store, 5);
+        for key in 0..2000 {
+            map.set(key, "F".to_string())?;
+        }
+        let hamt = map.flush()?;
+        let mut small: Hamt<_, String, usize, Sha256, 3> = Hamt::new_with_bit_width(&store, 5);
+        small.set(1, "F".to_string())?;
+        let small_root = small.flush()?;
+        // A non-HAMT block pointing at both, like a state root.
+        let state = store.put_cbor(&(hamt, "state", small_root), Code::Blake2b256)?;
+
+        assert_eq!(find_hamt_roots(&store, &[state])?, vec![hamt, small_root]);
+
+        let summary = analyze_hamt(&store, &hamt)?;
+        let stats = TreeStats::new(&map)?;
+        assert_eq!(summary.nodes, stats.nodes);
+        assert_eq!(summary.entries, 2000);
+        assert_eq!(summary.total_bytes, store.live_bytes(&[hamt])?);
+        assert_eq!(summary.max_bucket, 3);
+        assert_eq!(summary.min_bit_width, 5);
+        assert_eq!(summary.bucket_size, None);
+        Ok(())
+    }
+
+    #[test]
+    fn reports_enveloped_hamts() -> Result<()> {
+        let store = MemoryDB::default();
+        let mut map: Hamt<_, String, usize, Sha256, 3> =
+            Hamt::new_with_bit_width(&store, 4).with_envelope();
+        for key in 0..200 {
+            map.set(key, "F".to_string())?;
+        }
+        let envelope = map.flush()?;
+        let state = store.put_cbor(&("state", envelope), Code::Blake2b256)?;
+
+        assert_eq!(find_hamt_roots(&store, &[state])?, vec![envelope]);
+
+        let summary = analyze_hamt(&store, &envelope)?;
+        assert_eq!(summary.root, envelope.to_string());
+        assert_eq!(summary.entries, 200);
+        assert_eq!(summary.bit_width, Some(4));
+        assert_eq!(summary.bucket_size, Some(3));
+        assert_eq!(summary.hash.as_deref(), Some("sha2-256"));
+        Ok(())
+    }
+    #[test]
+    fn reads_other_node_formats() -> Result<()> {
+        let store = MemoryDB::default();
+        for (map_nodes, compact_bitfields) in [(true, false), (false, true)] {
+            let mut map: Hamt<_, String, usize, Sha256, 3> = Hamt::new_with_bit_width(&store, 8);
+            map.map_nodes = map_nodes;
+            map.compact_bitfields = compact_bitfields;
+            for key in 0..2000 {
+                map.set(key, "F".to_string())?;
+            }
+            let hamt = map.flush()?;
+            assert_eq!(find_hamt_roots(&store, &[hamt])?, vec![hamt]);
+            let summary = analyze_hamt(&store, &hamt)?;
+            assert_eq!(summary.nodes, TreeStats::new(&map)?.nodes);
+            assert_eq!(summary.entries, 2000);
+            assert_eq!(summary.min_bit_width, 8);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,51 @@
+use std::str::FromStr;
+
+use anyhow::{bail, Error, Result};
+
+use crate::bucket::parse_sweep;
+
+/// Bit widths the HAMT supports, a node has up to `2^bit_width` children.
+pub const BIT_WIDTHS: &[u32] = &[1, 2, 3, 4, 5, 6, 7, 8];
+
+/// A list of bit widths given on the command line, in the same formats as
+/// [`BucketSizes`](crate::bucket::BucketSizes).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BitWidths(pub Vec<u32>);
+
+impl BitWidths {
+    pub fn iter(&self) -> impl Iterator<Item = u32> + '_ {
+        self.0.iter().copied()
+    }
+
+    /// Returns the bit width if exactly one was given.
+    pub fn single(&self) -> Result<u32> {
+        match self.0.as_slice() {
+            [bit_width] => Ok(*bit_width),
+            bit_widths => bail!("expected a single bit width, got {bit_widths:?}"),
+        }
+    }
+}
+
+impl FromStr for BitWidths {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        parse_sweep(s, BIT_WIDTHS, "bit width").map(BitWidths)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_lists_and_ranges() {
+        let bit_widths: BitWidths = "4".parse().unwrap();
+        assert_eq!(bit_widths.single().unwrap(), 4);
+        let bit_widths: BitWidths = "1..=3,8".parse().unwrap();
+        assert_eq!(bit_widths.0, vec![1, 2, 3, 8]);
+        assert!(bit_widths.single().is_err());
+        assert!("0".parse::<BitWidths>().is_err());
+        assert!("9..12".parse::<BitWidths>().is_err());
+    }
+}
@@ -0,0 +1,397 @@
+//! A content-addressed B-tree, the ordered alternative to a HAMT, e.g. for
+//! directories that are listed in order.
+//!
+//! Entries are sorted by key and live in the leaves, internal nodes link to
+//! children together with the smallest key below each of them (a B+ tree).
+//! Nodes hold at most `fanout` entries or children and are split in half
+//! when they grow beyond that. Every node is a block of its own:
+//!
+//! ```text
+//! [height, [[key, value | cid], ...]]
+//! ```
+//!
+//! Leaves are at height 0. Unlike the HAMT, the shape of the tree depends on
+//! the order the keys were inserted in. Deletes only drop nodes that become
+//! empty, without merging those that are less than half full, and trees can't
+//! be loaded from a store; they're only built and flushed to measure them.
+
+use std::mem;
+
+use anyhow::{bail, Result};
+use cid::multihash::Code;
+use cid::Cid;
+use fvm_ipld_blockstore::{Block, Blockstore};
+use fvm_ipld_encoding::{to_vec, DAG_CBOR};
+use serde::Serialize;
+
+use crate::map::{IpldMap, MapStats};
+
+pub struct BTree<'a, BS, K, V> {
+    store: &'a BS,
+    fanout: usize,
+    height: u32,
+    root: Node<K, V>,
+    /// Length of the root block as of the last flush, unless there were
+    /// changes since.
+    root_size: Option<u64>,
+}
+
+enum Node<K, V> {
+    Leaf(Vec<(K, V)>),
+    /// Children by the smallest key below them.
+    Internal(Vec<(K, Child<K, V>)>),
+}
+
+struct Child<K, V> {
+    node: Node<K, V>,
+    /// CID and length of the block, unless the node changed since it was
+    /// last flushed.
+    block: Option<(Cid, u64)>,
+}
+
+#[derive(Serialize)]
+#[serde(untagged)]
+enum Slot<'n, V> {
+    Value(&'n V),
+    Link(Cid),
+}
+
+impl<'a, BS, K, V> BTree<'a, BS, K, V>
+where
+    BS: Blockstore,
+    K: Ord + Clone + Serialize,
+    V: Serialize,
+{
+    pub fn new_with_fanout(store: &'a BS, fanout: usize) -> Self {
+        assert!(fanout >= 2, "unsupported fanout {fanout}");
+        BTree {
+            store,
+            fanout,
+            height: 0,
+            root: Node::Leaf(Vec::new()),
+            root_size: None,
+        }
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Sets the value of `key`, returning the previous one.
+    pub fn set(&mut self, key: K, value: V) -> Option<V> {
+        self.root_size = None;
+        let (old, split) = self.root.set(key, value, self.fanout);
+        if let Some(right) = split {
+            let left = mem::replace(&mut self.root, Node::Leaf(Vec::new()));
+            self.root = Node::Internal(vec![Child::entry(left), Child::entry(right)]);
+            self.height += 1;
+        }
+        old
+    }
+
+    pub fn get(&self, key: &K) -> Option<&V> {
+        let mut node = &self.root;
+        loop {
+            match node {
+                Node::Leaf(entries) => {
+                    let i = entries.binary_search_by(|(k, _)| k.cmp(key)).ok()?;
+                    return Some(&entries[i].1);
+                }
+                Node::Internal(children) => node = &children[child_index(children, key)].1.node,
+            }
+        }
+    }
+
+    /// Deletes `key`, returning its value.
+    pub fn delete(&mut self, key: &K) -> Option<V> {
+        let old = self.root.delete(key)?;
+        self.root_size = None;
+        while let Node::Internal(children) = &mut self.root {
+            if children.len() > 1 {
+                break;
+            }
+            let (_, child) = children.pop().expect("empty nodes are removed");
+            self.root = child.node;
+            self.height -= 1;
+        }
+        Some(old)
+    }
+
+    /// Writes every changed node and the root to the store.
+    pub fn flush(&mut self) -> Result<Cid> {
+        flush_children(&mut self.root, self.height, self.store)?;
+        let (cid, size) = put(self.store, &self.root.encoding(self.height))?;
+        self.root_size = Some(size);
+        Ok(cid)
+    }
+
+    /// Total length of the blocks from the root to the leaf of `key`, which
+    /// proves its value. Fails if the tree wasn't flushed since it was last
+    /// changed.
+    pub fn proof_bytes(&self, key: &K) -> Result<Option<u64>> {
+        let Some(mut bytes) = self.root_size else {
+            bail!("the B-tree has to be flushed first");
+        };
+        let mut node = &self.root;
+        loop {
+            match node {
+                Node::Leaf(entries) => {
+                    let found = entries.binary_search_by(|(k, _)| k.cmp(key)).is_ok();
+                    return Ok(found.then_some(bytes));
+                }
+                Node::Internal(children) => {
+                    let child = &children[child_index(children, key)].1;
+                    let (_, size) = child.block.expect("flushed with the root");
+                    bytes += size;
+                    node = &child.node;
+                }
+            }
+        }
+    }
+}
+
+impl<K: Ord + Clone, V> Child<K, V> {
+    fn entry(node: Node<K, V>) -> (K, Self) {
+        (node.first_key().clone(), Child { node, block: None })
+    }
+}
+
+impl<K: Ord + Clone, V> Node<K, V> {
+    /// Returns the previous value of `key` and, if the node had to be split,
+    /// the new node of its upper half.
+    fn set(&mut self, key: K, value: V, fanout: usize) -> (Option<V>, Option<Self>) {
+        let old = match self {
+            Node::Leaf(entries) => match entries.binary_search_by(|(k, _)| k.cmp(&key)) {
+                Ok(i) => Some(mem::replace(&mut entries[i].1, value)),
+                Err(i) => {
+                    entries.insert(i, (key, value));
+                    None
+                }
+            },
+            Node::Internal(children) => {
+                let i = child_index(children, &key);
+                if key < children[i].0 {
+                    children[i].0 = key.clone();
+                }
+                let child = &mut children[i].1;
+                child.block = None;
+                let (old, split) = child.node.set(key, value, fanout);
+                if let Some(right) = split {
+                    children.insert(i + 1, Child::entry(right));
+                }
+                old
+            }
+        };
+        let split = (self.len() > fanout).then(|| self.split_off());
+        (old, split)
+    }
+
+    /// Deletes `key` and removes children that end up empty.
+    fn delete(&mut self, key: &K) -> Option<V> {
+        match self {
+            Node::Leaf(entries) => {
+                let i = entries.binary_search_by(|(k, _)| k.cmp(key)).ok()?;
+                Some(entries.remove(i).1)
+            }
+            Node::Internal(children) => {
+                let i = child_index(children, key);
+                let (first, child) = &mut children[i];
+                let old = child.node.delete(key)?;
+                child.block = None;
+                if child.node.len() == 0 {
+                    children.remove(i);
+                } else {
+                    *first = child.node.first_key().clone();
+                }
+                Some(old)
+            }
+        }
+    }
+
+    /// Counts the nodes and values below and including this one.
+    fn add_stats(&self, stats: &mut MapStats) {
+        stats.nodes += 1;
+        match self {
+            Node::Leaf(entries) => stats.values += entries.len() as u64,
+            Node::Internal(children) => {
+                for (_, child) in children {
+                    child.node.add_stats(stats);
+                }
+            }
+        }
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            Node::Leaf(entries) => entries.len(),
+            Node::Internal(children) => children.len(),
+        }
+    }
+
+    fn first_key(&self) -> &K {
+        match self {
+            Node::Leaf(entries) => &entries[0].0,
+            Node::Internal(children) => &children[0].0,
+        }
+    }
+
+    fn split_off(&mut self) -> Self {
+        let at = self.len() / 2;
+        match self {
+            Node::Leaf(entries) => Node::Leaf(entries.split_off(at)),
+            Node::Internal(children) => Node::Internal(children.split_off(at)),
+        }
+    }
+
+    /// `[height, entries]`, children have to be flushed.
+    fn encoding(&self, height: u32) -> (u32, Vec<(&K, Slot<'_, V>)>) {
+        let entries = match self {
+            Node::Leaf(entries) => entries.iter().map(|(k, v)| (k, Slot::Value(v))).collect(),
+            Node::Internal(children) => children
+                .iter()
+                .map(|(k, child)| {
+                    let (cid, _) = child.block.expect("children are flushed first");
+                    (k, Slot::Link(cid))
+                })
+                .collect(),
+        };
+        (height, entries)
+    }
+}
+
+impl<BS, K, V> IpldMap<K, V> for BTree<'_, BS, K, V>
+where
+    BS: Blockstore,
+    K: Ord + Clone + Serialize,
+    V: Serialize,
+{
+    fn set(&mut self, key: K, value: V) -> Result<Option<V>> {
+        Ok(BTree::set(self, key, value))
+    }
+
+    fn get(&self, key: &K) -> Result<Option<&V>> {
+        Ok(BTree::get(self, key))
+    }
+
+    fn delete(&mut self, key: &K) -> Result<Option<V>> {
+        Ok(BTree::delete(self, key))
+    }
+
+    fn flush(&mut self) -> Result<Cid> {
+        BTree::flush(self)
+    }
+
+    fn stats(&self) -> Result<MapStats> {
+        let mut stats = MapStats {
+            height: self.height,
+            ..MapStats::default()
+        };
+        self.root.add_stats(&mut stats);
+        Ok(stats)
+    }
+}
+
+/// Index of the child `key` belongs to, the first one for keys smaller than
+/// all others.
+fn child_index<K: Ord, C>(children: &[(K, C)], key: &K) -> usize {
+    children
+        .partition_point(|(k, _)| k <= key)
+        .saturating_sub(1)
+}
+
+/// Puts the changed descendants of `node` at `height`.
+fn flush_children<K, V>(node: &mut Node<K, V>, height: u32, store: &impl Blockstore) -> Result<()>
+where
+    K: Ord + Clone + Serialize,
+    V: Serialize,
+{
+    if let Node::Internal(children) = node {
+        for (_, child) in children.iter_mut() {
+            if child.block.is_none() {
+                flush_children(&mut child.node, height - 1, store)?;
+                child.block = Some(put(store, &child.node.encoding(height - 1))?);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn put(store: &impl Blockstore, value: &impl Serialize) -> Result<(Cid, u64)> {
+    let bytes = to_vec(value)?;
+    let cid = store.put(Code::Blake2b256, &Block::new(DAG_CBOR, &bytes))?;
+    Ok((cid, bytes.len() as u64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memorydb::MemoryDB;
+
+    #[test]
+    fn sets_and_gets_values() -> Result<()> {
+        let store = MemoryDB::default();
+        let mut tree = BTree::new_with_fanout(&store, 4);
+        for key in (0..100u64).rev() {
+            assert_eq!(tree.set(key, key * 2), None);
+        }
+        assert_eq!(tree.set(7, 0), Some(14));
+        assert_eq!(tree.get(&7), Some(&0));
+        assert_eq!(tree.get(&8), Some(&16));
+        assert_eq!(tree.get(&100), None);
+        // Half full nodes of at least 2 entries need 6 levels for 100.
+        assert!(tree.height() <= 6);
+
+        tree.flush()?;
+        let blocks = store.blocks();
+        assert!(blocks >= 25);
+        let proof = tree.proof_bytes(&0)?.unwrap();
+        assert_eq!(tree.proof_bytes(&100)?, None);
+        tree.set(0, 1);
+        assert!(tree.proof_bytes(&0).is_err());
+        tree.flush()?;
+        // Only the path to key 0 is rewritten.
+        assert_eq!(store.blocks(), blocks + tree.height() as usize + 1);
+        assert_eq!(tree.proof_bytes(&0)?, Some(proof));
+        Ok(())
+    }
+
+    #[test]
+    fn splits_nodes_in_half() -> Result<()> {
+        let store = MemoryDB::default();
+        let mut tree = BTree::new_with_fanout(&store, 2);
+        for key in [1u8, 2, 3] {
+            tree.set(key, ());
+        }
+        assert_eq!(tree.height(), 1);
+        let Node::Internal(children) = &tree.root else {
+            panic!("the root was split");
+        };
+        assert_eq!(children.iter().map(|(k, _)| *k).collect::<Vec<_>>(), [1, 2]);
+        tree.flush()?;
+        // The root and two leaves.
+        assert_eq!(store.blocks(), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn deletes_values_and_empty_nodes() {
+        let store = MemoryDB::default();
+        let mut tree = BTree::new_with_fanout(&store, 3);
+        for key in 0..50u32 {
+            tree.set(key, key);
+        }
+        let height = tree.height();
+        assert_eq!(tree.delete(&50), None);
+        assert_eq!(tree.delete(&0), Some(0));
+        assert_eq!(tree.get(&0), None);
+        assert_eq!(tree.get(&1), Some(&1));
+        for key in 1..49 {
+            tree.delete(&key);
+        }
+        assert!(tree.height() < height);
+        assert_eq!(tree.get(&49), Some(&49));
+        assert_eq!(tree.delete(&49), Some(49));
+        assert_eq!(tree.height(), 0);
+        assert_eq!(IpldMap::stats(&tree).unwrap().values, 0);
+    }
+}
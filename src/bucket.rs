@@ -0,0 +1,144 @@
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{bail, Error, Result};
+
+/// Bucket sizes `with_bucket_size!` is monomorphized over.
+pub const BUCKET_SIZES: &[usize] = &[
+    1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 24, 32, 48, 64, 128,
+];
+
+/// Runs `$body` with `$B` bound to the runtime value `$bucket_size` as a const.
+///
+/// Only sizes in [`BUCKET_SIZES`] are supported, anything else makes the
+/// surrounding function return an error.
+macro_rules! with_bucket_size {
+    ($bucket_size:expr, $B:ident => $body:expr) => {
+        $crate::bucket::with_bucket_size!(
+            @dispatch $bucket_size, $B => $body;
+            1 2 3 4 5 6 7 8 9 10 11 12 13 14 15 16 24 32 48 64 128
+        )
+    };
+    (@dispatch $bucket_size:expr, $B:ident => $body:expr; $($size:literal)*) => {
+        match $bucket_size {
+            $($size => {
+                const $B: usize = $size;
+                $body
+            })*
+            other => anyhow::bail!(
+                "unsupported bucket size {other}, expected one of {:?}",
+                $crate::bucket::BUCKET_SIZES
+            ),
+        }
+    };
+}
+
+pub(crate) use with_bucket_size;
+
+/// A list of bucket sizes given on the command line.
+///
+/// Accepts a single size (`3`), a comma separated list (`1,2,4`) or a range
+/// (`1..8`, `1..=16`). Ranges select every supported size they contain.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BucketSizes(pub Vec<usize>);
+
+impl BucketSizes {
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        self.0.iter().copied()
+    }
+
+    /// Returns the bucket size if exactly one was given.
+    pub fn single(&self) -> Result<usize> {
+        match self.0.as_slice() {
+            [size] => Ok(*size),
+            sizes => bail!("expected a single bucket size, got {sizes:?}"),
+        }
+    }
+}
+
+impl FromStr for BucketSizes {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        parse_sweep(s, BUCKET_SIZES, "bucket size").map(BucketSizes)
+    }
+}
+
+/// Parses a single value, a comma separated list or ranges of the values in
+/// `supported`, all of which are called `what` in errors.
+pub(crate) fn parse_sweep<T>(s: &str, supported: &[T], what: &str) -> Result<Vec<T>>
+where
+    T: FromStr + Copy + PartialOrd + fmt::Display + fmt::Debug,
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    let mut values = Vec::new();
+
+    for part in s.split(',') {
+        let part = part.trim();
+        if let Some((start, end)) = part.split_once("..") {
+            let start: T = start.parse()?;
+            let (end, inclusive) = match end.strip_prefix('=') {
+                Some(end) => (end.parse::<T>()?, true),
+                None => (end.parse::<T>()?, false),
+            };
+            let in_range: Vec<T> = supported
+                .iter()
+                .copied()
+                .filter(|&value| start <= value && (value < end || inclusive && value == end))
+                .collect();
+            if in_range.is_empty() {
+                bail!("no supported {what} in `{part}`");
+            }
+            values.extend(in_range);
+        } else {
+            let value = part.parse()?;
+            if !supported.contains(&value) {
+                bail!("unsupported {what} {value}, expected one of {supported:?}");
+            }
+            values.push(value);
+        }
+    }
+
+    Ok(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_lists_and_ranges() {
+        let sizes: BucketSizes = "1,3".parse().unwrap();
+        assert_eq!(sizes.0, vec![1, 3]);
+        let sizes: BucketSizes = "14..=32".parse().unwrap();
+        assert_eq!(sizes.0, vec![14, 15, 16, 24, 32]);
+        let sizes: BucketSizes = "1..3,64".parse().unwrap();
+        assert_eq!(sizes.0, vec![1, 2, 64]);
+        assert!("17".parse::<BucketSizes>().is_err());
+        assert!("17..20".parse::<BucketSizes>().is_err());
+    }
+
+    #[test]
+    fn dispatches_every_supported_size() -> Result<()> {
+        for &size in BUCKET_SIZES {
+            assert_eq!(with_bucket_size!(size, B => B), size);
+        }
+        Ok(())
+    }
+
+    /// The sizes `with_bucket_size!` lists are written out separately from
+    /// [`BUCKET_SIZES`], so check that it rejects every other size.
+    #[test]
+    fn dispatches_only_supported_sizes() {
+        fn dispatch(size: usize) -> Result<usize> {
+            Ok(with_bucket_size!(size, B => B))
+        }
+        for size in 0..=1024 {
+            assert_eq!(
+                dispatch(size).is_ok(),
+                BUCKET_SIZES.contains(&size),
+                "bucket size {size}"
+            );
+        }
+    }
+}
@@ -0,0 +1,83 @@
+//! How many loaded nodes a HAMT keeps cached behind its links, which the
+//! `cache` experiment compares.
+
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail};
+use fvm_ipld_hamt::CacheLimit;
+
+/// Limit of a HAMT's node cache.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheSize {
+    /// Every node loaded stays cached, as without a node cache.
+    Unbounded,
+    Limited(CacheLimit),
+}
+
+/// Sizes the `cache` experiment compares unless `--node-cache` is given.
+pub const CACHE_SIZES: &[CacheSize] = &[
+    CacheSize::Unbounded,
+    CacheSize::Limited(CacheLimit::Nodes(16)),
+    CacheSize::Limited(CacheLimit::Nodes(256)),
+    CacheSize::Limited(CacheLimit::Nodes(4096)),
+    CacheSize::Limited(CacheLimit::Bytes(1 << 16)),
+    CacheSize::Limited(CacheLimit::Bytes(1 << 20)),
+];
+
+impl CacheSize {
+    /// The limit of the node cache, one that's never reached if unbounded,
+    /// so that its hits and misses are still counted.
+    pub fn limit(self) -> CacheLimit {
+        match self {
+            CacheSize::Unbounded => CacheLimit::Nodes(u64::MAX),
+            CacheSize::Limited(limit) => limit,
+        }
+    }
+}
+
+impl fmt::Display for CacheSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CacheSize::Unbounded => f.write_str("none"),
+            CacheSize::Limited(CacheLimit::Nodes(nodes)) => write!(f, "nodes:{nodes}"),
+            CacheSize::Limited(CacheLimit::Bytes(bytes)) => write!(f, "bytes:{bytes}"),
+        }
+    }
+}
+
+impl FromStr for CacheSize {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let count = |count: &str| {
+            count
+                .parse()
+                .ok()
+                .filter(|&count| count > 0)
+                .ok_or_else(|| anyhow!("invalid count `{count}`, expected a positive integer"))
+        };
+        let size = match s.split_once(':') {
+            None if s == "none" => CacheSize::Unbounded,
+            Some(("nodes", nodes)) => CacheSize::Limited(CacheLimit::Nodes(count(nodes)?)),
+            Some(("bytes", bytes)) => CacheSize::Limited(CacheLimit::Bytes(count(bytes)?)),
+            _ => bail!("unknown node cache `{s}`, expected `none`, `nodes:<n>` or `bytes:<n>`"),
+        };
+        Ok(size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_and_prints_sizes() {
+        for size in CACHE_SIZES {
+            assert_eq!(size.to_string().parse::<CacheSize>().unwrap(), *size);
+        }
+        assert!("nodes:0".parse::<CacheSize>().is_err());
+        assert!("bytes".parse::<CacheSize>().is_err());
+        assert!("lru".parse::<CacheSize>().is_err());
+    }
+}
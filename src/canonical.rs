@@ -0,0 +1,345 @@
+//! Checking that the blocks of a HAMT are the ones this implementation would
+//! write for its entries, and rewriting them if not.
+//!
+//! Two HAMTs with the same entries and parameters only have the same root CID
+//! if every block is canonical. HAMTs written by other implementations can
+//! decode fine and still differ, with buckets in another order, nodes that
+//! should have collapsed into their parent, or another encoding of the same
+//! node.
+
+use std::cmp::Ordering;
+use std::fmt;
+
+use anyhow::Result;
+use cid::Cid;
+use fvm_ipld_blockstore::Blockstore;
+use fvm_ipld_encoding::de::DeserializeOwned;
+use fvm_ipld_hamt::node::Node;
+use fvm_ipld_hamt::pointer::Pointer;
+use fvm_ipld_hamt::{cid_config, CidConfig, Envelope, Hamt, Hash, HashAlgorithm};
+use serde::Serialize;
+
+use crate::invariants::is_collapsible;
+use crate::traverse::TraverseError;
+
+/// Why a block isn't canonical.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Problem {
+    /// Encoding the decoded block again gives other bytes.
+    Encoding,
+    /// The bucket in this slot isn't sorted by key, or has a key twice.
+    UnsortedBucket(u32),
+    /// The node should have collapsed into a bucket of its parent.
+    Collapsible,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Problem::Encoding => write!(f, "isn't canonically encoded"),
+            Problem::UnsortedBucket(slot) => write!(f, "has an unsorted bucket in slot {slot}"),
+            Problem::Collapsible => write!(f, "should have collapsed into its parent"),
+        }
+    }
+}
+
+/// A block that isn't canonical, and the slots on the path to its node.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NonCanonical {
+    pub cid: Cid,
+    pub path: Vec<u32>,
+    pub problem: Problem,
+}
+
+impl NonCanonical {
+    fn new(cid: &Cid, path: &[u32], problem: Problem) -> Self {
+        NonCanonical {
+            cid: *cid,
+            path: path.to_vec(),
+            problem,
+        }
+    }
+}
+
+impl fmt::Display for NonCanonical {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "block {} of node {:?} {}",
+            self.cid, self.path, self.problem
+        )
+    }
+}
+
+/// Decodes every block of the HAMT at `root`, an [`Envelope`] or a root
+/// node, and returns the ones that aren't canonical. Fails on blocks that
+/// are missing or aren't nodes at all.
+pub fn verify_canonical<S, K, V, H, const BUCKET_SIZE: usize>(
+    store: &S,
+    root: &Cid,
+) -> Result<Vec<NonCanonical>>
+where
+    S: Blockstore,
+    K: Hash + Eq + PartialOrd + Serialize + DeserializeOwned,
+    V: Serialize + DeserializeOwned,
+    H: HashAlgorithm,
+{
+    let mut problems = Vec::new();
+    let mut root = *root;
+    if let Some(envelope) = Envelope::load(store, &root)? {
+        check_encoding(store, &root, &envelope, &[], &mut problems)?;
+        root = envelope.root;
+    }
+    // The bit width only matters for finding keys, not for decoding nodes.
+    let hamt: Hamt<_, V, K, H, BUCKET_SIZE> = Hamt::load(&root, store)?;
+    check_encoding(store, &root, &hamt, &[], &mut problems)?;
+    verify_node(&hamt.root, &root, store, &mut Vec::new(), &mut problems)?;
+    Ok(problems)
+}
+
+/// Adds a problem if `value`, decoded from the block `cid`, encodes to other
+/// bytes than the block.
+fn check_encoding<S: Blockstore, T: Serialize>(
+    store: &S,
+    cid: &Cid,
+    value: &T,
+    path: &[u32],
+    problems: &mut Vec<NonCanonical>,
+) -> Result<()> {
+    let block = read_block(store, cid)?;
+    let encoded = CidConfig::of(cid).unwrap_or_default().encode(value)?;
+    if encoded != block {
+        problems.push(NonCanonical::new(cid, path, Problem::Encoding));
+    }
+    Ok(())
+}
+
+fn read_block<S: Blockstore>(store: &S, cid: &Cid) -> Result<Vec<u8>, TraverseError> {
+    store
+        .get(cid)
+        .map_err(|e| TraverseError::Store(*cid, e))?
+        .ok_or(TraverseError::MissingBlock(*cid))
+}
+
+fn verify_node<S, K, V, H, const BUCKET_SIZE: usize>(
+    node: &Node<K, V, H, BUCKET_SIZE>,
+    cid: &Cid,
+    store: &S,
+    path: &mut Vec<u32>,
+    problems: &mut Vec<NonCanonical>,
+) -> Result<()>
+where
+    S: Blockstore,
+    K: PartialOrd + Serialize + DeserializeOwned,
+    V: Serialize + DeserializeOwned,
+{
+    path.extend(node.skip.iter().map(|&slot| slot as u32));
+    if !path.is_empty() && is_collapsible(node) {
+        problems.push(NonCanonical::new(cid, path, Problem::Collapsible));
+    }
+    let slots = (0..256).filter(|&idx| node.bitfield.test_bit(idx));
+    for (idx, pointer) in slots.zip(&node.pointers) {
+        match pointer {
+            Pointer::Values(bucket) => {
+                if !bucket.windows(2).all(|pair| pair[0].key() < pair[1].key()) {
+                    problems.push(NonCanonical::new(cid, path, Problem::UnsortedBucket(idx)));
+                }
+            }
+            Pointer::Link { cid: child_cid, .. } => {
+                let block = read_block(store, child_cid)?;
+                let child: Node<K, V, H, BUCKET_SIZE> =
+                    cid_config::decode(child_cid.codec(), &block)
+                        .map_err(|e| TraverseError::Decode(*child_cid, e.into()))?;
+                path.push(idx);
+                check_encoding(store, child_cid, &child, path, problems)?;
+                verify_node(&child, child_cid, store, path, problems)?;
+                path.pop();
+            }
+            // Loaded nodes are never dirty.
+            Pointer::Dirty(_) => unreachable!("node {path:?} of a loaded HAMT is dirty"),
+        }
+    }
+    path.truncate(path.len() - node.skip.len());
+    Ok(())
+}
+
+/// Sorts the buckets of `hamt`, collapses the nodes that should have
+/// collapsed and writes every node again, canonically encoded. Returns the
+/// new root.
+pub fn normalize<BS, K, V, H, const BUCKET_SIZE: usize>(
+    hamt: &mut Hamt<BS, V, K, H, BUCKET_SIZE>,
+) -> Result<Cid>
+where
+    BS: Blockstore,
+    K: Hash + Eq + PartialOrd + Serialize + DeserializeOwned,
+    V: Serialize + DeserializeOwned,
+    H: HashAlgorithm,
+{
+    let mut root = std::mem::take(&mut hamt.root);
+    let normalized = normalize_node(&mut root, hamt.store());
+    hamt.root = root;
+    normalized?;
+    Ok(hamt.flush()?)
+}
+
+fn normalize_node<S, K, V, H, const BUCKET_SIZE: usize>(
+    node: &mut Node<K, V, H, BUCKET_SIZE>,
+    store: &S,
+) -> Result<()>
+where
+    S: Blockstore,
+    K: PartialOrd + DeserializeOwned,
+    V: DeserializeOwned,
+{
+    for pointer in &mut node.pointers {
+        let mut child = match pointer {
+            Pointer::Values(bucket) => {
+                sort_bucket(bucket);
+                continue;
+            }
+            Pointer::Link { cid, cache } => match cache.take() {
+                Some(child) => child,
+                None => {
+                    let block = read_block(store, cid)?;
+                    Box::new(
+                        cid_config::decode(cid.codec(), &block)
+                            .map_err(|e| TraverseError::Decode(*cid, e.into()))?,
+                    )
+                }
+            },
+            Pointer::Dirty(child) => std::mem::take(child),
+        };
+        normalize_node(&mut child, store)?;
+        // Writing the node again from scratch also re-encodes it.
+        *pointer = if is_collapsible(&child) {
+            let mut bucket: Vec<_> = child
+                .pointers
+                .into_iter()
+                .flat_map(|pointer| match pointer {
+                    Pointer::Values(bucket) => bucket,
+                    _ => unreachable!("collapsible nodes only have buckets"),
+                })
+                .collect();
+            sort_bucket(&mut bucket);
+            Pointer::Values(bucket)
+        } else {
+            Pointer::Dirty(child)
+        };
+    }
+    Ok(())
+}
+
+fn sort_bucket<K: PartialOrd, V>(bucket: &mut [fvm_ipld_hamt::KeyValuePair<K, V>]) {
+    bucket.sort_unstable_by(|a, b| a.key().partial_cmp(b.key()).unwrap_or(Ordering::Equal));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memorydb::MemoryDB;
+    use fvm_ipld_hamt::dag_json::DAG_JSON;
+    use fvm_ipld_hamt::Sha256;
+    use once_cell::sync::OnceCell;
+
+    type Map<'a> = Hamt<&'a MemoryDB, u64, u64, Sha256, 3>;
+
+    fn child<'a>(map: &'a Map, slot: usize) -> &'a Node<u64, u64, Sha256, 3> {
+        match &map.root.pointers[slot] {
+            Pointer::Link { cid, cache } => {
+                crate::traverse::resolve_link(cid, cache, map.store()).unwrap()
+            }
+            _ => panic!("expected a link"),
+        }
+    }
+
+    #[test]
+    fn finds_and_normalizes_non_canonical_blocks() -> Result<()> {
+        let store = MemoryDB::default();
+        let json = CidConfig {
+            codec: DAG_JSON,
+            ..CidConfig::default()
+        };
+        let mut map: Map = Hamt::new_with_bit_width(&store, 4).with_cid_config(json);
+        for key in 0..200 {
+            map.set(key, key)?;
+        }
+        let canonical = map.flush()?;
+        assert_eq!(
+            verify_canonical::<_, u64, u64, Sha256, 3>(&store, &canonical)?,
+            []
+        );
+
+        // Whitespace decodes like the node it's in, but isn't canonical.
+        let Pointer::Link { cid, .. } = &map.root.pointers[0] else {
+            panic!("expected a link");
+        };
+        let mut block = store.get(cid)?.unwrap();
+        block.insert(1, b' ');
+        let spaced = json.put(&store, &block)?;
+        map.root.pointers[0] = Pointer::Link {
+            cid: spaced,
+            cache: OnceCell::new(),
+        };
+
+        // A reversed bucket, and a bucket moved into a node of its own.
+        let slot = (1..map.root.pointers.len())
+            .find(|&slot| {
+                let node = child(&map, slot);
+                let buckets = node.pointers.iter().filter_map(|pointer| match pointer {
+                    Pointer::Values(bucket) => Some(bucket.len()),
+                    _ => None,
+                });
+                buckets.clone().any(|len| len > 1) && buckets.count() > 1
+            })
+            .expect("a node with two buckets");
+        let Pointer::Link { cache, .. } = &mut map.root.pointers[slot] else {
+            unreachable!();
+        };
+        let mut node = cache.take().unwrap();
+        let reversed = node
+            .pointers
+            .iter()
+            .position(|pointer| matches!(pointer, Pointer::Values(bucket) if bucket.len() > 1))
+            .unwrap();
+        let Pointer::Values(bucket) = &mut node.pointers[reversed] else {
+            unreachable!();
+        };
+        bucket.reverse();
+        let wrapped = node
+            .pointers
+            .iter()
+            .enumerate()
+            .position(|(i, pointer)| i != reversed && matches!(pointer, Pointer::Values(_)))
+            .unwrap();
+        let Pointer::Values(bucket) = std::mem::take(&mut node.pointers[wrapped]) else {
+            unreachable!();
+        };
+        let mut single = Node::default();
+        single.bitfield.set_bit(0);
+        single.pointers.push(Pointer::Values(bucket));
+        node.pointers[wrapped] = Pointer::Dirty(Box::new(single));
+        map.root.pointers[slot] = Pointer::Dirty(node);
+        let broken = map.flush()?;
+
+        let problems = verify_canonical::<_, u64, u64, Sha256, 3>(&store, &broken)?;
+        assert_eq!(problems.len(), 3, "{problems:?}");
+        assert_eq!(problems[0].cid, spaced);
+        assert_eq!(problems[0].problem, Problem::Encoding);
+        // The other two are in the same node, in the order of their slots.
+        for problem in &problems[1..] {
+            match problem.problem {
+                Problem::UnsortedBucket(_) => assert_eq!(problem.path.len(), 1),
+                Problem::Collapsible => assert_eq!(problem.path.len(), 2),
+                Problem::Encoding => panic!("{problem} too"),
+            }
+        }
+
+        let mut loaded: Map = Hamt::load_with_bit_width(&broken, &store, 4)?;
+        assert_eq!(normalize(&mut loaded)?, canonical);
+        assert_eq!(
+            verify_canonical::<_, u64, u64, Sha256, 3>(&store, &canonical)?,
+            []
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,201 @@
+//! [CAR](https://ipld.io/specs/transport/car/) import and export, for moving
+//! benchmark datasets between implementations.
+//!
+//! Both CARv1 and CARv2 files can be read. CARv2 files are written without an
+//! index, which readers only need for random access.
+
+use std::collections::HashSet;
+use std::io::{self, BufRead, BufReader, Read, Write};
+
+use anyhow::{anyhow, bail, Result};
+use cid::multihash::{Code, MultihashDigest};
+use cid::Cid;
+use fvm_ipld_blockstore::Blockstore;
+use fvm_ipld_encoding::{from_slice, to_vec, DAG_CBOR};
+use libipld_core::ipld::Ipld;
+use serde::{Deserialize, Serialize};
+
+/// Start of every CARv2 file, which reads as a CARv1 header of version 2.
+const V2_PRAGMA: [u8; 11] = [
+    0x0a, 0xa1, 0x67, 0x76, 0x65, 0x72, 0x73, 0x69, 0x6f, 0x6e, 0x02,
+];
+/// Characteristics, data offset, data size and index offset.
+const V2_HEADER_LEN: usize = 40;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Header {
+    #[serde(default)]
+    roots: Vec<Cid>,
+    version: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CarVersion {
+    V1,
+    V2,
+}
+
+/// Writes all blocks reachable from `roots` as a CAR file, parents first.
+pub fn write_car<S: Blockstore>(
+    store: &S,
+    roots: &[Cid],
+    version: CarVersion,
+    out: &mut impl Write,
+) -> Result<()> {
+    match version {
+        CarVersion::V1 => write_v1(store, roots, out),
+        CarVersion::V2 => {
+            let mut data = Vec::new();
+            write_v1(store, roots, &mut data)?;
+            let data_offset = (V2_PRAGMA.len() + V2_HEADER_LEN) as u64;
+
+            out.write_all(&V2_PRAGMA)?;
+            out.write_all(&[0; 16])?;
+            out.write_all(&data_offset.to_le_bytes())?;
+            out.write_all(&(data.len() as u64).to_le_bytes())?;
+            out.write_all(&0u64.to_le_bytes())?;
+            out.write_all(&data)?;
+            Ok(())
+        }
+    }
+}
+
+fn write_v1<S: Blockstore>(store: &S, roots: &[Cid], out: &mut impl Write) -> Result<()> {
+    let header = to_vec(&Header {
+        roots: roots.to_vec(),
+        version: 1,
+    })?;
+    write_varint(out, header.len())?;
+    out.write_all(&header)?;
+
+    let mut seen = HashSet::new();
+    let mut stack: Vec<Cid> = roots.iter().rev().copied().collect();
+    while let Some(cid) = stack.pop() {
+        if !seen.insert(cid) {
+            continue;
+        }
+        let block = store
+            .get(&cid)?
+            .ok_or_else(|| anyhow!("block {cid} not found"))?;
+        let cid_bytes = cid.to_bytes();
+        write_varint(out, cid_bytes.len() + block.len())?;
+        out.write_all(&cid_bytes)?;
+        out.write_all(&block)?;
+
+        if cid.codec() == DAG_CBOR {
+            let ipld: Ipld = from_slice(&block)?;
+            let mut links = Vec::new();
+            ipld.references(&mut links);
+            stack.extend(links.into_iter().rev());
+        }
+    }
+    Ok(())
+}
+
+/// Reads all blocks of a CARv1 or CARv2 file into `store`, checking them
+/// against their CIDs, and returns the roots.
+pub fn read_car<S: Blockstore>(input: impl Read, store: &S) -> Result<Vec<Cid>> {
+    let mut input = BufReader::new(input);
+    let header: Header = from_slice(&read_section(&mut input)?)?;
+    match header.version {
+        1 => read_v1_blocks(&mut input, store).map(|_| header.roots),
+        2 => {
+            let mut v2_header = [0; V2_HEADER_LEN];
+            input.read_exact(&mut v2_header)?;
+            let field = |i: usize| u64::from_le_bytes(v2_header[i..i + 8].try_into().unwrap());
+            let (data_offset, data_size) = (field(16), field(24));
+
+            let consumed = (V2_PRAGMA.len() + V2_HEADER_LEN) as u64;
+            let padding = data_offset
+                .checked_sub(consumed)
+                .ok_or_else(|| anyhow!("CARv2 data offset {data_offset} is inside the header"))?;
+            io::copy(&mut input.by_ref().take(padding), &mut io::sink())?;
+
+            let mut data = BufReader::new(input.take(data_size));
+            let inner: Header = from_slice(&read_section(&mut data)?)?;
+            if inner.version != 1 {
+                bail!("CARv2 payload has version {}", inner.version);
+            }
+            read_v1_blocks(&mut data, store)?;
+            Ok(inner.roots)
+        }
+        version => bail!("unsupported CAR version {version}"),
+    }
+}
+
+fn read_v1_blocks<S: Blockstore>(input: &mut impl BufRead, store: &S) -> Result<()> {
+    while !input.fill_buf()?.is_empty() {
+        let section = read_section(input)?;
+        let mut reader = io::Cursor::new(section.as_slice());
+        let cid = Cid::read_bytes(&mut reader)?;
+        let block = &section[reader.position() as usize..];
+
+        let code = Code::try_from(cid.hash().code())?;
+        if code.digest(block) != *cid.hash() {
+            bail!("block doesn't match {cid}");
+        }
+        store.put_keyed(&cid, block)?;
+    }
+    Ok(())
+}
+
+/// A length-prefixed section.
+fn read_section(input: &mut impl Read) -> Result<Vec<u8>> {
+    let len = unsigned_varint::io::read_u64(&mut *input)?;
+    let mut section = vec![0; len as usize];
+    input.read_exact(&mut section)?;
+    Ok(section)
+}
+
+fn write_varint(out: &mut impl Write, n: usize) -> io::Result<()> {
+    let mut buf = unsigned_varint::encode::u64_buffer();
+    out.write_all(unsigned_varint::encode::u64(n as u64, &mut buf))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memorydb::MemoryDB;
+    use fvm_ipld_hamt::{Hamt, Sha256};
+
+    #[test]
+    fn round_trips_both_versions() -> Result<()> {
+        let store = MemoryDB::default();
+        let mut map: Hamt<_, String, usize, Sha256, 3> = Hamt::new_with_bit_width(&store, 4);
+        for key in 0..1000 {
+            map.set(key, "F".to_string())?;
+        }
+        map.flush()?;
+        map.set(0, ".".to_string())?;
+        let root = map.flush()?;
+
+        for version in [CarVersion::V1, CarVersion::V2] {
+            let mut car = Vec::new();
+            write_car(&store, &[root], version, &mut car)?;
+            assert_eq!(car.starts_with(&V2_PRAGMA), version == CarVersion::V2);
+
+            let copy = MemoryDB::default();
+            assert_eq!(read_car(car.as_slice(), &copy)?, vec![root]);
+            // Only the latest version is reachable from the root.
+            assert_eq!(copy.bytes_stored(), store.live_bytes(&[root])?);
+            let map: Hamt<_, String, usize, Sha256, 3> =
+                Hamt::load_with_bit_width(&root, &copy, 4)?;
+            assert_eq!(map.get(&0)?, Some(&".".to_string()));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_corrupted_blocks() -> Result<()> {
+        let store = MemoryDB::default();
+        let mut map: Hamt<_, String, usize, Sha256, 3> = Hamt::new_with_bit_width(&store, 4);
+        map.set(1, "F".to_string())?;
+        let root = map.flush()?;
+
+        let mut car = Vec::new();
+        write_car(&store, &[root], CarVersion::V1, &mut car)?;
+        *car.last_mut().unwrap() ^= 1;
+        assert!(read_car(car.as_slice(), &MemoryDB::default()).is_err());
+        Ok(())
+    }
+}
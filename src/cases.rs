@@ -0,0 +1,284 @@
+//! Regression cases: operations to run against an empty HAMT and the root
+//! CIDs they should end up at, run with `rust-ipld-hamt cases`.
+//!
+//! The cases in regression/cases.json are self-generated, their roots were
+//! recorded from this implementation. No cases from go-hamt-ipld or any other
+//! implementation are bundled, so passing them says nothing about
+//! compatibility.
+//!
+//! A manifest is a JSON object with a list of cases:
+//!
+//! ```text
+//! {"cases": [{
+//!   "name": "set-and-delete", "bitWidth": 8, "bucketSize": 3,
+//!   "ops": [
+//!     {"op": "set", "key": "a", "value": 1},
+//!     {"op": "get", "key": "a", "value": 1},
+//!     {"op": "flush", "root": {"/": "bafy..."}},
+//!     {"op": "delete", "key": "a"},
+//!     {"op": "get", "key": "a"}
+//!   ],
+//!   "root": {"/": "bafy..."}
+//! }]}
+//! ```
+//!
+//! The manifest is read as DAG-JSON, so keys and values can be bytes
+//! (`{"/": {"bytes": "<base64>"}}`) and roots links or plain strings. String
+//! keys are their UTF-8 bytes. Keys are hashed with SHA2-256 and nodes
+//! written as DAG-CBOR, like go-hamt-ipld does. The bit width defaults to 8
+//! and the bucket size to 3. A `get` without a value expects the key to be
+//! missing.
+
+use std::fmt;
+
+use anyhow::{bail, Context, Result};
+use cid::Cid;
+use fvm_ipld_hamt::dag_json;
+use fvm_ipld_hamt::{BytesKey, Hamt, Sha256};
+use libipld_core::ipld::Ipld;
+use serde::{Deserialize, Serialize};
+
+use crate::bucket::with_bucket_size;
+use crate::memorydb::MemoryDB;
+
+/// The regression cases checked by the tests.
+pub const FIXTURES: &str = include_str!("../regression/cases.json");
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Op {
+    Set(Vec<u8>, Ipld),
+    Delete(Vec<u8>),
+    /// Looks up a key, expecting the value or, with `None`, no entry.
+    Get(Vec<u8>, Option<Ipld>),
+    /// Flushes the HAMT, expecting the root.
+    Flush(Cid),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Case {
+    pub name: String,
+    pub bit_width: u32,
+    pub bucket_size: usize,
+    pub ops: Vec<Op>,
+    /// Root after flushing once all operations ran.
+    pub root: Cid,
+}
+
+/// Whether a case passed, and if not the first operation that didn't do
+/// what the manifest expects.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Outcome {
+    pub case: String,
+    pub bit_width: u32,
+    pub bucket_size: usize,
+    pub ops: usize,
+    pub passed: bool,
+    pub failure: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Manifest {
+    cases: Vec<RawCase>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawCase {
+    name: String,
+    #[serde(default = "default_bit_width")]
+    bit_width: u32,
+    #[serde(default = "default_bucket_size")]
+    bucket_size: usize,
+    ops: Vec<RawOp>,
+    root: Ipld,
+}
+
+fn default_bit_width() -> u32 {
+    8
+}
+
+fn default_bucket_size() -> usize {
+    3
+}
+
+#[derive(Deserialize)]
+struct RawOp {
+    op: String,
+    key: Option<Ipld>,
+    value: Option<Ipld>,
+    root: Option<Ipld>,
+}
+
+impl TryFrom<RawOp> for Op {
+    type Error = anyhow::Error;
+
+    fn try_from(raw: RawOp) -> Result<Self> {
+        let key = raw.key.map(key_bytes).transpose()?;
+        Ok(match (raw.op.as_str(), key, raw.value, raw.root) {
+            ("set", Some(key), Some(value), _) => Op::Set(key, value),
+            ("delete", Some(key), _, _) => Op::Delete(key),
+            ("get", Some(key), value, _) => Op::Get(key, value),
+            ("flush", _, _, Some(root)) => Op::Flush(cid(root)?),
+            ("set", Some(_), None, _) => bail!("`set` needs a value"),
+            ("set" | "delete" | "get", ..) => bail!("`{}` needs a key", raw.op),
+            ("flush", ..) => bail!("`flush` needs a root"),
+            (other, ..) => bail!("unknown operation `{other}`"),
+        })
+    }
+}
+
+impl TryFrom<RawCase> for Case {
+    type Error = anyhow::Error;
+
+    fn try_from(raw: RawCase) -> Result<Self> {
+        let ops = raw
+            .ops
+            .into_iter()
+            .enumerate()
+            .map(|(i, op)| Op::try_from(op).with_context(|| format!("op {}", i + 1)))
+            .collect::<Result<_>>()?;
+        Ok(Case {
+            name: raw.name,
+            bit_width: raw.bit_width,
+            bucket_size: raw.bucket_size,
+            ops,
+            root: cid(raw.root)?,
+        })
+    }
+}
+
+fn key_bytes(key: Ipld) -> Result<Vec<u8>> {
+    match key {
+        Ipld::String(key) => Ok(key.into_bytes()),
+        Ipld::Bytes(key) => Ok(key),
+        other => bail!("keys are strings or bytes, not {other:?}"),
+    }
+}
+
+fn cid(root: Ipld) -> Result<Cid> {
+    match root {
+        Ipld::Link(cid) => Ok(cid),
+        Ipld::String(cid) => cid.parse().context("invalid root"),
+        other => bail!("roots are links or strings, not {other:?}"),
+    }
+}
+
+/// The cases of a manifest.
+pub fn parse(text: &str) -> Result<Vec<Case>> {
+    let manifest: Manifest = dag_json::from_slice(text.as_bytes())?;
+    manifest
+        .cases
+        .into_iter()
+        .enumerate()
+        .map(|(i, case)| Case::try_from(case).with_context(|| format!("case {}", i + 1)))
+        .collect()
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let key = |key: &[u8]| String::from_utf8_lossy(key).into_owned();
+        match self {
+            Op::Set(k, _) => write!(f, "set {:?}", key(k)),
+            Op::Delete(k) => write!(f, "delete {:?}", key(k)),
+            Op::Get(k, _) => write!(f, "get {:?}", key(k)),
+            Op::Flush(_) => write!(f, "flush"),
+        }
+    }
+}
+
+impl Case {
+    /// Runs the operations against an empty HAMT with the parameters of the
+    /// case.
+    pub fn run(&self) -> Outcome {
+        let result = self.check();
+        Outcome {
+            case: self.name.clone(),
+            bit_width: self.bit_width,
+            bucket_size: self.bucket_size,
+            ops: self.ops.len(),
+            passed: result.is_ok(),
+            failure: result.err().map(|e| format!("{e:#}")),
+        }
+    }
+
+    /// Fails at the first operation that doesn't do what's expected.
+    fn check(&self) -> Result<()> {
+        with_bucket_size!(self.bucket_size, B => self.check_with::<B>())
+    }
+
+    fn check_with<const BUCKET_SIZE: usize>(&self) -> Result<()> {
+        let store = MemoryDB::default();
+        let mut map: Hamt<_, Ipld, BytesKey, Sha256, BUCKET_SIZE> =
+            Hamt::new_with_bit_width(&store, self.bit_width);
+        for (i, op) in self.ops.iter().enumerate() {
+            let context = || format!("op {} ({op})", i + 1);
+            match op {
+                Op::Set(key, value) => {
+                    map.set(BytesKey(key.clone()), value.clone())
+                        .with_context(context)?;
+                }
+                Op::Delete(key) => {
+                    map.delete(&BytesKey(key.clone())).with_context(context)?;
+                }
+                Op::Get(key, expected) => {
+                    let value = map.get(&BytesKey(key.clone())).with_context(context)?;
+                    if value != expected.as_ref() {
+                        bail!("{}: expected {expected:?}, got {value:?}", context());
+                    }
+                }
+                Op::Flush(expected) => {
+                    let root = map.flush().with_context(context)?;
+                    if root != *expected {
+                        bail!("{}: expected root {expected}, got {root}", context());
+                    }
+                }
+            }
+        }
+        let root = map.flush()?;
+        if root != self.root {
+            bail!("expected root {}, got {root}", self.root);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EMPTY: &str = "bafy2bzaceamp42wmmgr2g2ymg46euououzfyck7szknvfacqscohrvaikwfay";
+
+    #[test]
+    fn passes_the_fixture_cases() -> Result<()> {
+        let failures: Vec<_> = parse(FIXTURES)?
+            .iter()
+            .map(Case::run)
+            .filter(|outcome| !outcome.passed)
+            .collect();
+        assert!(failures.is_empty(), "{failures:#?}");
+        Ok(())
+    }
+
+    #[test]
+    fn reports_mismatches_and_rejects_malformed_cases() -> Result<()> {
+        let case = |ops: &str| {
+            format!(r#"{{"cases": [{{"name": "c", "ops": [{ops}], "root": "{EMPTY}"}}]}}"#)
+        };
+        let cases = parse(&case(r#"{"op": "set", "key": "0", "value": 0}"#))?;
+        let outcome = cases[0].run();
+        assert!(!outcome.passed);
+        assert!(outcome.failure.unwrap().starts_with("expected root"));
+        let cases = parse(&case(r#"{"op": "get", "key": "0", "value": 0}"#))?;
+        assert!(cases[0]
+            .run()
+            .failure
+            .unwrap()
+            .starts_with("op 1 (get \"0\")"));
+
+        let err = parse(&case(r#"{"op": "set", "value": 0}"#)).unwrap_err();
+        assert_eq!(format!("{err:#}"), "case 1: op 1: `set` needs a key");
+        assert!(parse(&case(r#"{"op": "put", "key": "0"}"#)).is_err());
+        assert!(parse(&case(r#"{"op": "set", "key": 0, "value": 0}"#)).is_err());
+        Ok(())
+    }
+}
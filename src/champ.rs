@@ -0,0 +1,426 @@
+//! A CHAMP (Compressed Hash-Array Mapped Prefix-tree) to compare the HAMT's
+//! node layout against.
+//!
+//! Like the HAMT, a node has a slot for each of the `2^bit_width` values of
+//! the next hash bits. But instead of one bitmap with a pointer per slot,
+//! which is either a bucket or a link, a node has two bitmaps: one for the
+//! slots holding an entry and one for the slots holding a child. Entries are
+//! stored inline in one array and links in another:
+//!
+//! ```text
+//! [datamap, nodemap, [[key, value], ...], [cid, ...]]
+//! ```
+//!
+//! A slot holds a single entry, a second key with the same hash bits pushes
+//! both into a new child. Deleting an entry pulls a child left with a single
+//! one back up into its slot, so a tree only depends on its entries. Bitmaps
+//! are encoded like the HAMT's, as big-endian bytes without leading zeros.
+//! Trees are only built and flushed to measure them, they can't be loaded
+//! from a store.
+
+use std::cmp;
+use std::collections::BTreeMap;
+use std::marker::PhantomData;
+
+use anyhow::{bail, Result};
+use cid::multihash::Code;
+use cid::Cid;
+use fvm_ipld_blockstore::{Block, Blockstore};
+use fvm_ipld_encoding::serde_bytes::ByteBuf;
+use fvm_ipld_encoding::{to_vec, DAG_CBOR};
+use fvm_ipld_hamt::{Hamt, Hash, HashAlgorithm, Sha256};
+use serde::Serialize;
+
+use crate::experiments::delete::delete_in_batches;
+use crate::experiments::sizes::experiment;
+use crate::experiments::ExperimentContext;
+use crate::map::{map_sizes, IpldMap, MapStats};
+use crate::memorydb::MemoryDB;
+use crate::metered::MeteredStore;
+use crate::workload::{Key, Workload};
+
+pub struct Champ<'a, BS, K, V, H = Sha256> {
+    store: &'a BS,
+    bit_width: u32,
+    root: Node<K, V>,
+    hash: PhantomData<H>,
+}
+
+struct Node<K, V> {
+    /// By slot.
+    entries: BTreeMap<usize, (K, V)>,
+    children: BTreeMap<usize, Child<K, V>>,
+}
+
+struct Child<K, V> {
+    node: Node<K, V>,
+    /// Unless the node changed since it was last flushed.
+    cid: Option<Cid>,
+}
+
+impl<'a, BS, K, V, H> Champ<'a, BS, K, V, H>
+where
+    BS: Blockstore,
+    K: Hash + Eq + Serialize,
+    V: Serialize,
+    H: HashAlgorithm,
+{
+    pub fn new_with_bit_width(store: &'a BS, bit_width: u32) -> Self {
+        assert!(
+            (1..=8).contains(&bit_width),
+            "unsupported bit width {bit_width}"
+        );
+        Champ {
+            store,
+            bit_width,
+            root: Node::default(),
+            hash: PhantomData,
+        }
+    }
+
+    /// Sets the value of `key`, returning the previous one. Fails if `key`
+    /// has the same hash as another key.
+    pub fn set(&mut self, key: K, value: V) -> Result<Option<V>> {
+        let hash = H::hash(&key);
+        self.root.set::<H>(&hash, 0, self.bit_width, key, value)
+    }
+
+    /// Deletes `key`, returning its value.
+    pub fn delete(&mut self, key: &K) -> Result<Option<V>> {
+        let hash = H::hash(key);
+        self.root.delete(&hash, 0, self.bit_width, key)
+    }
+
+    pub fn get(&self, key: &K) -> Result<Option<&V>> {
+        let hash = H::hash(key);
+        let mut node = &self.root;
+        let mut depth = 0;
+        loop {
+            let slot = slot(&hash, depth, self.bit_width)?;
+            if let Some(child) = node.children.get(&slot) {
+                node = &child.node;
+                depth += 1;
+                continue;
+            }
+            return Ok(node
+                .entries
+                .get(&slot)
+                .filter(|(k, _)| k == key)
+                .map(|(_, v)| v));
+        }
+    }
+
+    /// Writes every changed node and the root to the store.
+    pub fn flush(&mut self) -> Result<Cid> {
+        self.root.flush(self.store, self.bit_width)
+    }
+}
+
+impl<K, V> Default for Node<K, V> {
+    fn default() -> Self {
+        Node {
+            entries: BTreeMap::new(),
+            children: BTreeMap::new(),
+        }
+    }
+}
+
+impl<K: Hash + Eq + Serialize, V: Serialize> Node<K, V> {
+    fn set<H: HashAlgorithm>(
+        &mut self,
+        hash: &[u8],
+        depth: u32,
+        bit_width: u32,
+        key: K,
+        value: V,
+    ) -> Result<Option<V>> {
+        let slot = slot(hash, depth, bit_width)?;
+        if let Some(child) = self.children.get_mut(&slot) {
+            child.cid = None;
+            return child.node.set::<H>(hash, depth + 1, bit_width, key, value);
+        }
+        match self.entries.remove(&slot) {
+            None => {
+                self.entries.insert(slot, (key, value));
+                Ok(None)
+            }
+            Some((existing, old)) if existing == key => {
+                self.entries.insert(slot, (key, value));
+                Ok(Some(old))
+            }
+            Some((existing, existing_value)) => {
+                let mut node = Node::default();
+                let existing_hash = H::hash(&existing);
+                node.set::<H>(
+                    &existing_hash,
+                    depth + 1,
+                    bit_width,
+                    existing,
+                    existing_value,
+                )?;
+                node.set::<H>(hash, depth + 1, bit_width, key, value)?;
+                self.children.insert(slot, Child { node, cid: None });
+                Ok(None)
+            }
+        }
+    }
+
+    /// Deletes `key`, pulling the entry of a child left with only one up into
+    /// the child's slot, as if the child had never been split off.
+    fn delete(&mut self, hash: &[u8], depth: u32, bit_width: u32, key: &K) -> Result<Option<V>> {
+        let slot = slot(hash, depth, bit_width)?;
+        if let Some(child) = self.children.get_mut(&slot) {
+            let deleted = child.node.delete(hash, depth + 1, bit_width, key)?;
+            if deleted.is_some() {
+                child.cid = None;
+                // Children of a child hold at least two entries each.
+                if child.node.children.is_empty() && child.node.entries.len() == 1 {
+                    let child = self.children.remove(&slot).expect("child");
+                    let entry = child.node.entries.into_values().next().expect("entry");
+                    self.entries.insert(slot, entry);
+                }
+            }
+            return Ok(deleted);
+        }
+        match self.entries.get(&slot) {
+            Some((existing, _)) if existing == key => {
+                Ok(self.entries.remove(&slot).map(|(_, value)| value))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Counts the nodes and values below and including this one at `depth`.
+    fn add_stats(&self, depth: u32, stats: &mut MapStats) {
+        stats.nodes += 1;
+        stats.values += self.entries.len() as u64;
+        stats.height = stats.height.max(depth);
+        for child in self.children.values() {
+            child.node.add_stats(depth + 1, stats);
+        }
+    }
+
+    fn flush(&mut self, store: &impl Blockstore, bit_width: u32) -> Result<Cid> {
+        for child in self.children.values_mut() {
+            if child.cid.is_none() {
+                child.cid = Some(child.node.flush(store, bit_width)?);
+            }
+        }
+        let bytes = to_vec(&self.encoding(bit_width))?;
+        store.put(Code::Blake2b256, &Block::new(DAG_CBOR, &bytes))
+    }
+
+    /// `[datamap, nodemap, entries, links]`, children have to be flushed.
+    #[allow(clippy::type_complexity)]
+    fn encoding(&self, bit_width: u32) -> (ByteBuf, ByteBuf, Vec<(&K, &V)>, Vec<Cid>) {
+        (
+            bitmap(bit_width, self.entries.keys()),
+            bitmap(bit_width, self.children.keys()),
+            self.entries.values().map(|(k, v)| (k, v)).collect(),
+            self.children
+                .values()
+                .map(|child| child.cid.expect("children are flushed first"))
+                .collect(),
+        )
+    }
+}
+
+impl<BS, K, V, H> IpldMap<K, V> for Champ<'_, BS, K, V, H>
+where
+    BS: Blockstore,
+    K: Hash + Eq + Serialize,
+    V: Serialize,
+    H: HashAlgorithm,
+{
+    fn set(&mut self, key: K, value: V) -> Result<Option<V>> {
+        Champ::set(self, key, value)
+    }
+
+    fn get(&self, key: &K) -> Result<Option<&V>> {
+        Champ::get(self, key)
+    }
+
+    fn delete(&mut self, key: &K) -> Result<Option<V>> {
+        Champ::delete(self, key)
+    }
+
+    fn flush(&mut self) -> Result<Cid> {
+        Champ::flush(self)
+    }
+
+    fn stats(&self) -> Result<MapStats> {
+        let mut stats = MapStats::default();
+        self.root.add_stats(0, &mut stats);
+        Ok(stats)
+    }
+}
+
+/// The `bit_width` bits of `hash` used at `depth`, most significant first.
+fn slot(hash: &[u8], depth: u32, bit_width: u32) -> Result<usize> {
+    let start = (depth * bit_width) as usize;
+    if start + bit_width as usize > hash.len() * 8 {
+        bail!("keys with equal hashes aren't supported");
+    }
+    let mut slot = 0;
+    for bit in start..start + bit_width as usize {
+        let set = hash[bit / 8] >> (7 - bit % 8) & 1;
+        slot = slot << 1 | set as usize;
+    }
+    Ok(slot)
+}
+
+/// Big-endian bitmap of the used `slots`, without leading zero bytes.
+fn bitmap<'s>(bit_width: u32, slots: impl Iterator<Item = &'s usize>) -> ByteBuf {
+    let mut bytes = vec![0u8; (1usize << bit_width).div_ceil(8)];
+    let len = bytes.len();
+    for &slot in slots {
+        bytes[len - 1 - slot / 8] |= 1 << (slot % 8);
+    }
+    let leading_zeros = bytes.iter().take_while(|&&byte| byte == 0).count();
+    ByteBuf::from(bytes.split_off(leading_zeros))
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChampResult {
+    n: usize,
+    m: usize,
+    bucket_size: usize,
+    bit_width: u32,
+    hamt_total_bytes: u64,
+    champ_total_bytes: u64,
+    hamt_avg_node_bytes: f64,
+    champ_avg_node_bytes: f64,
+    hamt_max_node_bytes: usize,
+    champ_max_node_bytes: usize,
+    hamt_byte_diff: u64,
+    champ_byte_diff: u64,
+    /// Bytes written by deleting `m` of the keys in batches of `batch_size`.
+    hamt_delete_byte_diff: u64,
+    champ_delete_byte_diff: u64,
+    hamt_nodes_after_delete: u64,
+    champ_nodes_after_delete: u64,
+}
+
+/// The measurements of [`experiment`] and of deleting `m` of the `n` keys,
+/// like the `delete` experiment, in a HAMT and in a CHAMP with the same keys.
+pub fn champ_experiment<const BUCKET_SIZE: usize>(
+    ctx: &ExperimentContext,
+    bit_width: u32,
+    n: usize,
+    m: usize,
+    batch_size: usize,
+    workload: &Workload,
+) -> Result<ChampResult> {
+    let hamt = experiment::<BUCKET_SIZE>(ctx, bit_width, n, m, workload);
+    let store = MeteredStore::new(MemoryDB::default());
+    let mut champ: Champ<_, _, _> = Champ::new_with_bit_width(&store, bit_width);
+    let keys = workload.keys(cmp::max(n, m), &mut ctx.rng());
+    let champ = map_sizes(&mut champ, &store, &keys, n, m)?;
+
+    let store = MemoryDB::default();
+    let mut map: Hamt<_, _, Key, Sha256, BUCKET_SIZE> = Hamt::new_with_bit_width(&store, bit_width);
+    let hamt_deletes = delete_in_batches(ctx, &mut map, &store, &keys[..n], m, batch_size)?;
+    let store = MemoryDB::default();
+    let mut map: Champ<_, _, _> = Champ::new_with_bit_width(&store, bit_width);
+    let champ_deletes = delete_in_batches(ctx, &mut map, &store, &keys[..n], m, batch_size)?;
+    Ok(ChampResult {
+        n,
+        m,
+        bucket_size: BUCKET_SIZE,
+        bit_width,
+        hamt_total_bytes: hamt.total_bytes,
+        champ_total_bytes: champ.total_bytes,
+        hamt_avg_node_bytes: hamt.avg_node_bytes,
+        champ_avg_node_bytes: champ.avg_node_bytes,
+        hamt_max_node_bytes: hamt.max_node_bytes,
+        champ_max_node_bytes: champ.max_node_bytes,
+        hamt_byte_diff: hamt.byte_difference,
+        champ_byte_diff: champ.byte_difference,
+        hamt_delete_byte_diff: hamt_deletes.byte_diff,
+        champ_delete_byte_diff: champ_deletes.byte_diff,
+        hamt_nodes_after_delete: hamt_deletes.nodes_after,
+        champ_nodes_after_delete: champ_deletes.nodes_after,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memorydb::MemoryDB;
+
+    #[test]
+    fn sets_and_gets_values() -> Result<()> {
+        let store = MemoryDB::default();
+        let mut champ: Champ<_, _, _> = Champ::new_with_bit_width(&store, 2);
+        for key in 0..100usize {
+            assert_eq!(champ.set(key, key * 2)?, None);
+        }
+        assert_eq!(champ.set(7, 0)?, Some(14));
+        assert_eq!(champ.get(&7)?, Some(&0));
+        assert_eq!(champ.get(&8)?, Some(&16));
+        assert_eq!(champ.get(&100)?, None);
+
+        champ.flush()?;
+        // With 4 slots per node, 100 entries need at least 25 nodes.
+        assert!(store.blocks() >= 25);
+        let blocks = store.blocks();
+        champ.set(8, 0)?;
+        champ.flush()?;
+        assert!(store.blocks() - blocks < 10);
+        Ok(())
+    }
+
+    #[test]
+    fn splits_slots_into_children() -> Result<()> {
+        let store = MemoryDB::default();
+        let mut champ: Champ<_, _, _> = Champ::new_with_bit_width(&store, 1);
+        let first_slot = |key: &usize| slot(&Sha256::hash(key), 0, 1).unwrap();
+        let other = (1..).find(|key| first_slot(key) == first_slot(&0)).unwrap();
+        champ.set(0, 1u8)?;
+        assert_eq!(champ.root.entries.len(), 1);
+        champ.set(other, 2u8)?;
+        assert!(champ.root.entries.is_empty());
+        assert_eq!(champ.root.children.len(), 1);
+        assert_eq!(champ.get(&0)?, Some(&1));
+        assert_eq!(champ.get(&other)?, Some(&2));
+        Ok(())
+    }
+
+    #[test]
+    fn deletes_to_the_tree_without_the_keys() -> Result<()> {
+        let store = MemoryDB::default();
+        let mut champ: Champ<_, _, _> = Champ::new_with_bit_width(&store, 2);
+        let mut fresh: Champ<_, _, _> = Champ::new_with_bit_width(&store, 2);
+        for key in 0..200usize {
+            champ.set(key, key)?;
+            if key % 3 != 0 {
+                fresh.set(key, key)?;
+            }
+        }
+        champ.flush()?;
+        for key in (0..200).step_by(3) {
+            assert_eq!(champ.delete(&key)?, Some(key));
+        }
+        assert_eq!(champ.delete(&0)?, None);
+        assert_eq!(champ.get(&3)?, None);
+        assert_eq!(champ.get(&4)?, Some(&4));
+        assert_eq!(champ.flush()?, fresh.flush()?);
+
+        for key in 0..200 {
+            champ.delete(&key)?;
+        }
+        assert!(champ.root.entries.is_empty() && champ.root.children.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn encodes_bitmaps_and_slots() -> Result<()> {
+        assert_eq!(slot(&[0b1011_0100, 0xff], 0, 3)?, 0b101);
+        assert_eq!(slot(&[0b1011_0100, 0xff], 2, 3)?, 0b001);
+        assert!(slot(&[0xff], 2, 3).is_err());
+        assert_eq!(bitmap(8, [0, 9].iter()).into_vec(), [0x02, 0x01]);
+        assert_eq!(bitmap(3, [7].iter()).into_vec(), [0x80]);
+        assert!(bitmap(4, [].iter()).is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,1327 @@
+//! Command-line parsing with `clap`: the subcommands, their options, and the
+//! [`Params`] experiments run with.
+
+use std::fmt::Display;
+use std::iter;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Result};
+use cid::Cid;
+use clap::{App, AppSettings, Arg, ArgMatches, ErrorKind, SubCommand};
+
+use crate::bit_width::BitWidths;
+use crate::bucket::BucketSizes;
+use crate::cache::CacheSize;
+use crate::car::CarVersion;
+use crate::compare::Threshold;
+use crate::delayed::Network;
+use crate::flush::FlushPolicy;
+use crate::output::{Delimiter, Format};
+use crate::plot::Chart;
+use crate::rng::DEFAULT_SEED;
+use crate::selector::Selector;
+use crate::traverse::Sampling;
+#[cfg(feature = "svg")]
+use crate::viz::SvgRenderer;
+use crate::viz::{DotRenderer, MermaidRenderer, RankDir, Renderer};
+use crate::wnfs::OpMix;
+use crate::workload::{ValueSizes, Workload};
+
+/// The subcommands and their options.
+fn app() -> App<'static, 'static> {
+    let experiments: Vec<&str> = Experiment::ALL.iter().map(|e| e.name()).collect();
+    App::new("rust-ipld-hamt")
+        .about("Measures how the parameters of IPLD HAMTs shape their storage and access costs")
+        .settings(&[
+            AppSettings::SubcommandRequiredElseHelp,
+            AppSettings::VersionlessSubcommands,
+            AppSettings::DisableVersion,
+        ])
+        .subcommand(
+            SubCommand::with_name("experiment")
+                .about("Run an experiment over every bit width and bucket size")
+                .arg(
+                    Arg::with_name("name")
+                        .required(true)
+                        .possible_values(&experiments)
+                        .help("The experiment to run"),
+                )
+                .arg(switch(
+                    "resume",
+                    "Continue an interrupted experiment whose results are in `--output`, \
+                     skipping finished bit widths and bucket sizes; finished points are \
+                     recorded in `<output>.manifest`",
+                ))
+                .args(&experiment_args())
+                .args(&results_args())
+                .arg(option(
+                    "plot",
+                    "chart",
+                    "Also chart a result column as SVG, `heatmap:<column>` with bucket sizes \
+                     across and bit widths down, or `line:<column>` with one line per bit width",
+                ))
+                .arg(option(
+                    "plot-output",
+                    "path",
+                    "Where `--plot` writes to [default: <column>.svg]",
+                )),
+        )
+        .subcommand(
+            SubCommand::with_name("dot")
+                .about("Draw a HAMT as a graph")
+                .arg(switch(
+                    "diff",
+                    "Render the versions before and after overwriting `m` entries, colored \
+                     by which nodes changed",
+                ))
+                .arg(option(
+                    "rankdir",
+                    "dir",
+                    "Graph direction: `TB`, `LR`, `BT` or `RL` [default: TB]",
+                ))
+                .arg(option(
+                    "font",
+                    "name",
+                    "Font of the labels [default: Helvetica]",
+                ))
+                .arg(option(
+                    "color-scheme",
+                    "name",
+                    "11 class graphviz color scheme [default: piyg11]",
+                ))
+                .arg(switch(
+                    "svg",
+                    "Draw an SVG with a radial layout instead of writing DOT; requires the \
+                     `svg` feature",
+                ))
+                .arg(switch(
+                    "mermaid",
+                    "Write a Mermaid flowchart instead of DOT",
+                ))
+                .args(&hamt_args("Number of entries inserted [default: 300]"))
+                .arg(option(
+                    "m",
+                    "count",
+                    "Number of entries `--diff` overwrites [default: 100]",
+                ))
+                .arg(option(
+                    "output",
+                    "path",
+                    "Write the graph to <path> instead of stdout",
+                )),
+        )
+        .subcommand(
+            SubCommand::with_name("car")
+                .about("Export the blocks of a HAMT with `n` entries as a CAR file")
+                .arg(switch("v2", "Write CARv2 instead of CARv1"))
+                .args(&hamt_args("Number of entries inserted [default: 100000]"))
+                .arg(option(
+                    "output",
+                    "path",
+                    "Write the CAR file to <path> instead of stdout",
+                )),
+        )
+        .subcommand(
+            SubCommand::with_name("analyze")
+                .about("Statistics for the HAMTs in a CAR file")
+                .arg(Arg::with_name("file").required(true).help("The CAR file"))
+                .arg(option(
+                    "root",
+                    "cid",
+                    "HAMT to analyze instead of all HAMTs reachable from the roots of the \
+                     CAR file",
+                ))
+                .args(&results_args()),
+        )
+        .subcommand(
+            SubCommand::with_name("report")
+                .about("Run several experiments and write their results as one HTML page")
+                .arg(
+                    option("experiments", "names", "Comma separated experiments")
+                        .default_value(REPORT_EXPERIMENTS),
+                )
+                .args(&experiment_args())
+                .arg(option(
+                    "output",
+                    "path",
+                    "Write the HTML to <path> instead of stdout",
+                )),
+        )
+        .subcommand(
+            SubCommand::with_name("regression")
+                .about("Write self-generated regression roots, or check the ones in a file")
+                .arg(option(
+                    "check",
+                    "file",
+                    "Regression vectors whose roots are compared against the HAMTs built \
+                     here, instead of writing new ones",
+                ))
+                .arg(option(
+                    "output",
+                    "path",
+                    "Write the roots to <path> instead of stdout",
+                )),
+        )
+        .subcommand(
+            SubCommand::with_name("cases")
+                .about("Run the regression cases of a manifest and report which pass")
+                .arg(
+                    Arg::with_name("manifest")
+                        .required(true)
+                        .help("The manifest, see src/cases.rs for the format"),
+                )
+                .args(&results_args()),
+        )
+        .subcommand(
+            SubCommand::with_name("compare")
+                .about("Compare the metrics of two result files, failing if any regressed")
+                .arg(
+                    Arg::with_name("baseline")
+                        .required(true)
+                        .help("Results to compare against"),
+                )
+                .arg(
+                    Arg::with_name("current")
+                        .required(true)
+                        .help("Results to check"),
+                )
+                .arg(option(
+                    "threshold",
+                    "percent",
+                    "Increase over the baseline at which a metric fails [default: 5]",
+                ))
+                .arg(option(
+                    "metrics",
+                    "columns",
+                    "Comma separated result columns to check instead of every numeric one",
+                ))
+                .args(&results_args()),
+        )
+        .subcommand(
+            SubCommand::with_name("study")
+                .about("Run the experiments of a study")
+                .arg(
+                    Arg::with_name("study")
+                        .required(true)
+                        .help("The study, see src/study.rs for the format"),
+                )
+                .arg(switch(
+                    "dry-run",
+                    "Print the experiments instead of running them",
+                )),
+        )
+}
+
+/// The options of the subcommands building a single HAMT.
+fn hamt_args(n: &'static str) -> Vec<Arg<'static, 'static>> {
+    vec![
+        option(
+            "bit-width",
+            "bits",
+            "Hash bits consumed per tree level, from 1 to 8 [default: 4]",
+        ),
+        option(
+            "bucket-size",
+            "size",
+            "Maximum number of entries per bucket [default: 3]",
+        ),
+        option("n", "count", n),
+    ]
+}
+
+/// The options of the subcommands running experiments, making up most of
+/// [`Params`].
+fn experiment_args() -> Vec<Arg<'static, 'static>> {
+    #[allow(unused_mut)]
+    let mut args = vec![
+        option(
+            "bit-width",
+            "bits",
+            "Hash bits consumed per tree level, from 1 to 8, either a single width, a list \
+             or a range like `--bucket-size`; experiments run once per width \
+             [default: 4, sweep: 1..=8]",
+        ),
+        option(
+            "bucket-size",
+            "sizes",
+            "Maximum number of entries per bucket, either a single size, a list (`1,2,4`) \
+             or a range (`1..=16`); `nested` pairs every size of the parent with every \
+             size of the children, `migration` every size of the source with every target \
+             [default: 3, sweep: 1..=16]",
+        ),
+        option(
+            "n",
+            "count",
+            "Number of entries inserted, or operations run by experiments simulating a \
+             workload [default: 100000, timeseries: 1000000]",
+        ),
+        option(
+            "m",
+            "count",
+            "Number of entries overwritten, deleted or inserted after building the HAMT, \
+             per version if several are flushed, or the most keys changed or proven at \
+             once [default: 100]",
+        ),
+        option(
+            "batch-size",
+            "count",
+            "Entries deleted, inserted or copied between flushes [default: 10]",
+        ),
+        option(
+            "lookups",
+            "count",
+            "Number of random keys looked up or proven [default: 1000]",
+        ),
+        option(
+            "node-cache",
+            "limit",
+            "Loaded nodes `cache` keeps cached behind links, evicting the least recently \
+             used: `nodes:<n>` of them, nodes of `bytes:<n>` blocks, or `none` for no \
+             limit [default: none, nodes:16, nodes:256, nodes:4096, bytes:65536 and \
+             bytes:1048576]",
+        ),
+        option(
+            "page-size",
+            "count",
+            "Entries per page in `paging` [default: 1000]",
+        ),
+        option(
+            "workload",
+            "name",
+            "Keys inserted: `sequential`, `uniform`, `clustered`, `paths`, `varint` and \
+             `addresses` (Filecoin actor state byte keys), or `zipf[:<exponent>]`, which \
+             changes the keys looked up or updated [default: sequential]",
+        ),
+        option(
+            "versions",
+            "count",
+            "Versions flushed into the same store [default: 10]",
+        ),
+        option(
+            "keep",
+            "count",
+            "Newest versions `refcount` keeps, dropping older ones and the blocks only \
+             they use [default: all]",
+        ),
+        option(
+            "value-size",
+            "sizes",
+            "Lengths of the values, or of the files `wnfs` writes: fixed (`64`), uniform \
+             (`16..=256`) or `lognormal:<median>[:<sigma>]` [default: each power of two \
+             from 1 to 1024, wnfs: lognormal:256]",
+        ),
+        option(
+            "key-length",
+            "bytes",
+            "Length of the string keys `keys` inserts [default: each power of two from 8 \
+             to 512]",
+        ),
+        option(
+            "max-depth",
+            "depth",
+            "Depth at which buckets stop splitting, or of the deepest nodes sampled, the \
+             root being at 0 [default: 0, 1, 2, 3 and no limit, sample: no limit]",
+        ),
+        option(
+            "value-threshold",
+            "bytes",
+            "Encoded size above which `external` stores values as blocks of their own \
+             [default: 64]",
+        ),
+        option(
+            "flush",
+            "policy",
+            "When to flush the overwrites or operations: `eager` after every one, \
+             `every:<k>` of them, or `bytes:<n>` once the changed entries encode to n \
+             bytes [default: eager, every:10, every:100, bytes:1024 and bytes:16384, \
+             timeseries: every:1000]",
+        ),
+        option(
+            "selector",
+            "selector",
+            "Part of the HAMT `selectors` extracts: `depth:<d>` levels below the root, the \
+             paths to keys starting with `prefix:<key>`, or links followed at random with \
+             probability `sample:<rate>` [default: depth:1, depth:2, prefix:1, prefix:42, \
+             sample:0.1 and sample:0.01]",
+        ),
+        option(
+            "sample",
+            "rate[:depth]",
+            "Share of the links out of the nodes at <depth> `sample` follows to estimate \
+             the totals of the HAMT [default: 0.5:1, 0.1:1, 0.1:2 and 0.01:2, depth: 1]",
+        ),
+        option(
+            "trace",
+            "file",
+            "Operations on Filecoin actor HAMTs `replay` runs, as newline delimited \
+             DAG-JSON, see src/replay.rs for the format",
+        ),
+        option(
+            "fs-ops",
+            "weights",
+            "Relative weights of the operations `wnfs` draws, from `mkdir`, `write`, \
+             `rename` and `rm` [default: mkdir:1,write:6,rename:1,rm:2]",
+        ),
+        option(
+            "latency",
+            "ms",
+            "Simulated round trip time per block fetched [default: 50]",
+        ),
+        option(
+            "bandwidth",
+            "bytes/s",
+            "Simulated bandwidth for fetching blocks, 0 for unlimited [default: 1000000]",
+        ),
+        switch("verify", "Check every block read or built against its CID"),
+        option(
+            "seed",
+            "seed",
+            "Seed of the random keys, values and orders experiments pick, recorded in \
+             every result [default: 7845]",
+        ),
+        option(
+            "repeat",
+            "k",
+            "Run every point k times, with the seeds from <seed> on, and report the mean \
+             of every numeric result with its `_stddev`, `_min` and `_max` [default: 1]",
+        ),
+        option(
+            "warmup",
+            "count",
+            "Runs of every point to drop before the repetitions [default: 0]",
+        ),
+        option(
+            "dir",
+            "path",
+            "Directory `disk` stores blocks in, one subdirectory per bucket size \
+             [default: a temporary directory removed afterwards]",
+        ),
+        switch(
+            "quiet",
+            "Don't report the progress of experiments on stderr",
+        ),
+    ];
+    #[cfg(feature = "profile")]
+    args.push(option(
+        "profile",
+        "dir",
+        "Also profile building a HAMT of <n> keys at every point, writing where \
+         inserting and flushing spend their time as folded stacks and SVG flame \
+         graphs to <dir>",
+    ));
+    args
+}
+
+/// The options of the subcommands writing results.
+fn results_args() -> Vec<Arg<'static, 'static>> {
+    vec![
+        option(
+            "output",
+            "path",
+            "Write the results to <path> instead of stdout",
+        ),
+        switch(
+            "append",
+            "Append to <path> rather than truncating it; the CSV header is only written if \
+             the file is empty",
+        ),
+        option(
+            "format",
+            "format",
+            "`csv`, `tsv`, `json` or `ndjson` [default: csv]",
+        ),
+        option(
+            "delimiter",
+            "delim",
+            "CSV field delimiter: `,`, `;` or `tab` [default: ;]",
+        ),
+        switch("no-header", "Don't write a CSV header line"),
+    ]
+}
+
+/// A `--name <value>` option.
+fn option(name: &'static str, value: &'static str, help: &'static str) -> Arg<'static, 'static> {
+    Arg::with_name(name)
+        .long(name)
+        .value_name(value)
+        .takes_value(true)
+        .help(help)
+}
+
+/// A `--name` switch.
+fn switch(name: &'static str, help: &'static str) -> Arg<'static, 'static> {
+    Arg::with_name(name).long(name).help(help)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Experiment(Experiment, Params),
+    Dot(Params, Renderer),
+    DotDiff(Params, Renderer),
+    /// Export the blocks of a HAMT with `n` entries as a CAR file.
+    Car(Params, CarVersion),
+    /// Statistics for the HAMTs in a CAR file, optionally only the one at `root`.
+    Analyze(PathBuf, Option<Cid>, Params),
+    /// Run several experiments and write their results as one HTML page.
+    Report(Vec<Experiment>, Params),
+    /// Write self-generated regression roots, or check the ones in a file.
+    Regression(Option<PathBuf>, Params),
+    /// Run the regression cases of a manifest and report which pass.
+    Cases(PathBuf, Params),
+    /// Deltas of the metrics of the results in the second file against the
+    /// baseline in the first, failing if any regressed.
+    Compare(PathBuf, PathBuf, Threshold, Params),
+    /// Run the experiments of a study, or with `true` only print them.
+    Study(PathBuf, bool),
+    /// Print the help clap rendered.
+    Help(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Experiment {
+    /// Total stored bytes, bytes per node and bytes written by overwriting `m` keys,
+    /// next to those of a single block with all entries, a B-tree and a prolly tree.
+    Sizes,
+    /// Number of blocks per power of two size range.
+    Blocks,
+    /// Node degree averages.
+    Degree,
+    /// Distribution of the depths keys are stored at.
+    Depth,
+    /// Node count, bytes and fanout of every tree level.
+    Levels,
+    /// Size of the Merkle proof for a single key, in a HAMT and a B-tree.
+    Proof,
+    /// Size of a single proof for a growing number of random keys.
+    MultiProof,
+    /// Bytes read from the store and simulated network time to look up a single key.
+    Lookup,
+    /// Hits, misses, evictions and bytes read of `lookups` random lookups
+    /// with node caches of different limits.
+    Cache,
+    /// Bytes written and nodes removed by deleting `m` keys.
+    Delete,
+    /// Blocks, bytes and simulated network time read iterating over all entries.
+    Scan,
+    /// Blocks and bytes read paging through all entries with cursors.
+    Paging,
+    /// Bytes written and time taken inserting `m` keys with `set` vs. `set_many`.
+    Batch,
+    /// Live vs. garbage bytes after overwriting `m` keys.
+    Gc,
+    /// Write and lookup times with blocks stored as files on disk.
+    Disk,
+    /// Bytes shared between successive versions, each updating `m` random keys.
+    Versions,
+    /// Bytes stored, looked up and rewritten by value size, with values inlined
+    /// in buckets vs. stored as blocks of their own.
+    Values,
+    /// Node bytes and update amplification by value size, with and without
+    /// storing values above `--value-threshold` as blocks of their own.
+    External,
+    /// Tree shape and build time with each of the supported hash functions.
+    Hashes,
+    /// Bucket sizes and depth with hashes truncated to a few bits, so keys collide.
+    Collisions,
+    /// `Sizes` for every combination of bit width and bucket size, as a matrix
+    /// for heatmaps.
+    Sweep,
+    /// Total bytes, bytes changed by overwriting `m` keys and proof size of
+    /// the keys `0..n` in a HAMT next to an AMT of the same bit width.
+    Amt,
+    /// Node sizes and bytes changed by overwriting and by deleting `m` keys
+    /// in a HAMT next to a CHAMP of the same bit width.
+    Champ,
+    /// Node sizes, bytes changed by overwriting and by deleting `m` keys and
+    /// blocks per key in a HAMT next to a radix trie of the raw keys with the
+    /// same bit width.
+    Radix,
+    /// Root and total bytes and bytes changed by overwriting `m` keys, with
+    /// and without the number of entries in the root, and the time `len`
+    /// takes on the loaded HAMT.
+    Len,
+    /// Node sizes and bytes changed by overwriting `m` keys with blocks
+    /// addressed by multihashes of different lengths.
+    Cids,
+    /// Node sizes and bytes changed by overwriting `m` keys with nodes encoded
+    /// as DAG-CBOR and as DAG-JSON.
+    Codecs,
+    /// Total and proof bytes of the keys `0..n` with and without compressing
+    /// every block.
+    Compression,
+    /// Memory the HAMT and its cached nodes take while building, and once
+    /// loaded again before and after reading every entry.
+    Memory,
+    /// Nodes and bytes written by the flush after each of `m` overwrites, and
+    /// the bytes each leaves behind as garbage.
+    Writes,
+    /// Flushes and bytes written overwriting `m` keys with different
+    /// policies of when to flush.
+    Flush,
+    /// Store, version and history bytes of successive versions kept in a root
+    /// log, and the bytes replicating each from an older version takes.
+    Chain,
+    /// Blocks and bytes a peer holding a version has to fetch to get one
+    /// with 1, 2, 4, ... up to `m` keys changed.
+    Delta,
+    /// Rounds of requests and bytes per round fetching the whole HAMT, and a
+    /// single key, from a peer that only sends blocks by CID.
+    Fetch,
+    /// Blocks and bytes visited and extracted by GraphSync-like selectors for
+    /// part of the HAMT.
+    Selectors,
+    /// Bytes and update amplification of a HAMT linking child HAMTs, for
+    /// every combination of the bucket sizes of parent and children.
+    Nested,
+    /// Node sizes and bytes stored per byte of keys and values by key length.
+    Keys,
+    /// `Sizes` storing the keys, and storing only their hashes, truncated to
+    /// 32, 16, 8 and 4 bytes.
+    HashOnly,
+    /// Root CIDs, bytes and slots shared by HAMTs of the same keys under
+    /// different salts.
+    Salt,
+    /// Depth and proof sizes with and without path compression, for keys
+    /// whose hashes share longer and longer prefixes.
+    Skip,
+    /// Sizes and lookup costs of HAMTs whose buckets stop splitting at a
+    /// maximum depth, taking any number of entries there.
+    MaxDepth,
+    /// Bytes written and blocks shared copying a HAMT into one of another
+    /// bucket size, for every combination of the bucket sizes of source and
+    /// target.
+    Migration,
+    /// Blocks shared between the versions kept of a long edit history, and
+    /// the bytes dropping the oldest ones reclaims.
+    RefCount,
+    /// Node, link and value totals estimated from walking a random share of
+    /// the subtrees, with confidence intervals, against the exact ones.
+    Sample,
+    /// Share of the nodes on each level by the number of bits set in their
+    /// bitfield, and their average serialized size.
+    Occupancy,
+    /// Bytes the bitfields take in the nodes, and the tree with bitfields in
+    /// the compact encoding against the one without.
+    Bitfield,
+    /// Sizes of the nodes written as maps of their slots to their pointers
+    /// against the usual bitfield and list, and the time decoding them takes.
+    NodeFormat,
+    /// Bytes stored and written replaying a captured trace of the operations
+    /// on Filecoin actor HAMTs.
+    Replay,
+    /// Bytes written and store growth per operation of a file system whose
+    /// directories are HAMTs, running `n` random operations.
+    Wnfs,
+    /// Cumulative bytes written, bytes written by each flush and node count
+    /// over a long run of `n` mixed inserts, overwrites and deletes.
+    TimeSeries,
+}
+
+impl Experiment {
+    pub const ALL: &'static [Experiment] = &[
+        Experiment::Sizes,
+        Experiment::Blocks,
+        Experiment::Degree,
+        Experiment::Depth,
+        Experiment::Levels,
+        Experiment::Proof,
+        Experiment::MultiProof,
+        Experiment::Lookup,
+        Experiment::Cache,
+        Experiment::Delete,
+        Experiment::Scan,
+        Experiment::Paging,
+        Experiment::Batch,
+        Experiment::Gc,
+        Experiment::Disk,
+        Experiment::Versions,
+        Experiment::Values,
+        Experiment::External,
+        Experiment::Hashes,
+        Experiment::Collisions,
+        Experiment::Sweep,
+        Experiment::Amt,
+        Experiment::Champ,
+        Experiment::Radix,
+        Experiment::Len,
+        Experiment::Cids,
+        Experiment::Codecs,
+        Experiment::Compression,
+        Experiment::Memory,
+        Experiment::Writes,
+        Experiment::Flush,
+        Experiment::Chain,
+        Experiment::Delta,
+        Experiment::Fetch,
+        Experiment::Selectors,
+        Experiment::Nested,
+        Experiment::Keys,
+        Experiment::HashOnly,
+        Experiment::Salt,
+        Experiment::Skip,
+        Experiment::MaxDepth,
+        Experiment::Migration,
+        Experiment::RefCount,
+        Experiment::Sample,
+        Experiment::Occupancy,
+        Experiment::Bitfield,
+        Experiment::NodeFormat,
+        Experiment::Replay,
+        Experiment::Wnfs,
+        Experiment::TimeSeries,
+    ];
+
+    /// Name on the command line.
+    pub fn name(self) -> &'static str {
+        match self {
+            Experiment::Sizes => "sizes",
+            Experiment::Blocks => "blocks",
+            Experiment::Degree => "degree",
+            Experiment::Depth => "depth",
+            Experiment::Levels => "levels",
+            Experiment::Proof => "proof",
+            Experiment::MultiProof => "multiproof",
+            Experiment::Lookup => "lookup",
+            Experiment::Cache => "cache",
+            Experiment::Delete => "delete",
+            Experiment::Scan => "scan",
+            Experiment::Paging => "paging",
+            Experiment::Batch => "batch",
+            Experiment::Gc => "gc",
+            Experiment::Disk => "disk",
+            Experiment::Versions => "versions",
+            Experiment::Values => "values",
+            Experiment::External => "external",
+            Experiment::Hashes => "hashes",
+            Experiment::Collisions => "collisions",
+            Experiment::Sweep => "sweep",
+            Experiment::Amt => "amt",
+            Experiment::Champ => "champ",
+            Experiment::Radix => "radix",
+            Experiment::Len => "len",
+            Experiment::Cids => "cids",
+            Experiment::Codecs => "codecs",
+            Experiment::Compression => "compression",
+            Experiment::Memory => "memory",
+            Experiment::Writes => "writes",
+            Experiment::Flush => "flush",
+            Experiment::Chain => "chain",
+            Experiment::Delta => "delta",
+            Experiment::Fetch => "fetch",
+            Experiment::Selectors => "selectors",
+            Experiment::Nested => "nested",
+            Experiment::Keys => "keys",
+            Experiment::HashOnly => "hashonly",
+            Experiment::Salt => "salt",
+            Experiment::Skip => "skip",
+            Experiment::MaxDepth => "maxdepth",
+            Experiment::Migration => "migration",
+            Experiment::RefCount => "refcount",
+            Experiment::Sample => "sample",
+            Experiment::Occupancy => "occupancy",
+            Experiment::Bitfield => "bitfield",
+            Experiment::NodeFormat => "nodeformat",
+            Experiment::Replay => "replay",
+            Experiment::Wnfs => "wnfs",
+            Experiment::TimeSeries => "timeseries",
+        }
+    }
+}
+
+impl FromStr for Experiment {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Experiment::ALL
+            .iter()
+            .copied()
+            .find(|experiment| experiment.name() == s)
+            .ok_or_else(|| anyhow!("unknown experiment `{s}`"))
+    }
+}
+
+/// Experiments in a `report` unless `--experiments` is given.
+const REPORT_EXPERIMENTS: &str = "sizes,blocks,depth,levels,proof,occupancy";
+
+/// A comma separated list of experiments.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Experiments(Vec<Experiment>);
+
+impl FromStr for Experiments {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        s.split(',')
+            .map(|name| name.trim().parse())
+            .collect::<Result<_>>()
+            .map(Experiments)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Params {
+    pub bit_widths: BitWidths,
+    pub bucket_sizes: BucketSizes,
+    pub n: usize,
+    pub m: usize,
+    pub batch_size: usize,
+    pub lookups: usize,
+    /// Only this node cache instead of all in `cache`.
+    pub node_cache: Option<CacheSize>,
+    pub page_size: usize,
+    pub versions: usize,
+    /// Only keep this many versions in `refcount` instead of all.
+    pub keep: Option<usize>,
+    pub workload: Workload,
+    pub value_sizes: Option<ValueSizes>,
+    /// Only this key length instead of all in `keys`.
+    pub key_length: Option<usize>,
+    /// Only this maximum depth instead of all in `maxdepth`.
+    pub max_depth: Option<u32>,
+    pub value_threshold: usize,
+    /// Only this policy instead of all in `flush`.
+    pub flush: Option<FlushPolicy>,
+    /// Only this selector instead of all in `selectors`.
+    pub selector: Option<Selector>,
+    /// Only this sampling instead of all in `sample`.
+    pub sample: Option<Sampling>,
+    /// Trace `replay` runs.
+    pub trace: Option<PathBuf>,
+    pub fs_ops: OpMix,
+    pub network: Network,
+    pub dir: Option<PathBuf>,
+    pub output: Option<PathBuf>,
+    pub append: bool,
+    pub format: Format,
+    pub header: bool,
+    pub plot: Option<Chart>,
+    pub plot_output: Option<PathBuf>,
+    /// Directory flame graphs of building a HAMT at every point go to.
+    #[cfg(feature = "profile")]
+    pub profile: Option<PathBuf>,
+    pub seed: u64,
+    /// Runs of every point, with consecutive seeds, summarized into one
+    /// record per row.
+    pub repeat: usize,
+    /// Runs of every point before the repetitions whose results are dropped.
+    pub warmup: usize,
+    /// Whether experiments check the blocks they read against their CIDs.
+    pub verify: bool,
+    /// Whether experiments report their progress on stderr.
+    pub progress: bool,
+    /// Skip points `output` already has results for.
+    pub resume: bool,
+}
+
+pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Command> {
+    let args = iter::once("rust-ipld-hamt".to_string()).chain(args);
+    let matches = match app().get_matches_from_safe(args) {
+        Ok(matches) => matches,
+        // Help for a missing subcommand too, as clap prints it then.
+        Err(e)
+            if matches!(
+                e.kind,
+                ErrorKind::HelpDisplayed | ErrorKind::MissingArgumentOrSubcommand
+            ) =>
+        {
+            return Ok(Command::Help(e.message))
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    let command = match matches.subcommand() {
+        ("experiment", Some(matches)) => {
+            let experiment = required(matches, "name")?;
+            let default_n = match experiment {
+                Experiment::TimeSeries => 1_000_000,
+                _ => 100_000,
+            };
+            let mut params = Params::from_matches(matches, default_n)?;
+            if experiment == Experiment::Sweep {
+                if !matches.is_present("bit-width") {
+                    params.bit_widths = BitWidths((1..=8).collect());
+                }
+                if !matches.is_present("bucket-size") {
+                    params.bucket_sizes = BucketSizes((1..=16).collect());
+                }
+            }
+            if experiment == Experiment::Replay && params.trace.is_none() {
+                bail!("`replay` needs a `--trace`");
+            }
+            if params.repeat == 0 {
+                bail!("`--repeat` needs at least one run");
+            }
+            if matches.is_present("resume") {
+                if params.output.is_none() || params.append {
+                    bail!("`--resume` needs an `--output` and can't be combined with `--append`");
+                }
+                if params.plot.is_some() {
+                    bail!("`--plot` only sees the results of this run, it can't be resumed");
+                }
+                if params.format == Format::Json {
+                    bail!("can't resume a JSON array, use the ndjson format instead");
+                }
+                params.resume = true;
+            }
+            Command::Experiment(experiment, params)
+        }
+        ("report", Some(matches)) => {
+            let Experiments(experiments) = required(matches, "experiments")?;
+            let params = Params::from_matches(matches, 100_000)?;
+            Command::Report(experiments, params)
+        }
+        ("dot", Some(matches)) => {
+            let params = Params::from_matches(matches, 300)?;
+            let renderer = renderer_from_matches(matches)?;
+            if matches.is_present("diff") {
+                Command::DotDiff(params, renderer)
+            } else {
+                Command::Dot(params, renderer)
+            }
+        }
+        ("car", Some(matches)) => {
+            let version = if matches.is_present("v2") {
+                CarVersion::V2
+            } else {
+                CarVersion::V1
+            };
+            let params = Params::from_matches(matches, 100_000)?;
+            Command::Car(params, version)
+        }
+        ("analyze", Some(matches)) => {
+            let path = required(matches, "file")?;
+            let root = value(matches, "root")?;
+            let params = Params::from_matches(matches, 0)?;
+            Command::Analyze(path, root, params)
+        }
+        ("regression", Some(matches)) => {
+            let check = value(matches, "check")?;
+            let params = Params::from_matches(matches, 0)?;
+            Command::Regression(check, params)
+        }
+        ("cases", Some(matches)) => {
+            let path = required(matches, "manifest")?;
+            let params = Params::from_matches(matches, 0)?;
+            Command::Cases(path, params)
+        }
+        ("compare", Some(matches)) => {
+            let baseline = required(matches, "baseline")?;
+            let current = required(matches, "current")?;
+            let mut threshold = Threshold::default();
+            if let Some(percent) = value::<f64>(matches, "threshold")? {
+                if !(percent >= 0.0 && percent.is_finite()) {
+                    bail!("`--threshold` needs a percentage of at least 0");
+                }
+                threshold.percent = percent;
+            }
+            threshold.metrics = matches
+                .value_of("metrics")
+                .map(|metrics| metrics.split(',').map(|m| m.trim().to_string()).collect());
+            let params = Params::from_matches(matches, 0)?;
+            Command::Compare(baseline, current, threshold, params)
+        }
+        ("study", Some(matches)) => {
+            let path = required(matches, "study")?;
+            Command::Study(path, matches.is_present("dry-run"))
+        }
+        _ => unreachable!("clap requires a known subcommand"),
+    };
+
+    Ok(command)
+}
+
+/// The value of `--name` parsed, if it was given.
+fn value<T>(matches: &ArgMatches, name: &str) -> Result<Option<T>>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    matches
+        .value_of(name)
+        .map(|value| {
+            value
+                .parse()
+                .map_err(|e| anyhow!("invalid value `{value}` for `--{name}`: {e}"))
+        })
+        .transpose()
+}
+
+/// [`value`] of an argument clap requires or has a default for.
+fn required<T>(matches: &ArgMatches, name: &str) -> Result<T>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    Ok(value(matches, name)?.expect("required or defaulted by clap"))
+}
+
+impl Params {
+    /// The parameters given in `matches`, with the defaults for those the
+    /// subcommand doesn't take.
+    fn from_matches(matches: &ArgMatches, default_n: usize) -> Result<Self> {
+        let format = value(matches, "format")?;
+        let delimiter = value::<Delimiter>(matches, "delimiter")?;
+        let format = match (format, delimiter) {
+            (None, delimiter) => Format::Csv(delimiter.unwrap_or(Delimiter::Semicolon)),
+            (Some(Format::Csv(_)), Some(delimiter)) => Format::Csv(delimiter),
+            (Some(format), None) => format,
+            (Some(format), Some(_)) => bail!("`--delimiter` can't be used with {format:?}"),
+        };
+
+        Ok(Params {
+            bit_widths: value(matches, "bit-width")?.unwrap_or_else(|| BitWidths(vec![4])),
+            bucket_sizes: value(matches, "bucket-size")?.unwrap_or_else(|| BucketSizes(vec![3])),
+            n: value(matches, "n")?.unwrap_or(default_n),
+            m: value(matches, "m")?.unwrap_or(100),
+            batch_size: value(matches, "batch-size")?.unwrap_or(10),
+            lookups: value(matches, "lookups")?.unwrap_or(1000),
+            node_cache: value(matches, "node-cache")?,
+            page_size: value(matches, "page-size")?.unwrap_or(1000),
+            versions: value(matches, "versions")?.unwrap_or(10),
+            keep: value(matches, "keep")?,
+            workload: value(matches, "workload")?.unwrap_or_default(),
+            value_sizes: value(matches, "value-size")?,
+            key_length: value(matches, "key-length")?,
+            max_depth: value(matches, "max-depth")?,
+            value_threshold: value(matches, "value-threshold")?.unwrap_or(64),
+            flush: value(matches, "flush")?,
+            selector: value(matches, "selector")?,
+            sample: value(matches, "sample")?,
+            trace: value(matches, "trace")?,
+            fs_ops: value(matches, "fs-ops")?.unwrap_or_default(),
+            network: Network {
+                latency: match value(matches, "latency")? {
+                    Some(millis) => Duration::from_millis(millis),
+                    None => Network::default().latency,
+                },
+                bandwidth: match value(matches, "bandwidth")? {
+                    Some(0) => None,
+                    Some(bandwidth) => Some(bandwidth),
+                    None => Network::default().bandwidth,
+                },
+            },
+            dir: value(matches, "dir")?,
+            output: value(matches, "output")?,
+            append: matches.is_present("append"),
+            format,
+            header: !matches.is_present("no-header"),
+            plot: value(matches, "plot")?,
+            plot_output: value(matches, "plot-output")?,
+            #[cfg(feature = "profile")]
+            profile: value(matches, "profile")?,
+            seed: value(matches, "seed")?.unwrap_or(DEFAULT_SEED),
+            repeat: value(matches, "repeat")?.unwrap_or(1),
+            warmup: value(matches, "warmup")?.unwrap_or(0),
+            verify: matches.is_present("verify"),
+            progress: !matches.is_present("quiet"),
+            resume: false,
+        })
+    }
+
+    /// Names and values of the parameters experiments take, with the names
+    /// of their options.
+    pub fn summary(&self) -> Vec<(&'static str, String)> {
+        let bandwidth = match self.network.bandwidth {
+            Some(bandwidth) => format!("{bandwidth} bytes/s"),
+            None => "unlimited".to_string(),
+        };
+        vec![
+            ("bit-width", format!("{:?}", self.bit_widths.0)),
+            ("bucket-size", format!("{:?}", self.bucket_sizes.0)),
+            ("n", self.n.to_string()),
+            ("m", self.m.to_string()),
+            ("batch-size", self.batch_size.to_string()),
+            ("lookups", self.lookups.to_string()),
+            (
+                "node-cache",
+                match &self.node_cache {
+                    Some(size) => size.to_string(),
+                    None => "all".to_string(),
+                },
+            ),
+            ("page-size", self.page_size.to_string()),
+            ("versions", self.versions.to_string()),
+            (
+                "keep",
+                match self.keep {
+                    Some(keep) => keep.to_string(),
+                    None => "all".to_string(),
+                },
+            ),
+            ("workload", format!("{:?}", self.workload)),
+            (
+                "value-size",
+                match &self.value_sizes {
+                    Some(sizes) => sizes.to_string(),
+                    None => "powers of two from 1 to 1024".to_string(),
+                },
+            ),
+            (
+                "key-length",
+                match self.key_length {
+                    Some(length) => length.to_string(),
+                    None => "powers of two from 8 to 512".to_string(),
+                },
+            ),
+            (
+                "max-depth",
+                match self.max_depth {
+                    Some(depth) => depth.to_string(),
+                    None => "0 to 3 and no limit".to_string(),
+                },
+            ),
+            ("value-threshold", self.value_threshold.to_string()),
+            (
+                "flush",
+                match &self.flush {
+                    Some(policy) => policy.to_string(),
+                    None => "all".to_string(),
+                },
+            ),
+            (
+                "selector",
+                match &self.selector {
+                    Some(selector) => selector.to_string(),
+                    None => "all".to_string(),
+                },
+            ),
+            (
+                "sample",
+                match &self.sample {
+                    Some(sampling) => sampling.to_string(),
+                    None => "all".to_string(),
+                },
+            ),
+            ("fs-ops", self.fs_ops.to_string()),
+            (
+                "trace",
+                match &self.trace {
+                    Some(path) => path.display().to_string(),
+                    None => "none".to_string(),
+                },
+            ),
+            ("latency", format!("{:?}", self.network.latency)),
+            ("bandwidth", bandwidth),
+            ("seed", self.seed.to_string()),
+            ("repeat", self.repeat.to_string()),
+            ("warmup", self.warmup.to_string()),
+        ]
+    }
+}
+
+fn renderer_from_matches(matches: &ArgMatches) -> Result<Renderer> {
+    let font = value::<String>(matches, "font")?;
+    let rankdir = value::<RankDir>(matches, "rankdir")?;
+    let color_scheme = value::<String>(matches, "color-scheme")?;
+
+    let renderer = match (matches.is_present("svg"), matches.is_present("mermaid")) {
+        (true, true) => bail!("`--svg` and `--mermaid` can't be combined"),
+        (true, false) => {
+            if rankdir.is_some() || color_scheme.is_some() {
+                bail!("`--rankdir` and `--color-scheme` don't apply to `--svg`");
+            }
+            #[cfg(feature = "svg")]
+            {
+                let mut renderer = SvgRenderer::default();
+                if let Some(font) = font {
+                    renderer = renderer.with_font(font);
+                }
+                Renderer::Svg(renderer)
+            }
+            #[cfg(not(feature = "svg"))]
+            bail!("`--svg` requires building with `--features svg`")
+        }
+        (false, true) => {
+            if color_scheme.is_some() {
+                bail!("`--color-scheme` doesn't apply to `--mermaid`");
+            }
+            let mut renderer = MermaidRenderer::default();
+            if let Some(font) = font {
+                renderer = renderer.with_font(font);
+            }
+            if let Some(rankdir) = rankdir {
+                renderer = renderer.with_rankdir(rankdir);
+            }
+            Renderer::Mermaid(renderer)
+        }
+        (false, false) => {
+            let mut renderer = DotRenderer::default();
+            if let Some(font) = font {
+                renderer = renderer.with_font(font);
+            }
+            if let Some(rankdir) = rankdir {
+                renderer = renderer.with_rankdir(rankdir);
+            }
+            if let Some(color_scheme) = color_scheme {
+                renderer = renderer.with_color_scheme(color_scheme);
+            }
+            Renderer::Dot(renderer)
+        }
+    };
+    Ok(renderer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn parses_experiment_flags() {
+        let command = parse(args(
+            "experiment sizes --bit-width 5 --bucket-size=1..3 --n 10",
+        ))
+        .unwrap();
+        assert_eq!(
+            command,
+            Command::Experiment(
+                Experiment::Sizes,
+                Params {
+                    bit_widths: BitWidths(vec![5]),
+                    bucket_sizes: BucketSizes(vec![1, 2]),
+                    n: 10,
+                    m: 100,
+                    batch_size: 10,
+                    lookups: 1000,
+                    node_cache: None,
+                    page_size: 1000,
+                    versions: 10,
+                    keep: None,
+                    workload: Workload::Sequential,
+                    value_sizes: None,
+                    key_length: None,
+                    max_depth: None,
+                    value_threshold: 64,
+                    flush: None,
+                    selector: None,
+                    sample: None,
+                    trace: None,
+                    fs_ops: OpMix::default(),
+                    network: Network::default(),
+                    dir: None,
+                    output: None,
+                    append: false,
+                    format: Format::Csv(Delimiter::Semicolon),
+                    header: true,
+                    plot: None,
+                    plot_output: None,
+                    #[cfg(feature = "profile")]
+                    profile: None,
+                    seed: DEFAULT_SEED,
+                    repeat: 1,
+                    warmup: 0,
+                    verify: false,
+                    progress: true,
+                    resume: false,
+                }
+            )
+        );
+    }
+
+    #[test]
+    fn sweeps_every_combination_by_default() {
+        let params = |line| match parse(args(line)).unwrap() {
+            Command::Experiment(Experiment::Sweep, params) => params,
+            other => panic!("expected a sweep, got {other:?}"),
+        };
+        let all = params("experiment sweep");
+        assert_eq!(all.bit_widths.0, (1..=8).collect::<Vec<_>>());
+        assert_eq!(all.bucket_sizes.0, (1..=16).collect::<Vec<_>>());
+        assert_eq!(params("experiment sweep --seed 42").seed, 42);
+        assert!(params("experiment sweep --output a.csv --resume").resume);
+        assert!(parse(args("experiment sweep --resume")).is_err());
+        let some = params("experiment sweep --bit-width 2,4 --bucket-size 3");
+        assert_eq!(some.bit_widths, BitWidths(vec![2, 4]));
+        assert_eq!(some.bucket_sizes, BucketSizes(vec![3]));
+    }
+
+    #[test]
+    fn parses_report_experiments() {
+        let experiments = |line| match parse(args(line)).unwrap() {
+            Command::Report(experiments, _) => experiments,
+            other => panic!("expected a report, got {other:?}"),
+        };
+        assert_eq!(
+            experiments("report"),
+            [
+                Experiment::Sizes,
+                Experiment::Blocks,
+                Experiment::Depth,
+                Experiment::Levels,
+                Experiment::Proof,
+                Experiment::Occupancy
+            ]
+        );
+        assert_eq!(
+            experiments("report --experiments sweep,multiproof"),
+            [Experiment::Sweep, Experiment::MultiProof]
+        );
+        assert!(parse(args("report --experiments sizes,nope")).is_err());
+        for &experiment in Experiment::ALL {
+            assert_eq!(experiment.name().parse::<Experiment>().unwrap(), experiment);
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_flags() {
+        assert!(parse(args("dot --bits 5")).is_err());
+        assert!(parse(args("experiment proof --n")).is_err());
+        assert!(parse(args("experiment nope")).is_err());
+        assert!(parse(args("experiment replay")).is_err());
+        assert!(parse(args("compare a.csv --threshold 1")).is_err());
+        assert!(parse(args("compare a.csv b.csv --threshold -1")).is_err());
+        assert!(parse(args("study --dry-run")).is_err());
+        assert!(parse(args("study a.toml --n 10")).is_err());
+    }
+
+    #[test]
+    fn rejects_flags_of_other_subcommands() {
+        assert!(parse(args("dot --diff --m 5 --output a.dot")).is_ok());
+        assert!(parse(args("experiment sizes --plot line:bytes --format json")).is_ok());
+        assert!(parse(args("dot --lookups 5")).is_err());
+        assert!(parse(args("dot --bit-width 4 --format json")).is_err());
+        assert!(parse(args("car --seed 1")).is_err());
+        assert!(parse(args("analyze a.car --n 10")).is_err());
+        assert!(parse(args("report --format json")).is_err());
+        assert!(parse(args("regression --bit-width 4")).is_err());
+        assert!(parse(args("cases a.json --repeat 2")).is_err());
+        assert!(parse(args("compare a.csv b.csv --plot line:bytes")).is_err());
+    }
+
+    #[test]
+    fn prints_help() {
+        for line in ["", "--help", "help", "experiment --help", "help dot"] {
+            match parse(args(line)).unwrap() {
+                Command::Help(help) => assert!(help.contains("USAGE"), "{help}"),
+                other => panic!("expected help for `{line}`, got {other:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn parses_compare_thresholds() {
+        match parse(args(
+            "compare a.csv b.csv --threshold 2.5 --metrics bytes,nodes",
+        ))
+        .unwrap()
+        {
+            Command::Compare(baseline, current, threshold, _) => {
+                assert_eq!(baseline, PathBuf::from("a.csv"));
+                assert_eq!(current, PathBuf::from("b.csv"));
+                assert_eq!(
+                    threshold,
+                    Threshold {
+                        percent: 2.5,
+                        metrics: Some(vec!["bytes".to_string(), "nodes".to_string()]),
+                    }
+                );
+            }
+            other => panic!("expected a comparison, got {other:?}"),
+        }
+    }
+}
@@ -0,0 +1,258 @@
+//! Deltas between the results of a run and those of a stored baseline, to
+//! catch changes to the HAMT that make it larger or write more.
+//!
+//! Rows are matched by their point: the parameter columns and every column
+//! that isn't a number. Rows with the same point, like the levels of a tree,
+//! are matched in order. Every other numeric column is a metric, and one that
+//! grew by more than the threshold regressed. Summaries of repeated runs are
+//! compared by their means, their `_stddev`, `_min` and `_max` are skipped.
+
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use crate::plot::Record;
+use crate::repeat::PARAMETERS;
+
+/// Columns that are neither part of the point nor a metric.
+const IGNORED: &[&str] = &["seed"];
+
+/// Prefix of the columns describing what produced the results, see
+/// [`crate::environment`].
+const ENVIRONMENT: &str = "env.";
+
+/// Suffixes of the columns [`crate::repeat`] adds next to a mean.
+const SUMMARY_SUFFIXES: &[&str] = &["_stddev", "_min", "_max"];
+
+/// When a metric counts as regressed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Threshold {
+    /// Largest increase, in percent of the baseline, that still passes.
+    pub percent: f64,
+    /// Only these metrics instead of all.
+    pub metrics: Option<Vec<String>>,
+}
+
+impl Default for Threshold {
+    fn default() -> Self {
+        Threshold {
+            percent: 5.0,
+            metrics: None,
+        }
+    }
+}
+
+impl Threshold {
+    fn includes(&self, metric: &str) -> bool {
+        self.metrics
+            .as_ref()
+            .is_none_or(|metrics| metrics.iter().any(|m| m == metric))
+    }
+}
+
+/// A metric of a point in both runs.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Delta {
+    pub point: String,
+    pub metric: String,
+    pub baseline: f64,
+    /// `None` if the current run has no such row or no number in the column.
+    pub current: Option<f64>,
+    pub delta: Option<f64>,
+    /// Delta in percent of the baseline, `None` for a baseline of 0.
+    pub change: Option<f64>,
+    /// Whether the metric grew by more than the threshold, or is missing.
+    pub regressed: bool,
+}
+
+/// Deltas of every metric of `baseline`, in its order. Rows only the current
+/// run has are ignored.
+pub fn compare(baseline: &[Record], current: &[Record], threshold: &Threshold) -> Vec<Delta> {
+    let mut rows: HashMap<String, &Record> = HashMap::new();
+    for (point, record) in points(current) {
+        rows.insert(point, record);
+    }
+
+    let mut deltas = Vec::new();
+    for (point, record) in points(baseline) {
+        let row = rows.get(&point);
+        for (name, field) in record {
+            let Some(baseline) = number(field) else {
+                continue;
+            };
+            if !is_metric(record, name) || !threshold.includes(name) {
+                continue;
+            }
+            let current = row.and_then(|row| column(row, name)).and_then(number);
+            let delta = current.map(|current| current - baseline);
+            let change = delta.and_then(|delta| {
+                if baseline != 0.0 {
+                    Some(delta / baseline.abs() * 100.0)
+                } else if delta == 0.0 {
+                    Some(0.0)
+                } else {
+                    None
+                }
+            });
+            let regressed = match (delta, change) {
+                (None, _) => true,
+                (Some(_), Some(change)) => change > threshold.percent,
+                (Some(delta), None) => delta > 0.0,
+            };
+            deltas.push(Delta {
+                point: point.clone(),
+                metric: name.clone(),
+                baseline,
+                current,
+                delta,
+                change,
+                regressed,
+            });
+        }
+    }
+    deltas
+}
+
+/// The records with their points, numbering the repeats of a point.
+fn points(records: &[Record]) -> Vec<(String, &Record)> {
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    records
+        .iter()
+        .map(|record| {
+            let mut columns: Vec<String> = record
+                .iter()
+                .filter(|(name, _)| !is_ignored(name))
+                .filter(|(name, field)| {
+                    PARAMETERS.contains(&name.as_str()) || number(field).is_none()
+                })
+                .map(|(name, field)| format!("{name}={field}"))
+                .collect();
+            // JSON results are read back with their columns sorted.
+            columns.sort();
+            let point = columns.join(" ");
+            let repeats = seen.entry(point.clone()).or_default();
+            *repeats += 1;
+            let point = match *repeats {
+                1 => point,
+                i if point.is_empty() => format!("#{i}"),
+                i => format!("{point} #{i}"),
+            };
+            (point, record)
+        })
+        .collect()
+}
+
+fn is_ignored(name: &str) -> bool {
+    IGNORED.contains(&name) || name.starts_with(ENVIRONMENT)
+}
+
+fn is_metric(record: &Record, name: &str) -> bool {
+    if PARAMETERS.contains(&name) || is_ignored(name) {
+        return false;
+    }
+    // The spread of a repeated run isn't a result of its own.
+    !SUMMARY_SUFFIXES.iter().any(|suffix| {
+        name.strip_suffix(suffix)
+            .is_some_and(|mean| column(record, mean).is_some())
+    })
+}
+
+fn column<'a>(record: &'a Record, name: &str) -> Option<&'a str> {
+    record
+        .iter()
+        .find(|(column, _)| column == name)
+        .map(|(_, field)| field.as_str())
+}
+
+fn number(field: &str) -> Option<f64> {
+    field.parse().ok().filter(|x: &f64| x.is_finite())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(fields: &[(&str, &str)]) -> Record {
+        fields
+            .iter()
+            .map(|&(name, field)| (name.to_string(), field.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn flags_metrics_grown_past_the_threshold() {
+        let row = |seed, bit_width, hash, bytes| {
+            record(&[
+                ("env.commit", seed),
+                ("seed", seed),
+                ("bit_width", bit_width),
+                ("hash", hash),
+                ("bytes", bytes),
+            ])
+        };
+        let baseline = [
+            row("1", "4", "sha256", "100"),
+            row("1", "4", "blake3", "100"),
+            row("1", "5", "sha256", "0"),
+        ];
+        // Other seed and row order, one row missing.
+        let current = [
+            row("2", "4", "blake3", "110"),
+            row("2", "4", "sha256", "104"),
+        ];
+        let deltas = compare(&baseline, &current, &Threshold::default());
+        assert_eq!(deltas.len(), 3);
+        assert_eq!(deltas[0].point, "bit_width=4 hash=sha256");
+        assert_eq!(deltas[0].metric, "bytes");
+        assert_eq!(deltas[0].current, Some(104.0));
+        assert_eq!(deltas[0].change, Some(4.0));
+        assert!(!deltas[0].regressed);
+        assert_eq!(deltas[1].change, Some(10.0));
+        assert!(deltas[1].regressed);
+        assert_eq!(deltas[2].current, None);
+        assert!(deltas[2].regressed);
+
+        let lenient = Threshold {
+            percent: 20.0,
+            metrics: Some(vec!["nodes".to_string()]),
+        };
+        assert!(compare(&baseline, &current, &lenient).is_empty());
+    }
+
+    #[test]
+    fn matches_repeated_points_in_order() {
+        let rows = |depths: &[&str]| -> Vec<Record> {
+            depths
+                .iter()
+                .enumerate()
+                .map(|(depth, nodes)| {
+                    record(&[
+                        ("depth", &depth.to_string()),
+                        ("nodes", nodes),
+                        ("nodes_stddev", "1"),
+                    ])
+                })
+                .collect()
+        };
+        let deltas = compare(
+            &rows(&["1", "16"]),
+            &rows(&["1", "12"]),
+            &Threshold::default(),
+        );
+        let metrics: Vec<_> = deltas
+            .iter()
+            .map(|d| (d.point.as_str(), &*d.metric))
+            .collect();
+        assert_eq!(
+            metrics,
+            [
+                ("", "depth"),
+                ("", "nodes"),
+                ("#2", "depth"),
+                ("#2", "nodes")
+            ]
+        );
+        assert_eq!(deltas[3].delta, Some(-4.0));
+        assert!(deltas.iter().all(|delta| !delta.regressed));
+    }
+}
@@ -0,0 +1,219 @@
+//! A blockstore that compresses blocks, to measure how well HAMT nodes
+//! compress when a store or transport compresses them individually.
+//!
+//! zstd isn't available to this build, so blocks are compressed with
+//! [`compress`], a greedy LZ77 compressor writing the LZ4 block format. zstd
+//! adds entropy coding on top of the same kind of matching and compresses
+//! better, so ratios here are a lower bound. Repeated keys and values of a
+//! bucket, and the common prefixes of CIDs, are what both find.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use anyhow::{bail, Result};
+use cid::Cid;
+use fvm_ipld_blockstore::Blockstore;
+
+/// Shortest match LZ4 encodes.
+const MIN_MATCH: usize = 4;
+/// Bytes at the end of a block that are always literals, as LZ4 requires.
+const LAST_LITERALS: usize = 5;
+/// Matches start at least this many bytes before the end of a block.
+const MATCH_LIMIT: usize = 12;
+const HASH_BITS: u32 = 12;
+
+/// Wraps a blockstore and stores compressed blocks in it, decompressing them
+/// again on `get`. CIDs stay those of the uncompressed blocks.
+#[derive(Debug, Default)]
+pub struct CompressedStore<S> {
+    inner: S,
+    uncompressed_bytes: AtomicU64,
+}
+
+impl<S> CompressedStore<S> {
+    pub fn new(inner: S) -> Self {
+        CompressedStore {
+            inner,
+            uncompressed_bytes: AtomicU64::new(0),
+        }
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    /// Sum of the sizes of all distinct blocks before compression.
+    pub fn uncompressed_bytes(&self) -> u64 {
+        self.uncompressed_bytes.load(Ordering::Relaxed)
+    }
+}
+
+impl<S: Blockstore> Blockstore for CompressedStore<S> {
+    fn has(&self, k: &Cid) -> Result<bool> {
+        self.inner.has(k)
+    }
+
+    fn get(&self, k: &Cid) -> Result<Option<Vec<u8>>> {
+        self.inner
+            .get(k)?
+            .map(|block| decompress(&block))
+            .transpose()
+    }
+
+    fn put_keyed(&self, k: &Cid, block: &[u8]) -> Result<()> {
+        if !self.inner.has(k)? {
+            self.uncompressed_bytes
+                .fetch_add(block.len() as u64, Ordering::Relaxed);
+        }
+        self.inner.put_keyed(k, &compress(block))
+    }
+}
+
+/// Compresses `input` into an LZ4 block.
+pub fn compress(input: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(input.len() / 2);
+    // Position + 1 of the last occurrence of each hashed 4 bytes.
+    let mut table = vec![0usize; 1 << HASH_BITS];
+    let mut anchor = 0;
+    let mut pos = 0;
+    while pos + MATCH_LIMIT < input.len() {
+        let bytes = u32::from_le_bytes(input[pos..pos + 4].try_into().unwrap());
+        let slot = (bytes.wrapping_mul(2_654_435_761) >> (32 - HASH_BITS)) as usize;
+        let candidate = std::mem::replace(&mut table[slot], pos + 1);
+        let matches = candidate > 0
+            && pos - (candidate - 1) <= u16::MAX as usize
+            && input[candidate - 1..candidate + 3] == input[pos..pos + 4];
+        if !matches {
+            pos += 1;
+            continue;
+        }
+
+        let start = candidate - 1;
+        let mut len = MIN_MATCH;
+        while pos + len < input.len() - LAST_LITERALS && input[start + len] == input[pos + len] {
+            len += 1;
+        }
+        write_sequence(&mut out, &input[anchor..pos], Some((pos - start, len)));
+        pos += len;
+        anchor = pos;
+    }
+    write_sequence(&mut out, &input[anchor..], None);
+    out
+}
+
+/// Decompresses an LZ4 block.
+pub fn decompress(input: &[u8]) -> Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(input.len() * 2);
+    let mut pos = 0;
+    loop {
+        let Some(&token) = input.get(pos) else {
+            bail!("truncated block");
+        };
+        pos += 1;
+        let literals = read_length(input, &mut pos, (token >> 4) as usize)?;
+        let Some(bytes) = input.get(pos..pos + literals) else {
+            bail!("truncated literals");
+        };
+        out.extend_from_slice(bytes);
+        pos += literals;
+        if pos == input.len() {
+            return Ok(out);
+        }
+
+        let Some(offset) = input.get(pos..pos + 2) else {
+            bail!("truncated offset");
+        };
+        let offset = u16::from_le_bytes([offset[0], offset[1]]) as usize;
+        pos += 2;
+        if offset == 0 || offset > out.len() {
+            bail!("invalid offset {offset}");
+        }
+        let len = read_length(input, &mut pos, (token & 0xf) as usize)? + MIN_MATCH;
+        // Matches may overlap the bytes they produce.
+        let start = out.len() - offset;
+        for i in 0..len {
+            out.push(out[start + i]);
+        }
+    }
+}
+
+fn write_sequence(out: &mut Vec<u8>, literals: &[u8], matched: Option<(usize, usize)>) {
+    let match_len = matched.map_or(0, |(_, len)| len - MIN_MATCH);
+    out.push((literals.len().min(15) << 4 | match_len.min(15)) as u8);
+    write_length(out, literals.len());
+    out.extend_from_slice(literals);
+    if let Some((offset, _)) = matched {
+        out.extend_from_slice(&(offset as u16).to_le_bytes());
+        write_length(out, match_len);
+    }
+}
+
+/// The bytes extending a length of 15 or more in a token.
+fn write_length(out: &mut Vec<u8>, len: usize) {
+    if len < 15 {
+        return;
+    }
+    let mut rest = len - 15;
+    while rest >= 255 {
+        out.push(255);
+        rest -= 255;
+    }
+    out.push(rest as u8);
+}
+
+fn read_length(input: &[u8], pos: &mut usize, len: usize) -> Result<usize> {
+    if len < 15 {
+        return Ok(len);
+    }
+    let mut len = len;
+    loop {
+        let Some(&byte) = input.get(*pos) else {
+            bail!("truncated length");
+        };
+        *pos += 1;
+        len += byte as usize;
+        if byte != 255 {
+            return Ok(len);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memorydb::MemoryDB;
+    use crate::rng::Rng;
+    use fvm_ipld_hamt::{Hamt, Sha256};
+
+    #[test]
+    fn round_trips() -> Result<()> {
+        let mut rng = Rng::new(1);
+        let random: Vec<u8> = (0..1000).map(|_| rng.next_u64() as u8).collect();
+        let repeated = b"abcdefgh".repeat(200);
+        let runs = [vec![0; 10_000], vec![7; 300]].concat();
+        for input in [&b""[..], b"short", &random, &repeated, &runs] {
+            let compressed = compress(input);
+            assert_eq!(decompress(&compressed)?, input);
+        }
+        assert!(compress(&repeated).len() < 50);
+        assert!(compress(&random).len() > random.len());
+        assert!(decompress(&compress(&repeated)[..20]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn stores_compressed_blocks() -> Result<()> {
+        let store = CompressedStore::new(MemoryDB::default());
+        let mut map: Hamt<_, String, usize, Sha256, 3> = Hamt::new_with_bit_width(&store, 4);
+        for key in 0..1000 {
+            map.set(key, "value".repeat(10))?;
+        }
+        let root = map.flush()?;
+        map.flush()?;
+        assert!(store.inner().bytes_stored() < store.uncompressed_bytes());
+
+        let loaded: Hamt<_, String, usize, Sha256, 3> =
+            Hamt::load_with_bit_width(&root, &store, 4)?;
+        assert_eq!(loaded.get(&7)?, Some(&"value".repeat(10)));
+        Ok(())
+    }
+}
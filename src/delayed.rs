@@ -0,0 +1,149 @@
+use anyhow::Result;
+use cid::Cid;
+use fvm_ipld_blockstore::Blockstore;
+use parking_lot::Mutex;
+use std::thread;
+use std::time::Duration;
+
+/// Cost of fetching blocks from a remote peer, e.g. over bitswap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Network {
+    /// Round trip time paid by every request.
+    pub latency: Duration,
+    /// Bytes per second, or `None` for unlimited bandwidth.
+    pub bandwidth: Option<u64>,
+}
+
+impl Default for Network {
+    /// 50ms round trips at 1MB/s.
+    fn default() -> Self {
+        Network {
+            latency: Duration::from_millis(50),
+            bandwidth: Some(1_000_000),
+        }
+    }
+}
+
+impl Network {
+    /// Time for a single request returning `bytes`.
+    pub fn fetch_time(&self, bytes: usize) -> Duration {
+        let transfer = match self.bandwidth {
+            Some(0) => Duration::MAX,
+            Some(bandwidth) => Duration::from_secs_f64(bytes as f64 / bandwidth as f64),
+            None => Duration::ZERO,
+        };
+        self.latency.saturating_add(transfer)
+    }
+}
+
+/// Wraps a blockstore as if it was on the other side of a [`Network`].
+///
+/// Every `has` pays the latency and every `get` the latency plus the transfer
+/// time of the block, one request at a time. Puts stay local and are free.
+/// By default the delays are only added up, see [`DelayedStore::elapsed`],
+/// so experiments don't actually take minutes.
+#[derive(Debug)]
+pub struct DelayedStore<S> {
+    inner: S,
+    network: Network,
+    sleep: bool,
+    elapsed: Mutex<Duration>,
+}
+
+impl<S> DelayedStore<S> {
+    pub fn new(inner: S, network: Network) -> Self {
+        DelayedStore {
+            inner,
+            network,
+            sleep: false,
+            elapsed: Mutex::new(Duration::ZERO),
+        }
+    }
+
+    /// Sleeps for each delay instead of only adding it up.
+    pub fn sleeping(mut self) -> Self {
+        self.sleep = true;
+        self
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn network(&self) -> Network {
+        self.network
+    }
+
+    /// Simulated time spent waiting on the network so far.
+    pub fn elapsed(&self) -> Duration {
+        *self.elapsed.lock()
+    }
+
+    pub fn reset(&self) {
+        *self.elapsed.lock() = Duration::ZERO;
+    }
+
+    fn delay(&self, bytes: usize) {
+        let delay = self.network.fetch_time(bytes);
+        {
+            let mut elapsed = self.elapsed.lock();
+            *elapsed = elapsed.saturating_add(delay);
+        }
+        if self.sleep {
+            thread::sleep(delay);
+        }
+    }
+}
+
+impl<S: Blockstore> Blockstore for DelayedStore<S> {
+    fn has(&self, k: &Cid) -> Result<bool> {
+        self.delay(0);
+        self.inner.has(k)
+    }
+
+    fn get(&self, k: &Cid) -> Result<Option<Vec<u8>>> {
+        let block = self.inner.get(k)?;
+        self.delay(block.as_ref().map_or(0, Vec::len));
+        Ok(block)
+    }
+
+    fn put_keyed(&self, k: &Cid, block: &[u8]) -> Result<()> {
+        self.inner.put_keyed(k, block)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memorydb::MemoryDB;
+    use crate::metered::MeteredStore;
+    use fvm_ipld_hamt::{Hamt, Sha256};
+
+    #[test]
+    fn charges_latency_and_transfer_per_get() -> Result<()> {
+        let network = Network {
+            latency: Duration::from_millis(10),
+            bandwidth: Some(1000),
+        };
+        let store = DelayedStore::new(MeteredStore::new(MemoryDB::default()), network);
+        let mut map: Hamt<_, String, usize, Sha256, 3> = Hamt::new_with_bit_width(&store, 4);
+        for key in 0..1000 {
+            map.set(key, "F".to_string())?;
+        }
+        let root = map.flush()?;
+        assert_eq!(store.elapsed(), Duration::ZERO);
+
+        let map: Hamt<_, String, usize, Sha256, 3> = Hamt::load_with_bit_width(&root, &store, 4)?;
+        let root_bytes = store.inner().snapshot().bytes_read;
+        assert_eq!(store.elapsed(), network.fetch_time(root_bytes as usize));
+
+        store.reset();
+        store.inner().reset();
+        map.for_each(|_, _| Ok(()))?;
+        let read = store.inner().snapshot();
+        // At 1000 bytes per second, every byte takes a millisecond.
+        let expected = network.latency * read.gets as u32 + Duration::from_millis(read.bytes_read);
+        assert!(store.elapsed().abs_diff(expected) < Duration::from_millis(1));
+        Ok(())
+    }
+}
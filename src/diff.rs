@@ -0,0 +1,275 @@
+//! Structural diff between two versions of a HAMT.
+//!
+//! Both roots are walked in lockstep and subtrees with equal CIDs are
+//! skipped, so the cost is proportional to the size of the change rather
+//! than the size of the map.
+
+use std::collections::HashSet;
+
+use anyhow::{anyhow, Result};
+use cid::Cid;
+use fvm_ipld_blockstore::Blockstore;
+use fvm_ipld_encoding::de::DeserializeOwned;
+use fvm_ipld_hamt::{cid_config, node::Node, pointer::Pointer, Sha256};
+
+/// Stored nodes don't depend on the hash algorithm, it's only needed to
+/// compute new positions.
+type StoredNode<K, V, const BUCKET_SIZE: usize> = Node<K, V, Sha256, BUCKET_SIZE>;
+
+/// Number of slots in a node's bitfield, enough for any bit width.
+const SLOTS: u32 = 256;
+
+#[derive(Debug)]
+pub struct HamtDiff<K, V> {
+    /// Entries only present in the right version.
+    pub added: Vec<(K, V)>,
+    /// Entries only present in the left version.
+    pub removed: Vec<(K, V)>,
+    /// Keys present in both versions as `(key, left value, right value)`.
+    pub changed: Vec<(K, V, V)>,
+    /// Blocks only reachable from the left root.
+    pub left_blocks: HashSet<Cid>,
+    /// Blocks only reachable from the right root.
+    pub right_blocks: HashSet<Cid>,
+}
+
+impl<K, V> HamtDiff<K, V> {
+    /// Whether both versions contain the same entries.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// Diffs the HAMTs stored at `left` and `right`.
+///
+/// Both versions have to use the same bit width and hash algorithm, otherwise
+/// entries end up in unrelated slots and everything shows up as changed.
+pub fn diff<S, K, V, const BUCKET_SIZE: usize>(
+    store: &S,
+    left: &Cid,
+    right: &Cid,
+) -> Result<HamtDiff<K, V>>
+where
+    S: Blockstore,
+    K: DeserializeOwned + PartialEq + Clone,
+    V: DeserializeOwned + PartialEq + Clone,
+{
+    let mut walk = Walk {
+        store,
+        left_blocks: HashSet::new(),
+        right_blocks: HashSet::new(),
+        added: Vec::new(),
+        removed: Vec::new(),
+        changed: Vec::new(),
+    };
+    walk.links::<BUCKET_SIZE>(left, right)?;
+
+    let left_only = walk.left_blocks.difference(&walk.right_blocks).copied();
+    let right_only = walk.right_blocks.difference(&walk.left_blocks).copied();
+    Ok(HamtDiff {
+        left_blocks: left_only.collect(),
+        right_blocks: right_only.collect(),
+        added: walk.added,
+        removed: walk.removed,
+        changed: walk.changed,
+    })
+}
+
+struct Walk<'a, S, K, V> {
+    store: &'a S,
+    left_blocks: HashSet<Cid>,
+    right_blocks: HashSet<Cid>,
+    added: Vec<(K, V)>,
+    removed: Vec<(K, V)>,
+    changed: Vec<(K, V, V)>,
+}
+
+impl<S, K, V> Walk<'_, S, K, V>
+where
+    S: Blockstore,
+    K: DeserializeOwned + PartialEq + Clone,
+    V: DeserializeOwned + PartialEq + Clone,
+{
+    fn load<const BUCKET_SIZE: usize>(&self, cid: &Cid) -> Result<StoredNode<K, V, BUCKET_SIZE>> {
+        cid_config::get(self.store, cid)?.ok_or_else(|| anyhow!("block {cid} not found"))
+    }
+
+    fn links<const BUCKET_SIZE: usize>(&mut self, left: &Cid, right: &Cid) -> Result<()> {
+        if left == right {
+            return Ok(());
+        }
+        let left_node = self.load::<BUCKET_SIZE>(left)?;
+        let right_node = self.load::<BUCKET_SIZE>(right)?;
+        self.left_blocks.insert(*left);
+        self.right_blocks.insert(*right);
+        self.nodes(left_node, right_node)
+    }
+
+    fn nodes<const BUCKET_SIZE: usize>(
+        &mut self,
+        left: StoredNode<K, V, BUCKET_SIZE>,
+        right: StoredNode<K, V, BUCKET_SIZE>,
+    ) -> Result<()> {
+        // Nodes skipping different levels hold different hashes in the same
+        // slots, so only their entries can be compared.
+        if left.skip != right.skip {
+            let mut old = Vec::new();
+            self.entries(Pointer::Dirty(Box::new(left)), true, &mut old)?;
+            let mut new = Vec::new();
+            self.entries(Pointer::Dirty(Box::new(right)), false, &mut new)?;
+            self.compare(old, new);
+            return Ok(());
+        }
+
+        let mut left_pointers = left.pointers.into_iter();
+        let mut right_pointers = right.pointers.into_iter();
+
+        for idx in 0..SLOTS {
+            let l = left.bitfield.test_bit(idx).then(|| left_pointers.next());
+            let r = right.bitfield.test_bit(idx).then(|| right_pointers.next());
+
+            match (l.flatten(), r.flatten()) {
+                (None, None) => {}
+                (Some(Pointer::Link { cid: l, .. }), Some(Pointer::Link { cid: r, .. })) => {
+                    self.links::<BUCKET_SIZE>(&l, &r)?
+                }
+                (l, r) => {
+                    let mut old = Vec::new();
+                    if let Some(pointer) = l {
+                        self.entries(pointer, true, &mut old)?;
+                    }
+                    let mut new = Vec::new();
+                    if let Some(pointer) = r {
+                        self.entries(pointer, false, &mut new)?;
+                    }
+                    self.compare(old, new);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Collects all entries below `pointer`, recording the visited blocks on
+    /// the given side.
+    fn entries<const BUCKET_SIZE: usize>(
+        &mut self,
+        pointer: Pointer<K, V, Sha256, BUCKET_SIZE>,
+        left: bool,
+        entries: &mut Vec<(K, V)>,
+    ) -> Result<()> {
+        let node = match pointer {
+            Pointer::Values(values) => {
+                for kv in &values {
+                    entries.push((kv.key().clone(), kv.value(self.store)?.clone()));
+                }
+                return Ok(());
+            }
+            Pointer::Link { cid, .. } => {
+                let node = self.load::<BUCKET_SIZE>(&cid)?;
+                if left {
+                    self.left_blocks.insert(cid);
+                } else {
+                    self.right_blocks.insert(cid);
+                }
+                node
+            }
+            Pointer::Dirty(node) => *node,
+        };
+
+        for pointer in node.pointers {
+            self.entries(pointer, left, entries)?;
+        }
+        Ok(())
+    }
+
+    /// Matches up the entries of one slot in both versions.
+    fn compare(&mut self, old: Vec<(K, V)>, mut new: Vec<(K, V)>) {
+        for (key, value) in old {
+            match new.iter().position(|(k, _)| *k == key) {
+                Some(i) => {
+                    let (_, new_value) = new.swap_remove(i);
+                    if new_value != value {
+                        self.changed.push((key, value, new_value));
+                    }
+                }
+                None => self.removed.push((key, value)),
+            }
+        }
+        self.added.extend(new);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memorydb::MemoryDB;
+    use fvm_ipld_hamt::Hamt;
+
+    type Map<'a> = Hamt<&'a MemoryDB, String, usize, Sha256, 3>;
+
+    #[test]
+    fn reports_changed_keys_and_blocks() -> Result<()> {
+        let store = MemoryDB::default();
+        let mut map: Map = Hamt::new_with_bit_width(&store, 4);
+        for key in 0..1000 {
+            map.set(key, "old".to_string())?;
+        }
+        let left = map.flush()?;
+
+        for key in 0..10 {
+            map.set(key, "new".to_string())?;
+        }
+        for key in 10..15 {
+            map.delete(&key)?;
+        }
+        for key in 1000..1003 {
+            map.set(key, "added".to_string())?;
+        }
+        let right = map.flush()?;
+
+        let mut diff = diff::<_, usize, String, 3>(&store, &left, &right)?;
+        diff.changed.sort_unstable();
+        diff.removed.sort_unstable();
+        diff.added.sort_unstable();
+
+        let changed: Vec<usize> = diff.changed.iter().map(|(k, _, _)| *k).collect();
+        assert_eq!(changed, (0..10).collect::<Vec<_>>());
+        assert!(diff
+            .changed
+            .iter()
+            .all(|(_, l, r)| l == "old" && r == "new"));
+        let removed: Vec<usize> = diff.removed.iter().map(|(k, _)| *k).collect();
+        assert_eq!(removed, (10..15).collect::<Vec<_>>());
+        let added: Vec<usize> = diff.added.iter().map(|(k, _)| *k).collect();
+        assert_eq!(added, (1000..1003).collect::<Vec<_>>());
+
+        assert!(diff.left_blocks.contains(&left));
+        assert!(diff.right_blocks.contains(&right));
+        assert!(diff.left_blocks.is_disjoint(&diff.right_blocks));
+
+        // Dropping the blocks unique to the old version is exactly what gc does.
+        let garbage: u64 = diff
+            .left_blocks
+            .iter()
+            .map(|cid| store.get(cid).unwrap().unwrap().len() as u64)
+            .sum();
+        assert_eq!(store.gc(&[right])?, garbage);
+        Ok(())
+    }
+
+    #[test]
+    fn identical_roots_have_no_diff() -> Result<()> {
+        let store = MemoryDB::default();
+        let mut map: Map = Hamt::new_with_bit_width(&store, 4);
+        for key in 0..100 {
+            map.set(key, "value".to_string())?;
+        }
+        let root = map.flush()?;
+
+        let diff = diff::<_, usize, String, 3>(&store, &root, &root)?;
+        assert!(diff.is_empty());
+        assert!(diff.left_blocks.is_empty() && diff.right_blocks.is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,97 @@
+//! What produced a set of results, recorded next to them so they can still be
+//! read once the crate, the vendored HAMT or the machine changed.
+//!
+//! The commit, the HAMT version and the compiler are those of the build, see
+//! build.rs; the CPU and the time are those of the run.
+
+use std::fs;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Environment {
+    /// Commit of the crate, with `-dirty` if it had uncommitted changes.
+    pub commit: String,
+    /// Version of the vendored `fvm_ipld_hamt` and the commit's tree of it.
+    pub hamt_version: String,
+    pub rustc: String,
+    pub cpu: String,
+    /// Start of the run, in UTC.
+    pub timestamp: String,
+}
+
+impl Environment {
+    pub fn current() -> Self {
+        let since_epoch = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        Environment {
+            commit: env!("BUILD_COMMIT").to_string(),
+            hamt_version: env!("BUILD_HAMT_VERSION").to_string(),
+            rustc: env!("BUILD_RUSTC_VERSION").to_string(),
+            cpu: cpu(),
+            timestamp: timestamp(since_epoch.as_secs()),
+        }
+    }
+
+    /// Names and values, listed with the parameters of a report.
+    pub fn summary(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("commit", self.commit.clone()),
+            ("hamt-version", self.hamt_version.clone()),
+            ("rustc", self.rustc.clone()),
+            ("cpu", self.cpu.clone()),
+            ("timestamp", self.timestamp.clone()),
+        ]
+    }
+}
+
+/// The model name of the first CPU where Linux reports one, otherwise the
+/// architecture, with the number of threads.
+fn cpu() -> String {
+    let model = fs::read_to_string("/proc/cpuinfo").ok().and_then(|info| {
+        info.lines()
+            .filter_map(|line| line.split_once(':'))
+            .find(|(key, _)| key.trim() == "model name")
+            .map(|(_, model)| model.trim().to_string())
+    });
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let model = model.unwrap_or_else(|| std::env::consts::ARCH.to_string());
+    format!("{model} x {threads}")
+}
+
+/// `secs` since the epoch as RFC 3339, e.g. `2024-02-29T12:00:00Z`.
+fn timestamp(secs: u64) -> String {
+    let (days, secs) = (secs / 86_400, secs % 86_400);
+    // Days to a civil date, from Howard Hinnant's `civil_from_days`.
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        secs / 3600,
+        secs % 3600 / 60,
+        secs % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_timestamps() {
+        assert_eq!(timestamp(0), "1970-01-01T00:00:00Z");
+        assert_eq!(timestamp(951_782_400), "2000-02-29T00:00:00Z");
+        assert_eq!(timestamp(1_709_208_000), "2024-02-29T12:00:00Z");
+        assert_eq!(timestamp(1_735_689_599), "2024-12-31T23:59:59Z");
+    }
+}
@@ -0,0 +1,110 @@
+//! The experiments, each in a module of its own, or next to the data structure
+//! it compares the HAMT against.
+
+pub mod batch;
+pub mod bitfield;
+pub mod blocks;
+pub mod cache;
+pub mod chain;
+pub mod cids;
+pub mod collisions;
+pub mod compression;
+pub mod degree;
+pub mod delete;
+pub mod delta;
+pub mod depth;
+pub mod disk;
+pub mod external;
+pub mod fetch;
+pub mod flush;
+pub mod gc;
+pub mod hash_only;
+pub mod hashes;
+pub mod keys;
+pub mod len;
+pub mod levels;
+pub mod lookup;
+pub mod max_depth;
+pub mod memory;
+pub mod migration;
+pub mod multi_proof;
+pub mod nested;
+pub mod node_format;
+pub mod occupancy;
+pub mod paging;
+pub mod proof;
+pub mod refcount;
+pub mod replay;
+pub mod salt;
+pub mod sample;
+pub mod scan;
+pub mod selectors;
+pub mod sizes;
+pub mod skip;
+pub mod time_series;
+pub mod values;
+pub mod versions;
+pub mod writes;
+
+use crate::cli::Params;
+use crate::rng::{Rng, DEFAULT_SEED};
+use crate::verify::VerifyingStore;
+
+/// Settings every experiment of a run shares, recorded with each result so
+/// it can be reproduced exactly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExperimentContext {
+    /// Seed of all random choices, like the keys of a workload or the order
+    /// of deletes.
+    seed: u64,
+    /// Whether to check the blocks traversals read against their CIDs.
+    verify: bool,
+}
+
+impl ExperimentContext {
+    pub fn new(params: &Params) -> Self {
+        ExperimentContext {
+            seed: params.seed,
+            verify: params.verify,
+        }
+    }
+
+    /// A generator starting from the seed, the same for every call.
+    pub fn rng(&self) -> Rng {
+        Rng::new(self.seed)
+    }
+
+    /// `store`, checking the blocks read from it if `--verify` was given.
+    pub fn verifying<S>(&self, store: S) -> VerifyingStore<S> {
+        if self.verify {
+            VerifyingStore::new(store)
+        } else {
+            VerifyingStore::passthrough(store)
+        }
+    }
+}
+
+impl Default for ExperimentContext {
+    fn default() -> Self {
+        ExperimentContext {
+            seed: DEFAULT_SEED,
+            verify: false,
+        }
+    }
+}
+
+#[cfg(test)]
+const SWEEP_BUCKET_SIZES: &[usize] = &[1, 2, 3, 5, 8, 12, 16, 32, 64, 128];
+
+/// Formats `n` followed by `f(bucket_size)` for every size in the sweep.
+#[cfg(test)]
+fn sweep_row<T: ToString>(
+    n: usize,
+    mut f: impl FnMut(usize) -> anyhow::Result<T>,
+) -> anyhow::Result<String> {
+    let mut row = vec![n.to_string()];
+    for &bucket_size in SWEEP_BUCKET_SIZES {
+        row.push(f(bucket_size)?.to_string());
+    }
+    Ok(row.join("; "))
+}
@@ -0,0 +1,79 @@
+//! The `batch` experiment: bytes written and time taken inserting `m` keys with
+//! `set` vs. `set_many`.
+
+use std::cmp;
+use std::time::Instant;
+
+use fvm_ipld_hamt::{Hamt, Sha256};
+use serde::Serialize;
+
+use crate::memorydb::MemoryDB;
+
+#[derive(Debug, Serialize)]
+pub struct BatchResult {
+    n: usize,
+    m: usize,
+    bucket_size: usize,
+    bit_width: u32,
+    batch_size: usize,
+    /// `set` or `set_many`.
+    method: &'static str,
+    byte_diff: u64,
+    micros: u64,
+}
+
+/// Inserts `m` new keys in batches of `batch_size`, flushing after every
+/// batch, once with one `set` per key and once with one `set_many` per batch.
+/// Both start from the same snapshot of the `n` keys before.
+pub fn batch_experiment<const BUCKET_SIZE: usize>(
+    bit_width: u32,
+    n: usize,
+    m: usize,
+    batch_size: usize,
+) -> Vec<BatchResult> {
+    let store = MemoryDB::default();
+    let mut map: Hamt<_, _, usize, Sha256, BUCKET_SIZE> =
+        Hamt::new_with_bit_width(&store, bit_width);
+    let value = "F";
+
+    for key in 0..n {
+        map.set(key, value.to_string()).unwrap();
+    }
+    let root = map.flush().unwrap();
+    let total_bytes = store.bytes_stored();
+    let base = store.snapshot();
+
+    let keys: Vec<usize> = (n..n + m).collect();
+    [false, true]
+        .into_iter()
+        .map(|batched| {
+            store.restore(&base);
+            let mut map: Hamt<_, String, usize, Sha256, BUCKET_SIZE> =
+                Hamt::load_with_bit_width(&root, &store, bit_width).unwrap();
+            let start = Instant::now();
+            for batch in keys.chunks(cmp::max(batch_size, 1)) {
+                let entries = batch.iter().map(|&key| (key, value.to_string()));
+                if batched {
+                    map.set_many(entries).unwrap();
+                } else {
+                    for (key, value) in entries {
+                        map.set(key, value).unwrap();
+                    }
+                }
+                map.flush().unwrap();
+            }
+            let micros = start.elapsed().as_micros() as u64;
+
+            BatchResult {
+                n,
+                m,
+                bucket_size: BUCKET_SIZE,
+                bit_width,
+                batch_size,
+                method: if batched { "set_many" } else { "set" },
+                byte_diff: store.bytes_stored() - total_bytes,
+                micros,
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,71 @@
+//! The `bitfield` experiment: bytes the bitfields take in the nodes, and the
+//! tree with bitfields in the compact encoding against the one without.
+
+use anyhow::Result;
+use fvm_ipld_hamt::{Hamt, Sha256};
+use serde::Serialize;
+
+use crate::experiments::ExperimentContext;
+use crate::memorydb::MemoryDB;
+use crate::stats;
+use crate::workload::{Key, Workload};
+
+#[derive(Debug, Serialize)]
+pub struct BitfieldResult {
+    n: usize,
+    bucket_size: usize,
+    bit_width: u32,
+    nodes: u64,
+    /// Bytes of the bitfields in the blocks, with their CBOR headers.
+    bitfield_bytes: u64,
+    bitfield_bytes_per_node: f64,
+    /// Of `total_bytes`.
+    bitfield_share: f64,
+    total_bytes: u64,
+    /// The same with bitfields in the compact encoding.
+    compact_bitfield_bytes: u64,
+    compact_bitfield_bytes_per_node: f64,
+    compact_total_bytes: u64,
+    /// Of `total_bytes`, negative if the compact tree is larger.
+    saved_share: f64,
+}
+
+/// Builds a HAMT of `n` keys of `workload` with bitfields as big endian
+/// bytes and one with compact bitfields, and measures what the bitfields
+/// take of each.
+pub fn bitfield_experiment<const BUCKET_SIZE: usize>(
+    ctx: &ExperimentContext,
+    bit_width: u32,
+    n: usize,
+    workload: &Workload,
+) -> Result<BitfieldResult> {
+    let build = |compact: bool| -> Result<(stats::Histogram, u64)> {
+        let store = MemoryDB::default();
+        let mut map: Hamt<_, _, Key, Sha256, BUCKET_SIZE> =
+            Hamt::new_with_bit_width(&store, bit_width);
+        map.compact_bitfields = compact;
+        let value = "F";
+        for key in workload.keys(n, &mut ctx.rng()) {
+            map.set(key, value.to_string())?;
+        }
+        map.flush()?;
+        Ok((stats::bitfield_sizes(&map)?, store.bytes_stored()))
+    };
+    let (dense, total_bytes) = build(false)?;
+    let (compact, compact_total_bytes) = build(true)?;
+    let nodes = dense.len();
+    Ok(BitfieldResult {
+        n,
+        bucket_size: BUCKET_SIZE,
+        bit_width,
+        nodes,
+        bitfield_bytes: dense.sum(),
+        bitfield_bytes_per_node: dense.sum() as f64 / nodes as f64,
+        bitfield_share: dense.sum() as f64 / total_bytes as f64,
+        total_bytes,
+        compact_bitfield_bytes: compact.sum(),
+        compact_bitfield_bytes_per_node: compact.sum() as f64 / nodes as f64,
+        compact_total_bytes,
+        saved_share: 1.0 - compact_total_bytes as f64 / total_bytes as f64,
+    })
+}
@@ -0,0 +1,88 @@
+//! The `cache` experiment: hits, misses, evictions and bytes read of `lookups`
+//! random lookups with node caches of different limits.
+
+use std::time::Instant;
+
+use anyhow::Result;
+use fvm_ipld_hamt::{Hamt, Sha256};
+use serde::Serialize;
+
+use crate::cache::CacheSize;
+use crate::experiments::ExperimentContext;
+use crate::memory;
+use crate::memorydb::MemoryDB;
+use crate::metered::MeteredStore;
+use crate::workload::{Key, Workload};
+
+#[derive(Debug, Serialize)]
+pub struct CacheResult {
+    n: usize,
+    bucket_size: usize,
+    bit_width: u32,
+    lookups: usize,
+    node_cache: String,
+    /// Links followed to cached nodes and to nodes loaded from the store.
+    hits: u64,
+    misses: u64,
+    evictions: u64,
+    hit_rate: f64,
+    /// Blocks and bytes read from the store, the root included.
+    blocks_read: u64,
+    bytes_read: u64,
+    /// Memory the HAMT takes after the last lookup.
+    memory_bytes: usize,
+    micros: u64,
+}
+
+/// Looks up `lookups` random keys of `workload` in a HAMT loaded with a node
+/// cache of `size`, trimming the cache after every lookup.
+pub fn cache_experiment<const BUCKET_SIZE: usize>(
+    ctx: &ExperimentContext,
+    bit_width: u32,
+    n: usize,
+    lookups: usize,
+    workload: &Workload,
+    size: CacheSize,
+) -> Result<CacheResult> {
+    let store = MemoryDB::default();
+    let mut map: Hamt<_, _, Key, Sha256, BUCKET_SIZE> = Hamt::new_with_bit_width(&store, bit_width);
+    let value = "F";
+
+    let mut rng = ctx.rng();
+    let keys = workload.keys(n, &mut rng);
+    for key in &keys {
+        map.set(key.clone(), value.to_string())?;
+    }
+    let root = map.flush()?;
+
+    let metered = MeteredStore::new(&store);
+    let mut map: Hamt<_, String, Key, Sha256, BUCKET_SIZE> =
+        Hamt::load_with_bit_width(&root, &metered, bit_width)?.with_node_cache(size.limit());
+    let sampler = workload.sampler(n);
+    let start = Instant::now();
+    for _ in 0..lookups {
+        if let Some(key) = keys.get(sampler.sample(&mut rng)) {
+            map.get(key)?;
+        }
+        map.trim_node_cache();
+    }
+    let micros = start.elapsed().as_micros() as u64;
+
+    let stats = map.node_cache_stats().expect("has a node cache");
+    let reads = metered.snapshot();
+    Ok(CacheResult {
+        n,
+        bucket_size: BUCKET_SIZE,
+        bit_width,
+        lookups,
+        node_cache: size.to_string(),
+        hits: stats.hits,
+        misses: stats.misses,
+        evictions: stats.evictions,
+        hit_rate: stats.hit_rate(),
+        blocks_read: reads.gets,
+        bytes_read: reads.bytes_read,
+        memory_bytes: memory::hamt_memory(&map),
+        micros,
+    })
+}
@@ -0,0 +1,106 @@
+//! The `chain` experiment: store, version and history bytes of successive
+//! versions kept in a root log, and the bytes replicating each from an older
+//! version takes.
+
+use anyhow::Result;
+use cid::multihash::Code;
+use cid::Cid;
+use fvm_ipld_encoding::CborStore;
+use fvm_ipld_hamt::{Hamt, Sha256};
+use serde::Serialize;
+
+use crate::experiments::ExperimentContext;
+use crate::memorydb::MemoryDB;
+use crate::workload::{Key, Workload};
+
+#[derive(Debug, Serialize)]
+pub struct ChainResult {
+    n: usize,
+    m: usize,
+    bucket_size: usize,
+    bit_width: u32,
+    version: usize,
+    /// Everything in the store, the root log included.
+    store_bytes: u64,
+    /// Bytes reachable from this version's root.
+    version_bytes: u64,
+    /// Bytes reachable from the head of the root log, every version so far.
+    history_bytes: u64,
+    /// Bytes a peer holding the previous version has to fetch.
+    sync_from_previous_bytes: u64,
+    /// Bytes a peer holding the first version has to fetch.
+    sync_from_first_bytes: u64,
+}
+
+/// An entry of the root log `chain_experiment` keeps in the store, linking
+/// the root of a version and the previous entry, like the commits of a
+/// versioned file system.
+#[derive(Serialize)]
+struct LogEntry {
+    version: u64,
+    root: Cid,
+    previous: Option<Cid>,
+}
+
+/// Flushes `versions` versions, each overwriting `m` random keys of the
+/// previous one, and appends every root to a log stored next to them. Then
+/// measures the store, the newest version, the whole history and what
+/// replicating a version takes from an older one.
+pub fn chain_experiment<const BUCKET_SIZE: usize>(
+    ctx: &ExperimentContext,
+    bit_width: u32,
+    n: usize,
+    m: usize,
+    versions: usize,
+    workload: &Workload,
+) -> Result<Vec<ChainResult>> {
+    let store = MemoryDB::default();
+    let mut map: Hamt<_, _, Key, Sha256, BUCKET_SIZE> = Hamt::new_with_bit_width(&store, bit_width);
+    let value = "F";
+
+    let mut rng = ctx.rng();
+    let keys = workload.keys(n, &mut rng);
+    for key in &keys {
+        map.set(key.clone(), value.to_string())?;
+    }
+    let sampler = workload.sampler(n);
+    let mut roots: Vec<Cid> = Vec::with_capacity(versions);
+    let mut head = None;
+    let mut rows = Vec::with_capacity(versions);
+    for version in 0..versions {
+        if version > 0 {
+            for _ in 0..m {
+                if let Some(key) = keys.get(sampler.sample(&mut rng)) {
+                    map.set(key.clone(), version.to_string())?;
+                }
+            }
+        }
+        let root = map.flush()?;
+        let entry = LogEntry {
+            version: version as u64,
+            root,
+            previous: head,
+        };
+        let log = store.put_cbor(&entry, Code::Blake2b256)?;
+        head = Some(log);
+
+        let sync_from = |from: Option<&Cid>| match from {
+            Some(from) => store.delta_bytes(&[*from], &[root]),
+            None => Ok(0),
+        };
+        rows.push(ChainResult {
+            n,
+            m,
+            bucket_size: BUCKET_SIZE,
+            bit_width,
+            version,
+            store_bytes: store.bytes_stored(),
+            version_bytes: store.live_bytes(&[root])?,
+            history_bytes: store.live_bytes(&[log])?,
+            sync_from_previous_bytes: sync_from(roots.last())?,
+            sync_from_first_bytes: sync_from(roots.first())?,
+        });
+        roots.push(root);
+    }
+    Ok(rows)
+}
@@ -0,0 +1,87 @@
+//! The `cids` and `codecs` experiments: node sizes and bytes changed by
+//! overwriting `m` keys, with blocks addressed by multihashes of different
+//! lengths or with nodes encoded as DAG-CBOR and as DAG-JSON.
+
+use std::cmp;
+
+use anyhow::Result;
+use cid::multihash::{Code, MultihashDigest};
+use fvm_ipld_encoding::DAG_CBOR;
+use fvm_ipld_hamt::dag_json::DAG_JSON;
+use fvm_ipld_hamt::{CidConfig, Hamt, Sha256};
+use serde::Serialize;
+
+use crate::experiments::ExperimentContext;
+use crate::map::map_sizes;
+use crate::memorydb::MemoryDB;
+use crate::metered::MeteredStore;
+use crate::verify::VerifyingStore;
+
+/// Multihashes compared by the `cids` experiment, by their multicodec
+/// names.
+pub const MULTIHASHES: &[(&str, Code)] = &[
+    ("blake2s-128", Code::Blake2s128),
+    ("sha2-256", Code::Sha2_256),
+    ("blake2b-256", Code::Blake2b256),
+    ("blake3", Code::Blake3_256),
+    ("sha2-512", Code::Sha2_512),
+    ("blake2b-512", Code::Blake2b512),
+];
+
+/// Block encodings compared by the `codecs` experiment.
+pub const CODECS: &[(&str, u64)] = &[("dag-cbor", DAG_CBOR), ("dag-json", DAG_JSON)];
+
+#[derive(Debug, Serialize)]
+pub struct CidResult {
+    n: usize,
+    m: usize,
+    bucket_size: usize,
+    bit_width: u32,
+    multihash: &'static str,
+    codec: &'static str,
+    digest_bytes: usize,
+    total_bytes: u64,
+    avg_node_bytes: f64,
+    max_node_bytes: usize,
+    byte_diff: u64,
+}
+
+/// The sizes [`experiment`](super::sizes::experiment) measures for the keys
+/// `0..n`, with blocks encoded and addressed by `cid_config`.
+pub fn cid_experiment<const BUCKET_SIZE: usize>(
+    ctx: &ExperimentContext,
+    bit_width: u32,
+    n: usize,
+    m: usize,
+    multihash: &'static str,
+    codec: &'static str,
+    cid_config: CidConfig,
+) -> Result<CidResult> {
+    let store = MeteredStore::new(MemoryDB::default());
+    let mut map: Hamt<_, _, usize, Sha256, BUCKET_SIZE> =
+        Hamt::new_with_bit_width(&store, bit_width).with_cid_config(cid_config);
+    let keys: Vec<usize> = (0..cmp::max(n, m)).collect();
+    let sizes = map_sizes(&mut map, &store, &keys, n, m)?;
+    if ctx.verify {
+        let root = map.flush()?;
+        let verifying = VerifyingStore::new(store.inner());
+        let map: Hamt<_, String, usize, Sha256, BUCKET_SIZE> =
+            Hamt::load_with_bit_width(&root, &verifying, bit_width)?;
+        for entry in map.iter() {
+            entry?;
+        }
+    }
+    Ok(CidResult {
+        n,
+        m,
+        bucket_size: BUCKET_SIZE,
+        bit_width,
+        multihash,
+        codec,
+        digest_bytes: cid_config.mh_code.digest(&[]).size() as usize,
+        total_bytes: sizes.total_bytes,
+        avg_node_bytes: sizes.avg_node_bytes,
+        max_node_bytes: sizes.max_node_bytes,
+        byte_diff: sizes.byte_difference,
+    })
+}
@@ -0,0 +1,71 @@
+//! The `collisions` experiment: bucket sizes and depth with hashes truncated to
+//! a few bits, so keys collide.
+
+use std::cmp;
+
+use fvm_ipld_hamt::{Hamt, HashAlgorithm};
+use serde::Serialize;
+
+use crate::experiments::ExperimentContext;
+use crate::memorydb::MemoryDB;
+use crate::stats;
+use crate::workload::{Key, Workload};
+
+#[derive(Debug, Serialize)]
+pub struct CollisionResult {
+    n: usize,
+    bucket_size: usize,
+    bit_width: u32,
+    /// Bits of the hash that are kept.
+    hash_bits: u32,
+    max_bucket: Option<usize>,
+    /// Buckets holding more than `bucket_size` colliding keys.
+    overflowing_buckets: u64,
+    /// Share of the keys in overflowing buckets.
+    overflowing_keys: f64,
+    mean_depth: f64,
+    max_depth: Option<usize>,
+    avg_node_bytes: f64,
+    max_node_bytes: usize,
+}
+
+/// Builds a HAMT of `n` keys of `workload` with the hash function `H`, which
+/// is usually too short to tell all keys apart.
+pub fn collision_experiment<const BUCKET_SIZE: usize, H: HashAlgorithm>(
+    ctx: &ExperimentContext,
+    bit_width: u32,
+    n: usize,
+    workload: &Workload,
+) -> CollisionResult {
+    let store = MemoryDB::default();
+    let mut map: Hamt<_, _, Key, H, BUCKET_SIZE> = Hamt::new_with_bit_width(&store, bit_width);
+    let value = "F";
+    for key in workload.keys(n, &mut ctx.rng()) {
+        map.set(key, value.to_string()).unwrap();
+    }
+    map.flush().unwrap();
+
+    let buckets = stats::bucket_sizes(&map).unwrap();
+    let (overflowing_buckets, overflowing_keys) = buckets
+        .counts()
+        .iter()
+        .enumerate()
+        .skip(BUCKET_SIZE + 1)
+        .fold((0, 0), |(buckets, keys), (size, &count)| {
+            (buckets + count, keys + size as u64 * count)
+        });
+    let depths = stats::key_depths(&map).unwrap();
+    CollisionResult {
+        n,
+        bucket_size: BUCKET_SIZE,
+        bit_width,
+        hash_bits: H::BITS,
+        max_bucket: buckets.max(),
+        overflowing_buckets,
+        overflowing_keys: overflowing_keys as f64 / cmp::max(n, 1) as f64,
+        mean_depth: depths.mean(),
+        max_depth: depths.max(),
+        avg_node_bytes: store.bytes_average(),
+        max_node_bytes: store.bytes_max(),
+    }
+}
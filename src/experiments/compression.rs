@@ -0,0 +1,58 @@
+//! The `compression` experiment: total and proof bytes of the keys `0..n` with
+//! and without compressing every block.
+
+use anyhow::Result;
+use fvm_ipld_hamt::{Hamt, Sha256};
+use serde::Serialize;
+
+use crate::compressed::{self, CompressedStore};
+use crate::memorydb::MemoryDB;
+use crate::proof;
+
+#[derive(Debug, Serialize)]
+pub struct CompressionResult {
+    n: usize,
+    bucket_size: usize,
+    bit_width: u32,
+    total_bytes: u64,
+    compressed_bytes: u64,
+    compression_ratio: f64,
+    /// Of key `0`.
+    proof_bytes: u64,
+    /// Of key `0`, with every block of the proof compressed on its own.
+    compressed_proof_bytes: u64,
+}
+
+/// Total and proof bytes of the keys `0..n`, with and without compressing
+/// every block.
+pub fn compression_experiment<const BUCKET_SIZE: usize>(
+    bit_width: u32,
+    n: usize,
+) -> Result<CompressionResult> {
+    let store = CompressedStore::new(MemoryDB::default());
+    let mut map: Hamt<_, _, usize, Sha256, BUCKET_SIZE> =
+        Hamt::new_with_bit_width(&store, bit_width);
+    for key in 0..n {
+        map.set(key, "F".to_string())?;
+    }
+    map.flush()?;
+    let total_bytes = store.uncompressed_bytes();
+    let compressed_bytes = store.inner().bytes_stored();
+
+    let proof = proof::generate_proof(&map, &0)?;
+    let compressed_proof_bytes = proof
+        .blocks()
+        .iter()
+        .map(|block| compressed::compress(block).len() as u64)
+        .sum();
+    Ok(CompressionResult {
+        n,
+        bucket_size: BUCKET_SIZE,
+        bit_width,
+        total_bytes,
+        compressed_bytes,
+        compression_ratio: total_bytes as f64 / compressed_bytes as f64,
+        proof_bytes: proof.bytes(),
+        compressed_proof_bytes,
+    })
+}
@@ -0,0 +1,73 @@
+//! The `degree` experiment: node degree averages.
+
+use fvm_ipld_hamt::{Hamt, Sha256};
+use serde::Serialize;
+
+use crate::experiments::ExperimentContext;
+use crate::memorydb::MemoryDB;
+use crate::stats;
+use crate::workload::{Key, Workload};
+
+#[derive(Debug, Serialize)]
+pub struct DegreeResult {
+    n: usize,
+    bucket_size: usize,
+    bit_width: u32,
+    nodes: u64,
+    links: u64,
+    min_degree: Option<usize>,
+    median_degree: Option<usize>,
+    p90_degree: Option<usize>,
+    max_degree: Option<usize>,
+    values: u64,
+    links_per_node: f64,
+    values_per_node: f64,
+}
+
+pub fn degree_experiment<const BUCKET_SIZE: usize>(
+    ctx: &ExperimentContext,
+    bit_width: u32,
+    n: usize,
+    workload: &Workload,
+) -> DegreeResult {
+    let store = MemoryDB::default();
+    let mut map: Hamt<_, _, Key, Sha256, BUCKET_SIZE> = Hamt::new_with_bit_width(&store, bit_width);
+    let value = "F";
+
+    for key in workload.keys(n, &mut ctx.rng()) {
+        map.set(key, value.to_string()).unwrap();
+    }
+
+    let stats = stats::stats_parallel(&map.into_view()).unwrap();
+    DegreeResult {
+        n,
+        bucket_size: BUCKET_SIZE,
+        bit_width,
+        nodes: stats.nodes,
+        links: stats.links,
+        min_degree: stats.min_degree(),
+        median_degree: stats.degree_percentile(50.0),
+        p90_degree: stats.degree_percentile(90.0),
+        max_degree: stats.max_degree(),
+        values: stats.values,
+        links_per_node: stats.links_per_node(),
+        values_per_node: stats.values_per_node(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BUCKET_SIZE;
+
+    #[test]
+    fn experiment_avg_node_degree() {
+        let result = degree_experiment::<BUCKET_SIZE>(
+            &ExperimentContext::default(),
+            4,
+            100_000,
+            &Workload::Sequential,
+        );
+        println!("{:#?}", result);
+    }
+}
@@ -0,0 +1,94 @@
+//! The `delete` experiment: bytes written and nodes removed by deleting `m`
+//! keys.
+
+use std::cmp;
+
+use anyhow::Result;
+use fvm_ipld_hamt::{Hamt, Sha256};
+use serde::Serialize;
+
+use crate::experiments::ExperimentContext;
+use crate::map::IpldMap;
+use crate::memorydb::MemoryDB;
+
+#[derive(Debug, Serialize)]
+pub struct DeletionResult {
+    n: usize,
+    m: usize,
+    bucket_size: usize,
+    bit_width: u32,
+    batch_size: usize,
+    total_bytes: u64,
+    byte_diff: u64,
+    nodes_before: u64,
+    nodes_after: u64,
+}
+
+pub fn deletion_experiment<const BUCKET_SIZE: usize>(
+    ctx: &ExperimentContext,
+    bit_width: u32,
+    n: usize,
+    m: usize,
+    batch_size: usize,
+) -> DeletionResult {
+    let store = MemoryDB::default();
+    let mut map: Hamt<_, _, usize, Sha256, BUCKET_SIZE> =
+        Hamt::new_with_bit_width(&store, bit_width);
+    let keys: Vec<usize> = (0..n).collect();
+    let stats = delete_in_batches(ctx, &mut map, &store, &keys, m, batch_size).unwrap();
+    DeletionResult {
+        n,
+        m,
+        bucket_size: BUCKET_SIZE,
+        bit_width,
+        batch_size,
+        total_bytes: stats.total_bytes,
+        byte_diff: stats.byte_diff,
+        nodes_before: stats.nodes_before,
+        nodes_after: stats.nodes_after,
+    }
+}
+
+pub(crate) struct DeletionStats {
+    pub(crate) total_bytes: u64,
+    pub(crate) byte_diff: u64,
+    pub(crate) nodes_before: u64,
+    pub(crate) nodes_after: u64,
+}
+
+/// Sets `keys`, then deletes `m` random ones in batches of `batch_size`,
+/// flushing after every batch, to see how well deletes collapse the tree
+/// again.
+pub(crate) fn delete_in_batches<K: Clone, M: IpldMap<K, String>>(
+    ctx: &ExperimentContext,
+    map: &mut M,
+    store: &MemoryDB,
+    keys: &[K],
+    m: usize,
+    batch_size: usize,
+) -> Result<DeletionStats> {
+    for key in keys {
+        map.set(key.clone(), "F".to_string())?;
+    }
+    map.flush()?;
+
+    let total_bytes = store.bytes_stored();
+    let nodes_before = map.stats()?.nodes;
+
+    let mut keys = keys.to_vec();
+    ctx.rng().shuffle(&mut keys);
+
+    for batch in keys[..cmp::min(m, keys.len())].chunks(cmp::max(batch_size, 1)) {
+        for key in batch {
+            map.delete(key)?;
+        }
+        map.flush()?;
+    }
+
+    Ok(DeletionStats {
+        total_bytes,
+        byte_diff: store.bytes_stored() - total_bytes,
+        nodes_before,
+        nodes_after: map.stats()?.nodes,
+    })
+}
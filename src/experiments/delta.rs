@@ -0,0 +1,79 @@
+//! The `delta` experiment: blocks and bytes a peer holding a version has to
+//! fetch to get one with 1, 2, 4, ... up to `m` keys changed.
+
+use std::{cmp, iter};
+
+use anyhow::Result;
+use fvm_ipld_hamt::{Hamt, Sha256};
+use serde::Serialize;
+
+use crate::experiments::ExperimentContext;
+use crate::memorydb::MemoryDB;
+use crate::sync;
+use crate::workload::{Key, Workload};
+
+#[derive(Debug, Serialize)]
+pub struct DeltaResult {
+    n: usize,
+    bucket_size: usize,
+    bit_width: u32,
+    changed_keys: usize,
+    /// Blocks of the new version missing from the old one, see
+    /// [`sync::sync_delta`].
+    delta_blocks: u64,
+    delta_bytes: u64,
+    bytes_per_key: f64,
+}
+
+/// Flushes `n` keys of `workload`, then for 1, 2, 4, ... up to `m` keys
+/// changes that many random keys of this first version and measures what a
+/// peer holding it has to fetch to get the changed one.
+pub fn delta_experiment<const BUCKET_SIZE: usize>(
+    ctx: &ExperimentContext,
+    bit_width: u32,
+    n: usize,
+    m: usize,
+    workload: &Workload,
+) -> Result<Vec<DeltaResult>> {
+    let store = MemoryDB::default();
+    let mut map: Hamt<_, _, Key, Sha256, BUCKET_SIZE> = Hamt::new_with_bit_width(&store, bit_width);
+    let value = "F";
+
+    let mut rng = ctx.rng();
+    let mut keys = workload.keys(n, &mut rng);
+    for key in &keys {
+        map.set(key.clone(), value.to_string())?;
+    }
+    let base = map.flush()?;
+    // Every count changes the keys of the smaller ones and some more.
+    rng.shuffle(&mut keys);
+
+    let m = cmp::min(m, keys.len());
+    let mut counts: Vec<usize> = iter::successors(Some(1), |count| Some(count * 2))
+        .take_while(|&count| count < m)
+        .collect();
+    if m > 0 {
+        counts.push(m);
+    }
+
+    let mut rows = Vec::with_capacity(counts.len());
+    for changed_keys in counts {
+        let mut map: Hamt<_, String, Key, Sha256, BUCKET_SIZE> =
+            Hamt::load_with_bit_width(&base, &store, bit_width)?;
+        for key in &keys[..changed_keys] {
+            map.set(key.clone(), "G".to_string())?;
+        }
+        let root = map.flush()?;
+        let (delta_blocks, delta_bytes) = sync::delta_size(&store, &base, &root)?;
+        rows.push(DeltaResult {
+            n,
+            bucket_size: BUCKET_SIZE,
+            bit_width,
+            changed_keys,
+            delta_blocks,
+            delta_bytes,
+            bytes_per_key: delta_bytes as f64 / changed_keys as f64,
+        });
+    }
+    Ok(rows)
+}
@@ -0,0 +1,51 @@
+//! The `depth` experiment: distribution of the depths keys are stored at.
+
+use fvm_ipld_hamt::{Hamt, Sha256};
+use serde::Serialize;
+
+use crate::experiments::ExperimentContext;
+use crate::memorydb::MemoryDB;
+use crate::stats;
+use crate::workload::{Key, Workload};
+
+#[derive(Debug, Serialize)]
+pub struct DepthResult {
+    n: usize,
+    bucket_size: usize,
+    bit_width: u32,
+    pub mean_depth: f64,
+    median_depth: Option<usize>,
+    p99_depth: Option<usize>,
+    max_depth: Option<usize>,
+    /// Number of keys at each depth, starting at the root.
+    keys_per_depth: stats::Histogram,
+}
+
+pub fn depth_experiment<const BUCKET_SIZE: usize>(
+    ctx: &ExperimentContext,
+    bit_width: u32,
+    n: usize,
+    workload: &Workload,
+) -> DepthResult {
+    let store = MemoryDB::default();
+    let mut map: Hamt<_, _, Key, Sha256, BUCKET_SIZE> = Hamt::new_with_bit_width(&store, bit_width);
+    let value = "F";
+
+    for key in workload.keys(n, &mut ctx.rng()) {
+        map.set(key, value.to_string()).unwrap();
+    }
+    map.flush().unwrap();
+
+    let depths = stats::key_depths(&map).unwrap();
+
+    DepthResult {
+        n,
+        bucket_size: BUCKET_SIZE,
+        bit_width,
+        mean_depth: depths.mean(),
+        median_depth: depths.median(),
+        p99_depth: depths.percentile(99.0),
+        max_depth: depths.max(),
+        keys_per_depth: depths,
+    }
+}
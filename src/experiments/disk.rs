@@ -0,0 +1,66 @@
+//! The `disk` experiment: write and lookup times with blocks stored as files on
+//! disk.
+
+use std::cmp;
+use std::time::Instant;
+
+use fvm_ipld_hamt::{Hamt, Sha256};
+use serde::Serialize;
+
+use crate::experiments::ExperimentContext;
+use crate::filestore::FileStore;
+
+#[derive(Debug, Serialize)]
+pub struct DiskResult {
+    n: usize,
+    bucket_size: usize,
+    bit_width: u32,
+    blocks: usize,
+    total_bytes: u64,
+    /// Time to insert all entries and flush them.
+    write_micros: u64,
+    lookups: usize,
+    /// Time per lookup of a random key in a freshly loaded HAMT.
+    avg_lookup_micros: f64,
+}
+
+pub fn disk_experiment<const BUCKET_SIZE: usize>(
+    ctx: &ExperimentContext,
+    store: &FileStore,
+    bit_width: u32,
+    n: usize,
+    lookups: usize,
+) -> DiskResult {
+    let value = "F";
+
+    let start = Instant::now();
+    let mut map: Hamt<_, _, usize, Sha256, BUCKET_SIZE> =
+        Hamt::new_with_bit_width(store, bit_width);
+    for key in 0..n {
+        map.set(key, value.to_string()).unwrap();
+    }
+    let root = map.flush().unwrap();
+    let write_micros = start.elapsed().as_micros() as u64;
+
+    let mut rng = ctx.rng();
+    let reader = ctx.verifying(store);
+    let start = Instant::now();
+    for _ in 0..lookups {
+        let key = rng.below(cmp::max(n, 1) as u64) as usize;
+        let map: Hamt<_, String, usize, Sha256, BUCKET_SIZE> =
+            Hamt::load_with_bit_width(&root, &reader, bit_width).unwrap();
+        map.get(&key).unwrap();
+    }
+    let lookup_micros = start.elapsed().as_micros() as u64;
+
+    DiskResult {
+        n,
+        bucket_size: BUCKET_SIZE,
+        bit_width,
+        blocks: store.blocks().unwrap(),
+        total_bytes: store.bytes_stored().unwrap(),
+        write_micros,
+        lookups,
+        avg_lookup_micros: lookup_micros as f64 / lookups as f64,
+    }
+}
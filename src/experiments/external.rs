@@ -0,0 +1,109 @@
+//! The `external` experiment: node bytes and update amplification by value
+//! size, with and without storing values above `--value-threshold` as blocks of
+//! their own.
+
+use std::cmp;
+
+use cid::multihash::Code;
+use fvm_ipld_encoding::{to_vec, CborStore};
+use fvm_ipld_hamt::{Hamt, Sha256};
+use serde::Serialize;
+
+use crate::experiments::ExperimentContext;
+use crate::memorydb::MemoryDB;
+use crate::workload::ValueSizes;
+
+#[derive(Debug, Serialize)]
+pub struct ExternalValueResult {
+    n: usize,
+    m: usize,
+    bucket_size: usize,
+    bit_width: u32,
+    value_size: String,
+    value_threshold: usize,
+    /// Share of the values stored as blocks of their own.
+    external_values: f64,
+    inline_total_bytes: u64,
+    inline_avg_node_bytes: f64,
+    /// Bytes written overwriting `m` keys per byte of new values.
+    inline_update_amplification: f64,
+    /// Including the value blocks.
+    external_total_bytes: u64,
+    /// Excluding the value blocks.
+    external_avg_node_bytes: f64,
+    external_update_amplification: f64,
+}
+
+/// Compares a HAMT keeping all values of `sizes` in its buckets with one
+/// storing values that encode to more than `threshold` bytes as blocks of
+/// their own: the size of the nodes, and how many bytes overwriting `m` keys
+/// writes relative to the size of the new values.
+pub fn external_value_experiment<const BUCKET_SIZE: usize>(
+    ctx: &ExperimentContext,
+    bit_width: u32,
+    n: usize,
+    m: usize,
+    threshold: usize,
+    sizes: ValueSizes,
+) -> ExternalValueResult {
+    let mut rng = ctx.rng();
+    let values: Vec<String> = (0..n).map(|_| sizes.value(&mut rng)).collect();
+    let updates: Vec<String> = (0..m).map(|_| sizes.value(&mut rng)).collect();
+    let update_bytes: usize = updates.iter().map(String::len).sum();
+    // The value blocks the threshold produces, to tell them apart from nodes.
+    let value_blocks = MemoryDB::default();
+    for value in &values {
+        if to_vec(value).unwrap().len() > threshold {
+            value_blocks.put_cbor(value, Code::Blake2b256).unwrap();
+        }
+    }
+
+    let measure = |threshold: Option<usize>| {
+        let store = MemoryDB::default();
+        let mut map: Hamt<_, String, usize, Sha256, BUCKET_SIZE> =
+            Hamt::new_with_bit_width(&store, bit_width);
+        map.value_threshold = threshold;
+        for (key, value) in values.iter().enumerate() {
+            map.set(key, value.clone()).unwrap();
+        }
+        map.flush().unwrap();
+        let (blocks, total_bytes) = map.par_reachable_size().unwrap();
+        let (node_bytes, nodes) = match threshold {
+            Some(_) => (
+                total_bytes - value_blocks.bytes_stored(),
+                blocks - value_blocks.blocks() as u64,
+            ),
+            None => (total_bytes, blocks),
+        };
+
+        for (key, value) in updates.iter().enumerate() {
+            map.set(key, value.clone()).unwrap();
+        }
+        map.flush().unwrap();
+        let written = store.bytes_stored() - total_bytes;
+        (
+            total_bytes,
+            node_bytes as f64 / nodes as f64,
+            written as f64 / update_bytes as f64,
+        )
+    };
+    let (inline_total_bytes, inline_avg_node_bytes, inline_update_amplification) = measure(None);
+    let (external_total_bytes, external_avg_node_bytes, external_update_amplification) =
+        measure(Some(threshold));
+
+    ExternalValueResult {
+        n,
+        m,
+        bucket_size: BUCKET_SIZE,
+        bit_width,
+        value_size: sizes.to_string(),
+        value_threshold: threshold,
+        external_values: value_blocks.blocks() as f64 / cmp::max(n, 1) as f64,
+        inline_total_bytes,
+        inline_avg_node_bytes,
+        inline_update_amplification,
+        external_total_bytes,
+        external_avg_node_bytes,
+        external_update_amplification,
+    }
+}
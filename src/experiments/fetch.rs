@@ -0,0 +1,92 @@
+//! The `fetch` experiment: rounds of requests and bytes per round fetching the
+//! whole HAMT, and a single key, from a peer that only sends blocks by CID.
+
+use anyhow::Result;
+use fvm_ipld_hamt::{Hamt, Sha256};
+use serde::Serialize;
+
+use crate::experiments::ExperimentContext;
+use crate::fetch::{self, Round, RoundCounter};
+use crate::memorydb::MemoryDB;
+use crate::workload::{Key, Workload};
+
+#[derive(Debug, Serialize)]
+pub struct FetchResult {
+    n: usize,
+    bucket_size: usize,
+    bit_width: u32,
+    /// `full` for everything below the root, `key` for a single key.
+    fetch: &'static str,
+    round: usize,
+    /// Fetches that took this many rounds or more.
+    fetches: usize,
+    avg_blocks: f64,
+    avg_bytes: f64,
+}
+
+/// Fetches a HAMT of `n` keys of `workload` from a peer that only answers
+/// requests for known CIDs, once entirely and once per key for `lookups`
+/// random keys, and measures the blocks and bytes received per round.
+pub fn fetch_experiment<const BUCKET_SIZE: usize>(
+    ctx: &ExperimentContext,
+    bit_width: u32,
+    n: usize,
+    lookups: usize,
+    workload: &Workload,
+) -> Result<Vec<FetchResult>> {
+    let store = MemoryDB::default();
+    let mut map: Hamt<_, _, Key, Sha256, BUCKET_SIZE> = Hamt::new_with_bit_width(&store, bit_width);
+    let value = "F";
+
+    let mut rng = ctx.rng();
+    let keys = workload.keys(n, &mut rng);
+    for key in &keys {
+        map.set(key.clone(), value.to_string())?;
+    }
+    let root = map.flush()?;
+
+    let row = |fetch, round, fetches, totals: Round| FetchResult {
+        n,
+        bucket_size: BUCKET_SIZE,
+        bit_width,
+        fetch,
+        round,
+        fetches,
+        avg_blocks: totals.blocks as f64 / fetches as f64,
+        avg_bytes: totals.bytes as f64 / fetches as f64,
+    };
+    let reader = ctx.verifying(&store);
+    let mut rows: Vec<FetchResult> = fetch::fetch_all(&reader, &root)?
+        .into_iter()
+        .enumerate()
+        .map(|(round, totals)| row("full", round, 1, totals))
+        .collect();
+
+    let sampler = workload.sampler(n);
+    let remote = RoundCounter::new(&reader);
+    // Fetches reaching each round and their blocks and bytes in it.
+    let mut key_rounds: Vec<(usize, Round)> = Vec::new();
+    for _ in 0..lookups {
+        let map: Hamt<_, String, Key, Sha256, BUCKET_SIZE> =
+            Hamt::load_with_bit_width(&root, &remote, bit_width)?;
+        if let Some(key) = keys.get(sampler.sample(&mut rng)) {
+            map.get(key)?;
+        }
+        for (i, round) in remote.take_rounds().into_iter().enumerate() {
+            if key_rounds.len() <= i {
+                key_rounds.push((0, Round::default()));
+            }
+            let (fetches, totals) = &mut key_rounds[i];
+            *fetches += 1;
+            totals.blocks += round.blocks;
+            totals.bytes += round.bytes;
+        }
+    }
+    rows.extend(
+        key_rounds
+            .into_iter()
+            .enumerate()
+            .map(|(round, (fetches, totals))| row("key", round, fetches, totals)),
+    );
+    Ok(rows)
+}
@@ -0,0 +1,90 @@
+//! The `flush` experiment: flushes and bytes written overwriting `m` keys with
+//! different policies of when to flush.
+
+use anyhow::Result;
+use fvm_ipld_encoding::to_vec;
+use fvm_ipld_hamt::{Hamt, Sha256};
+use serde::Serialize;
+
+use crate::experiments::ExperimentContext;
+use crate::flush::FlushPolicy;
+use crate::memorydb::MemoryDB;
+use crate::metered::MeteredStore;
+use crate::workload::{Key, Workload};
+
+#[derive(Debug, Serialize)]
+pub struct FlushResult {
+    n: usize,
+    m: usize,
+    bucket_size: usize,
+    bit_width: u32,
+    flush_policy: String,
+    flushes: usize,
+    nodes_written: u64,
+    bytes_written: u64,
+    /// Growth of the store.
+    new_bytes: u64,
+    /// `bytes_written` per byte of the updated entries.
+    write_amplification: f64,
+}
+
+/// Overwrites `m` random keys of `workload`, flushing as `policy` says and
+/// once more at the end, and measures what the flushes write.
+pub fn flush_experiment<const BUCKET_SIZE: usize>(
+    ctx: &ExperimentContext,
+    bit_width: u32,
+    n: usize,
+    m: usize,
+    workload: &Workload,
+    policy: FlushPolicy,
+) -> Result<FlushResult> {
+    let store = MeteredStore::new(MemoryDB::default());
+    let mut map: Hamt<_, _, Key, Sha256, BUCKET_SIZE> = Hamt::new_with_bit_width(&store, bit_width);
+    let value = "F";
+
+    let mut rng = ctx.rng();
+    let keys = workload.keys(n, &mut rng);
+    for key in &keys {
+        map.set(key.clone(), value.to_string())?;
+    }
+    map.flush()?;
+    let stored_bytes = store.inner().bytes_stored();
+    let before = store.snapshot();
+
+    let sampler = workload.sampler(n);
+    let mut flusher = policy.flusher();
+    let mut flushes = 0;
+    let mut entry_bytes = 0;
+    for op in 0..m {
+        let key = match keys.get(sampler.sample(&mut rng)) {
+            Some(key) => key,
+            None => break,
+        };
+        let value = op.to_string();
+        let bytes = to_vec(&(key, &value))?.len() as u64;
+        entry_bytes += bytes;
+        map.set(key.clone(), value)?;
+        if flusher.record(bytes) {
+            map.flush()?;
+            flushes += 1;
+        }
+    }
+    if flusher.is_dirty() {
+        map.flush()?;
+        flushes += 1;
+    }
+    let traffic = store.snapshot() - before;
+
+    Ok(FlushResult {
+        n,
+        m,
+        bucket_size: BUCKET_SIZE,
+        bit_width,
+        flush_policy: policy.to_string(),
+        flushes,
+        nodes_written: traffic.puts,
+        bytes_written: traffic.bytes_written,
+        new_bytes: store.inner().bytes_stored() - stored_bytes,
+        write_amplification: traffic.bytes_written as f64 / entry_bytes as f64,
+    })
+}
@@ -0,0 +1,57 @@
+//! The `gc` experiment: live vs. garbage bytes after overwriting `m` keys.
+
+use fvm_ipld_hamt::{Hamt, Sha256};
+use serde::Serialize;
+
+use crate::memorydb::MemoryDB;
+
+#[derive(Debug, Serialize)]
+pub struct GcResult {
+    n: usize,
+    m: usize,
+    bucket_size: usize,
+    bit_width: u32,
+    total_bytes: u64,
+    byte_diff: u64,
+    live_bytes: u64,
+    garbage_bytes: u64,
+}
+
+/// Like [`experiment`](super::sizes::experiment), but splits the bytes in the
+/// store after overwriting `m` keys into blocks still reachable from the new
+/// root and garbage left behind by the old version.
+pub fn gc_experiment<const BUCKET_SIZE: usize>(bit_width: u32, n: usize, m: usize) -> GcResult {
+    let store = MemoryDB::default();
+    let mut map: Hamt<_, _, usize, Sha256, BUCKET_SIZE> =
+        Hamt::new_with_bit_width(&store, bit_width);
+    let value = "F";
+
+    for key in 0..n {
+        map.set(key, value.to_string()).unwrap();
+    }
+    map.flush().unwrap();
+    let total_bytes = store.bytes_stored();
+
+    let value_after = ".";
+
+    for key in 0..m {
+        map.set(key, value_after.to_string()).unwrap();
+    }
+    let root = map.flush().unwrap();
+    let byte_diff = store.bytes_stored() - total_bytes;
+
+    let live_bytes = store.live_bytes(&[root]).unwrap();
+    let garbage_bytes = store.gc(&[root]).unwrap();
+    debug_assert_eq!(store.bytes_stored(), live_bytes);
+
+    GcResult {
+        n,
+        m,
+        bucket_size: BUCKET_SIZE,
+        bit_width,
+        total_bytes,
+        byte_diff,
+        live_bytes,
+        garbage_bytes,
+    }
+}
@@ -0,0 +1,93 @@
+//! The `hashonly` experiment: the `sizes` measurements storing the keys, and
+//! storing only their hashes, truncated to 32, 16, 8 and 4 bytes.
+
+use std::cmp;
+use std::collections::HashSet;
+
+use anyhow::Result;
+use fvm_ipld_encoding::de::DeserializeOwned;
+use fvm_ipld_encoding::to_vec;
+use fvm_ipld_hamt::{BytesKey, Hamt, Hash, HashAlgorithm, HashOnly, Sha256};
+use serde::Serialize;
+
+use crate::experiments::ExperimentContext;
+use crate::map::map_sizes;
+use crate::memorydb::MemoryDB;
+use crate::metered::MeteredStore;
+use crate::workload::{Key, Workload};
+
+#[derive(Debug, Serialize)]
+pub struct HashOnlyResult {
+    n: usize,
+    m: usize,
+    bucket_size: usize,
+    bit_width: u32,
+    /// `full` for the original keys, `hash:<bytes>` for that many bytes of
+    /// their SHA-256 hash.
+    key_storage: String,
+    total_bytes: u64,
+    avg_node_bytes: f64,
+    max_node_bytes: usize,
+    #[serde(rename = "byte_diff")]
+    byte_difference: u64,
+    /// Original keys that became the same stored key.
+    collisions: usize,
+}
+
+/// `Sizes` of a HAMT of `n` keys of `workload`, once storing the keys and
+/// once storing only their hashes, truncated to fewer and fewer bytes.
+pub fn hash_only_experiment<const BUCKET_SIZE: usize>(
+    ctx: &ExperimentContext,
+    bit_width: u32,
+    n: usize,
+    m: usize,
+    workload: &Workload,
+) -> Result<Vec<HashOnlyResult>> {
+    // Overwriting more than `n` keys inserts new ones.
+    let keys = workload.keys(cmp::max(n, m), &mut ctx.rng());
+
+    fn sizes<K, H, const BUCKET_SIZE: usize>(
+        bit_width: u32,
+        n: usize,
+        m: usize,
+        key_storage: String,
+        keys: &[K],
+    ) -> Result<HashOnlyResult>
+    where
+        K: Hash + Eq + PartialOrd + Clone + Serialize + DeserializeOwned,
+        H: HashAlgorithm,
+    {
+        let distinct: HashSet<Vec<u8>> = keys[..n].iter().map(to_vec).collect::<Result<_, _>>()?;
+        let store = MeteredStore::new(MemoryDB::default());
+        let mut map: Hamt<_, _, K, H, BUCKET_SIZE> = Hamt::new_with_bit_width(&store, bit_width);
+        let sizes = map_sizes(&mut map, &store, keys, n, m)?;
+        Ok(HashOnlyResult {
+            n,
+            m,
+            bucket_size: BUCKET_SIZE,
+            bit_width,
+            key_storage,
+            total_bytes: sizes.total_bytes,
+            avg_node_bytes: sizes.avg_node_bytes,
+            max_node_bytes: sizes.max_node_bytes,
+            byte_difference: sizes.byte_difference,
+            collisions: n - distinct.len(),
+        })
+    }
+
+    macro_rules! hash_only {
+        ($($bytes:literal),*) => {
+            vec![
+                sizes::<Key, Sha256, BUCKET_SIZE>(bit_width, n, m, "full".to_string(), &keys)?,
+                $(sizes::<BytesKey, HashOnly<Sha256, $bytes>, BUCKET_SIZE>(
+                    bit_width,
+                    n,
+                    m,
+                    format!("hash:{}", $bytes),
+                    &keys.iter().map(HashOnly::<Sha256, $bytes>::key).collect::<Vec<_>>(),
+                )?,)*
+            ]
+        };
+    }
+    Ok(hash_only!(32, 16, 8, 4))
+}
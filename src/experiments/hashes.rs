@@ -0,0 +1,76 @@
+//! The `hashes` experiment: tree shape and build time with each of the
+//! supported hash functions.
+
+use std::time::Instant;
+
+use fvm_ipld_hamt::{Hamt, HashAlgorithm};
+use serde::Serialize;
+
+use crate::experiments::ExperimentContext;
+use crate::memorydb::MemoryDB;
+use crate::stats;
+use crate::stats::TreeStats;
+use crate::workload::{Key, Workload};
+
+#[derive(Debug, Serialize)]
+pub struct HashResult {
+    n: usize,
+    bucket_size: usize,
+    bit_width: u32,
+    hash: &'static str,
+    /// Time to hash every key once.
+    hash_micros: u64,
+    /// Time to insert all entries and flush them.
+    build_micros: u64,
+    nodes: u64,
+    total_bytes: u64,
+    avg_node_bytes: f64,
+    links_per_node: f64,
+    mean_depth: f64,
+    max_depth: Option<usize>,
+}
+
+/// Builds a HAMT of `n` keys of `workload` hashed with `H`. Structurally all
+/// hash functions should look alike, unless they distribute the keys badly.
+pub fn hash_experiment<const BUCKET_SIZE: usize, H: HashAlgorithm>(
+    ctx: &ExperimentContext,
+    name: &'static str,
+    bit_width: u32,
+    n: usize,
+    workload: &Workload,
+) -> HashResult {
+    let keys = workload.keys(n, &mut ctx.rng());
+    let value = "F";
+
+    let start = Instant::now();
+    for key in &keys {
+        std::hint::black_box(H::hash(key));
+    }
+    let hash_micros = start.elapsed().as_micros() as u64;
+
+    let store = MemoryDB::default();
+    let start = Instant::now();
+    let mut map: Hamt<_, _, Key, H, BUCKET_SIZE> = Hamt::new_with_bit_width(&store, bit_width);
+    for key in keys {
+        map.set(key, value.to_string()).unwrap();
+    }
+    map.flush().unwrap();
+    let build_micros = start.elapsed().as_micros() as u64;
+
+    let tree = TreeStats::new(&map).unwrap();
+    let depths = stats::key_depths(&map).unwrap();
+    HashResult {
+        n,
+        bucket_size: BUCKET_SIZE,
+        bit_width,
+        hash: name,
+        hash_micros,
+        build_micros,
+        nodes: tree.nodes,
+        total_bytes: store.bytes_stored(),
+        avg_node_bytes: store.bytes_average(),
+        links_per_node: tree.links_per_node(),
+        mean_depth: depths.mean(),
+        max_depth: depths.max(),
+    }
+}
@@ -0,0 +1,68 @@
+//! The `keys` experiment: node sizes and bytes stored per byte of keys and
+//! values by key length.
+
+use anyhow::Result;
+use fvm_ipld_hamt::{Hamt, Sha256};
+use serde::Serialize;
+
+use crate::experiments::ExperimentContext;
+use crate::memorydb::MemoryDB;
+
+#[derive(Debug, Serialize)]
+pub struct KeyLengthResult {
+    n: usize,
+    bucket_size: usize,
+    bit_width: u32,
+    key_length: usize,
+    total_bytes: u64,
+    avg_node_bytes: f64,
+    max_node_bytes: usize,
+    /// Bytes of all keys and values, without any encoding.
+    payload_bytes: u64,
+    /// `total_bytes` per byte of payload.
+    overhead_ratio: f64,
+    /// Part of `total_bytes` taken by the keys.
+    key_share: f64,
+}
+
+/// Inserts `n` random string keys of `key_length` bytes, like the name
+/// filters WNFS uses as keys, and measures how the nodes grow with them.
+pub fn key_length_experiment<const BUCKET_SIZE: usize>(
+    ctx: &ExperimentContext,
+    bit_width: u32,
+    n: usize,
+    key_length: usize,
+) -> Result<KeyLengthResult> {
+    let store = MemoryDB::default();
+    let mut map: Hamt<_, _, String, Sha256, BUCKET_SIZE> =
+        Hamt::new_with_bit_width(&store, bit_width);
+    let value = "F";
+
+    let mut rng = ctx.rng();
+    let mut key_bytes = 0;
+    for i in 0..n {
+        // The index keeps keys distinct, random digits fill up the rest.
+        let mut key = format!("{i:x}-");
+        while key.len() < key_length {
+            key.push(char::from_digit(rng.below(16) as u32, 16).unwrap());
+        }
+        key_bytes += key.len() as u64;
+        map.set(key, value.to_string())?;
+    }
+    map.flush()?;
+
+    let total_bytes = store.bytes_stored();
+    let payload_bytes = key_bytes + (n * value.len()) as u64;
+    Ok(KeyLengthResult {
+        n,
+        bucket_size: BUCKET_SIZE,
+        bit_width,
+        key_length,
+        total_bytes,
+        avg_node_bytes: store.bytes_average(),
+        max_node_bytes: store.bytes_max(),
+        payload_bytes,
+        overhead_ratio: total_bytes as f64 / payload_bytes as f64,
+        key_share: key_bytes as f64 / total_bytes as f64,
+    })
+}
@@ -0,0 +1,68 @@
+//! The `len` experiment: root and total bytes and bytes changed by overwriting
+//! `m` keys, with and without the number of entries in the root, and the time
+//! `len` takes on the loaded HAMT.
+
+use std::cmp;
+use std::time::Instant;
+
+use anyhow::Result;
+use fvm_ipld_blockstore::Blockstore;
+use fvm_ipld_hamt::{Hamt, Sha256};
+use serde::Serialize;
+
+use crate::map::map_sizes;
+use crate::memorydb::MemoryDB;
+use crate::metered::MeteredStore;
+
+#[derive(Debug, Serialize)]
+pub struct LenResult {
+    n: usize,
+    m: usize,
+    bucket_size: usize,
+    bit_width: u32,
+    len_in_root: bool,
+    root_bytes: usize,
+    total_bytes: u64,
+    byte_diff: u64,
+    /// Time `len` takes on the HAMT loaded from its root.
+    len_micros: u64,
+}
+
+/// The sizes [`experiment`](super::sizes::experiment) measures for the keys
+/// `0..n`, with or without the number of entries stored in the root, and the
+/// time it takes to get that number once the HAMT is loaded again.
+pub fn len_experiment<const BUCKET_SIZE: usize>(
+    bit_width: u32,
+    n: usize,
+    m: usize,
+    len_in_root: bool,
+) -> Result<LenResult> {
+    let store = MeteredStore::new(MemoryDB::default());
+    let mut map: Hamt<_, _, usize, Sha256, BUCKET_SIZE> =
+        Hamt::new_with_bit_width(&store, bit_width);
+    if len_in_root {
+        map = map.with_len_in_root();
+    }
+    let keys: Vec<usize> = (0..cmp::max(n, m)).collect();
+    let sizes = map_sizes(&mut map, &store, &keys, n, m)?;
+    let root = map.flush()?;
+    let root_bytes = store.inner().get(&root)?.map_or(0, |block| block.len());
+
+    let loaded: Hamt<_, String, usize, Sha256, BUCKET_SIZE> =
+        Hamt::load_with_bit_width(&root, store.inner(), bit_width)?;
+    let start = Instant::now();
+    let len = loaded.len()?;
+    let len_micros = start.elapsed().as_micros() as u64;
+    assert_eq!(len, n as u64);
+    Ok(LenResult {
+        n,
+        m,
+        bucket_size: BUCKET_SIZE,
+        bit_width,
+        len_in_root,
+        root_bytes,
+        total_bytes: sizes.total_bytes,
+        byte_diff: sizes.byte_difference,
+        len_micros,
+    })
+}
@@ -0,0 +1,45 @@
+//! The `levels` experiment: node count, bytes and fanout of every tree level.
+
+use fvm_ipld_hamt::{Hamt, Sha256};
+use serde::Serialize;
+
+use crate::experiments::ExperimentContext;
+use crate::memorydb::MemoryDB;
+use crate::stats;
+use crate::workload::{Key, Workload};
+
+#[derive(Debug, Serialize)]
+pub struct LevelResult {
+    n: usize,
+    bucket_size: usize,
+    bit_width: u32,
+    #[serde(flatten)]
+    level: stats::LevelSummary,
+}
+
+pub fn levels_experiment<const BUCKET_SIZE: usize>(
+    ctx: &ExperimentContext,
+    bit_width: u32,
+    n: usize,
+    workload: &Workload,
+) -> Vec<LevelResult> {
+    let store = MemoryDB::default();
+    let mut map: Hamt<_, _, Key, Sha256, BUCKET_SIZE> = Hamt::new_with_bit_width(&store, bit_width);
+    let value = "F";
+
+    for key in workload.keys(n, &mut ctx.rng()) {
+        map.set(key, value.to_string()).unwrap();
+    }
+    map.flush().unwrap();
+
+    stats::stats_per_level(&map)
+        .unwrap()
+        .into_iter()
+        .map(|level| LevelResult {
+            n,
+            bucket_size: BUCKET_SIZE,
+            bit_width,
+            level,
+        })
+        .collect()
+}
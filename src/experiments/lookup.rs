@@ -0,0 +1,84 @@
+//! The `lookup` experiment: bytes read from the store and simulated network
+//! time to look up a single key.
+
+use std::cmp;
+
+use fvm_ipld_blockstore::tracking::TrackingBlockstore;
+use fvm_ipld_hamt::{Hamt, Sha256};
+use serde::Serialize;
+
+use crate::delayed::{DelayedStore, Network};
+use crate::experiments::ExperimentContext;
+use crate::memorydb::MemoryDB;
+use crate::workload::{Key, Workload};
+
+#[derive(Debug, Serialize)]
+pub struct LookupResult {
+    n: usize,
+    bucket_size: usize,
+    bit_width: u32,
+    lookups: usize,
+    avg_bytes: f64,
+    min_bytes: usize,
+    max_bytes: usize,
+    avg_blocks: f64,
+    avg_network_millis: f64,
+}
+
+/// Measures how many bytes have to be fetched from the store to `get` a single
+/// random key from a freshly loaded HAMT, root block included, and how long
+/// that takes over `network`.
+pub fn lookup_experiment<const BUCKET_SIZE: usize>(
+    ctx: &ExperimentContext,
+    bit_width: u32,
+    n: usize,
+    lookups: usize,
+    workload: &Workload,
+    network: Network,
+) -> LookupResult {
+    let store = MemoryDB::default();
+    let mut map: Hamt<_, _, Key, Sha256, BUCKET_SIZE> = Hamt::new_with_bit_width(&store, bit_width);
+    let value = "F";
+
+    let mut rng = ctx.rng();
+    let keys = workload.keys(n, &mut rng);
+    for key in &keys {
+        map.set(key.clone(), value.to_string()).unwrap();
+    }
+    let root = map.flush().unwrap();
+
+    let sampler = workload.sampler(n);
+    let mut total_bytes = 0;
+    let mut total_blocks = 0;
+    let mut min_bytes = usize::MAX;
+    let mut max_bytes = 0;
+    let remote = DelayedStore::new(ctx.verifying(&store), network);
+
+    for _ in 0..lookups {
+        let key = keys.get(sampler.sample(&mut rng));
+        let tracking = TrackingBlockstore::new(&remote);
+        let map: Hamt<_, String, Key, Sha256, BUCKET_SIZE> =
+            Hamt::load_with_bit_width(&root, &tracking, bit_width).unwrap();
+        if let Some(key) = key {
+            map.get(key).unwrap();
+        }
+
+        let stats = *tracking.stats.borrow();
+        total_bytes += stats.br;
+        total_blocks += stats.r;
+        min_bytes = cmp::min(min_bytes, stats.br);
+        max_bytes = cmp::max(max_bytes, stats.br);
+    }
+
+    LookupResult {
+        n,
+        bucket_size: BUCKET_SIZE,
+        bit_width,
+        lookups,
+        avg_bytes: total_bytes as f64 / lookups as f64,
+        min_bytes: if lookups == 0 { 0 } else { min_bytes },
+        max_bytes,
+        avg_blocks: total_blocks as f64 / lookups as f64,
+        avg_network_millis: remote.elapsed().as_secs_f64() * 1000.0 / lookups as f64,
+    }
+}
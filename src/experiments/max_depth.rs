@@ -0,0 +1,92 @@
+//! The `maxdepth` experiment: sizes and lookup costs of HAMTs whose buckets
+//! stop splitting at a maximum depth, taking any number of entries there.
+
+use std::time::Instant;
+
+use anyhow::Result;
+use fvm_ipld_blockstore::tracking::TrackingBlockstore;
+use fvm_ipld_hamt::{Hamt, Sha256};
+use serde::Serialize;
+
+use crate::experiments::ExperimentContext;
+use crate::memorydb::MemoryDB;
+use crate::stats;
+use crate::workload::{Key, Workload};
+
+#[derive(Debug, Serialize)]
+pub struct MaxDepthResult {
+    n: usize,
+    bucket_size: usize,
+    bit_width: u32,
+    /// Depth below which buckets stop splitting, `None` for no limit.
+    max_depth: Option<u32>,
+    blocks: u64,
+    total_bytes: u64,
+    max_bucket: Option<usize>,
+    max_node_bytes: usize,
+    lookups: usize,
+    avg_lookup_blocks: f64,
+    avg_lookup_bytes: f64,
+    /// Time per lookup of a random key in a freshly loaded HAMT.
+    avg_lookup_micros: f64,
+}
+
+/// Inserts `n` keys of `workload` into a HAMT whose buckets at `max_depth`
+/// take every entry reaching them, and looks up `lookups` random keys in a
+/// freshly loaded copy: fewer levels to fetch, but larger blocks at the
+/// bottom.
+pub fn max_depth_experiment<const BUCKET_SIZE: usize>(
+    ctx: &ExperimentContext,
+    bit_width: u32,
+    n: usize,
+    lookups: usize,
+    workload: &Workload,
+    max_depth: Option<u32>,
+) -> Result<MaxDepthResult> {
+    let store = MemoryDB::default();
+    let mut map: Hamt<_, _, Key, Sha256, BUCKET_SIZE> = Hamt::new_with_bit_width(&store, bit_width);
+    map.max_depth = max_depth;
+    let value = "F";
+
+    let mut rng = ctx.rng();
+    let keys = workload.keys(n, &mut rng);
+    for key in &keys {
+        map.set(key.clone(), value.to_string())?;
+    }
+    let root = map.flush()?;
+    let (blocks, total_bytes) = map.reachable_size()?;
+    let buckets = stats::bucket_sizes(&map)?;
+
+    let sampler = workload.sampler(n);
+    let (mut lookup_blocks, mut lookup_bytes) = (0, 0);
+    let reader = ctx.verifying(&store);
+    let start = Instant::now();
+    for _ in 0..lookups {
+        let tracking = TrackingBlockstore::new(&reader);
+        let mut map: Hamt<_, String, Key, Sha256, BUCKET_SIZE> =
+            Hamt::load_with_bit_width(&root, &tracking, bit_width)?;
+        map.max_depth = max_depth;
+        if let Some(key) = keys.get(sampler.sample(&mut rng)) {
+            map.get(key)?;
+        }
+        let stats = *tracking.stats.borrow();
+        lookup_blocks += stats.r;
+        lookup_bytes += stats.br;
+    }
+    let elapsed = start.elapsed();
+
+    Ok(MaxDepthResult {
+        n,
+        bucket_size: BUCKET_SIZE,
+        bit_width,
+        max_depth,
+        blocks,
+        total_bytes,
+        max_bucket: buckets.max(),
+        max_node_bytes: store.bytes_max(),
+        lookups,
+        avg_lookup_blocks: lookup_blocks as f64 / lookups as f64,
+        avg_lookup_bytes: lookup_bytes as f64 / lookups as f64,
+        avg_lookup_micros: elapsed.as_secs_f64() * 1e6 / lookups as f64,
+    })
+}
@@ -0,0 +1,78 @@
+//! The `memory` experiment: memory the HAMT and its cached nodes take while
+//! building, and once loaded again before and after reading every entry.
+
+use std::cmp;
+
+use anyhow::Result;
+use fvm_ipld_hamt::{Hamt, Sha256};
+use serde::Serialize;
+
+use crate::experiments::ExperimentContext;
+use crate::memory;
+use crate::memorydb::MemoryDB;
+use crate::workload::{Key, Workload};
+
+#[derive(Debug, Serialize)]
+pub struct MemoryResult {
+    n: usize,
+    bucket_size: usize,
+    bit_width: u32,
+    /// `building` after every tenth of the entries, then `flushed`, `loaded`
+    /// from the root and `scanned` after iterating over the loaded HAMT.
+    stage: &'static str,
+    entries: usize,
+    memory_bytes: usize,
+    bytes_per_entry: f64,
+    /// Bytes in the store, for comparison, once flushed.
+    stored_bytes: u64,
+}
+
+/// Memory the HAMT takes while inserting `n` keys of `workload`, and after
+/// loading it again before and after every node was read once.
+pub fn memory_experiment<const BUCKET_SIZE: usize>(
+    ctx: &ExperimentContext,
+    bit_width: u32,
+    n: usize,
+    workload: &Workload,
+) -> Result<Vec<MemoryResult>> {
+    let store = MemoryDB::default();
+    let mut map: Hamt<_, _, Key, Sha256, BUCKET_SIZE> = Hamt::new_with_bit_width(&store, bit_width);
+    let value = "F";
+
+    let row = |stage, entries, memory_bytes, stored_bytes| MemoryResult {
+        n,
+        bucket_size: BUCKET_SIZE,
+        bit_width,
+        stage,
+        entries,
+        memory_bytes,
+        bytes_per_entry: memory_bytes as f64 / cmp::max(entries, 1) as f64,
+        stored_bytes,
+    };
+
+    let step = cmp::max(n / 10, 1);
+    let mut rows = Vec::new();
+    for (i, key) in workload.keys(n, &mut ctx.rng()).into_iter().enumerate() {
+        map.set(key, value.to_string())?;
+        if (i + 1) % step == 0 {
+            rows.push(row("building", i + 1, memory::hamt_memory(&map), 0));
+        }
+    }
+    let root = map.flush()?;
+    let stored_bytes = store.bytes_stored();
+    rows.push(row("flushed", n, memory::hamt_memory(&map), stored_bytes));
+
+    let loaded: Hamt<_, String, Key, Sha256, BUCKET_SIZE> =
+        Hamt::load_with_bit_width(&root, &store, bit_width)?;
+    rows.push(row("loaded", n, memory::hamt_memory(&loaded), stored_bytes));
+    for entry in loaded.iter() {
+        entry?;
+    }
+    rows.push(row(
+        "scanned",
+        n,
+        memory::hamt_memory(&loaded),
+        stored_bytes,
+    ));
+    Ok(rows)
+}
@@ -0,0 +1,127 @@
+//! The `migration` experiment: bytes written and blocks shared copying a HAMT
+//! into one of another bucket size, for every combination of the bucket sizes
+//! of source and target.
+
+use std::cmp;
+
+use anyhow::Result;
+use cid::Cid;
+use fvm_ipld_hamt::{Hamt, Sha256};
+use serde::Serialize;
+
+use crate::experiments::ExperimentContext;
+use crate::memorydb::MemoryDB;
+use crate::metered::MeteredStore;
+use crate::workload::{Key, Workload};
+
+/// The HAMT `migration_experiment` copies into, behind a trait object like
+/// the children of `nested_experiment`.
+pub trait MigrationTarget {
+    fn bucket_size(&self) -> usize;
+
+    /// Inserts `entries` as they come into a new HAMT, flushing after every
+    /// `batch_size` of them, and returns its final root.
+    fn migrate(
+        &self,
+        store: &MeteredStore<MemoryDB>,
+        entries: &mut dyn Iterator<Item = Result<(Key, String)>>,
+        batch_size: usize,
+    ) -> Result<Cid>;
+}
+
+pub struct Target<const BUCKET_SIZE: usize> {
+    pub bit_width: u32,
+}
+
+impl<const BUCKET_SIZE: usize> MigrationTarget for Target<BUCKET_SIZE> {
+    fn bucket_size(&self) -> usize {
+        BUCKET_SIZE
+    }
+
+    fn migrate(
+        &self,
+        store: &MeteredStore<MemoryDB>,
+        entries: &mut dyn Iterator<Item = Result<(Key, String)>>,
+        batch_size: usize,
+    ) -> Result<Cid> {
+        let mut target: Hamt<_, String, Key, Sha256, BUCKET_SIZE> =
+            Hamt::new_with_bit_width(store, self.bit_width);
+        for (i, entry) in entries.enumerate() {
+            let (key, value) = entry?;
+            target.set(key, value)?;
+            if (i + 1) % cmp::max(batch_size, 1) == 0 {
+                target.flush()?;
+            }
+        }
+        Ok(target.flush()?)
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct MigrationResult {
+    n: usize,
+    batch_size: usize,
+    /// Of the source.
+    bucket_size: usize,
+    target_bucket_size: usize,
+    bit_width: u32,
+    source_bytes: u64,
+    target_bytes: u64,
+    /// Read from the source and written for the target while migrating,
+    /// including the versions of the target that later flushes replaced.
+    bytes_read: u64,
+    bytes_written: u64,
+    /// `bytes_written` per byte of the final target.
+    write_amplification: f64,
+    /// Blocks of the target the store already had as part of the source.
+    shared_blocks: usize,
+    shared_bytes: u64,
+}
+
+/// Inserts `n` keys of `workload` into a HAMT, then streams its entries from
+/// a freshly loaded copy into a HAMT of `target`'s bucket size in the same
+/// store, like changing the parameters of a live dataset without holding
+/// all of it in memory.
+pub fn migration_experiment<const BUCKET_SIZE: usize>(
+    ctx: &ExperimentContext,
+    bit_width: u32,
+    n: usize,
+    batch_size: usize,
+    workload: &Workload,
+    target: &dyn MigrationTarget,
+) -> Result<MigrationResult> {
+    let store = MeteredStore::new(MemoryDB::default());
+    let mut map: Hamt<_, _, Key, Sha256, BUCKET_SIZE> = Hamt::new_with_bit_width(&store, bit_width);
+    for key in workload.keys(n, &mut ctx.rng()) {
+        map.set(key, "F".to_string())?;
+    }
+    let source_root = map.flush()?;
+
+    let before = store.snapshot();
+    let source: Hamt<_, String, Key, Sha256, BUCKET_SIZE> =
+        Hamt::load_with_bit_width(&source_root, &store, bit_width)?;
+    let mut entries = source.iter().map(|entry| {
+        let (key, value) = entry?;
+        Ok((key.clone(), value.clone()))
+    });
+    let target_root = target.migrate(&store, &mut entries, batch_size)?;
+    let migration = store.snapshot() - before;
+
+    let db = store.inner();
+    let target_bytes = db.live_bytes(&[target_root])?;
+    let (shared_blocks, shared_bytes) = db.shared(&[source_root], &[target_root])?;
+    Ok(MigrationResult {
+        n,
+        batch_size,
+        bucket_size: BUCKET_SIZE,
+        target_bucket_size: target.bucket_size(),
+        bit_width,
+        source_bytes: db.live_bytes(&[source_root])?,
+        target_bytes,
+        bytes_read: migration.bytes_read,
+        bytes_written: migration.bytes_written,
+        write_amplification: migration.bytes_written as f64 / target_bytes as f64,
+        shared_blocks,
+        shared_bytes,
+    })
+}
@@ -0,0 +1,62 @@
+//! The `multiproof` experiment: size of a single proof for a growing number of
+//! random keys.
+
+use std::cmp;
+
+use fvm_ipld_hamt::{Hamt, Sha256};
+use serde::Serialize;
+
+use crate::experiments::ExperimentContext;
+use crate::memorydb::MemoryDB;
+use crate::proof;
+
+#[derive(Debug, Serialize)]
+pub struct MultiProofResult {
+    n: usize,
+    bucket_size: usize,
+    bit_width: u32,
+    keys: usize,
+    proof_bytes: u64,
+    /// Bytes added by the last key compared to a proof without it.
+    marginal_bytes: u64,
+}
+
+/// Proof sizes for the first `1..=m` of a random sequence of distinct keys.
+pub fn multi_proof_experiment<const BUCKET_SIZE: usize>(
+    ctx: &ExperimentContext,
+    bit_width: u32,
+    n: usize,
+    m: usize,
+) -> Vec<MultiProofResult> {
+    let store = MemoryDB::default();
+    let mut map: Hamt<_, _, usize, Sha256, BUCKET_SIZE> =
+        Hamt::new_with_bit_width(&store, bit_width);
+    let value = "F";
+
+    for key in 0..n {
+        map.set(key, value.to_string()).unwrap();
+    }
+    map.flush().unwrap();
+
+    let mut keys: Vec<usize> = (0..n).collect();
+    ctx.rng().shuffle(&mut keys);
+
+    let mut previous_bytes = 0;
+    (1..=cmp::min(m, n))
+        .map(|count| {
+            let proof_bytes = proof::generate_multi_proof(&map, &keys[..count])
+                .unwrap()
+                .bytes();
+            let marginal_bytes = proof_bytes - previous_bytes;
+            previous_bytes = proof_bytes;
+            MultiProofResult {
+                n,
+                bucket_size: BUCKET_SIZE,
+                bit_width,
+                keys: count,
+                proof_bytes,
+                marginal_bytes,
+            }
+        })
+        .collect()
+}
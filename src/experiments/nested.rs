@@ -0,0 +1,151 @@
+//! The `nested` experiment: bytes and update amplification of a HAMT linking
+//! child HAMTs, for every combination of the bucket sizes of parent and
+//! children.
+
+use std::cmp;
+
+use anyhow::Result;
+use cid::Cid;
+use fvm_ipld_encoding::to_vec;
+use fvm_ipld_hamt::{Hamt, Sha256};
+use serde::Serialize;
+
+use crate::experiments::ExperimentContext;
+use crate::memorydb::MemoryDB;
+use crate::metered::MeteredStore;
+use crate::workload::Key;
+
+/// The child HAMTs of `nested_experiment`, behind a trait object so that
+/// parent and children can have bucket sizes of their own without compiling
+/// every combination.
+pub trait ChildMaps {
+    fn bucket_size(&self) -> usize;
+
+    /// Flushes a new child with the keys `0..len`.
+    fn create(&self, store: &MeteredStore<MemoryDB>, len: usize) -> Result<Cid>;
+
+    /// Overwrites `key` in the child at `root` and returns its new root.
+    fn set(
+        &self,
+        store: &MeteredStore<MemoryDB>,
+        root: &Cid,
+        key: Key,
+        value: String,
+    ) -> Result<Cid>;
+}
+
+pub struct Children<const BUCKET_SIZE: usize> {
+    pub bit_width: u32,
+}
+
+impl<const BUCKET_SIZE: usize> ChildMaps for Children<BUCKET_SIZE> {
+    fn bucket_size(&self) -> usize {
+        BUCKET_SIZE
+    }
+
+    fn create(&self, store: &MeteredStore<MemoryDB>, len: usize) -> Result<Cid> {
+        let mut child: Hamt<_, String, Key, Sha256, BUCKET_SIZE> =
+            Hamt::new_with_bit_width(store, self.bit_width);
+        for key in 0..len {
+            child.set(Key::Int(key), "F".to_string())?;
+        }
+        Ok(child.flush()?)
+    }
+
+    fn set(
+        &self,
+        store: &MeteredStore<MemoryDB>,
+        root: &Cid,
+        key: Key,
+        value: String,
+    ) -> Result<Cid> {
+        let mut child: Hamt<_, String, Key, Sha256, BUCKET_SIZE> =
+            Hamt::load_with_bit_width(root, store, self.bit_width)?;
+        child.set(key, value)?;
+        Ok(child.flush()?)
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct NestedResult {
+    n: usize,
+    m: usize,
+    /// Of the parent.
+    bucket_size: usize,
+    child_bucket_size: usize,
+    bit_width: u32,
+    children: usize,
+    total_bytes: u64,
+    /// Average bytes written per update by the child's flush, and by the
+    /// parent's after linking the child's new root.
+    child_bytes_written: f64,
+    parent_bytes_written: f64,
+    /// Bytes written per byte of the updated entry.
+    write_amplification: f64,
+}
+
+/// Spreads `n` entries over about `√n` child HAMTs, like files in the
+/// directories of a WNFS tree, and links them from a parent HAMT. Then
+/// updates `m` random entries, each flushing its child and the parent.
+pub fn nested_experiment<const BUCKET_SIZE: usize>(
+    ctx: &ExperimentContext,
+    bit_width: u32,
+    n: usize,
+    m: usize,
+    children: &dyn ChildMaps,
+) -> Result<NestedResult> {
+    let store = MeteredStore::new(MemoryDB::default());
+    let mut parent: Hamt<_, Cid, Key, Sha256, BUCKET_SIZE> =
+        Hamt::new_with_bit_width(&store, bit_width);
+
+    let child_count = cmp::max(1, (n as f64).sqrt().ceil() as usize);
+    let mut child_lens = Vec::with_capacity(child_count);
+    for child in 0..child_count {
+        let len = n / child_count + usize::from(child < n % child_count);
+        parent.set(Key::Int(child), children.create(&store, len)?)?;
+        child_lens.push(len);
+    }
+    let mut root = parent.flush()?;
+
+    let mut rng = ctx.rng();
+    let mut child_bytes = 0;
+    let mut parent_bytes = 0;
+    let mut entry_bytes = 0;
+    let mut updates = 0;
+    for op in 0..m {
+        let child = rng.below(child_count as u64) as usize;
+        if child_lens[child] == 0 {
+            continue;
+        }
+        let key = Key::Int(rng.below(child_lens[child] as u64) as usize);
+        let value = op.to_string();
+        entry_bytes += to_vec(&(&key, &value))?.len() as u64;
+
+        let before = store.snapshot();
+        let child_root = *parent
+            .get(&Key::Int(child))?
+            .expect("every child is linked");
+        let child_root = children.set(&store, &child_root, key, value)?;
+        let linked = store.snapshot();
+        parent.set(Key::Int(child), child_root)?;
+        root = parent.flush()?;
+        let after = store.snapshot();
+
+        child_bytes += (linked - before).bytes_written;
+        parent_bytes += (after - linked).bytes_written;
+        updates += 1;
+    }
+
+    Ok(NestedResult {
+        n,
+        m,
+        bucket_size: BUCKET_SIZE,
+        child_bucket_size: children.bucket_size(),
+        bit_width,
+        children: child_count,
+        total_bytes: store.inner().live_bytes(&[root])?,
+        child_bytes_written: child_bytes as f64 / updates as f64,
+        parent_bytes_written: parent_bytes as f64 / updates as f64,
+        write_amplification: (child_bytes + parent_bytes) as f64 / entry_bytes as f64,
+    })
+}
@@ -0,0 +1,91 @@
+//! The `nodeformat` experiment: sizes of the nodes written as maps of their
+//! slots to their pointers against the usual bitfield and list, and the time
+//! decoding them takes.
+
+use std::time::Instant;
+
+use anyhow::Result;
+use cid::Cid;
+use fvm_ipld_hamt::{Hamt, Sha256};
+use serde::Serialize;
+
+use crate::experiments::ExperimentContext;
+use crate::memorydb::MemoryDB;
+use crate::workload::{Key, Workload};
+
+/// Times the `nodeformat` experiment decodes every node, keeping the fastest.
+const DECODE_PASSES: usize = 3;
+
+#[derive(Debug, Serialize)]
+pub struct NodeFormatResult {
+    n: usize,
+    bucket_size: usize,
+    bit_width: u32,
+    nodes: usize,
+    /// Of the nodes as a bitfield and a list of pointers.
+    list_bytes: u64,
+    list_avg_node_bytes: f64,
+    list_max_node_bytes: usize,
+    /// Of the nodes as maps of their slots to their pointers.
+    map_bytes: u64,
+    map_avg_node_bytes: f64,
+    map_max_node_bytes: usize,
+    /// `map_bytes` over `list_bytes`.
+    size_ratio: f64,
+    /// Fastest of loading the HAMT and iterating over every entry, which
+    /// decodes every node.
+    list_decode_micros: u64,
+    map_decode_micros: u64,
+}
+
+/// Builds a HAMT of `n` keys of `workload` with nodes in either format, and
+/// compares their sizes and how long reading every node back takes.
+pub fn node_format_experiment<const BUCKET_SIZE: usize>(
+    ctx: &ExperimentContext,
+    bit_width: u32,
+    n: usize,
+    workload: &Workload,
+) -> Result<NodeFormatResult> {
+    let build = |map_nodes: bool| -> Result<(MemoryDB, Cid)> {
+        let store = MemoryDB::default();
+        let mut map: Hamt<_, _, Key, Sha256, BUCKET_SIZE> =
+            Hamt::new_with_bit_width(&store, bit_width);
+        map.map_nodes = map_nodes;
+        let value = "F";
+        for key in workload.keys(n, &mut ctx.rng()) {
+            map.set(key, value.to_string())?;
+        }
+        let root = map.flush()?;
+        Ok((store, root))
+    };
+    let decode_micros = |store: &MemoryDB, root: &Cid| -> Result<u64> {
+        let mut fastest = u64::MAX;
+        for _ in 0..DECODE_PASSES {
+            let start = Instant::now();
+            let map: Hamt<_, String, Key, Sha256, BUCKET_SIZE> =
+                Hamt::load_with_bit_width(root, store, bit_width)?;
+            for entry in map.iter() {
+                entry?;
+            }
+            fastest = fastest.min(start.elapsed().as_micros() as u64);
+        }
+        Ok(fastest)
+    };
+    let (list, list_root) = build(false)?;
+    let (map, map_root) = build(true)?;
+    Ok(NodeFormatResult {
+        n,
+        bucket_size: BUCKET_SIZE,
+        bit_width,
+        nodes: list.blocks(),
+        list_bytes: list.bytes_stored(),
+        list_avg_node_bytes: list.bytes_average(),
+        list_max_node_bytes: list.bytes_max(),
+        map_bytes: map.bytes_stored(),
+        map_avg_node_bytes: map.bytes_average(),
+        map_max_node_bytes: map.bytes_max(),
+        size_ratio: map.bytes_stored() as f64 / list.bytes_stored() as f64,
+        list_decode_micros: decode_micros(&list, &list_root)?,
+        map_decode_micros: decode_micros(&map, &map_root)?,
+    })
+}
@@ -0,0 +1,62 @@
+//! The `occupancy` experiment: share of the nodes on each level by the number
+//! of bits set in their bitfield, and their average serialized size.
+
+use anyhow::Result;
+use fvm_ipld_hamt::{Hamt, Sha256};
+use serde::Serialize;
+
+use crate::experiments::ExperimentContext;
+use crate::memorydb::MemoryDB;
+use crate::stats;
+use crate::workload::{Key, Workload};
+
+#[derive(Debug, Serialize)]
+pub struct OccupancyRow {
+    n: usize,
+    bucket_size: usize,
+    bit_width: u32,
+    depth: usize,
+    /// Bits set in the bitfield of the nodes counted, their number of
+    /// pointers.
+    set_bits: usize,
+    nodes: u64,
+    /// Of the nodes on this level.
+    share: f64,
+    avg_bytes: Option<f64>,
+}
+
+/// Nodes of each level of a HAMT of `n` keys of `workload` by their number
+/// of set bits, with a row for every count from 0 to `2^bit_width`.
+pub fn occupancy_experiment<const BUCKET_SIZE: usize>(
+    ctx: &ExperimentContext,
+    bit_width: u32,
+    n: usize,
+    workload: &Workload,
+) -> Result<Vec<OccupancyRow>> {
+    let store = MemoryDB::default();
+    let mut map: Hamt<_, _, Key, Sha256, BUCKET_SIZE> = Hamt::new_with_bit_width(&store, bit_width);
+    let value = "F";
+    for key in workload.keys(n, &mut ctx.rng()) {
+        map.set(key, value.to_string())?;
+    }
+    map.flush()?;
+
+    let mut rows = Vec::new();
+    for (depth, level) in stats::occupancy_per_level(&map)?.iter().enumerate() {
+        let total = level.set_bits.len();
+        for set_bits in 0..=1usize << bit_width {
+            let nodes = level.set_bits.counts().get(set_bits).copied().unwrap_or(0);
+            rows.push(OccupancyRow {
+                n,
+                bucket_size: BUCKET_SIZE,
+                bit_width,
+                depth,
+                set_bits,
+                nodes,
+                share: nodes as f64 / total as f64,
+                avg_bytes: level.avg_bytes(set_bits),
+            });
+        }
+    }
+    Ok(rows)
+}
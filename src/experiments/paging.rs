@@ -0,0 +1,82 @@
+//! The `paging` experiment: blocks and bytes read paging through all entries
+//! with cursors.
+
+use std::cmp;
+
+use fvm_ipld_blockstore::tracking::TrackingBlockstore;
+use fvm_ipld_hamt::{Cursor, Hamt, Sha256};
+use serde::Serialize;
+
+use crate::experiments::ExperimentContext;
+use crate::memorydb::MemoryDB;
+
+#[derive(Debug, Serialize)]
+pub struct PagingResult {
+    n: usize,
+    bucket_size: usize,
+    bit_width: u32,
+    page_size: usize,
+    pages: usize,
+    blocks_read: usize,
+    bytes_read: usize,
+    avg_blocks_per_page: f64,
+    max_blocks_per_page: usize,
+}
+
+/// Reads all entries in pages of `page_size`, loading the HAMT from its root
+/// for every page and resuming from the cursor of the previous one.
+pub fn paging_experiment<const BUCKET_SIZE: usize>(
+    ctx: &ExperimentContext,
+    bit_width: u32,
+    n: usize,
+    page_size: usize,
+) -> PagingResult {
+    let store = MemoryDB::default();
+    let mut map: Hamt<_, _, usize, Sha256, BUCKET_SIZE> =
+        Hamt::new_with_bit_width(&store, bit_width);
+    let value = "F";
+
+    for key in 0..n {
+        map.set(key, value.to_string()).unwrap();
+    }
+    let root = map.flush().unwrap();
+
+    let mut pages = 0;
+    let mut entries = 0;
+    let mut blocks_read = 0;
+    let mut bytes_read = 0;
+    let mut max_blocks_per_page = 0;
+    let mut cursor = Some(Cursor::default());
+    let reader = ctx.verifying(&store);
+
+    while let Some(position) = cursor {
+        let tracking = TrackingBlockstore::new(&reader);
+        let map: Hamt<_, String, usize, Sha256, BUCKET_SIZE> =
+            Hamt::load_with_bit_width(&root, &tracking, bit_width).unwrap();
+        let mut page = map.iter_from(&position).unwrap();
+        for entry in page.by_ref().take(cmp::max(page_size, 1)) {
+            entry.unwrap();
+            entries += 1;
+        }
+        cursor = page.cursor();
+
+        let stats = *tracking.stats.borrow();
+        pages += 1;
+        blocks_read += stats.r;
+        bytes_read += stats.br;
+        max_blocks_per_page = cmp::max(max_blocks_per_page, stats.r);
+    }
+    assert_eq!(entries, n);
+
+    PagingResult {
+        n,
+        bucket_size: BUCKET_SIZE,
+        bit_width,
+        page_size,
+        pages,
+        blocks_read,
+        bytes_read,
+        avg_blocks_per_page: blocks_read as f64 / pages as f64,
+        max_blocks_per_page,
+    }
+}
@@ -0,0 +1,75 @@
+//! The `proof` experiment: size of the Merkle proof for a single key, in a HAMT
+//! and a B-tree.
+
+use anyhow::Result;
+use fvm_ipld_hamt::{Hamt, Sha256};
+use serde::Serialize;
+
+use crate::btree::BTree;
+use crate::memorydb::MemoryDB;
+use crate::proof;
+
+#[derive(Debug, Serialize)]
+pub struct ProofResult {
+    n: usize,
+    bucket_size: usize,
+    bit_width: u32,
+    proof_bytes: u64,
+    /// Of a [`BTree`] with a fanout of `2^bit_width` and the same keys.
+    btree_proof_bytes: u64,
+}
+
+/// Proof sizes of key `0` in a HAMT and in a B-tree with the keys `0..n`.
+pub fn proof_experiment<const BUCKET_SIZE: usize>(bit_width: u32, n: usize) -> Result<ProofResult> {
+    Ok(ProofResult {
+        n,
+        bucket_size: BUCKET_SIZE,
+        bit_width,
+        proof_bytes: merkle_proof_bytes_experiment::<BUCKET_SIZE>(bit_width, n),
+        btree_proof_bytes: btree_proof_bytes_experiment(bit_width, n)?,
+    })
+}
+
+pub fn merkle_proof_bytes_experiment<const BUCKET_SIZE: usize>(bit_width: u32, n: usize) -> u64 {
+    let store = MemoryDB::default();
+    let mut map: Hamt<_, _, usize, Sha256, BUCKET_SIZE> =
+        Hamt::new_with_bit_width(&store, bit_width);
+    let value = "F";
+
+    for key in 0..n {
+        map.set(key, value.to_string()).unwrap();
+    }
+    map.flush().unwrap();
+
+    proof::generate_proof(&map, &0).unwrap().bytes()
+}
+
+/// Like [`merkle_proof_bytes_experiment`] for a [`BTree`].
+fn btree_proof_bytes_experiment(bit_width: u32, n: usize) -> Result<u64> {
+    let store = MemoryDB::default();
+    let mut tree = BTree::new_with_fanout(&store, 1 << bit_width);
+    for key in 0..n {
+        tree.set(key, "F".to_string());
+    }
+    tree.flush()?;
+    Ok(tree.proof_bytes(&0)?.unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bucket::with_bucket_size;
+    use crate::experiments::sweep_row;
+
+    #[test]
+    fn test_merkle_proof_bytes() -> Result<()> {
+        for i in 1..=10 {
+            let n = 10_000 * i;
+            let row = sweep_row(n, |bucket_size| {
+                Ok(with_bucket_size!(bucket_size, B => merkle_proof_bytes_experiment::<B>(4, n)))
+            })?;
+            println!("{row}");
+        }
+        Ok(())
+    }
+}
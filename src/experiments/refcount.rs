@@ -0,0 +1,100 @@
+//! The `refcount` experiment: blocks shared between the versions kept of a long
+//! edit history, and the bytes dropping the oldest ones reclaims.
+
+use std::collections::VecDeque;
+
+use anyhow::Result;
+use fvm_ipld_hamt::{Hamt, Sha256};
+use serde::Serialize;
+
+use crate::experiments::ExperimentContext;
+use crate::memorydb::MemoryDB;
+use crate::refcount::{RefCountedStore, Sharing};
+use crate::workload::{Key, Workload};
+
+#[derive(Debug, Serialize)]
+pub struct RefCountResult {
+    n: usize,
+    m: usize,
+    bucket_size: usize,
+    bit_width: u32,
+    version: usize,
+    /// Versions kept once this one is added and the oldest dropped.
+    kept: usize,
+    blocks: usize,
+    bytes: u64,
+    /// Blocks reachable from more than one kept version.
+    shared_blocks: usize,
+    shared_bytes: u64,
+    /// Average number of kept versions a block is reachable from.
+    mean_refs: f64,
+    /// Bytes freed dropping the version no longer kept.
+    reclaimed_bytes: u64,
+}
+
+/// Updates `m` random keys per version like [`versions_experiment`], but
+/// counts the versions referencing each block and drops the oldest version
+/// once there are more than `keep`.
+pub fn refcount_experiment<const BUCKET_SIZE: usize>(
+    ctx: &ExperimentContext,
+    bit_width: u32,
+    n: usize,
+    m: usize,
+    versions: usize,
+    keep: Option<usize>,
+    workload: &Workload,
+) -> Result<Vec<RefCountResult>> {
+    let store = RefCountedStore::new(MemoryDB::default());
+    let mut map: Hamt<_, _, Key, Sha256, BUCKET_SIZE> = Hamt::new_with_bit_width(&store, bit_width);
+
+    let mut rng = ctx.rng();
+    let keys = workload.keys(n, &mut rng);
+    for key in &keys {
+        map.set(key.clone(), "F".to_string())?;
+    }
+    let sampler = workload.sampler(n);
+    // The current version is always kept, the HAMT still links its nodes.
+    let keep = keep.unwrap_or(usize::MAX).max(1);
+    let mut kept = VecDeque::new();
+    let mut rows = Vec::with_capacity(versions);
+    for version in 0..versions {
+        if version > 0 {
+            for _ in 0..m {
+                if let Some(key) = keys.get(sampler.sample(&mut rng)) {
+                    map.set(key.clone(), version.to_string())?;
+                }
+            }
+        }
+        let root = map.flush()?;
+        store.retain(&root)?;
+        kept.push_back(root);
+        let mut reclaimed_bytes = 0;
+        if kept.len() > keep {
+            let oldest = kept.pop_front().expect("more versions than kept");
+            reclaimed_bytes = store.release(&oldest)?;
+        }
+
+        let Sharing {
+            blocks,
+            bytes,
+            shared_blocks,
+            shared_bytes,
+            references,
+        } = store.sharing();
+        rows.push(RefCountResult {
+            n,
+            m,
+            bucket_size: BUCKET_SIZE,
+            bit_width,
+            version,
+            kept: kept.len(),
+            blocks,
+            bytes,
+            shared_blocks,
+            shared_bytes,
+            mean_refs: references as f64 / blocks as f64,
+            reclaimed_bytes,
+        });
+    }
+    Ok(rows)
+}
@@ -0,0 +1,61 @@
+//! The `replay` experiment: bytes stored and written replaying a captured trace
+//! of the operations on Filecoin actor HAMTs.
+
+use anyhow::Result;
+use cid::Cid;
+use serde::Serialize;
+
+use crate::memorydb::MemoryDB;
+use crate::metered::MeteredStore;
+use crate::replay::Trace;
+
+#[derive(Debug, Serialize)]
+pub struct ReplayResult {
+    bucket_size: usize,
+    bit_width: u32,
+    ops: usize,
+    hamts: usize,
+    /// Flushes of single HAMTs, see [`Replayed`](crate::replay::Replayed).
+    flushes: usize,
+    /// Reachable from the last roots of the HAMTs.
+    total_bytes: u64,
+    /// Everything written, including the nodes later versions replaced.
+    stored_bytes: u64,
+    blocks_written: u64,
+    bytes_written: u64,
+    bytes_written_per_flush: f64,
+    /// Of `blocks_written`, blocks that were already in the store.
+    put_hits: u64,
+    blocks_read: u64,
+    bytes_read: u64,
+    /// `get`s of keys that weren't set.
+    missing_gets: usize,
+}
+
+/// Replays `trace` into empty HAMTs and measures what they store and the
+/// traffic it takes.
+pub fn replay_experiment<const BUCKET_SIZE: usize>(
+    bit_width: u32,
+    trace: &Trace,
+) -> Result<ReplayResult> {
+    let store = MeteredStore::new(MemoryDB::default());
+    let replayed = trace.replay::<_, BUCKET_SIZE>(&store, bit_width)?;
+    let roots: Vec<Cid> = replayed.roots.values().copied().collect();
+    let traffic = store.snapshot();
+    Ok(ReplayResult {
+        bucket_size: BUCKET_SIZE,
+        bit_width,
+        ops: trace.ops.len(),
+        hamts: roots.len(),
+        flushes: replayed.flushes,
+        total_bytes: store.inner().live_bytes(&roots)?,
+        stored_bytes: store.inner().bytes_stored(),
+        blocks_written: traffic.puts,
+        bytes_written: traffic.bytes_written,
+        bytes_written_per_flush: traffic.bytes_written as f64 / replayed.flushes.max(1) as f64,
+        put_hits: traffic.put_hits,
+        blocks_read: traffic.gets,
+        bytes_read: traffic.bytes_read,
+        missing_gets: replayed.missing,
+    })
+}
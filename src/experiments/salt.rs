@@ -0,0 +1,80 @@
+//! The `salt` experiment: root CIDs, bytes and slots shared by HAMTs of the
+//! same keys under different salts.
+
+use anyhow::{bail, Result};
+use fvm_ipld_hamt::{Hamt, HashAlgorithm, Sha256};
+use serde::Serialize;
+
+use crate::experiments::ExperimentContext;
+use crate::memorydb::MemoryDB;
+use crate::workload::{Key, Workload};
+
+#[derive(Debug, Serialize)]
+pub struct SaltResult {
+    n: usize,
+    bucket_size: usize,
+    bit_width: u32,
+    /// Salt mixed into the key hashes, `none` for the empty one.
+    salt: String,
+    root: String,
+    total_bytes: u64,
+    /// Bytes of blocks the HAMT shares with the unsalted one of the same
+    /// keys.
+    shared_bytes: u64,
+    /// Fraction of keys in the same slot of the root as in the unsalted HAMT.
+    same_slot: f64,
+}
+
+/// HAMTs of the same `n` keys of `workload` under different salts, stored
+/// side by side, and what they have in common with the unsalted one.
+pub fn salt_experiment<const BUCKET_SIZE: usize>(
+    ctx: &ExperimentContext,
+    bit_width: u32,
+    n: usize,
+    workload: &Workload,
+) -> Result<Vec<SaltResult>> {
+    let keys = workload.keys(n, &mut ctx.rng());
+    let slot = |key: &Key, salt: &[u8]| Sha256::hash_salted(key, salt)[0] >> (8 - bit_width);
+    let store = MemoryDB::default();
+
+    let mut unsalted = None;
+    let mut rows = Vec::new();
+    for salt in ["", "tree 1", "tree 2"] {
+        let salt = salt.as_bytes();
+        let mut map: Hamt<_, _, Key, Sha256, BUCKET_SIZE> =
+            Hamt::new_with_bit_width(&store, bit_width).with_salt(salt.to_vec());
+        for key in &keys {
+            map.set(key.clone(), "F".to_string())?;
+        }
+        let root = map.flush()?;
+        let unsalted = *unsalted.get_or_insert(root);
+
+        let map: Hamt<_, String, Key, Sha256, BUCKET_SIZE> =
+            Hamt::load_with_bit_width(&root, &store, bit_width)?.with_salt(salt.to_vec());
+        for key in &keys {
+            if map.get(key)?.is_none() {
+                bail!("key {key:?} not found under salt {salt:?}");
+            }
+        }
+
+        let total_bytes = store.live_bytes(&[root])?;
+        let same_slot = keys
+            .iter()
+            .filter(|key| slot(key, salt) == slot(key, &[]))
+            .count();
+        rows.push(SaltResult {
+            n,
+            bucket_size: BUCKET_SIZE,
+            bit_width,
+            salt: match salt {
+                [] => "none".to_string(),
+                salt => String::from_utf8_lossy(salt).into_owned(),
+            },
+            root: root.to_string(),
+            total_bytes,
+            shared_bytes: total_bytes - store.delta_bytes(&[unsalted], &[root])?,
+            same_slot: same_slot as f64 / keys.len() as f64,
+        });
+    }
+    Ok(rows)
+}
@@ -0,0 +1,93 @@
+//! The `sample` experiment: node, link and value totals estimated from walking
+//! a random share of the subtrees, with confidence intervals, against the exact
+//! ones.
+
+use std::time::Instant;
+
+use anyhow::Result;
+use fvm_ipld_hamt::{Hamt, Sha256};
+use serde::Serialize;
+
+use crate::experiments::ExperimentContext;
+use crate::memorydb::MemoryDB;
+use crate::stats;
+use crate::stats::{Estimate, SampledStats};
+use crate::traverse::{Sampling, Walk};
+use crate::workload::{Key, Workload};
+
+#[derive(Debug, Serialize)]
+pub struct SampleResult {
+    n: usize,
+    bucket_size: usize,
+    bit_width: u32,
+    sampling: String,
+    max_depth: Option<usize>,
+    /// Totals of the nodes down to `max_depth`.
+    nodes: u64,
+    links: u64,
+    values: u64,
+    /// The same, estimated from the sampled walk.
+    nodes_estimate: Estimate,
+    links_estimate: Estimate,
+    values_estimate: Estimate,
+    visited_nodes: u64,
+    subtrees: u64,
+    sampled_subtrees: u64,
+    /// Times walking every node and walking the sample, loading the nodes
+    /// from the store.
+    exact_micros: u64,
+    sampled_micros: u64,
+}
+
+/// Estimates the totals of a HAMT of `n` keys of `workload` from a walk of
+/// only the subtrees `sampling` picks, and compares them to the exact ones.
+pub fn sample_experiment<const BUCKET_SIZE: usize>(
+    ctx: &ExperimentContext,
+    bit_width: u32,
+    n: usize,
+    workload: &Workload,
+    sampling: Sampling,
+    max_depth: Option<usize>,
+) -> Result<SampleResult> {
+    let store = MemoryDB::default();
+    let mut map: Hamt<_, _, Key, Sha256, BUCKET_SIZE> = Hamt::new_with_bit_width(&store, bit_width);
+    let value = "F";
+    for key in workload.keys(n, &mut ctx.rng()) {
+        map.set(key, value.to_string())?;
+    }
+    let root = map.flush()?;
+
+    let walk = |sample| Walk {
+        max_depth,
+        sample,
+        seed: ctx.seed,
+    };
+    let timed = |walk: &Walk| -> Result<(SampledStats, u64)> {
+        // Reload so every walk has to load its nodes.
+        let map: Hamt<_, String, Key, Sha256, BUCKET_SIZE> =
+            Hamt::load_with_bit_width(&root, &store, bit_width)?;
+        let start = Instant::now();
+        let stats = stats::sampled_stats(&map, walk)?;
+        Ok((stats, start.elapsed().as_micros() as u64))
+    };
+    let (exact, exact_micros) = timed(&walk(None))?;
+    let (sampled, sampled_micros) = timed(&walk(Some(sampling)))?;
+    Ok(SampleResult {
+        n,
+        bucket_size: BUCKET_SIZE,
+        bit_width,
+        sampling: sampling.to_string(),
+        max_depth,
+        nodes: exact.nodes.value as u64,
+        links: exact.links.value as u64,
+        values: exact.values.value as u64,
+        nodes_estimate: sampled.nodes,
+        links_estimate: sampled.links,
+        values_estimate: sampled.values,
+        visited_nodes: sampled.visited_nodes,
+        subtrees: sampled.subtrees,
+        sampled_subtrees: sampled.sampled_subtrees,
+        exact_micros,
+        sampled_micros,
+    })
+}
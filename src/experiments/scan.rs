@@ -0,0 +1,70 @@
+//! The `scan` experiment: blocks, bytes and simulated network time read
+//! iterating over all entries.
+
+use std::cmp;
+use std::time::Instant;
+
+use fvm_ipld_blockstore::tracking::TrackingBlockstore;
+use fvm_ipld_hamt::{Hamt, Sha256};
+use serde::Serialize;
+
+use crate::delayed::{DelayedStore, Network};
+use crate::experiments::ExperimentContext;
+use crate::memorydb::MemoryDB;
+use crate::workload::{Key, Workload};
+
+#[derive(Debug, Serialize)]
+pub struct ScanResult {
+    n: usize,
+    bucket_size: usize,
+    bit_width: u32,
+    blocks_read: usize,
+    bytes_read: usize,
+    bytes_per_entry: f64,
+    micros: u64,
+    /// Time the scan would take fetching one block at a time over the network.
+    network_millis: f64,
+}
+
+/// Reads every entry of a freshly loaded HAMT through `Hamt::iter`.
+pub fn scan_experiment<const BUCKET_SIZE: usize>(
+    ctx: &ExperimentContext,
+    bit_width: u32,
+    n: usize,
+    workload: &Workload,
+    network: Network,
+) -> ScanResult {
+    let store = MemoryDB::default();
+    let mut map: Hamt<_, _, Key, Sha256, BUCKET_SIZE> = Hamt::new_with_bit_width(&store, bit_width);
+    let value = "F";
+
+    for key in workload.keys(n, &mut ctx.rng()) {
+        map.set(key, value.to_string()).unwrap();
+    }
+    let root = map.flush().unwrap();
+
+    let remote = DelayedStore::new(ctx.verifying(&store), network);
+    let tracking = TrackingBlockstore::new(&remote);
+    let start = Instant::now();
+    let map: Hamt<_, String, Key, Sha256, BUCKET_SIZE> =
+        Hamt::load_with_bit_width(&root, &tracking, bit_width).unwrap();
+    let mut entries = 0;
+    for entry in map.iter() {
+        entry.unwrap();
+        entries += 1;
+    }
+    let micros = start.elapsed().as_micros() as u64;
+    assert_eq!(entries, n);
+
+    let stats = *tracking.stats.borrow();
+    ScanResult {
+        n,
+        bucket_size: BUCKET_SIZE,
+        bit_width,
+        blocks_read: stats.r,
+        bytes_read: stats.br,
+        bytes_per_entry: stats.br as f64 / cmp::max(n, 1) as f64,
+        micros,
+        network_millis: remote.elapsed().as_secs_f64() * 1000.0,
+    }
+}
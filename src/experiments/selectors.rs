@@ -0,0 +1,66 @@
+//! The `selectors` experiment: blocks and bytes visited and extracted by
+//! GraphSync-like selectors for part of the HAMT.
+
+use anyhow::Result;
+use fvm_ipld_hamt::{Hamt, Sha256};
+use serde::Serialize;
+
+use crate::experiments::ExperimentContext;
+use crate::memorydb::MemoryDB;
+use crate::selector;
+use crate::selector::Selector;
+use crate::workload::{Key, Workload};
+
+#[derive(Debug, Serialize)]
+pub struct SelectorResult {
+    n: usize,
+    bucket_size: usize,
+    bit_width: u32,
+    selector: String,
+    total_bytes: u64,
+    /// Blocks and bytes read to find the selected ones.
+    visited_blocks: u64,
+    visited_bytes: u64,
+    /// Blocks and bytes copied into the new store.
+    selected_blocks: u64,
+    selected_bytes: u64,
+    entries: u64,
+    /// `selected_bytes` per selected entry.
+    bytes_per_entry: f64,
+}
+
+/// Builds a HAMT of `n` keys of `workload` and extracts the part `selector`
+/// selects into a store of its own.
+pub fn selector_experiment<const BUCKET_SIZE: usize>(
+    ctx: &ExperimentContext,
+    bit_width: u32,
+    n: usize,
+    workload: &Workload,
+    selector: &Selector,
+) -> Result<SelectorResult> {
+    let store = MemoryDB::default();
+    let mut map: Hamt<_, _, Key, Sha256, BUCKET_SIZE> = Hamt::new_with_bit_width(&store, bit_width);
+    let value = "F";
+
+    let mut rng = ctx.rng();
+    for key in workload.keys(n, &mut rng) {
+        map.set(key, value.to_string())?;
+    }
+    let root = map.flush()?;
+
+    let target = MemoryDB::default();
+    let selection = selector::select(&store, &root, selector, &mut rng, &target)?;
+    Ok(SelectorResult {
+        n,
+        bucket_size: BUCKET_SIZE,
+        bit_width,
+        selector: selector.to_string(),
+        total_bytes: store.live_bytes(&[root])?,
+        visited_blocks: selection.visited_blocks,
+        visited_bytes: selection.visited_bytes,
+        selected_blocks: selection.selected_blocks,
+        selected_bytes: selection.selected_bytes,
+        entries: selection.entries,
+        bytes_per_entry: selection.selected_bytes as f64 / selection.entries as f64,
+    })
+}
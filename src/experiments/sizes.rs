@@ -0,0 +1,151 @@
+//! The `sizes` and `sweep` experiments: total stored bytes, bytes per node and
+//! bytes written by overwriting `m` keys, next to those of a single block with
+//! all entries, a B-tree and a prolly tree, for one HAMT or as a matrix over
+//! every combination of bit width and bucket size.
+
+use std::cmp;
+
+use fvm_ipld_hamt::{Hamt, Sha256};
+use serde::Serialize;
+
+use crate::btree::BTree;
+use crate::experiments::ExperimentContext;
+use crate::flat::FlatMap;
+use crate::map::map_sizes;
+use crate::memorydb::MemoryDB;
+use crate::metered::MeteredStore;
+use crate::prolly::ProllyTree;
+use crate::workload::{Key, Workload};
+
+#[derive(Debug, Serialize)]
+pub struct ExperimentResult {
+    pub n: usize,
+    pub m: usize,
+    pub bucket_size: usize,
+    pub bit_width: u32,
+    pub total_bytes: u64,
+    pub avg_node_bytes: f64,
+    pub max_node_bytes: usize,
+    #[serde(rename = "byte_diff")]
+    pub byte_difference: u64,
+    /// Bytes put while flushing the overwrites, including unchanged blocks.
+    pub bytes_written: u64,
+    pub put_hits: u64,
+    /// `total_bytes` and `byte_diff` of all entries in a single block, see
+    /// [`FlatMap`].
+    pub flat_total_bytes: u64,
+    pub flat_byte_diff: u64,
+    /// The same for a [`BTree`] with a fanout of `2^bit_width`.
+    pub btree_total_bytes: u64,
+    pub btree_byte_diff: u64,
+    /// And for a [`ProllyTree`] with chunks of `2^bit_width` entries on
+    /// average.
+    pub prolly_total_bytes: u64,
+    pub prolly_byte_diff: u64,
+}
+
+pub fn experiment<const BUCKET_SIZE: usize>(
+    ctx: &ExperimentContext,
+    bit_width: u32,
+    n: usize,
+    m: usize,
+    workload: &Workload,
+) -> ExperimentResult {
+    // Overwriting more than `n` keys inserts new ones.
+    let keys = workload.keys(cmp::max(n, m), &mut ctx.rng());
+
+    let store = MeteredStore::new(MemoryDB::default());
+    let mut map: Hamt<_, _, Key, Sha256, BUCKET_SIZE> = Hamt::new_with_bit_width(&store, bit_width);
+    let hamt = map_sizes(&mut map, &store, &keys, n, m).unwrap();
+
+    let flat_store = MeteredStore::new(MemoryDB::default());
+    let mut flat = FlatMap::new(&flat_store);
+    let flat = map_sizes(&mut flat, &flat_store, &keys, n, m).unwrap();
+
+    let btree_store = MeteredStore::new(MemoryDB::default());
+    let mut btree = BTree::new_with_fanout(&btree_store, 1 << bit_width);
+    let btree = map_sizes(&mut btree, &btree_store, &keys, n, m).unwrap();
+
+    let prolly_store = MeteredStore::new(MemoryDB::default());
+    let mut prolly = ProllyTree::new_with_fanout(&prolly_store, 1 << bit_width);
+    let prolly = map_sizes(&mut prolly, &prolly_store, &keys, n, m).unwrap();
+
+    ExperimentResult {
+        n,
+        m,
+        bucket_size: BUCKET_SIZE,
+        bit_width,
+        total_bytes: hamt.total_bytes,
+        avg_node_bytes: hamt.avg_node_bytes,
+        max_node_bytes: hamt.max_node_bytes,
+        byte_difference: hamt.byte_difference,
+        bytes_written: hamt.traffic.bytes_written,
+        put_hits: hamt.traffic.put_hits,
+        flat_total_bytes: flat.total_bytes,
+        flat_byte_diff: flat.byte_difference,
+        btree_total_bytes: btree.total_bytes,
+        btree_byte_diff: btree.byte_difference,
+        prolly_total_bytes: prolly.total_bytes,
+        prolly_byte_diff: prolly.byte_difference,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bucket::with_bucket_size;
+    use crate::experiments::sweep_row;
+    use anyhow::Result;
+
+    #[test]
+    fn test_avg_node_bytes() -> Result<()> {
+        for i in 1..=1000 {
+            let n = 100 * i;
+            let row = sweep_row(n, |bucket_size| {
+                Ok(with_bucket_size!(bucket_size, B => avg_node_bytes_experiment::<B>(4, n) as u32))
+            })?;
+            println!("{row}");
+        }
+        Ok(())
+    }
+
+    fn avg_node_bytes_experiment<const BUCKET_SIZE: usize>(bit_width: u32, n: usize) -> f64 {
+        let store = MemoryDB::default();
+        let mut map: Hamt<_, _, usize, Sha256, BUCKET_SIZE> =
+            Hamt::new_with_bit_width(&store, bit_width);
+        let value = "F";
+
+        for key in 0..n {
+            map.set(key, value.to_string()).unwrap();
+        }
+        map.flush().unwrap();
+
+        store.bytes_average()
+    }
+
+    #[test]
+    fn test_max_node_bytes() -> Result<()> {
+        for i in 1..=1000 {
+            let n = 100 * i;
+            let row = sweep_row(n, |bucket_size| {
+                Ok(with_bucket_size!(bucket_size, B => max_node_bytes_experiment::<B>(4, n)))
+            })?;
+            println!("{row}");
+        }
+        Ok(())
+    }
+
+    fn max_node_bytes_experiment<const BUCKET_SIZE: usize>(bit_width: u32, n: usize) -> usize {
+        let store = MemoryDB::default();
+        let mut map: Hamt<_, _, usize, Sha256, BUCKET_SIZE> =
+            Hamt::new_with_bit_width(&store, bit_width);
+        let value = "F";
+
+        for key in 0..n {
+            map.set(key, value.to_string()).unwrap();
+        }
+        map.flush().unwrap();
+
+        store.bytes_max()
+    }
+}
@@ -0,0 +1,93 @@
+//! The `skip` experiment: depth and proof sizes with and without path
+//! compression, for keys whose hashes share longer and longer prefixes.
+
+use anyhow::{bail, Result};
+use fvm_ipld_hamt::{BytesKey, Hamt, HashOnly, Sha256};
+use serde::Serialize;
+
+use crate::experiments::ExperimentContext;
+use crate::memorydb::MemoryDB;
+use crate::{proof, stats};
+
+#[derive(Debug, Serialize)]
+pub struct SkipResult {
+    n: usize,
+    bucket_size: usize,
+    bit_width: u32,
+    /// Leading hash bits shared by the keys of a cluster.
+    shared_bits: u32,
+    path_compression: bool,
+    blocks: u64,
+    total_bytes: u64,
+    mean_depth: f64,
+    max_depth: Option<usize>,
+    avg_proof_blocks: f64,
+    avg_proof_bytes: f64,
+}
+
+/// Depth and proof sizes of HAMTs of `n` keys whose hashes come in clusters
+/// of `BUCKET_SIZE + 1` sharing their first bits, which split only where the
+/// hashes diverge, with and without path compression, proving `lookups`
+/// random keys. Keys are their own hash, like keys an adversary searched for
+/// hash prefixes.
+pub fn skip_experiment<const BUCKET_SIZE: usize>(
+    ctx: &ExperimentContext,
+    bit_width: u32,
+    n: usize,
+    lookups: usize,
+) -> Result<Vec<SkipResult>> {
+    let value = "F".to_string();
+    let mut rows = Vec::new();
+    for shared_bits in [0, 16, 32, 64, 128] {
+        let mut rng = ctx.rng();
+        let mut cluster = Vec::new();
+        let mut keys = Vec::with_capacity(n);
+        for i in 0..n {
+            let mut hash: Vec<u8> = (0..32).map(|_| rng.next_u64() as u8).collect();
+            if i % (BUCKET_SIZE + 1) == 0 {
+                cluster = hash.clone();
+            }
+            for bit in 0..shared_bits as usize {
+                let mask = 0x80 >> (bit % 8);
+                hash[bit / 8] = hash[bit / 8] & !mask | cluster[bit / 8] & mask;
+            }
+            keys.push(BytesKey(hash));
+        }
+
+        for path_compression in [false, true] {
+            let store = MemoryDB::default();
+            let mut map: Hamt<_, _, BytesKey, HashOnly<Sha256, 32>, BUCKET_SIZE> =
+                Hamt::new_with_bit_width(&store, bit_width);
+            map.path_compression = path_compression;
+            map.set_many(keys.iter().map(|key| (key.clone(), value.clone())))?;
+            let root = map.flush()?;
+            let (blocks, total_bytes) = map.reachable_size()?;
+            let depths = stats::key_depths(&map)?;
+
+            let (mut proof_blocks, mut proof_bytes) = (0, 0);
+            for _ in 0..lookups {
+                let key = &keys[rng.below(n as u64) as usize];
+                let proof = proof::generate_proof(&map, key)?;
+                if !proof.verify(&root, key, &value)? {
+                    bail!("proof of {key:?} doesn't verify");
+                }
+                proof_blocks += proof.blocks().len();
+                proof_bytes += proof.bytes();
+            }
+            rows.push(SkipResult {
+                n,
+                bucket_size: BUCKET_SIZE,
+                bit_width,
+                shared_bits,
+                path_compression,
+                blocks,
+                total_bytes,
+                mean_depth: depths.mean(),
+                max_depth: depths.max(),
+                avg_proof_blocks: proof_blocks as f64 / lookups as f64,
+                avg_proof_bytes: proof_bytes as f64 / lookups as f64,
+            });
+        }
+    }
+    Ok(rows)
+}
@@ -0,0 +1,106 @@
+//! The `timeseries` experiment: cumulative bytes written, bytes written by each
+//! flush and node count over a long run of `n` mixed inserts, overwrites and
+//! deletes.
+
+use anyhow::Result;
+use fvm_ipld_encoding::to_vec;
+use fvm_ipld_hamt::{Hamt, Sha256};
+use serde::Serialize;
+
+use crate::experiments::ExperimentContext;
+use crate::flush::FlushPolicy;
+use crate::memorydb::MemoryDB;
+use crate::metered::MeteredStore;
+use crate::stats::TreeStats;
+use crate::workload::{Key, Workload};
+
+/// When `timeseries` flushes unless `--flush` is given.
+pub const TIME_SERIES_FLUSH: FlushPolicy = FlushPolicy::Every(1000);
+
+/// Shares of the operations of `timeseries` that insert a new key and that
+/// overwrite one, the rest delete one.
+const TIME_SERIES_INSERTS: f64 = 0.5;
+
+const TIME_SERIES_OVERWRITES: f64 = 0.3;
+
+#[derive(Debug, Serialize)]
+pub struct TimeSeriesRow {
+    n: usize,
+    bucket_size: usize,
+    bit_width: u32,
+    flush_policy: String,
+    /// Operations run before this flush.
+    op: usize,
+    entries: usize,
+    /// Since the first operation.
+    bytes_written: u64,
+    /// By this flush.
+    flush_bytes: u64,
+    flush_nodes: u64,
+    /// Everything in the store, including nodes of older versions.
+    store_bytes: u64,
+    /// Of the current version.
+    nodes: u64,
+}
+
+/// Runs `n` operations inserting keys of `workload` in order, overwriting
+/// and deleting random ones, and records the writes of every flush `policy`
+/// makes, to see how they settle over a long run.
+pub fn time_series_experiment<const BUCKET_SIZE: usize>(
+    ctx: &ExperimentContext,
+    bit_width: u32,
+    n: usize,
+    workload: &Workload,
+    policy: FlushPolicy,
+) -> Result<Vec<TimeSeriesRow>> {
+    let store = MeteredStore::new(MemoryDB::default());
+    let mut map: Hamt<_, _, Key, Sha256, BUCKET_SIZE> = Hamt::new_with_bit_width(&store, bit_width);
+
+    let mut rng = ctx.rng();
+    let keys = workload.keys(n, &mut rng);
+    let mut inserted = 0;
+    // Indices into `keys` of the entries in the map.
+    let mut live: Vec<usize> = Vec::new();
+    let mut flusher = policy.flusher();
+    let mut last = store.snapshot();
+    let mut rows = Vec::new();
+    for op in 0..n {
+        let x = rng.next_f64();
+        let value = op.to_string();
+        let bytes = if x < TIME_SERIES_INSERTS || live.is_empty() {
+            let key = &keys[inserted];
+            live.push(inserted);
+            inserted += 1;
+            map.set(key.clone(), value.clone())?;
+            to_vec(&(key, &value))?.len()
+        } else if x < TIME_SERIES_INSERTS + TIME_SERIES_OVERWRITES {
+            let key = &keys[live[rng.below(live.len() as u64) as usize]];
+            map.set(key.clone(), value.clone())?;
+            to_vec(&(key, &value))?.len()
+        } else {
+            let i = live.swap_remove(rng.below(live.len() as u64) as usize);
+            map.delete(&keys[i])?;
+            to_vec(&keys[i])?.len()
+        };
+        if flusher.record(bytes as u64) || op + 1 == n {
+            map.flush()?;
+            let now = store.snapshot();
+            let flush = now - last;
+            last = now;
+            rows.push(TimeSeriesRow {
+                n,
+                bucket_size: BUCKET_SIZE,
+                bit_width,
+                flush_policy: policy.to_string(),
+                op: op + 1,
+                entries: live.len(),
+                bytes_written: now.bytes_written,
+                flush_bytes: flush.bytes_written,
+                flush_nodes: flush.puts,
+                store_bytes: store.inner().bytes_stored(),
+                nodes: TreeStats::new(&map)?.nodes,
+            });
+        }
+    }
+    Ok(rows)
+}
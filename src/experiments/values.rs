@@ -0,0 +1,136 @@
+//! The `values` experiment: bytes stored, looked up and rewritten by value
+//! size, with values inlined in buckets vs. stored as blocks of their own.
+
+use std::cmp;
+
+use cid::multihash::Code;
+use cid::Cid;
+use fvm_ipld_blockstore::tracking::TrackingBlockstore;
+use fvm_ipld_blockstore::Blockstore;
+use fvm_ipld_encoding::CborStore;
+use fvm_ipld_hamt::{Hamt, Sha256};
+use serde::Serialize;
+
+use crate::experiments::ExperimentContext;
+use crate::memorydb::MemoryDB;
+use crate::workload::ValueSizes;
+
+#[derive(Debug, Serialize)]
+pub struct ValueSizeResult {
+    n: usize,
+    m: usize,
+    bucket_size: usize,
+    bit_width: u32,
+    value_size: String,
+    avg_value_bytes: f64,
+    inline_total_bytes: u64,
+    inline_avg_node_bytes: f64,
+    inline_lookup_bytes: f64,
+    inline_update_bytes: u64,
+    /// Including the value blocks.
+    linked_total_bytes: u64,
+    linked_avg_node_bytes: f64,
+    /// Bytes of the nodes on the path and of the value block.
+    linked_lookup_bytes: f64,
+    linked_update_bytes: u64,
+}
+
+/// Compares storing values of `sizes` directly in the buckets with storing
+/// each as a block of its own, linked from the bucket by CID: the bytes
+/// stored, the bytes read looking up a random key, and the bytes written
+/// overwriting `m` keys.
+pub fn value_size_experiment<const BUCKET_SIZE: usize>(
+    ctx: &ExperimentContext,
+    bit_width: u32,
+    n: usize,
+    m: usize,
+    lookups: usize,
+    sizes: ValueSizes,
+) -> ValueSizeResult {
+    let mut rng = ctx.rng();
+    let values: Vec<String> = (0..n).map(|_| sizes.value(&mut rng)).collect();
+    let updates: Vec<String> = (0..m).map(|_| sizes.value(&mut rng)).collect();
+    let lookup_keys: Vec<usize> = (0..lookups)
+        .map(|_| rng.below(cmp::max(n, 1) as u64) as usize)
+        .collect();
+    let value_bytes: usize = values.iter().map(String::len).sum();
+
+    let avg_lookup_bytes = |bytes: usize| bytes as f64 / lookups as f64;
+
+    let inline = MemoryDB::default();
+    let mut map: Hamt<_, String, usize, Sha256, BUCKET_SIZE> =
+        Hamt::new_with_bit_width(&inline, bit_width);
+    for (key, value) in values.iter().enumerate() {
+        map.set(key, value.clone()).unwrap();
+    }
+    let root = map.flush().unwrap();
+    let inline_total_bytes = inline.bytes_stored();
+    let inline_avg_node_bytes = inline.bytes_average();
+
+    let mut lookup_bytes = 0;
+    for &key in &lookup_keys {
+        let tracking = TrackingBlockstore::new(&inline);
+        let map: Hamt<_, String, usize, Sha256, BUCKET_SIZE> =
+            Hamt::load_with_bit_width(&root, &tracking, bit_width).unwrap();
+        map.get(&key).unwrap();
+        lookup_bytes += tracking.stats.borrow().br;
+    }
+    let inline_lookup_bytes = avg_lookup_bytes(lookup_bytes);
+
+    for (key, value) in updates.iter().enumerate() {
+        map.set(key, value.clone()).unwrap();
+    }
+    map.flush().unwrap();
+    let inline_update_bytes = inline.bytes_stored() - inline_total_bytes;
+
+    // Value blocks go into a store of their own, so the nodes can be told
+    // apart from them.
+    let linked = MemoryDB::default();
+    let value_store = MemoryDB::default();
+    let linked_bytes = || linked.bytes_stored() + value_store.bytes_stored();
+    let mut map: Hamt<_, Cid, usize, Sha256, BUCKET_SIZE> =
+        Hamt::new_with_bit_width(&linked, bit_width);
+    for (key, value) in values.iter().enumerate() {
+        let cid = value_store.put_cbor(value, Code::Blake2b256).unwrap();
+        map.set(key, cid).unwrap();
+    }
+    let root = map.flush().unwrap();
+    let linked_total_bytes = linked_bytes();
+    let linked_avg_node_bytes = linked.bytes_average();
+
+    let mut lookup_bytes = 0;
+    for &key in &lookup_keys {
+        let tracking = TrackingBlockstore::new(&linked);
+        let map: Hamt<_, Cid, usize, Sha256, BUCKET_SIZE> =
+            Hamt::load_with_bit_width(&root, &tracking, bit_width).unwrap();
+        if let Some(cid) = map.get(&key).unwrap() {
+            lookup_bytes += value_store.get(cid).unwrap().map_or(0, |block| block.len());
+        }
+        lookup_bytes += tracking.stats.borrow().br;
+    }
+    let linked_lookup_bytes = avg_lookup_bytes(lookup_bytes);
+
+    for (key, value) in updates.iter().enumerate() {
+        let cid = value_store.put_cbor(value, Code::Blake2b256).unwrap();
+        map.set(key, cid).unwrap();
+    }
+    map.flush().unwrap();
+    let linked_update_bytes = linked_bytes() - linked_total_bytes;
+
+    ValueSizeResult {
+        n,
+        m,
+        bucket_size: BUCKET_SIZE,
+        bit_width,
+        value_size: sizes.to_string(),
+        avg_value_bytes: value_bytes as f64 / cmp::max(n, 1) as f64,
+        inline_total_bytes,
+        inline_avg_node_bytes,
+        inline_lookup_bytes,
+        inline_update_bytes,
+        linked_total_bytes,
+        linked_avg_node_bytes,
+        linked_lookup_bytes,
+        linked_update_bytes,
+    }
+}
@@ -0,0 +1,77 @@
+//! The `versions` experiment: bytes shared between successive versions, each
+//! updating `m` random keys.
+
+use fvm_ipld_hamt::{Hamt, Sha256};
+use serde::Serialize;
+
+use crate::experiments::ExperimentContext;
+use crate::memorydb::MemoryDB;
+use crate::workload::{Key, Workload};
+
+#[derive(Debug, Serialize)]
+pub struct VersionsResult {
+    n: usize,
+    m: usize,
+    bucket_size: usize,
+    bit_width: u32,
+    version: usize,
+    /// Bytes reachable from this version's root alone.
+    version_bytes: u64,
+    /// Bytes of all versions so far if each was stored on its own.
+    summed_bytes: u64,
+    /// Bytes actually stored for all versions so far.
+    unique_bytes: u64,
+    /// Fraction of `summed_bytes` saved by sharing blocks between versions.
+    shared: f64,
+}
+
+/// Flushes `versions` successive versions into the same store, each
+/// overwriting `m` random keys of the previous one, and compares the stored
+/// bytes with what storing every version separately would take.
+pub fn versions_experiment<const BUCKET_SIZE: usize>(
+    ctx: &ExperimentContext,
+    bit_width: u32,
+    n: usize,
+    m: usize,
+    versions: usize,
+    workload: &Workload,
+) -> Vec<VersionsResult> {
+    let store = MemoryDB::default();
+    let mut map: Hamt<_, _, Key, Sha256, BUCKET_SIZE> = Hamt::new_with_bit_width(&store, bit_width);
+    let value = "F";
+
+    let mut rng = ctx.rng();
+    let keys = workload.keys(n, &mut rng);
+    for key in &keys {
+        map.set(key.clone(), value.to_string()).unwrap();
+    }
+    let sampler = workload.sampler(n);
+    let mut summed_bytes = 0;
+    let mut rows = Vec::with_capacity(versions);
+    for version in 0..versions {
+        if version > 0 {
+            for _ in 0..m {
+                if let Some(key) = keys.get(sampler.sample(&mut rng)) {
+                    map.set(key.clone(), version.to_string()).unwrap();
+                }
+            }
+        }
+        map.flush().unwrap();
+        let (_, version_bytes) = map.par_reachable_size().unwrap();
+        summed_bytes += version_bytes;
+        let unique_bytes = store.bytes_stored();
+
+        rows.push(VersionsResult {
+            n,
+            m,
+            bucket_size: BUCKET_SIZE,
+            bit_width,
+            version,
+            version_bytes,
+            summed_bytes,
+            unique_bytes,
+            shared: 1.0 - unique_bytes as f64 / summed_bytes as f64,
+        });
+    }
+    rows
+}
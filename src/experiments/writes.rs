@@ -0,0 +1,86 @@
+//! The `writes` experiment: nodes and bytes written by the flush after each of
+//! `m` overwrites, and the bytes each leaves behind as garbage.
+
+use anyhow::Result;
+use fvm_ipld_hamt::{Hamt, Sha256};
+use serde::Serialize;
+
+use crate::experiments::ExperimentContext;
+use crate::memorydb::MemoryDB;
+use crate::metered::MeteredStore;
+use crate::workload::{Key, Workload};
+
+#[derive(Debug, Serialize)]
+pub struct WriteResult {
+    n: usize,
+    m: usize,
+    bucket_size: usize,
+    bit_width: u32,
+    /// Index of the `set`, starting at 0.
+    op: usize,
+    /// Nodes serialized by the flush after the `set`, the root included.
+    nodes_written: u64,
+    bytes_written: u64,
+    /// Nodes written that were already in the store.
+    put_hits: u64,
+    /// Growth of the store.
+    new_bytes: u64,
+    /// Bytes of blocks the `set` made unreachable from the new root.
+    garbage_bytes: u64,
+}
+
+/// Overwrites `m` random keys of `workload` one by one, flushing after every
+/// `set`, to tell the path copied by each update apart from the blocks it
+/// leaves behind.
+pub fn writes_experiment<const BUCKET_SIZE: usize>(
+    ctx: &ExperimentContext,
+    bit_width: u32,
+    n: usize,
+    m: usize,
+    workload: &Workload,
+) -> Result<Vec<WriteResult>> {
+    let store = MeteredStore::new(MemoryDB::default());
+    let mut map: Hamt<_, _, Key, Sha256, BUCKET_SIZE> = Hamt::new_with_bit_width(&store, bit_width);
+    let value = "F";
+
+    let mut rng = ctx.rng();
+    let keys = workload.keys(n, &mut rng);
+    for key in &keys {
+        map.set(key.clone(), value.to_string())?;
+    }
+    map.flush()?;
+    let mut stored_bytes = store.inner().bytes_stored();
+    let (_, mut live_bytes) = map.par_reachable_size()?;
+
+    let sampler = workload.sampler(n);
+    let mut rows = Vec::with_capacity(m);
+    for op in 0..m {
+        let key = match keys.get(sampler.sample(&mut rng)) {
+            Some(key) => key,
+            None => break,
+        };
+        map.set(key.clone(), op.to_string())?;
+        let before = store.snapshot();
+        map.flush()?;
+        let traffic = store.snapshot() - before;
+
+        let new_stored_bytes = store.inner().bytes_stored();
+        let (_, new_live_bytes) = map.par_reachable_size()?;
+        let new_bytes = new_stored_bytes - stored_bytes;
+        rows.push(WriteResult {
+            n,
+            m,
+            bucket_size: BUCKET_SIZE,
+            bit_width,
+            op,
+            nodes_written: traffic.puts,
+            bytes_written: traffic.bytes_written,
+            put_hits: traffic.put_hits,
+            new_bytes,
+            garbage_bytes: (new_bytes + live_bytes).saturating_sub(new_live_bytes),
+        });
+        stored_bytes = new_stored_bytes;
+        live_bytes = new_live_bytes;
+    }
+    Ok(rows)
+}
@@ -0,0 +1,163 @@
+//! A blockstore injecting faults into the blocks it returns, to check that
+//! traversals surface missing and corrupt blocks as errors instead of
+//! panicking.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use anyhow::Result;
+use cid::Cid;
+use fvm_ipld_blockstore::Blockstore;
+use parking_lot::Mutex;
+
+use crate::rng::Rng;
+
+/// Probabilities of the faults injected into every block read, at most one
+/// per read.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Faults {
+    /// Returning no block at all, as if it was never stored.
+    pub drop: f64,
+    /// Cutting the block off at a random length.
+    pub truncate: f64,
+    /// Flipping a random bit of the block.
+    pub flip: f64,
+}
+
+/// Wraps a blockstore and corrupts the blocks `get` returns at the rates
+/// given by [`Faults`]. The stored blocks are left alone, so reading a block
+/// again can succeed.
+#[derive(Debug)]
+pub struct FaultyStore<S> {
+    inner: S,
+    faults: Faults,
+    rng: Mutex<Rng>,
+    injected: AtomicU64,
+}
+
+impl<S> FaultyStore<S> {
+    pub fn new(inner: S, faults: Faults, seed: u64) -> Self {
+        FaultyStore {
+            inner,
+            faults,
+            rng: Mutex::new(Rng::new(seed)),
+            injected: AtomicU64::new(0),
+        }
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    /// Number of reads a fault was injected into so far.
+    pub fn injected(&self) -> u64 {
+        self.injected.load(Ordering::Relaxed)
+    }
+}
+
+impl<S: Blockstore> Blockstore for FaultyStore<S> {
+    fn has(&self, k: &Cid) -> Result<bool> {
+        self.inner.has(k)
+    }
+
+    fn get(&self, k: &Cid) -> Result<Option<Vec<u8>>> {
+        let mut block = match self.inner.get(k)? {
+            Some(block) => block,
+            None => return Ok(None),
+        };
+        let Faults {
+            drop,
+            truncate,
+            flip,
+        } = self.faults;
+        let mut rng = self.rng.lock();
+        let roll = rng.next_f64();
+        if roll < drop {
+            self.injected.fetch_add(1, Ordering::Relaxed);
+            return Ok(None);
+        } else if roll < drop + truncate {
+            block.truncate(rng.below(block.len() as u64) as usize);
+        } else if roll < drop + truncate + flip {
+            let bit = rng.below(8 * block.len() as u64);
+            block[bit as usize / 8] ^= 1 << (bit % 8);
+        } else {
+            return Ok(Some(block));
+        }
+        self.injected.fetch_add(1, Ordering::Relaxed);
+        Ok(Some(block))
+    }
+
+    fn put_keyed(&self, k: &Cid, block: &[u8]) -> Result<()> {
+        self.inner.put_keyed(k, block)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memorydb::MemoryDB;
+    use fvm_ipld_hamt::{Hamt, Sha256};
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    type Map<'a> = Hamt<&'a FaultyStore<&'a MemoryDB>, String, usize, Sha256, 3>;
+
+    /// Reads, updates and deletes some keys of the HAMT at `root`, returning
+    /// the first error.
+    fn exercise(store: &FaultyStore<&MemoryDB>, root: &Cid) -> Result<()> {
+        let mut map: Map = Hamt::load_with_bit_width(root, store, 4)?;
+        for key in 0..100 {
+            map.get(&key)?;
+        }
+        for entry in map.iter() {
+            entry?;
+        }
+        for key in 0..20 {
+            map.set(key, "new".to_string())?;
+            map.delete(&(key + 1000))?;
+        }
+        map.flush()?;
+        Ok(())
+    }
+
+    #[test]
+    fn faults_surface_as_errors() -> Result<()> {
+        let store = MemoryDB::default();
+        let mut map: Hamt<_, String, usize, Sha256, 3> = Hamt::new_with_bit_width(&store, 4);
+        for key in 0..2000 {
+            map.set(key, "F".to_string())?;
+        }
+        let root = map.flush()?;
+
+        let kinds = [
+            Faults {
+                drop: 0.1,
+                ..Faults::default()
+            },
+            Faults {
+                truncate: 0.1,
+                ..Faults::default()
+            },
+            Faults {
+                flip: 0.1,
+                ..Faults::default()
+            },
+        ];
+        for faults in kinds {
+            let mut errors = 0;
+            for seed in 0..50 {
+                let faulty = FaultyStore::new(&store, faults, seed);
+                let result = catch_unwind(AssertUnwindSafe(|| exercise(&faulty, &root)));
+                match result {
+                    Ok(result) => errors += result.is_err() as usize,
+                    Err(_) => panic!("{faults:?} with seed {seed} panicked"),
+                }
+            }
+            // Flipped bits can still decode, into other entries.
+            assert!(errors > 0, "{faults:?} never caused an error");
+        }
+
+        let clean = FaultyStore::new(&store, Faults::default(), 0);
+        exercise(&clean, &root)?;
+        assert_eq!(clean.injected(), 0);
+        Ok(())
+    }
+}
@@ -0,0 +1,130 @@
+//! Fetching a DAG from a peer over a request/response protocol like
+//! bitswap, where only blocks whose CIDs are already known can be requested.
+//!
+//! Every round requests the CIDs discovered so far, so the number of rounds
+//! is the depth of the DAG however wide it is, and round trips rather than
+//! bytes dominate fetching deep trees.
+
+use std::collections::HashSet;
+
+use anyhow::{anyhow, Result};
+use cid::Cid;
+use fvm_ipld_blockstore::Blockstore;
+use fvm_ipld_encoding::{from_slice, DAG_CBOR};
+use libipld_core::ipld::Ipld;
+use parking_lot::Mutex;
+
+/// Blocks and bytes received in one round of requests.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Round {
+    pub blocks: u64,
+    pub bytes: u64,
+}
+
+/// Fetches everything reachable from `root`, every round requesting all
+/// links found in the blocks of the previous one. Only DAG-CBOR blocks are
+/// followed.
+pub fn fetch_all<S: Blockstore>(store: &S, root: &Cid) -> Result<Vec<Round>> {
+    let mut seen = HashSet::from([*root]);
+    let mut wanted = vec![*root];
+    let mut rounds = Vec::new();
+    while !wanted.is_empty() {
+        let mut round = Round::default();
+        let mut discovered = Vec::new();
+        for cid in wanted {
+            let block = store
+                .get(&cid)?
+                .ok_or_else(|| anyhow!("block {cid} not found"))?;
+            round.blocks += 1;
+            round.bytes += block.len() as u64;
+            if cid.codec() == DAG_CBOR {
+                let ipld: Ipld = from_slice(&block)?;
+                let mut links = Vec::new();
+                ipld.references(&mut links);
+                discovered.extend(links.into_iter().filter(|link| seen.insert(*link)));
+            }
+        }
+        rounds.push(round);
+        wanted = discovered;
+    }
+    Ok(rounds)
+}
+
+/// Wraps a blockstore as a remote peer, recording every `get` as a round of
+/// its own, as a client walking a HAMT one node at a time has to.
+#[derive(Debug)]
+pub struct RoundCounter<S> {
+    inner: S,
+    rounds: Mutex<Vec<Round>>,
+}
+
+impl<S> RoundCounter<S> {
+    pub fn new(inner: S) -> Self {
+        RoundCounter {
+            inner,
+            rounds: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    /// Rounds since the last `take_rounds`, which starts counting over.
+    pub fn take_rounds(&self) -> Vec<Round> {
+        std::mem::take(&mut *self.rounds.lock())
+    }
+}
+
+impl<S: Blockstore> Blockstore for RoundCounter<S> {
+    fn get(&self, k: &Cid) -> Result<Option<Vec<u8>>> {
+        let block = self.inner.get(k)?;
+        if let Some(block) = &block {
+            self.rounds.lock().push(Round {
+                blocks: 1,
+                bytes: block.len() as u64,
+            });
+        }
+        Ok(block)
+    }
+
+    fn put_keyed(&self, k: &Cid, block: &[u8]) -> Result<()> {
+        self.inner.put_keyed(k, block)
+    }
+
+    fn has(&self, k: &Cid) -> Result<bool> {
+        self.inner.has(k)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memorydb::MemoryDB;
+    use fvm_ipld_hamt::{Hamt, Sha256};
+
+    #[test]
+    fn rounds_follow_the_levels() -> Result<()> {
+        let store = MemoryDB::default();
+        let mut map: Hamt<_, String, usize, Sha256, 3> = Hamt::new_with_bit_width(&store, 4);
+        for key in 0..1000 {
+            map.set(key, "F".to_string())?;
+        }
+        let root = map.flush()?;
+
+        let rounds = fetch_all(&store, &root)?;
+        assert_eq!(rounds[0].blocks, 1);
+        assert_eq!(
+            rounds.iter().map(|round| round.bytes).sum::<u64>(),
+            store.live_bytes(&[root])?
+        );
+
+        let remote = RoundCounter::new(&store);
+        let map: Hamt<_, String, usize, Sha256, 3> = Hamt::load_with_bit_width(&root, &remote, 4)?;
+        assert_eq!(map.get(&7)?.map(String::as_str), Some("F"));
+        let key_rounds = remote.take_rounds();
+        assert!(key_rounds.len() > 1 && key_rounds.len() <= rounds.len());
+        assert!(remote.take_rounds().is_empty());
+        Ok(())
+    }
+}
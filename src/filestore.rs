@@ -0,0 +1,155 @@
+use anyhow::{Context, Result};
+use cid::Cid;
+use fvm_ipld_blockstore::Blockstore;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Content-addressed files in a directory, one per block.
+///
+/// Blocks are sharded into subdirectories by the next-to-last two characters
+/// of their CID, like the flatfs datastore of go-ipfs, so no directory grows
+/// too large.
+#[derive(Debug)]
+pub struct FileStore {
+    dir: PathBuf,
+    /// Whether `dir` is removed again when the store is dropped.
+    temporary: bool,
+}
+
+impl FileStore {
+    /// Opens the store in `dir`, creating the directory if necessary.
+    pub fn open(dir: impl Into<PathBuf>) -> Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)
+            .with_context(|| format!("couldn't create store directory {}", dir.display()))?;
+        Ok(FileStore {
+            dir,
+            temporary: false,
+        })
+    }
+
+    /// Opens a store in a fresh directory below the system's temporary
+    /// directory, which is deleted when the store is dropped.
+    pub fn temporary() -> Result<Self> {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH)?.as_nanos();
+        let name = format!(
+            "rust-ipld-hamt-{}-{nanos}-{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        );
+        let mut store = Self::open(std::env::temp_dir().join(name))?;
+        store.temporary = true;
+        Ok(store)
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Sum of the sizes of all block files.
+    pub fn bytes_stored(&self) -> Result<u64> {
+        let mut bytes = 0;
+        self.for_each_file(|metadata| bytes += metadata.len())?;
+        Ok(bytes)
+    }
+
+    pub fn blocks(&self) -> Result<usize> {
+        let mut blocks = 0;
+        self.for_each_file(|_| blocks += 1)?;
+        Ok(blocks)
+    }
+
+    fn for_each_file(&self, mut f: impl FnMut(fs::Metadata)) -> Result<()> {
+        for shard in fs::read_dir(&self.dir)? {
+            let shard = shard?;
+            if !shard.file_type()?.is_dir() {
+                continue;
+            }
+            for file in fs::read_dir(shard.path())? {
+                let metadata = file?.metadata()?;
+                if metadata.is_file() {
+                    f(metadata);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn path(&self, cid: &Cid) -> PathBuf {
+        let name = cid.to_string();
+        let shard = &name[name.len() - 3..name.len() - 1];
+        self.dir.join(shard).join(name)
+    }
+}
+
+impl Drop for FileStore {
+    fn drop(&mut self) {
+        if self.temporary {
+            let _ = fs::remove_dir_all(&self.dir);
+        }
+    }
+}
+
+impl Blockstore for FileStore {
+    fn has(&self, k: &Cid) -> Result<bool> {
+        Ok(self.path(k).is_file())
+    }
+
+    fn get(&self, k: &Cid) -> Result<Option<Vec<u8>>> {
+        match fs::read(self.path(k)) {
+            Ok(block) => Ok(Some(block)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn put_keyed(&self, k: &Cid, block: &[u8]) -> Result<()> {
+        let path = self.path(k);
+        if path.is_file() {
+            return Ok(());
+        }
+        fs::create_dir_all(path.parent().expect("block paths have a shard directory"))?;
+        // Write to a temporary file first, so readers never see partial blocks.
+        let partial = path.with_extension("partial");
+        fs::write(&partial, block)?;
+        fs::rename(&partial, &path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memorydb::MemoryDB;
+    use fvm_ipld_hamt::{Hamt, Sha256};
+
+    #[test]
+    fn stores_the_same_hamt_as_memory() -> Result<()> {
+        let files = FileStore::temporary()?;
+        let memory = MemoryDB::default();
+        let mut on_disk: Hamt<_, String, usize, Sha256, 3> = Hamt::new_with_bit_width(&files, 4);
+        let mut in_memory: Hamt<_, String, usize, Sha256, 3> = Hamt::new_with_bit_width(&memory, 4);
+        for key in 0..1000 {
+            on_disk.set(key, "F".to_string())?;
+            in_memory.set(key, "F".to_string())?;
+        }
+        let root = on_disk.flush()?;
+        assert_eq!(root, in_memory.flush()?);
+        assert_eq!(files.bytes_stored()?, memory.bytes_stored());
+
+        let reloaded: Hamt<_, String, usize, Sha256, 3> =
+            Hamt::load_with_bit_width(&root, &files, 4)?;
+        assert_eq!(reloaded.get(&999)?, Some(&"F".to_string()));
+
+        let dir = files.dir().to_owned();
+        drop(reloaded);
+        drop(on_disk);
+        drop(files);
+        assert!(!dir.exists());
+        Ok(())
+    }
+}
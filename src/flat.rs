@@ -0,0 +1,135 @@
+//! The simplest alternative to a HAMT: all entries in a single block, a CBOR
+//! map that's rewritten completely whenever it's flushed after a change.
+//!
+//! Reading any entry means fetching the whole block and every change writes
+//! all entries again, but for small maps a single block is as small as it
+//! gets, which makes it a baseline for the overhead of the HAMT's nodes.
+
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use cid::multihash::Code;
+use cid::Cid;
+use fvm_ipld_blockstore::{Block, Blockstore};
+use fvm_ipld_encoding::{to_vec, DAG_CBOR};
+use serde::Serialize;
+
+use crate::map::{IpldMap, MapStats};
+
+pub struct FlatMap<'a, BS, K, V> {
+    store: &'a BS,
+    entries: BTreeMap<K, V>,
+    /// CID and length of the block as of the last flush, unless there were
+    /// changes since.
+    block: Option<(Cid, u64)>,
+}
+
+impl<'a, BS: Blockstore, K: Ord + Serialize, V: Serialize> FlatMap<'a, BS, K, V> {
+    pub fn new(store: &'a BS) -> Self {
+        FlatMap {
+            store,
+            entries: BTreeMap::new(),
+            block: None,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get(&self, key: &K) -> Option<&V> {
+        self.entries.get(key)
+    }
+
+    pub fn set(&mut self, key: K, value: V) -> Option<V> {
+        self.block = None;
+        self.entries.insert(key, value)
+    }
+
+    pub fn delete(&mut self, key: &K) -> Option<V> {
+        let old = self.entries.remove(key);
+        if old.is_some() {
+            self.block = None;
+        }
+        old
+    }
+
+    /// Writes all entries as one block, unless they're unchanged since the
+    /// last flush.
+    pub fn flush(&mut self) -> Result<Cid> {
+        if let Some((cid, _)) = self.block {
+            return Ok(cid);
+        }
+        let bytes = to_vec(&self.entries)?;
+        let cid = self
+            .store
+            .put(Code::Blake2b256, &Block::new(DAG_CBOR, &bytes))?;
+        self.block = Some((cid, bytes.len() as u64));
+        Ok(cid)
+    }
+
+    /// Length of the block, which is also the proof of any entry. `None` if
+    /// there are unflushed changes.
+    pub fn block_size(&self) -> Option<u64> {
+        self.block.map(|(_, size)| size)
+    }
+}
+
+impl<BS: Blockstore, K: Ord + Serialize, V: Serialize> IpldMap<K, V> for FlatMap<'_, BS, K, V> {
+    fn set(&mut self, key: K, value: V) -> Result<Option<V>> {
+        Ok(FlatMap::set(self, key, value))
+    }
+
+    fn get(&self, key: &K) -> Result<Option<&V>> {
+        Ok(FlatMap::get(self, key))
+    }
+
+    fn delete(&mut self, key: &K) -> Result<Option<V>> {
+        Ok(FlatMap::delete(self, key))
+    }
+
+    fn flush(&mut self) -> Result<Cid> {
+        FlatMap::flush(self)
+    }
+
+    fn stats(&self) -> Result<MapStats> {
+        Ok(MapStats {
+            nodes: 1,
+            values: self.len() as u64,
+            height: 0,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memorydb::MemoryDB;
+
+    #[test]
+    fn rewrites_the_block_on_changes() -> Result<()> {
+        let store = MemoryDB::default();
+        let mut map = FlatMap::new(&store);
+        map.set(1u64, "a".to_string());
+        map.set(2, "b".to_string());
+        let first = map.flush()?;
+        // {1: "a", 2: "b"}
+        assert_eq!(map.block_size(), Some(7));
+        assert_eq!(map.flush()?, first);
+        assert_eq!(store.blocks(), 1);
+
+        assert_eq!(map.delete(&3), None);
+        assert_eq!(map.flush()?, first);
+        assert_eq!(map.set(1, "c".to_string()), Some("a".to_string()));
+        assert_eq!(map.block_size(), None);
+        assert_ne!(map.flush()?, first);
+        assert_eq!(store.blocks(), 2);
+        assert_eq!(map.get(&1), Some(&"c".to_string()));
+        assert_eq!(map.len(), 2);
+        Ok(())
+    }
+}
@@ -0,0 +1,136 @@
+//! When to flush a map that's being updated, as a database commits after
+//! every transaction, in batches, or once enough changed.
+
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail};
+
+/// How often changes are flushed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlushPolicy {
+    /// After every operation.
+    Eager,
+    /// After every `k` operations.
+    Every(usize),
+    /// Once the entries changed since the last flush encode to at least
+    /// this many bytes. The nodes on their paths aren't counted, so this is
+    /// a lower bound of what the flush writes.
+    DirtyBytes(u64),
+}
+
+/// Policies the `flush` experiment compares unless `--flush` is given.
+pub const FLUSH_POLICIES: &[FlushPolicy] = &[
+    FlushPolicy::Eager,
+    FlushPolicy::Every(10),
+    FlushPolicy::Every(100),
+    FlushPolicy::DirtyBytes(1 << 10),
+    FlushPolicy::DirtyBytes(1 << 14),
+];
+
+impl FlushPolicy {
+    /// Starts counting operations from a flush.
+    pub fn flusher(self) -> Flusher {
+        Flusher {
+            policy: self,
+            ops: 0,
+            dirty_bytes: 0,
+        }
+    }
+}
+
+/// Counts the operations since the last flush under a [`FlushPolicy`].
+#[derive(Debug, Clone)]
+pub struct Flusher {
+    policy: FlushPolicy,
+    ops: usize,
+    dirty_bytes: u64,
+}
+
+impl Flusher {
+    /// Records an operation changing an entry that encodes to `bytes`, and
+    /// returns whether to flush now. Counting starts over if it does.
+    pub fn record(&mut self, bytes: u64) -> bool {
+        self.ops += 1;
+        self.dirty_bytes += bytes;
+        let flush = match self.policy {
+            FlushPolicy::Eager => true,
+            FlushPolicy::Every(k) => self.ops >= k,
+            FlushPolicy::DirtyBytes(threshold) => self.dirty_bytes >= threshold,
+        };
+        if flush {
+            self.ops = 0;
+            self.dirty_bytes = 0;
+        }
+        flush
+    }
+
+    /// Whether operations were recorded since the last flush.
+    pub fn is_dirty(&self) -> bool {
+        self.ops > 0
+    }
+}
+
+impl fmt::Display for FlushPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FlushPolicy::Eager => f.write_str("eager"),
+            FlushPolicy::Every(k) => write!(f, "every:{k}"),
+            FlushPolicy::DirtyBytes(bytes) => write!(f, "bytes:{bytes}"),
+        }
+    }
+}
+
+impl FromStr for FlushPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let count = |count: &str| {
+            count
+                .parse()
+                .ok()
+                .filter(|&count| count > 0)
+                .ok_or_else(|| anyhow!("invalid count `{count}`, expected a positive integer"))
+        };
+        let policy = match s.split_once(':') {
+            None if s == "eager" => FlushPolicy::Eager,
+            Some(("every", k)) => FlushPolicy::Every(count(k)?),
+            Some(("bytes", bytes)) => FlushPolicy::DirtyBytes(count(bytes)? as u64),
+            _ => bail!("unknown flush policy `{s}`, expected `eager`, `every:<k>` or `bytes:<n>`"),
+        };
+        Ok(policy)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_and_prints_policies() {
+        for policy in FLUSH_POLICIES {
+            assert_eq!(policy.to_string().parse::<FlushPolicy>().unwrap(), *policy);
+        }
+        assert!("every:0".parse::<FlushPolicy>().is_err());
+        assert!("bytes".parse::<FlushPolicy>().is_err());
+        assert!("lazy".parse::<FlushPolicy>().is_err());
+    }
+
+    #[test]
+    fn flushes_by_count_and_bytes() {
+        let flushes = |policy: FlushPolicy, bytes: &[u64]| {
+            let mut flusher = policy.flusher();
+            let flushes: Vec<bool> = bytes.iter().map(|&b| flusher.record(b)).collect();
+            (flushes, flusher.is_dirty())
+        };
+        assert_eq!(flushes(FlushPolicy::Eager, &[1, 1]), (vec![true, true], false));
+        assert_eq!(
+            flushes(FlushPolicy::Every(2), &[1, 1, 1]),
+            (vec![false, true, false], true)
+        );
+        assert_eq!(
+            flushes(FlushPolicy::DirtyBytes(10), &[4, 4, 4, 20, 1]),
+            (vec![false, false, true, true, false], true)
+        );
+    }
+}
@@ -0,0 +1,207 @@
+//! Structural invariants of a HAMT, which every sequence of operations has to
+//! preserve for roots to be canonical.
+//!
+//! - The bitfield of a node has a bit set for each of its pointers.
+//! - Nodes other than the root aren't empty.
+//! - Buckets aren't empty and hold at most `BUCKET_SIZE` entries, unless the
+//!   hashes of their keys are exhausted or they are at the maximum depth.
+//! - No node is below the maximum depth.
+//! - The keys of a bucket are sorted and unique.
+//! - Every key is in the slot its hash selects at that depth.
+//! - No node below the root could collapse into a bucket of its parent, which
+//!   deletes take care of.
+//! - With path compression, no flushed node below the root has a single
+//!   pointer to another node, and no node skipping levels could collapse.
+
+use anyhow::{ensure, Result};
+use fvm_ipld_blockstore::Blockstore;
+use fvm_ipld_encoding::de::DeserializeOwned;
+use fvm_ipld_hamt::hash_bits::HashBits;
+use fvm_ipld_hamt::node::Node;
+use fvm_ipld_hamt::pointer::Pointer;
+use fvm_ipld_hamt::{Hamt, Hash, HashAlgorithm};
+use serde::Serialize;
+
+use crate::traverse::{resolved, Resolved};
+
+/// Checks the invariants of every node, loading them from the store if
+/// needed. Errors name the first broken one and the slots on the path to the
+/// node it's broken in.
+pub fn verify_invariants<BS, K, V, H, const BUCKET_SIZE: usize>(
+    hamt: &Hamt<BS, V, K, H, BUCKET_SIZE>,
+) -> Result<()>
+where
+    BS: Blockstore,
+    K: Hash + Eq + PartialOrd + Serialize + DeserializeOwned,
+    V: Serialize + DeserializeOwned,
+    H: HashAlgorithm,
+{
+    verify_node(
+        &hamt.root,
+        hamt.store(),
+        hamt.bit_width,
+        hamt.hash_limit(),
+        hamt.path_compression,
+        &hamt.salt,
+        false,
+        &mut Vec::new(),
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+fn verify_node<S, K, V, H, const BUCKET_SIZE: usize>(
+    node: &Node<K, V, H, BUCKET_SIZE>,
+    store: &S,
+    bit_width: u32,
+    limit: u32,
+    compressed: bool,
+    salt: &[u8],
+    flushed: bool,
+    path: &mut Vec<u32>,
+) -> Result<()>
+where
+    S: Blockstore,
+    K: Hash + Eq + PartialOrd + DeserializeOwned,
+    V: DeserializeOwned,
+    H: HashAlgorithm,
+{
+    // Skipped levels count like nodes of their own.
+    path.extend(node.skip.iter().map(|&slot| slot as u32));
+    let depth = path.len() as u32;
+    ensure!(
+        node.bitfield.count_ones() == node.pointers.len(),
+        "node {path:?} has {} bits set but {} pointers",
+        node.bitfield.count_ones(),
+        node.pointers.len()
+    );
+    ensure!(
+        (1u32 << bit_width..256).all(|idx| !node.bitfield.test_bit(idx)),
+        "node {path:?} has bits set beyond its width"
+    );
+    ensure!(
+        depth * bit_width < limit,
+        "node {path:?} is below the maximum depth"
+    );
+    if depth > 0 {
+        ensure!(!node.pointers.is_empty(), "node {path:?} is empty");
+        ensure!(
+            !is_collapsible(node),
+            "node {path:?} should have collapsed into its parent"
+        );
+    }
+    if compressed && flushed {
+        ensure!(
+            !matches!(node.pointers[..], [Pointer::Link { .. }]),
+            "node {path:?} should have merged with its only child"
+        );
+    }
+
+    let slots = (0..1u32 << bit_width).filter(|&idx| node.bitfield.test_bit(idx));
+    for (idx, pointer) in slots.zip(&node.pointers) {
+        match resolved(pointer, store)? {
+            Resolved::Link(child) => {
+                let flushed = matches!(pointer, Pointer::Link { .. });
+                path.push(idx);
+                verify_node(
+                    child, store, bit_width, limit, compressed, salt, flushed, path,
+                )?;
+                path.pop();
+            }
+            Resolved::Bucket(bucket) => {
+                ensure!(!bucket.is_empty(), "bucket {idx} of node {path:?} is empty");
+                // Only buckets at the deepest level overflow.
+                let hash = H::hash_salted(bucket[0].key(), salt);
+                let exhausted = HashBits::new_at_index(&hash, (depth + 1) * bit_width)
+                    .with_limit(limit)
+                    .exhausted(bit_width);
+                ensure!(
+                    bucket.len() <= BUCKET_SIZE || exhausted,
+                    "bucket {idx} of node {path:?} has {} entries",
+                    bucket.len()
+                );
+                ensure!(
+                    bucket.windows(2).all(|pair| pair[0].key() < pair[1].key()),
+                    "bucket {idx} of node {path:?} isn't sorted"
+                );
+                for entry in bucket {
+                    let hash = H::hash_salted(entry.key(), salt);
+                    let mut bits = HashBits::new(&hash).with_limit(H::BITS);
+                    let slots = (0..=depth)
+                        .map(|_| bits.next(bit_width))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    ensure!(
+                        slots[..path.len()] == path[..],
+                        "bucket {idx} of node {path:?} has a key of path {slots:?}"
+                    );
+                    let slot = slots[path.len()];
+                    ensure!(
+                        slot == idx,
+                        "bucket {idx} of node {path:?} has a key of slot {slot}"
+                    );
+                }
+            }
+        }
+    }
+    path.truncate(path.len() - node.skip.len());
+    Ok(())
+}
+
+/// Whether deleting from `node` should have replaced it by a bucket, see
+/// `Pointer::clean`.
+pub(crate) fn is_collapsible<K, V, H, const BUCKET_SIZE: usize>(node: &Node<K, V, H, BUCKET_SIZE>) -> bool {
+    let mut values = 0;
+    for pointer in &node.pointers {
+        match pointer {
+            Pointer::Values(bucket) => values += bucket.len(),
+            _ => return false,
+        }
+    }
+    values <= BUCKET_SIZE
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memorydb::MemoryDB;
+    use fvm_ipld_hamt::{KeyValuePair, Sha256};
+
+    #[test]
+    fn holds_after_sets_and_deletes() -> Result<()> {
+        let store = MemoryDB::default();
+        let mut map: Hamt<_, _, u64, Sha256, 3> = Hamt::new_with_bit_width(&store, 2);
+        for key in 0..500 {
+            map.set(key, key)?;
+        }
+        verify_invariants(&map)?;
+        for key in (0..500).filter(|key| key % 3 != 0) {
+            map.delete(&key)?;
+            verify_invariants(&map)?;
+        }
+        let cid = map.flush()?;
+        let loaded: Hamt<_, u64, u64, Sha256, 3> = Hamt::load_with_bit_width(&cid, &store, 2)?;
+        verify_invariants(&loaded)
+    }
+
+    #[test]
+    fn finds_broken_nodes() -> Result<()> {
+        let store = MemoryDB::default();
+        let mut map: Hamt<_, _, u64, Sha256, 3> = Hamt::new_with_bit_width(&store, 2);
+        map.set(0, 0)?;
+        verify_invariants(&map)?;
+
+        let Pointer::Values(bucket) = &mut map.root.pointers[0] else {
+            panic!("expected a bucket");
+        };
+        bucket.push(KeyValuePair::new(0, 0));
+        let err = verify_invariants(&map).unwrap_err();
+        assert!(
+            err.to_string().ends_with("of node [] isn't sorted"),
+            "{err}"
+        );
+
+        map.root.pointers.push(Pointer::Values(Vec::new()));
+        let err = verify_invariants(&map).unwrap_err();
+        assert!(err.to_string().contains("bits set but"), "{err}");
+        Ok(())
+    }
+}
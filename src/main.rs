@@ -1,437 +1,926 @@
+pub mod amt;
+pub mod analyze;
+pub mod bit_width;
+pub mod btree;
+pub mod bucket;
+pub mod cache;
+pub mod canonical;
+pub mod car;
+pub mod cases;
+pub mod champ;
+mod cli;
+pub mod compare;
+pub mod compressed;
+pub mod delayed;
+pub mod diff;
+pub mod environment;
+pub mod experiments;
+pub mod faulty;
+pub mod fetch;
+pub mod filestore;
+pub mod flat;
+pub mod flush;
+pub mod invariants;
+pub mod map;
+pub mod memory;
 pub mod memorydb;
+pub mod metered;
+pub mod output;
+pub mod plot;
+#[cfg(feature = "profile")]
+pub mod profile;
+pub mod progress;
+pub mod prolly;
+pub mod proof;
+pub mod radix;
+pub mod refcount;
+pub mod regression;
+pub mod repeat;
+pub mod replay;
+pub mod report;
+pub mod rng;
+pub mod selector;
+pub mod stats;
+pub mod study;
+pub mod sync;
+pub mod traverse;
+pub mod verify;
+pub mod viz;
+pub mod wnfs;
+pub mod workload;
 
 #[cfg(test)]
 mod tests;
 
-use std::{cmp, ops::AddAssign};
+use std::{
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Write},
+    path::Path,
+};
 
+use amt::amt_experiment;
+use anyhow::{bail, Context, Result};
+use bucket::with_bucket_size;
+use cache::CACHE_SIZES;
+use champ::champ_experiment;
 use cid::Cid;
-use fvm_ipld_blockstore::Blockstore;
-use fvm_ipld_encoding::{de::DeserializeOwned, serde_bytes::Deserialize, CborStore};
-use fvm_ipld_hamt::{
-    bitfield::Bitfield, node::Node, pointer::Pointer, Hamt, Hash, HashAlgorithm, KeyValuePair,
-    Sha256,
+use cli::{Command, Experiment, Params};
+use environment::Environment;
+use experiments::{
+    batch::batch_experiment,
+    bitfield::bitfield_experiment,
+    blocks::block_size_experiment,
+    cache::cache_experiment,
+    chain::chain_experiment,
+    cids::{cid_experiment, CODECS, MULTIHASHES},
+    collisions::collision_experiment,
+    compression::compression_experiment,
+    degree::degree_experiment,
+    delete::deletion_experiment,
+    delta::delta_experiment,
+    depth::depth_experiment,
+    disk::disk_experiment,
+    external::external_value_experiment,
+    fetch::fetch_experiment,
+    flush::flush_experiment,
+    gc::gc_experiment,
+    hash_only::hash_only_experiment,
+    hashes::hash_experiment,
+    keys::key_length_experiment,
+    len::len_experiment,
+    levels::levels_experiment,
+    lookup::lookup_experiment,
+    max_depth::max_depth_experiment,
+    memory::memory_experiment,
+    migration::{migration_experiment, MigrationTarget, Target},
+    multi_proof::multi_proof_experiment,
+    nested::{nested_experiment, ChildMaps, Children},
+    node_format::node_format_experiment,
+    occupancy::occupancy_experiment,
+    paging::paging_experiment,
+    proof::proof_experiment,
+    refcount::refcount_experiment,
+    replay::replay_experiment,
+    salt::salt_experiment,
+    sample::sample_experiment,
+    scan::scan_experiment,
+    selectors::selector_experiment,
+    sizes::experiment,
+    skip::skip_experiment,
+    time_series::{time_series_experiment, TIME_SERIES_FLUSH},
+    values::value_size_experiment,
+    versions::versions_experiment,
+    writes::writes_experiment,
+    ExperimentContext,
 };
+use filestore::FileStore;
+use flush::FLUSH_POLICIES;
+use fvm_ipld_hamt::{Blake3, CidConfig, Hamt, Sha256, Truncated, XxHash};
 use memorydb::MemoryDB;
-use once_cell::unsync::OnceCell;
-use serde::Serialize;
+use output::{Format, Manifest, ResultsWriter};
+use plot::Distribution;
+use progress::Progress;
+use radix::radix_experiment;
+use replay::Trace;
+use report::{Report, Section, Snapshot};
+use viz::{Graph, Renderer};
+use wnfs::{wnfs_experiment, FILE_SIZES};
+use workload::ValueSizes;
 
+#[cfg(test)]
 const BUCKET_SIZE: usize = 1;
 
-fn main() {
-    test_hamt_dot();
-    // println!("Bucket Size {BUCKET_SIZE}");
-    // ExperimentResult::print_csv_header();
-
-    // let n = 100_000;
-    // let step_size = 1;
-    // let window_start = 0;
-    // let window_end = 100;
-    // let max_steps = (window_end - window_start) / step_size;
-    // let mut ms = vec![];
-
-    // for x in 0..max_steps {
-    //     ms.push((x + 1) * step_size + window_start);
-    // }
-
-    // for m in ms.iter() {
-    //     experiment::<BUCKET_SIZE>(4, n, *m).print_csv();
-    // }
-}
-
-struct ExperimentResult {
-    n: usize,
-    m: usize,
-    bucket_size: usize,
-    bit_width: u32,
-    total_bytes: u64,
-    byte_difference: u64,
+fn main() -> Result<()> {
+    run_command(cli::parse(std::env::args().skip(1))?)
 }
 
-impl ExperimentResult {
-    fn print_csv_header() {
-        println!("\n\nn;m;bucket_size;bit_width;total_bytes;byte_diff");
+fn run_command(command: Command) -> Result<()> {
+    match command {
+        Command::Help(help) => println!("{help}"),
+        Command::Experiment(kind, params) => {
+            let mut out = open_checkpointed(kind, &params)?.with_column("seed", &params.seed)?;
+            if params.plot.is_some() {
+                out = out.keeping_records();
+            }
+            run_experiment(kind, &params, &mut out)?;
+            if let Some(chart) = &params.plot {
+                let path = params
+                    .plot_output
+                    .clone()
+                    .unwrap_or_else(|| chart.default_path());
+                chart.render_to_file(out.kept_records(), &path)?;
+            }
+            out.finish()?;
+        }
+        Command::Report(experiments, params) => {
+            let parameters = params.summary().into_iter();
+            let mut report = Report::new("HAMT experiments")
+                .with_parameters(parameters.chain(Environment::current().summary()));
+            for experiment in experiments {
+                let mut out = ResultsWriter::in_memory().with_column("seed", &params.seed)?;
+                run_experiment(experiment, &params, &mut out)?;
+                let mut section = Section::new(experiment.name(), out.into_kept_records());
+                if experiment == Experiment::Occupancy {
+                    section = section
+                        .with_distribution(&Distribution::new("set_bits", "share", "depth"))?;
+                }
+                report.add_section(section);
+            }
+            report.add_section(snapshot_section(&params)?);
+            match &params.output {
+                Some(path) => report.render_to_file(path)?,
+                None => report.render(&mut io::stdout().lock())?,
+            }
+        }
+        Command::Dot(params, renderer) => {
+            let graph = with_bucket_size!(params.bucket_sizes.single()?, B => {
+                hamt_graph::<B>(params.bit_widths.single()?, params.n)
+            });
+            render_graph(&renderer, &graph, params.output.as_deref())?;
+        }
+        Command::DotDiff(params, renderer) => {
+            let graph = with_bucket_size!(params.bucket_sizes.single()?, B => {
+                hamt_diff_graph::<B>(params.bit_widths.single()?, params.n, params.m)?
+            });
+            render_graph(&renderer, &graph, params.output.as_deref())?;
+        }
+        Command::Car(params, version) => {
+            let store = MemoryDB::default();
+            let root = with_bucket_size!(params.bucket_sizes.single()?, B => {
+                build_hamt::<B>(&store, params.bit_widths.single()?, params.n)?
+            });
+            match &params.output {
+                Some(path) => {
+                    let mut out = BufWriter::new(File::create(path)?);
+                    car::write_car(&store, &[root], version, &mut out)?;
+                    out.flush()?;
+                }
+                None => car::write_car(&store, &[root], version, &mut io::stdout().lock())?,
+            }
+        }
+        Command::Regression(check, params) => match check {
+            Some(path) => {
+                let vectors = regression::parse(&fs::read_to_string(&path)?)?;
+                let mut mismatches = 0;
+                for vector in &vectors {
+                    let root = vector.build()?;
+                    if root == vector.root {
+                        println!("ok {}", vector.name);
+                    } else {
+                        mismatches += 1;
+                        println!(
+                            "mismatch {}: expected {}, built {root}",
+                            vector.name, vector.root
+                        );
+                    }
+                }
+                if mismatches > 0 {
+                    bail!("{mismatches} of {} vectors don't match", vectors.len());
+                }
+            }
+            None => {
+                let text = regression::generate()?;
+                match &params.output {
+                    Some(path) => fs::write(path, text)?,
+                    None => print!("{text}"),
+                }
+            }
+        },
+        Command::Cases(path, params) => {
+            let cases = cases::parse(&fs::read_to_string(&path)?)
+                .with_context(|| format!("reading {}", path.display()))?;
+            let mut out = open_results(&params)?;
+            let mut failed = 0;
+            for case in &cases {
+                let outcome = case.run();
+                failed += !outcome.passed as usize;
+                out.write(&outcome)?;
+            }
+            out.finish()?;
+            if failed > 0 {
+                bail!("{failed} of {} cases failed", cases.len());
+            }
+        }
+        Command::Compare(baseline, current, threshold, params) => {
+            let read = |path: &Path| {
+                fs::read_to_string(path)
+                    .map_err(Into::into)
+                    .and_then(|text| output::read_records(&text))
+                    .with_context(|| format!("reading {}", path.display()))
+            };
+            let deltas = compare::compare(&read(&baseline)?, &read(&current)?, &threshold);
+            let mut out = open_results(&params)?;
+            let mut regressed = 0;
+            for delta in &deltas {
+                regressed += delta.regressed as usize;
+                out.write(delta)?;
+            }
+            out.finish()?;
+            if regressed > 0 {
+                bail!(
+                    "{regressed} of {} metrics regressed by more than {}% or are missing",
+                    deltas.len(),
+                    threshold.percent
+                );
+            }
+        }
+        Command::Study(path, dry_run) => {
+            let runs = study::parse(&fs::read_to_string(&path)?)
+                .with_context(|| format!("reading {}", path.display()))?;
+            // Parsed up front, so a mistake in the last run doesn't only show
+            // once the others are done.
+            let commands = runs
+                .iter()
+                .map(|run| cli::parse(run.args.clone()).with_context(|| run.to_string()))
+                .collect::<Result<Vec<_>>>()?;
+            for (run, command) in runs.iter().zip(commands) {
+                if dry_run {
+                    println!("{run}");
+                } else {
+                    run_command(command).with_context(|| run.to_string())?;
+                }
+            }
+        }
+        Command::Analyze(path, root, params) => {
+            let store = MemoryDB::default();
+            let car_roots = car::read_car(BufReader::new(File::open(&path)?), &store)?;
+            let roots = match root {
+                Some(root) => vec![root],
+                None => analyze::find_hamt_roots(&store, &car_roots)?,
+            };
+            let mut out = open_results(&params)?;
+            for root in roots {
+                out.write(&analyze::analyze_hamt(&store, &root)?)?;
+            }
+            out.finish()?;
+        }
     }
 
-    fn print_csv(&self) {
-        println!(
-            "{};{};{};{};{};{}",
-            self.n,
-            self.m,
-            self.bucket_size,
-            self.bit_width,
-            self.total_bytes,
-            self.byte_difference
-        )
-    }
+    Ok(())
 }
 
-fn experiment<const BUCKET_SIZE: usize>(bit_width: u32, n: usize, m: usize) -> ExperimentResult {
-    let store = MemoryDB::default();
+/// Inserts the keys `0..n` with the same value used by the experiments and
+/// flushes the HAMT.
+fn build_hamt<const BUCKET_SIZE: usize>(store: &MemoryDB, bit_width: u32, n: usize) -> Result<Cid> {
     let mut map: Hamt<_, _, usize, Sha256, BUCKET_SIZE> =
-        Hamt::new_with_bit_width(&store, bit_width);
+        Hamt::new_with_bit_width(store, bit_width);
     let value = "F";
 
     for key in 0..n {
-        map.set(key, value.to_string()).unwrap();
+        map.set(key, value.to_string())?;
     }
-
-    let _cid = map.flush().unwrap();
-    let total_bytes = store.bytes_stored();
-
-    let value_after = ".";
-
-    for key in 0..m {
-        map.set(key, value_after.to_string()).unwrap();
-    }
-
-    let _cid_after = map.flush().unwrap();
-    let bytes_after = store.bytes_stored();
-    let byte_difference = bytes_after - total_bytes;
-
-    let result = ExperimentResult {
-        n,
-        m,
-        bucket_size: BUCKET_SIZE,
-        bit_width,
-        total_bytes,
-        byte_difference,
-    };
-
-    result
+    Ok(map.flush()?)
 }
 
-#[test]
-fn experiment_avg_node_degree() {
-    let avg = total_avg_node_degree::<BUCKET_SIZE>(4, 100_000);
-    println!("{:#?}", avg);
-    println!("{}", avg.links_per_node());
-    println!("{}", avg.values_per_node());
-}
-
-#[derive(Clone, Debug)]
-struct Averages {
-    nodes: u64,
-    links: u64,
-    min_degree: u64,
-    max_degree: u64,
-    values: u64,
-}
-
-impl Averages {
-    fn links_per_node(&self) -> f64 {
-        self.links as f64 / self.nodes as f64
-    }
-
-    fn values_per_node(&self) -> f64 {
-        self.values as f64 / self.nodes as f64
+/// Number of entries in the HAMT drawn at the end of a report.
+const SNAPSHOT_ENTRIES: usize = 100;
+
+/// A small HAMT with the first bit width and bucket size of `params`, as DOT
+/// and, with the `svg` feature, drawn.
+fn snapshot_section(params: &Params) -> Result<Section> {
+    let bit_width = params
+        .bit_widths
+        .iter()
+        .next()
+        .expect("at least one bit width");
+    let bucket_size = params
+        .bucket_sizes
+        .iter()
+        .next()
+        .expect("at least one bucket size");
+    let graph = with_bucket_size!(bucket_size, B => hamt_graph::<B>(bit_width, SNAPSHOT_ENTRIES));
+
+    let mut snapshots = Vec::new();
+    #[cfg(feature = "svg")]
+    {
+        let mut svg = Vec::new();
+        viz::SvgRenderer::default().render(&graph, &mut svg)?;
+        snapshots.push(Snapshot::Svg(String::from_utf8(svg)?));
     }
+    let mut dot = Vec::new();
+    viz::DotRenderer::default().render(&graph, &mut dot)?;
+    snapshots.push(Snapshot::Source(String::from_utf8(dot)?));
+
+    Ok(Section {
+        title: format!(
+            "{SNAPSHOT_ENTRIES} entries, bit width {bit_width}, bucket size {bucket_size}"
+        ),
+        snapshots,
+        ..Section::default()
+    })
 }
 
-impl AddAssign for Averages {
-    fn add_assign(&mut self, rhs: Self) {
-        self.nodes += rhs.nodes;
-        self.links += rhs.nodes;
-        self.values += rhs.values;
-        self.min_degree = cmp::min(self.min_degree, rhs.min_degree);
-        self.max_degree = cmp::max(self.max_degree, rhs.max_degree);
+fn render_graph(renderer: &Renderer, graph: &Graph, path: Option<&Path>) -> Result<()> {
+    match path {
+        Some(path) => renderer.render_to_file(graph, path)?,
+        None => renderer.render(graph, &mut io::stdout().lock())?,
     }
+    Ok(())
 }
 
-fn total_avg_node_degree<const BUCKET_SIZE: usize>(bit_width: u32, n: usize) -> Averages {
-    let store = MemoryDB::default();
-    let mut map: Hamt<_, _, usize, Sha256, BUCKET_SIZE> =
-        Hamt::new_with_bit_width(&store, bit_width);
-    let value = "F";
-
-    for key in 0..n {
-        map.set(key, value.to_string()).unwrap();
-    }
-
-    avg_node_degree(&map.root, &store)
-}
-
-fn avg_node_degree<S, K, V, H, const BUCKET_SIZE: usize>(
-    node: &Node<K, V, H, BUCKET_SIZE>,
-    store: &S,
-) -> Averages
-where
-    K: Hash + Eq + PartialOrd + DeserializeOwned,
-    H: HashAlgorithm,
-    V: DeserializeOwned,
-    S: Blockstore,
-{
-    let mut avg = Averages {
-        nodes: 1,
-        links: 0,
-        values: 0,
-        min_degree: 0,
-        max_degree: 0,
+fn open_results(params: &Params) -> Result<ResultsWriter> {
+    let writer = match (&params.output, params.append) {
+        (Some(path), true) => ResultsWriter::append(path, params.format)?,
+        (Some(path), false) => ResultsWriter::create(path, params.format)?,
+        (None, _) => ResultsWriter::stdout(params.format),
     };
-
-    let mut degree: u64 = 0;
-
-    for pointer in node.pointers.iter() {
-        match pointer {
-            Pointer::Values(v) => {
-                avg.values += v.len() as u64;
-            }
-            Pointer::Link { cid, cache } => {
-                degree += 1;
-                if let Some(child_node) = resolve_link(cid, cache, store) {
-                    avg += avg_node_degree(child_node, store);
-                }
-            }
-            Pointer::Dirty(child_node) => {
-                degree += 1;
-                avg += avg_node_degree(child_node, store);
-            }
-        }
-    }
-
-    avg.links += degree;
-    avg.min_degree = cmp::min(avg.min_degree, degree);
-    avg.max_degree = cmp::max(avg.max_degree, degree);
-
-    avg
+    writer
+        .with_header(params.header)
+        .with_column("env", &Environment::current())
 }
 
-fn resolve_link<'a, S, K, V, H, const BUCKET_SIZE: usize>(
-    cid: &Cid,
-    cache: &'a OnceCell<Box<Node<K, V, H, BUCKET_SIZE>>>,
-    store: &'a S,
-) -> Option<&'a Node<K, V, H, BUCKET_SIZE>>
-where
-    K: Hash + Eq + PartialOrd + DeserializeOwned,
-    H: HashAlgorithm,
-    V: DeserializeOwned,
-    S: Blockstore,
-{
-    if let Some(cached_node) = cache.get() {
-        return Some(cached_node);
+/// Like [`open_results`], but for files also keeps a manifest of the finished
+/// points, to resume from with `--resume`.
+fn open_checkpointed(kind: Experiment, params: &Params) -> Result<ResultsWriter> {
+    let path = match &params.output {
+        Some(path) if !params.append && params.format != Format::Json => path,
+        _ => return open_results(params),
+    };
+    let run = params
+        .summary()
+        .into_iter()
+        .fold(kind.name().to_string(), |run, (name, value)| {
+            format!("{run} {name}={value}")
+        });
+    let run = format!("{run} format={:?}", params.format);
+    let writer = if params.resume {
+        ResultsWriter::resume(path, params.format, Manifest::resume(path, &run)?)?
     } else {
-        let node = if let Some(node) = store.get_cbor(cid).unwrap() {
-            node
-        } else {
-            return None;
-        };
-
-        // Ignore error intentionally, the cache value will always be the same
-        let cache_node = cache.get_or_init(|| node);
-        return Some(cache_node);
-    }
-}
-
-enum Resolved<'a, K, V, H, const BUCKET_SIZE: usize> {
-    Link(&'a Node<K, V, H, BUCKET_SIZE>),
-    Bucket(&'a Vec<KeyValuePair<K, V>>),
+        ResultsWriter::create(path, params.format)?.with_manifest(Manifest::create(path, &run)?)
+    };
+    writer
+        .with_header(params.header)
+        .with_column("env", &Environment::current())
 }
 
-fn resolved<'a, S, K, V, H, const BUCKET_SIZE: usize>(
-    pointer: &'a Pointer<K, V, H, BUCKET_SIZE>,
-    store: &'a S,
-) -> Resolved<'a, K, V, H, BUCKET_SIZE>
-where
-    K: Hash + Eq + PartialOrd + DeserializeOwned,
-    H: HashAlgorithm,
-    V: DeserializeOwned,
-    S: Blockstore,
-{
-    match pointer {
-        Pointer::Values(v) => Resolved::Bucket(v),
-        Pointer::Link { cid, cache } => {
-            if let Some(node) = resolve_link(cid, cache, store) {
-                Resolved::Link(node)
+/// Runs the experiment once per combination of bit width and bucket size.
+fn run_experiment(kind: Experiment, params: &Params, out: &mut ResultsWriter) -> Result<()> {
+    let points = params.bit_widths.0.len() * params.bucket_sizes.0.len();
+    let mut progress = Progress::new(kind.name(), points, params.progress);
+    for bit_width in params.bit_widths.iter() {
+        for bucket_size in params.bucket_sizes.iter() {
+            progress.start(format!("bit width {bit_width}, bucket size {bucket_size}"));
+            if out.is_done(bit_width, bucket_size) {
+                progress.skip_point();
+                continue;
+            }
+            if params.repeat > 1 || params.warmup > 0 {
+                run_repeated(kind, params, bit_width, bucket_size, out)?;
             } else {
-                unreachable!()
+                run_experiment_with(kind, params, bit_width, bucket_size, out)?;
             }
+            #[cfg(feature = "profile")]
+            if let Some(dir) = &params.profile {
+                profile_point(kind, params, bit_width, bucket_size, dir)?;
+            }
+            out.checkpoint(bit_width, bucket_size)?;
+            progress.finish_point();
         }
-        Pointer::Dirty(node) => Resolved::Link(node),
     }
+    progress.finish();
+    Ok(())
 }
 
-struct Dot {
-    nodes: Vec<String>,
-    vertices: Vec<(String, String)>,
-}
-
-impl Dot {
-    fn new() -> Self {
-        Dot {
-            nodes: Vec::new(),
-            vertices: Vec::new(),
-        }
+/// Runs a point `--warmup` times, dropping the results, and then `--repeat`
+/// times with the seeds from `--seed` on, writing a summary of the records of
+/// the repetitions.
+fn run_repeated(
+    kind: Experiment,
+    params: &Params,
+    bit_width: u32,
+    bucket_size: usize,
+    out: &mut ResultsWriter,
+) -> Result<()> {
+    for _ in 0..params.warmup {
+        run_experiment_with(kind, params, bit_width, bucket_size, &mut ResultsWriter::in_memory())?;
     }
-
-    fn extend(&mut self, other: Self) {
-        self.nodes.extend(other.nodes);
-        self.vertices.extend(other.vertices);
+    let mut runs = Vec::with_capacity(params.repeat);
+    for i in 0..params.repeat {
+        let params = Params {
+            seed: params.seed.wrapping_add(i as u64),
+            ..params.clone()
+        };
+        let mut records = ResultsWriter::in_memory();
+        run_experiment_with(kind, &params, bit_width, bucket_size, &mut records)?;
+        runs.push(records.into_kept_records());
     }
-}
-
-fn cidstr(cid: &Cid) -> String {
-    let str = cid.to_string();
-    str[str.len() - 8..str.len()].to_string()
-}
-
-fn bitfieldstr(bitfield: Bitfield, len: u32) -> String {
-    let mut str = String::new();
-    for i in 0..len {
-        if bitfield.test_bit(i) {
-            str += "1";
-        } else {
-            str += "0";
-        }
+    for record in repeat::aggregate(&runs) {
+        out.write(&record)?;
     }
-    str
+    Ok(())
 }
 
-fn hamt_to_dot<S, K, V, H, const BUCKET_SIZE: usize>(hamt: &Hamt<S, K, V, H, BUCKET_SIZE>) -> Dot
-where
-    K: Hash + Eq + PartialOrd + Serialize + DeserializeOwned + ToString,
-    H: HashAlgorithm,
-    V: Serialize + DeserializeOwned + Hash + Eq + PartialOrd + ToString,
-    S: Blockstore + Clone,
-{
-    node_to_dot(&hamt.root, &mut hamt.store().clone(), hamt.bit_width).0
+/// Profiles building a HAMT of `n` keys of the workload at a point, writing
+/// the insert and flush phases to `dir` as `<experiment>-bw<bit
+/// width>-bs<bucket size>-<phase>.svg` and `.folded`.
+#[cfg(feature = "profile")]
+fn profile_point(
+    kind: Experiment,
+    params: &Params,
+    bit_width: u32,
+    bucket_size: usize,
+    dir: &Path,
+) -> Result<()> {
+    let ctx = ExperimentContext::new(params);
+    let keys = params.workload.keys(params.n, &mut ctx.rng());
+    let (insert, flush) =
+        with_bucket_size!(bucket_size, B => profile::build::<B>(keys, bit_width))?;
+    fs::create_dir_all(dir).with_context(|| format!("creating {}", dir.display()))?;
+    let point = format!("{}-bw{bit_width}-bs{bucket_size}", kind.name());
+    for (phase, profile) in [("insert", insert), ("flush", flush)] {
+        let title = format!(
+            "{phase}: {} keys, bit width {bit_width}, bucket size {bucket_size}",
+            params.n
+        );
+        profile.write_to_files(&dir.join(format!("{point}-{phase}")), &title)?;
+    }
+    Ok(())
 }
 
-fn node_to_dot<S, K, V, H, const BUCKET_SIZE: usize>(
-    node: &Node<K, V, H, BUCKET_SIZE>,
-    store: &mut S,
+fn run_experiment_with(
+    kind: Experiment,
+    params: &Params,
     bit_width: u32,
-) -> (Dot, Cid)
-where
-    K: Hash + Eq + PartialOrd + Serialize + DeserializeOwned + ToString,
-    H: HashAlgorithm,
-    V: Serialize + DeserializeOwned + ToString,
-    S: Blockstore + Clone,
-{
-    let mut dot = Dot::new();
-
-    use cid::multihash::Code;
-
-    let node_cid = store.put_cbor(&node, Code::Blake2b256).unwrap();
-    let from = cidstr(&node_cid);
-
-    let mut node_str = format!(
-        "\"{from}\" [
-    label=<
-        <table border=\"0\" cellborder=\"1\" cellspacing=\"0\">
-            <tr><td colspan=\"{BUCKET_SIZE}\">{}</td></tr>
-            <tr><td colspan=\"{BUCKET_SIZE}\">{}</td></tr>\n",
-        from.clone(),
-        bitfieldstr(node.bitfield, 1 << bit_width)
-    );
+    bucket_size: usize,
+    out: &mut ResultsWriter,
+) -> Result<()> {
+    let Params {
+        n,
+        m,
+        batch_size,
+        lookups,
+        page_size,
+        versions,
+        keep,
+        value_threshold,
+        network,
+        ..
+    } = *params;
+    let ctx = ExperimentContext::new(params);
+    let workload = &params.workload;
+    let cache_sizes = match params.node_cache {
+        Some(size) => vec![size],
+        None => CACHE_SIZES.to_vec(),
+    };
+    let value_sweep = match params.value_sizes {
+        Some(sizes) => vec![sizes],
+        None => (0..=10).map(|exp| ValueSizes::Fixed(1 << exp)).collect(),
+    };
+    let flush_policies = match params.flush {
+        Some(policy) => vec![policy],
+        None => FLUSH_POLICIES.to_vec(),
+    };
+    let key_lengths = match params.key_length {
+        Some(length) => vec![length],
+        None => (3..=9).map(|exp| 1 << exp).collect(),
+    };
+    let selectors = match &params.selector {
+        Some(selector) => vec![selector.clone()],
+        None => selector::selectors(),
+    };
+    let max_depths = match params.max_depth {
+        Some(depth) => vec![Some(depth)],
+        None => vec![Some(0), Some(1), Some(2), Some(3), None],
+    };
+    let samplings = match params.sample {
+        Some(sampling) => vec![sampling],
+        None => traverse::samplings(),
+    };
 
-    for pointer in node.pointers.iter() {
-        match resolved(pointer, &store.clone()) {
-            Resolved::Bucket(bucket) => {
-                node_str += format!(
-                    "<tr>{}</tr>",
-                    bucket
-                        .iter()
-                        .map(|kv| format!(
-                            "<td align=\"left\"><font face=\"mono\">{}:</font> {}</td>",
-                            hex::encode(H::hash(kv.key()))[..8].to_string(),
-                            kv.key().to_string()
-                        ))
-                        .collect::<Vec<String>>()
-                        .join(", ")
-                )
-                .as_str();
-            }
-            Resolved::Link(child_node) => {
-                let (child_dot, child_cid) = node_to_dot(child_node, store, bit_width);
-                dot.extend(child_dot);
-                let to = cidstr(&child_cid);
-                dot.vertices.push((from.clone(), to));
+    match kind {
+        Experiment::Sizes | Experiment::Sweep => {
+            let result = with_bucket_size!(bucket_size, B => experiment::<B>(&ctx, bit_width, n, m, workload));
+            out.write(&result)?;
+        }
+        Experiment::Degree => {
+            let result = with_bucket_size!(bucket_size, B => {
+                degree_experiment::<B>(&ctx, bit_width, n, workload)
+            });
+            out.write(&result)?;
+        }
+        Experiment::Proof => {
+            let result = with_bucket_size!(bucket_size, B => proof_experiment::<B>(bit_width, n))?;
+            out.write(&result)?;
+        }
+        Experiment::MultiProof => {
+            let rows = with_bucket_size!(bucket_size, B => {
+                multi_proof_experiment::<B>(&ctx, bit_width, n, m)
+            });
+            for row in rows {
+                out.write(&row)?;
+            }
+        }
+        Experiment::Scan => {
+            let result = with_bucket_size!(bucket_size, B => scan_experiment::<B>(&ctx, bit_width, n, workload, network));
+            out.write(&result)?;
+        }
+        Experiment::Paging => {
+            let result = with_bucket_size!(bucket_size, B => {
+                paging_experiment::<B>(&ctx, bit_width, n, page_size)
+            });
+            out.write(&result)?;
+        }
+        Experiment::Batch => {
+            let results = with_bucket_size!(bucket_size, B => {
+                batch_experiment::<B>(bit_width, n, m, batch_size)
+            });
+            for result in results {
+                out.write(&result)?;
+            }
+        }
+        Experiment::Delete => {
+            let result = with_bucket_size!(bucket_size, B => {
+                deletion_experiment::<B>(&ctx, bit_width, n, m, batch_size)
+            });
+            out.write(&result)?;
+        }
+        Experiment::Gc => {
+            let result = with_bucket_size!(bucket_size, B => gc_experiment::<B>(bit_width, n, m));
+            out.write(&result)?;
+        }
+        Experiment::Disk => {
+            let store = match &params.dir {
+                Some(dir) => FileStore::open(dir.join(format!("bucket-size-{bucket_size}")))?,
+                None => FileStore::temporary()?,
+            };
+            let result = with_bucket_size!(bucket_size, B => {
+                disk_experiment::<B>(&ctx, &store, bit_width, n, lookups)
+            });
+            out.write(&result)?;
+        }
+        Experiment::Versions => {
+            let rows = with_bucket_size!(bucket_size, B => {
+                versions_experiment::<B>(&ctx, bit_width, n, m, versions, workload)
+            });
+            for row in rows {
+                out.write(&row)?;
+            }
+        }
+        Experiment::Values => {
+            for &sizes in &value_sweep {
+                let result = with_bucket_size!(bucket_size, B => {
+                    value_size_experiment::<B>(&ctx, bit_width, n, m, lookups, sizes)
+                });
+                out.write(&result)?;
+            }
+        }
+        Experiment::External => {
+            for &sizes in &value_sweep {
+                let result = with_bucket_size!(bucket_size, B => {
+                    external_value_experiment::<B>(&ctx, bit_width, n, m, value_threshold, sizes)
+                });
+                out.write(&result)?;
+            }
+        }
+        Experiment::Hashes => {
+            let rows = with_bucket_size!(bucket_size, B => [
+                hash_experiment::<B, Sha256>(&ctx, "sha256", bit_width, n, workload),
+                hash_experiment::<B, Blake3>(&ctx, "blake3", bit_width, n, workload),
+                hash_experiment::<B, XxHash>(&ctx, "xxhash", bit_width, n, workload),
+            ]);
+            for row in rows {
+                out.write(&row)?;
+            }
+        }
+        Experiment::Collisions => {
+            let rows = with_bucket_size!(bucket_size, B => [
+                collision_experiment::<B, Truncated<Sha256, 8>>(&ctx, bit_width, n, workload),
+                collision_experiment::<B, Truncated<Sha256, 12>>(&ctx, bit_width, n, workload),
+                collision_experiment::<B, Truncated<Sha256, 16>>(&ctx, bit_width, n, workload),
+                collision_experiment::<B, Truncated<Sha256, 20>>(&ctx, bit_width, n, workload),
+                collision_experiment::<B, Truncated<Sha256, 24>>(&ctx, bit_width, n, workload),
+                collision_experiment::<B, Truncated<Sha256, 32>>(&ctx, bit_width, n, workload),
+                collision_experiment::<B, Sha256>(&ctx, bit_width, n, workload),
+            ]);
+            for row in rows {
+                out.write(&row)?;
+            }
+        }
+        Experiment::Blocks => {
+            let rows = with_bucket_size!(bucket_size, B => {
+                block_size_experiment::<B>(&ctx, bit_width, n, workload)
+            });
+            for row in rows {
+                out.write(&row)?;
+            }
+        }
+        Experiment::Levels => {
+            let rows = with_bucket_size!(bucket_size, B => levels_experiment::<B>(&ctx, bit_width, n, workload));
+            for row in rows {
+                out.write(&row)?;
+            }
+        }
+        Experiment::Depth => {
+            let result = with_bucket_size!(bucket_size, B => depth_experiment::<B>(&ctx, bit_width, n, workload));
+            out.write(&result)?;
+        }
+        Experiment::Amt => {
+            let result = with_bucket_size!(bucket_size, B => {
+                amt_experiment::<B>(&ctx, bit_width, n, m)
+            })?;
+            out.write(&result)?;
+        }
+        Experiment::Champ => {
+            let result = with_bucket_size!(bucket_size, B => {
+                champ_experiment::<B>(&ctx, bit_width, n, m, batch_size, workload)
+            })?;
+            out.write(&result)?;
+        }
+        Experiment::Radix => {
+            let result = with_bucket_size!(bucket_size, B => {
+                radix_experiment::<B>(&ctx, bit_width, n, m, batch_size, workload)
+            })?;
+            out.write(&result)?;
+        }
+        Experiment::Len => {
+            for len_in_root in [false, true] {
+                let result = with_bucket_size!(bucket_size, B => {
+                    len_experiment::<B>(bit_width, n, m, len_in_root)
+                })?;
+                out.write(&result)?;
+            }
+        }
+        Experiment::Cids => {
+            for &(multihash, mh_code) in MULTIHASHES {
+                let cid_config = CidConfig {
+                    mh_code,
+                    ..CidConfig::default()
+                };
+                let result = with_bucket_size!(bucket_size, B => {
+                    cid_experiment::<B>(&ctx, bit_width, n, m, multihash, "dag-cbor", cid_config)
+                })?;
+                out.write(&result)?;
+            }
+        }
+        Experiment::Codecs => {
+            for &(codec_name, codec) in CODECS {
+                let cid_config = CidConfig {
+                    codec,
+                    ..CidConfig::default()
+                };
+                let result = with_bucket_size!(bucket_size, B => {
+                    cid_experiment::<B>(
+                        &ctx,
+                        bit_width,
+                        n,
+                        m,
+                        "blake2b-256",
+                        codec_name,
+                        cid_config,
+                    )
+                })?;
+                out.write(&result)?;
+            }
+        }
+        Experiment::Compression => {
+            let result = with_bucket_size!(bucket_size, B => {
+                compression_experiment::<B>(bit_width, n)
+            })?;
+            out.write(&result)?;
+        }
+        Experiment::Cache => {
+            for &size in &cache_sizes {
+                let result = with_bucket_size!(bucket_size, B => {
+                    cache_experiment::<B>(&ctx, bit_width, n, lookups, workload, size)
+                })?;
+                out.write(&result)?;
+            }
+        }
+        Experiment::Lookup => {
+            let result = with_bucket_size!(bucket_size, B => {
+                lookup_experiment::<B>(&ctx, bit_width, n, lookups, workload, network)
+            });
+            out.write(&result)?;
+        }
+        Experiment::Writes => {
+            let rows = with_bucket_size!(bucket_size, B => {
+                writes_experiment::<B>(&ctx, bit_width, n, m, workload)
+            })?;
+            for row in rows {
+                out.write(&row)?;
+            }
+        }
+        Experiment::Flush => {
+            for &policy in &flush_policies {
+                let result = with_bucket_size!(bucket_size, B => {
+                    flush_experiment::<B>(&ctx, bit_width, n, m, workload, policy)
+                })?;
+                out.write(&result)?;
+            }
+        }
+        Experiment::Chain => {
+            let rows = with_bucket_size!(bucket_size, B => {
+                chain_experiment::<B>(&ctx, bit_width, n, m, versions, workload)
+            })?;
+            for row in rows {
+                out.write(&row)?;
+            }
+        }
+        Experiment::Delta => {
+            let rows = with_bucket_size!(bucket_size, B => {
+                delta_experiment::<B>(&ctx, bit_width, n, m, workload)
+            })?;
+            for row in rows {
+                out.write(&row)?;
+            }
+        }
+        Experiment::Fetch => {
+            let rows = with_bucket_size!(bucket_size, B => {
+                fetch_experiment::<B>(&ctx, bit_width, n, lookups, workload)
+            })?;
+            for row in rows {
+                out.write(&row)?;
+            }
+        }
+        Experiment::Selectors => {
+            for selector in &selectors {
+                let result = with_bucket_size!(bucket_size, B => {
+                    selector_experiment::<B>(&ctx, bit_width, n, workload, selector)
+                })?;
+                out.write(&result)?;
+            }
+        }
+        Experiment::Nested => {
+            for child_bucket_size in params.bucket_sizes.iter() {
+                let children = with_bucket_size!(child_bucket_size, C => {
+                    Box::new(Children::<C> { bit_width }) as Box<dyn ChildMaps>
+                });
+                let result = with_bucket_size!(bucket_size, B => {
+                    nested_experiment::<B>(&ctx, bit_width, n, m, &*children)
+                })?;
+                out.write(&result)?;
+            }
+        }
+        Experiment::Keys => {
+            for &key_length in &key_lengths {
+                let result = with_bucket_size!(bucket_size, B => {
+                    key_length_experiment::<B>(&ctx, bit_width, n, key_length)
+                })?;
+                out.write(&result)?;
+            }
+        }
+        Experiment::HashOnly => {
+            let rows = with_bucket_size!(bucket_size, B => {
+                hash_only_experiment::<B>(&ctx, bit_width, n, m, workload)
+            })?;
+            for row in rows {
+                out.write(&row)?;
+            }
+        }
+        Experiment::Salt => {
+            let rows = with_bucket_size!(bucket_size, B => {
+                salt_experiment::<B>(&ctx, bit_width, n, workload)
+            })?;
+            for row in rows {
+                out.write(&row)?;
+            }
+        }
+        Experiment::Skip => {
+            let rows = with_bucket_size!(bucket_size, B => {
+                skip_experiment::<B>(&ctx, bit_width, n, lookups)
+            })?;
+            for row in rows {
+                out.write(&row)?;
+            }
+        }
+        Experiment::MaxDepth => {
+            for &max_depth in &max_depths {
+                let result = with_bucket_size!(bucket_size, B => {
+                    max_depth_experiment::<B>(&ctx, bit_width, n, lookups, workload, max_depth)
+                })?;
+                out.write(&result)?;
+            }
+        }
+        Experiment::Migration => {
+            for target_bucket_size in params.bucket_sizes.iter() {
+                let target = with_bucket_size!(target_bucket_size, T => {
+                    Box::new(Target::<T> { bit_width }) as Box<dyn MigrationTarget>
+                });
+                let result = with_bucket_size!(bucket_size, B => {
+                    migration_experiment::<B>(&ctx, bit_width, n, batch_size, workload, &*target)
+                })?;
+                out.write(&result)?;
+            }
+        }
+        Experiment::RefCount => {
+            let rows = with_bucket_size!(bucket_size, B => {
+                refcount_experiment::<B>(&ctx, bit_width, n, m, versions, keep, workload)
+            })?;
+            for row in rows {
+                out.write(&row)?;
+            }
+        }
+        Experiment::Sample => {
+            let max_depth = params.max_depth.map(|depth| depth as usize);
+            for &sampling in &samplings {
+                let result = with_bucket_size!(bucket_size, B => {
+                    sample_experiment::<B>(&ctx, bit_width, n, workload, sampling, max_depth)
+                })?;
+                out.write(&result)?;
+            }
+        }
+        Experiment::Occupancy => {
+            let rows = with_bucket_size!(bucket_size, B => {
+                occupancy_experiment::<B>(&ctx, bit_width, n, workload)
+            })?;
+            for row in rows {
+                out.write(&row)?;
+            }
+        }
+        Experiment::Bitfield => {
+            let result = with_bucket_size!(bucket_size, B => {
+                bitfield_experiment::<B>(&ctx, bit_width, n, workload)
+            })?;
+            out.write(&result)?;
+        }
+        Experiment::NodeFormat => {
+            let result = with_bucket_size!(bucket_size, B => {
+                node_format_experiment::<B>(&ctx, bit_width, n, workload)
+            })?;
+            out.write(&result)?;
+        }
+        Experiment::Replay => {
+            let Some(path) = &params.trace else {
+                bail!("`replay` needs a `--trace`");
+            };
+            let trace = Trace::load(path)?;
+            let result = with_bucket_size!(bucket_size, B => {
+                replay_experiment::<B>(bit_width, &trace)
+            })?;
+            out.write(&result)?;
+        }
+        Experiment::Wnfs => {
+            let sizes = params.value_sizes.unwrap_or(FILE_SIZES);
+            let rows = with_bucket_size!(bucket_size, B => {
+                wnfs_experiment::<B>(&ctx, bit_width, n, &params.fs_ops, &sizes)
+            })?;
+            for row in rows {
+                out.write(&row)?;
+            }
+        }
+        Experiment::TimeSeries => {
+            let policy = params.flush.unwrap_or(TIME_SERIES_FLUSH);
+            let rows = with_bucket_size!(bucket_size, B => {
+                time_series_experiment::<B>(&ctx, bit_width, n, workload, policy)
+            })?;
+            for row in rows {
+                out.write(&row)?;
+            }
+        }
+        Experiment::Memory => {
+            let rows = with_bucket_size!(bucket_size, B => {
+                memory_experiment::<B>(&ctx, bit_width, n, workload)
+            })?;
+            for row in rows {
+                out.write(&row)?;
             }
         }
     }
 
-    node_str += "        </table>
-    >
-]";
-
-    dot.nodes.push(node_str);
-
-    (dot, node_cid)
-}
-
-fn test_hamt_dot() {
-    let bit_width = 4;
-    let n = 300;
-    let store = MemoryDB::default();
-    let mut map: Hamt<_, _, usize, Sha256, 3> = Hamt::new_with_bit_width(&store, bit_width);
-    let value = "F";
-
-    for key in 0..n {
-        map.set(key, value.to_string()).unwrap();
-    }
-    map.flush().unwrap();
-
-    println!("digraph G {{");
-    println!(
-        "\n  compound = true
-  fontname = \"Helvetica\"
-
-  edge [
-    colorscheme = \"piyg11\"
-    fontname = \"Helvetica\"
-  ];
-
-  node [
-    shape = plaintext
-    style = filled
-    colorscheme = \"piyg11\"
-    fontname = \"Helvetica\"
-
-    color = 2
-    fontcolor = 2
-    fillcolor = 5
-  ];
-
-  graph [
-    colorscheme = \"piyg11\"
-    color = 10
-    style = \"rounded,filled\"
-    fontcolor = 7
-  ];\n"
-    );
-    let dot = hamt_to_dot(&map);
-    for node in dot.nodes {
-        println!("{node}");
-    }
-    for (from, to) in dot.vertices {
-        println!("  \"{from}\" -> \"{to}\"");
-    }
-    println!("}}");
-}
-
-#[test]
-fn test_avg_node_bytes() {
-    for i in 1..=1000 {
-        let n = 100 * i;
-        println!(
-            "{}; {}; {}; {}; {}; {}; {}; {}; {}; {}; {}",
-            n,
-            avg_node_bytes_experiment::<1>(4, n) as u32,
-            avg_node_bytes_experiment::<2>(4, n) as u32,
-            avg_node_bytes_experiment::<3>(4, n) as u32,
-            avg_node_bytes_experiment::<5>(4, n) as u32,
-            avg_node_bytes_experiment::<8>(4, n) as u32,
-            avg_node_bytes_experiment::<12>(4, n) as u32,
-            avg_node_bytes_experiment::<16>(4, n) as u32,
-            avg_node_bytes_experiment::<32>(4, n) as u32,
-            avg_node_bytes_experiment::<64>(4, n) as u32,
-            avg_node_bytes_experiment::<128>(4, n) as u32,
-        );
-    }
+    Ok(())
 }
 
-#[cfg(test)]
-fn avg_node_bytes_experiment<const BUCKET_SIZE: usize>(bit_width: u32, n: usize) -> f64 {
+fn hamt_graph<const BUCKET_SIZE: usize>(bit_width: u32, n: usize) -> Graph {
     let store = MemoryDB::default();
     let mut map: Hamt<_, _, usize, Sha256, BUCKET_SIZE> =
         Hamt::new_with_bit_width(&store, bit_width);
@@ -442,32 +931,12 @@ fn avg_node_bytes_experiment<const BUCKET_SIZE: usize>(bit_width: u32, n: usize)
     }
     map.flush().unwrap();
 
-    store.bytes_average()
-}
-
-#[test]
-fn test_max_node_bytes() {
-    for i in 1..=1000 {
-        let n = 100 * i;
-        println!(
-            "{}; {}; {}; {}; {}; {}; {}; {}; {}; {}; {}",
-            n,
-            max_node_bytes_experiment::<1>(4, n),
-            max_node_bytes_experiment::<2>(4, n),
-            max_node_bytes_experiment::<3>(4, n),
-            max_node_bytes_experiment::<5>(4, n),
-            max_node_bytes_experiment::<8>(4, n),
-            max_node_bytes_experiment::<12>(4, n),
-            max_node_bytes_experiment::<16>(4, n),
-            max_node_bytes_experiment::<32>(4, n),
-            max_node_bytes_experiment::<64>(4, n),
-            max_node_bytes_experiment::<128>(4, n),
-        );
-    }
+    viz::hamt_to_graph(&map.into_view()).unwrap()
 }
 
-#[cfg(test)]
-fn max_node_bytes_experiment<const BUCKET_SIZE: usize>(bit_width: u32, n: usize) -> usize {
+/// Like [`hamt_graph`], but overwrites `m` keys after the first flush and
+/// shows both versions.
+fn hamt_diff_graph<const BUCKET_SIZE: usize>(bit_width: u32, n: usize, m: usize) -> Result<Graph> {
     let store = MemoryDB::default();
     let mut map: Hamt<_, _, usize, Sha256, BUCKET_SIZE> =
         Hamt::new_with_bit_width(&store, bit_width);
@@ -476,49 +945,14 @@ fn max_node_bytes_experiment<const BUCKET_SIZE: usize>(bit_width: u32, n: usize)
     for key in 0..n {
         map.set(key, value.to_string()).unwrap();
     }
-    map.flush().unwrap();
-
-    store.bytes_max()
-}
-
-#[test]
-fn test_merkle_proof_bytes() {
-    for i in 1..=10 {
-        let n = 10_000 * i;
-        println!(
-            "{}; {}; {}; {}; {}; {}; {}; {}; {}; {}; {}",
-            n,
-            merkle_proof_bytes_experiment::<1>(4, n),
-            merkle_proof_bytes_experiment::<2>(4, n),
-            merkle_proof_bytes_experiment::<3>(4, n),
-            merkle_proof_bytes_experiment::<5>(4, n),
-            merkle_proof_bytes_experiment::<8>(4, n),
-            merkle_proof_bytes_experiment::<12>(4, n),
-            merkle_proof_bytes_experiment::<16>(4, n),
-            merkle_proof_bytes_experiment::<32>(4, n),
-            merkle_proof_bytes_experiment::<64>(4, n),
-            merkle_proof_bytes_experiment::<128>(4, n)
-        );
-    }
-}
+    let old = map.flush().unwrap();
 
-#[cfg(test)]
-fn merkle_proof_bytes_experiment<const BUCKET_SIZE: usize>(bit_width: u32, n: usize) -> u64 {
-    let store = MemoryDB::default();
-    let mut map: Hamt<_, _, usize, Sha256, BUCKET_SIZE> =
-        Hamt::new_with_bit_width(&store, bit_width);
-    let value = "F";
+    let value_after = ".";
 
-    for key in 0..n {
-        map.set(key, value.to_string()).unwrap();
+    for key in 0..m {
+        map.set(key, value_after.to_string()).unwrap();
     }
-    map.flush().unwrap();
-
-    let bytes_before = store.bytes_stored();
-
-    map.set(0, "N".to_string()).unwrap();
-    map.flush().unwrap();
+    let new = map.flush().unwrap();
 
-    let bytes_after = store.bytes_stored();
-    bytes_after - bytes_before
+    viz::hamt_diff_to_graph::<_, usize, String, Sha256, BUCKET_SIZE>(&store, bit_width, &old, &new)
 }
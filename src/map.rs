@@ -0,0 +1,183 @@
+//! A common interface to the HAMT and the structures it's compared against,
+//! so that experiments can measure any of them the same way.
+
+use anyhow::Result;
+use cid::Cid;
+use fvm_ipld_blockstore::Blockstore;
+use fvm_ipld_encoding::de::DeserializeOwned;
+use fvm_ipld_hamt::{Hamt, Hash, HashAlgorithm};
+use serde::Serialize;
+
+use crate::memorydb::MemoryDB;
+use crate::metered::{MeteredStore, StoreStats};
+use crate::stats::TreeStats;
+
+pub trait IpldMap<K, V> {
+    /// Sets the value of `key`, returning the previous one.
+    fn set(&mut self, key: K, value: V) -> Result<Option<V>>;
+
+    fn get(&self, key: &K) -> Result<Option<&V>>;
+
+    /// Sets the value of `key` unless it has one, returning whether it was
+    /// set.
+    fn set_if_absent(&mut self, key: K, value: V) -> Result<bool> {
+        if self.get(&key)?.is_some() {
+            return Ok(false);
+        }
+        self.set(key, value)?;
+        Ok(true)
+    }
+
+    /// Deletes `key`, returning its value.
+    fn delete(&mut self, key: &K) -> Result<Option<V>>;
+
+    /// Writes every changed node to the store and returns the CID of the
+    /// root.
+    fn flush(&mut self) -> Result<Cid>;
+
+    /// Shape of the tree as of the last flush.
+    fn stats(&self) -> Result<MapStats>;
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct MapStats {
+    /// Number of blocks, nodes stored inline in their parent don't count.
+    pub nodes: u64,
+    pub values: u64,
+    /// Number of blocks below the root on the longest path.
+    pub height: u32,
+}
+
+impl<BS, K, V, H, const BUCKET_SIZE: usize> IpldMap<K, V> for Hamt<BS, V, K, H, BUCKET_SIZE>
+where
+    BS: Blockstore,
+    K: Hash + Eq + PartialOrd + Serialize + DeserializeOwned,
+    V: Serialize + DeserializeOwned + PartialEq,
+    H: HashAlgorithm,
+{
+    fn set(&mut self, key: K, value: V) -> Result<Option<V>> {
+        Ok(Hamt::set(self, key, value)?)
+    }
+
+    fn get(&self, key: &K) -> Result<Option<&V>> {
+        Ok(Hamt::get(self, key)?)
+    }
+
+    fn set_if_absent(&mut self, key: K, value: V) -> Result<bool> {
+        Ok(Hamt::set_if_absent(self, key, value)?)
+    }
+
+    fn delete(&mut self, key: &K) -> Result<Option<V>> {
+        Ok(Hamt::delete(self, key)?.map(|(_, value)| value))
+    }
+
+    fn flush(&mut self) -> Result<Cid> {
+        Ok(Hamt::flush(self)?)
+    }
+
+    fn stats(&self) -> Result<MapStats> {
+        let stats = TreeStats::new(self)?;
+        Ok(MapStats {
+            nodes: stats.nodes,
+            values: stats.values,
+            height: stats.levels.len().saturating_sub(1) as u32,
+        })
+    }
+}
+
+/// Sizes of a map in [`map_sizes`].
+pub struct MapSizes {
+    pub total_bytes: u64,
+    pub avg_node_bytes: f64,
+    pub max_node_bytes: usize,
+    pub byte_difference: u64,
+    /// Traffic of flushing the overwrites.
+    pub traffic: StoreStats,
+}
+
+/// Sets the first `n` of `keys` and flushes, then overwrites the first `m`
+/// with another value and flushes again, measuring the blocks `map` put into
+/// `store`.
+pub fn map_sizes<K: Clone, M: IpldMap<K, String>>(
+    map: &mut M,
+    store: &MeteredStore<MemoryDB>,
+    keys: &[K],
+    n: usize,
+    m: usize,
+) -> Result<MapSizes> {
+    for key in &keys[..n] {
+        map.set(key.clone(), "F".to_string())?;
+    }
+    map.flush()?;
+    let total_bytes = store.inner().bytes_stored();
+    let avg_node_bytes = store.inner().bytes_average();
+    let max_node_bytes = store.inner().bytes_max();
+    let before = store.snapshot();
+
+    for key in &keys[..m] {
+        map.set(key.clone(), ".".to_string())?;
+    }
+    map.flush()?;
+    Ok(MapSizes {
+        total_bytes,
+        avg_node_bytes,
+        max_node_bytes,
+        byte_difference: store.inner().bytes_stored() - total_bytes,
+        traffic: store.snapshot() - before,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::btree::BTree;
+    use crate::champ::Champ;
+    use crate::flat::FlatMap;
+    use crate::memorydb::MemoryDB;
+    use crate::prolly::ProllyTree;
+    use fvm_ipld_hamt::Sha256;
+
+    fn sets_and_gets(map: &mut impl IpldMap<u64, String>) -> Result<()> {
+        for key in 0..99 {
+            assert_eq!(map.set(key, key.to_string())?, None);
+        }
+        assert!(map.set_if_absent(99, "99".to_string())?);
+        assert_eq!(map.set(7, "seven".to_string())?, Some("7".to_string()));
+        assert!(!map.set_if_absent(7, "7".to_string())?);
+        assert_eq!(map.get(&7)?, Some(&"seven".to_string()));
+        assert_eq!(map.get(&99)?, Some(&"99".to_string()));
+        assert_eq!(map.get(&100)?, None);
+        map.flush()?;
+        assert_eq!(map.stats()?.values, 100);
+        Ok(())
+    }
+
+    #[test]
+    fn maps_agree() -> Result<()> {
+        let store = MemoryDB::default();
+        let mut hamt: Hamt<_, _, u64, Sha256, 3> = Hamt::new_with_bit_width(&store, 4);
+        let mut flat = FlatMap::new(&store);
+        let mut btree = BTree::new_with_fanout(&store, 16);
+        let mut prolly = ProllyTree::new_with_fanout(&store, 16);
+        let mut champ: Champ<_, _, _> = Champ::new_with_bit_width(&store, 4);
+        sets_and_gets(&mut hamt)?;
+        sets_and_gets(&mut flat)?;
+        sets_and_gets(&mut btree)?;
+        sets_and_gets(&mut prolly)?;
+        sets_and_gets(&mut champ)?;
+
+        for map in [
+            &mut hamt as &mut dyn IpldMap<u64, String>,
+            &mut flat,
+            &mut btree,
+            &mut prolly,
+            &mut champ,
+        ] {
+            assert_eq!(map.delete(&7)?, Some("seven".to_string()));
+            assert_eq!(map.delete(&7)?, None);
+            map.flush()?;
+            assert_eq!(map.stats()?.values, 99);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,131 @@
+//! Heap memory held by a HAMT in memory: its nodes, buckets, and the child
+//! nodes cached behind links.
+//!
+//! Stored bytes say how large a HAMT is on disk, but a long-lived process
+//! also keeps every node it ever loaded, decoded, in the link caches.
+
+use std::mem::size_of;
+
+use cid::Cid;
+use fvm_ipld_hamt::{node::Node, pointer::Pointer, Hamt, KeyValuePair};
+
+use crate::workload::Key;
+
+/// Bytes a value owns on the heap, beyond its own `size_of`.
+pub trait HeapSize {
+    fn heap_size(&self) -> usize;
+
+    /// `size_of` the value plus what it owns on the heap.
+    fn deep_size_of(&self) -> usize
+    where
+        Self: Sized,
+    {
+        size_of::<Self>() + self.heap_size()
+    }
+}
+
+macro_rules! no_heap {
+    ($($t:ty),*) => {
+        $(impl HeapSize for $t {
+            fn heap_size(&self) -> usize {
+                0
+            }
+        })*
+    };
+}
+
+no_heap!(u8, u32, u64, usize, i64, Cid);
+
+impl HeapSize for String {
+    fn heap_size(&self) -> usize {
+        self.capacity()
+    }
+}
+
+impl HeapSize for Key {
+    fn heap_size(&self) -> usize {
+        match self {
+            Key::Int(_) => 0,
+            Key::Path(path) => path.heap_size(),
+            Key::Bytes(bytes) => bytes.capacity(),
+        }
+    }
+}
+
+/// Unused capacity counts, it's allocated all the same.
+impl<T: HeapSize> HeapSize for Vec<T> {
+    fn heap_size(&self) -> usize {
+        self.capacity() * size_of::<T>() + self.iter().map(T::heap_size).sum::<usize>()
+    }
+}
+
+/// External values count only once they were loaded.
+impl<K: HeapSize, V: HeapSize> HeapSize for KeyValuePair<K, V> {
+    fn heap_size(&self) -> usize {
+        self.key().heap_size() + self.loaded_value().map_or(0, V::heap_size)
+    }
+}
+
+impl<K: HeapSize, V: HeapSize, H, const BUCKET_SIZE: usize> HeapSize
+    for Pointer<K, V, H, BUCKET_SIZE>
+{
+    fn heap_size(&self) -> usize {
+        match self {
+            Pointer::Values(values) => values.heap_size(),
+            Pointer::Link { cache, .. } => cache.get().map_or(0, |node| node.deep_size_of()),
+            Pointer::Dirty(node) => node.deep_size_of(),
+        }
+    }
+}
+
+impl<K: HeapSize, V: HeapSize, H, const BUCKET_SIZE: usize> HeapSize
+    for Node<K, V, H, BUCKET_SIZE>
+{
+    fn heap_size(&self) -> usize {
+        self.pointers.heap_size()
+    }
+}
+
+/// Bytes `hamt` takes in memory, the store it refers to excluded.
+pub fn hamt_memory<S, K, V, H, const BUCKET_SIZE: usize>(
+    hamt: &Hamt<S, V, K, H, BUCKET_SIZE>,
+) -> usize
+where
+    K: HeapSize,
+    V: HeapSize,
+{
+    size_of::<Hamt<S, V, K, H, BUCKET_SIZE>>() + hamt.root.heap_size()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memorydb::MemoryDB;
+    use fvm_ipld_hamt::Sha256;
+
+    #[test]
+    fn counts_cached_nodes() -> anyhow::Result<()> {
+        let store = MemoryDB::default();
+        let mut map: Hamt<_, String, usize, Sha256, 3> = Hamt::new_with_bit_width(&store, 4);
+        let empty = hamt_memory(&map);
+        for key in 0..1000 {
+            map.set(key, "F".repeat(100))?;
+        }
+        let built = hamt_memory(&map);
+        // Every value alone takes 100 bytes.
+        assert!(built > empty + 1000 * 100);
+
+        let root = map.flush()?;
+        assert_eq!(hamt_memory(&map), built);
+
+        let loaded: Hamt<_, String, usize, Sha256, 3> =
+            Hamt::load_with_bit_width(&root, &store, 4)?;
+        let unloaded = hamt_memory(&loaded);
+        assert!(unloaded < built / 10);
+        for entry in loaded.iter() {
+            entry?;
+        }
+        assert!(hamt_memory(&loaded) > unloaded + 1000 * 100);
+        Ok(())
+    }
+}
@@ -1,23 +1,95 @@
 use anyhow::Result;
 use cid::Cid;
 use fvm_ipld_blockstore::Blockstore;
+use fvm_ipld_encoding::DAG_CBOR;
+use libipld_core::ipld::Ipld;
 use parking_lot::RwLock;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+#[cfg(feature = "profile")]
+use crate::profile;
+use crate::stats::BlockSizeHistogram;
 
 /// A thread-safe `HashMap` wrapper.
 #[derive(Debug, Default)]
 pub struct MemoryDB {
-    db: RwLock<HashMap<Vec<u8>, Vec<u8>>>,
+    db: RwLock<Blocks>,
+}
+
+type Layer = HashMap<Vec<u8>, Vec<u8>>;
+
+/// Blocks put since the last snapshot, on top of the layers frozen by
+/// earlier ones. Blocks are never in more than one layer.
+#[derive(Debug, Default, Clone)]
+struct Blocks {
+    frozen: Vec<Arc<Layer>>,
+    top: Layer,
+}
+
+impl Blocks {
+    fn layers(&self) -> impl Iterator<Item = &Layer> {
+        self.frozen.iter().map(|layer| &**layer).chain([&self.top])
+    }
+
+    fn get(&self, key: &[u8]) -> Option<&Vec<u8>> {
+        self.layers().find_map(|layer| layer.get(key))
+    }
+
+    fn contains_key(&self, key: &[u8]) -> bool {
+        self.get(key).is_some()
+    }
+
+    fn len(&self) -> usize {
+        self.layers().map(Layer::len).sum()
+    }
+
+    fn values(&self) -> impl Iterator<Item = &Vec<u8>> {
+        self.layers().flat_map(Layer::values)
+    }
+
+    /// Copies the blocks of the frozen layers into the top one, so they can
+    /// be removed without changing the snapshots sharing them.
+    fn thaw(&mut self) {
+        for layer in std::mem::take(&mut self.frozen) {
+            self.top.extend(
+                layer
+                    .iter()
+                    .map(|(key, value)| (key.clone(), value.clone())),
+            );
+        }
+    }
+}
+
+impl std::ops::Index<&Vec<u8>> for Blocks {
+    type Output = Vec<u8>;
+
+    fn index(&self, key: &Vec<u8>) -> &Vec<u8> {
+        self.get(key).expect("block not in store")
+    }
+}
+
+/// The blocks of a [`MemoryDB`] at some point, to go back to with
+/// [`MemoryDB::restore`].
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    frozen: Vec<Arc<Layer>>,
 }
 
 impl MemoryDB {
+    /// Sum of the sizes of all blocks, of every version and structure put
+    /// into the store. `Hamt::reachable_size` only counts those of one HAMT.
     pub fn bytes_stored(&self) -> u64 {
-        let map = self.db.read().clone();
-        let mut count: u64 = 0;
-        for value in map.values() {
-            count += value.len() as u64;
-        }
-        count
+        self.db
+            .read()
+            .values()
+            .map(|value| value.len() as u64)
+            .sum()
+    }
+
+    /// Number of blocks stored.
+    pub fn blocks(&self) -> usize {
+        self.db.read().len()
     }
 
     pub fn bytes_average(&self) -> f64 {
@@ -26,12 +98,133 @@ impl MemoryDB {
     }
 
     pub fn bytes_max(&self) -> usize {
-        let map = self.db.read().clone();
-        let mut max = 0;
-        for value in map.values() {
-            max = std::cmp::max(value.len(), max);
+        self.db.read().values().map(Vec::len).max().unwrap_or(0)
+    }
+
+    /// Sizes of all stored blocks, bucketed by powers of two.
+    pub fn block_size_histogram(&self) -> BlockSizeHistogram {
+        let mut histogram = BlockSizeHistogram::default();
+        for value in self.db.read().values() {
+            histogram.add(value.len());
+        }
+        histogram
+    }
+
+    /// Sum of the sizes of all blocks reachable from `roots`.
+    pub fn live_bytes(&self, roots: &[Cid]) -> Result<u64> {
+        let live = self.reachable(roots)?;
+        let map = self.db.read();
+        Ok(live.iter().map(|key| map[key].len() as u64).sum())
+    }
+
+    /// Sum of the sizes of the blocks reachable from `to` but not from
+    /// `from`: what a peer holding `from` has to fetch to get `to`.
+    pub fn delta_bytes(&self, from: &[Cid], to: &[Cid]) -> Result<u64> {
+        let have = self.reachable(from)?;
+        let want = self.reachable(to)?;
+        let map = self.db.read();
+        Ok(want
+            .difference(&have)
+            .map(|key| map[key].len() as u64)
+            .sum())
+    }
+
+    /// Number and size of the blocks reachable from both `a` and `b`, which
+    /// a store holding both only keeps once.
+    pub fn shared(&self, a: &[Cid], b: &[Cid]) -> Result<(usize, u64)> {
+        let a = self.reachable(a)?;
+        let b = self.reachable(b)?;
+        let map = self.db.read();
+        let shared: Vec<_> = a.intersection(&b).collect();
+        Ok((
+            shared.len(),
+            shared.iter().map(|key| map[*key].len() as u64).sum(),
+        ))
+    }
+
+    /// Removes every block that isn't reachable from `roots` and returns the
+    /// number of bytes freed. Blocks kept from snapshots are copied, leaving
+    /// the snapshots as they were.
+    pub fn gc(&self, roots: &[Cid]) -> Result<u64> {
+        let live = self.reachable(roots)?;
+        let mut map = self.db.write();
+        map.thaw();
+        let mut freed = 0;
+        map.top.retain(|key, value| {
+            let keep = live.contains(key);
+            if !keep {
+                freed += value.len() as u64;
+            }
+            keep
+        });
+        Ok(freed)
+    }
+
+    /// Removes the blocks `cids`, wherever they're linked from, and returns
+    /// the number of bytes freed. Missing blocks are skipped.
+    pub fn remove(&self, cids: &[Cid]) -> u64 {
+        let mut map = self.db.write();
+        map.thaw();
+        cids.iter()
+            .filter_map(|cid| map.top.remove(&cid.to_bytes()))
+            .map(|block| block.len() as u64)
+            .sum()
+    }
+
+    /// Freezes the blocks stored so far, so the store can be
+    /// [restored](Self::restore) to them after branching off into different
+    /// updates. Neither copies any blocks.
+    pub fn snapshot(&self) -> Snapshot {
+        let mut map = self.db.write();
+        if !map.top.is_empty() {
+            let top = std::mem::take(&mut map.top);
+            map.frozen.push(Arc::new(top));
+        }
+        Snapshot {
+            frozen: map.frozen.clone(),
+        }
+    }
+
+    /// Drops every block put since `snapshot` was taken and brings back
+    /// those removed since.
+    pub fn restore(&self, snapshot: &Snapshot) {
+        *self.db.write() = Blocks {
+            frozen: snapshot.frozen.clone(),
+            top: Layer::default(),
+        };
+    }
+
+    /// Keys of all blocks reachable from `roots` by following DAG-CBOR links.
+    /// Links to blocks that aren't in the store are ignored.
+    fn reachable(&self, roots: &[Cid]) -> Result<HashSet<Vec<u8>>> {
+        let map = self.db.read();
+        let mut live = HashSet::new();
+        let mut stack = roots.to_vec();
+
+        while let Some(cid) = stack.pop() {
+            let key = cid.to_bytes();
+            if live.contains(&key) {
+                continue;
+            }
+            if let Some(block) = map.get(&key) {
+                if cid.codec() == DAG_CBOR {
+                    let ipld: Ipld = fvm_ipld_encoding::from_slice(block)?;
+                    ipld.references(&mut stack);
+                }
+                live.insert(key);
+            }
+        }
+
+        Ok(live)
+    }
+
+    /// Puts `block` under `cid`, unless a frozen layer has it already.
+    fn insert(&self, cid: &Cid, block: &[u8]) {
+        let key = cid.to_bytes();
+        let mut map = self.db.write();
+        if !map.frozen.iter().any(|layer| layer.contains_key(&key)) {
+            map.top.insert(key, block.into());
         }
-        max
     }
 }
 
@@ -53,7 +246,113 @@ impl Blockstore for MemoryDB {
     }
 
     fn put_keyed(&self, k: &Cid, block: &[u8]) -> Result<()> {
-        self.db.write().insert(k.to_bytes(), block.into());
+        #[cfg(feature = "profile")]
+        profile::put(k, block, || self.insert(k, block));
+        #[cfg(not(feature = "profile"))]
+        self.insert(k, block);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fvm_ipld_hamt::Hamt;
+
+    #[test]
+    fn gc_keeps_only_reachable_blocks() -> Result<()> {
+        let store = MemoryDB::default();
+        let mut map: Hamt<_, String, usize> = Hamt::new_with_bit_width(&store, 2);
+        for key in 0..200 {
+            map.set(key, "old".to_string())?;
+        }
+        let old_root = map.flush()?;
+        for key in 0..20 {
+            map.set(key, "new".to_string())?;
+        }
+        let new_root = map.flush()?;
+
+        let total = store.bytes_stored();
+        let live = store.live_bytes(&[new_root])?;
+        assert!(live < total);
+        assert_eq!(store.live_bytes(&[old_root, new_root])?, total);
+
+        assert_eq!(store.gc(&[new_root])?, total - live);
+        assert_eq!(store.bytes_stored(), live);
+
+        let map: Hamt<_, String, usize> = Hamt::load_with_bit_width(&new_root, &store, 2)?;
+        assert_eq!(map.get(&0)?.map(String::as_str), Some("new"));
+        assert_eq!(map.get(&199)?.map(String::as_str), Some("old"));
+        Ok(())
+    }
+
+    #[test]
+    fn delta_bytes_skips_shared_blocks() -> Result<()> {
+        let store = MemoryDB::default();
+        let mut map: Hamt<_, String, usize> = Hamt::new_with_bit_width(&store, 2);
+        for key in 0..200 {
+            map.set(key, "old".to_string())?;
+        }
+        let old_root = map.flush()?;
+        map.set(0, "new".to_string())?;
+        let new_root = map.flush()?;
+
+        let delta = store.delta_bytes(&[old_root], &[new_root])?;
+        assert!(delta > 0);
+        assert!(delta < store.live_bytes(&[new_root])?);
+        assert_eq!(
+            store.delta_bytes(&[], &[new_root])?,
+            store.live_bytes(&[new_root])?
+        );
+        assert_eq!(store.delta_bytes(&[new_root], &[new_root])?, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn restore_drops_blocks_put_since_snapshot() -> Result<()> {
+        let store = MemoryDB::default();
+        let mut map: Hamt<_, String, usize> = Hamt::new_with_bit_width(&store, 2);
+        for key in 0..200 {
+            map.set(key, "old".to_string())?;
+        }
+        let base_root = map.flush()?;
+        let base_bytes = store.bytes_stored();
+        let base = store.snapshot();
+
+        for branch in ["a", "b"] {
+            store.restore(&base);
+            let mut map: Hamt<_, String, usize> = Hamt::load_with_bit_width(&base_root, &store, 2)?;
+            map.set(0, branch.to_string())?;
+            let root = map.flush()?;
+            assert!(store.bytes_stored() > base_bytes);
+            store.gc(&[root])?;
+            assert_eq!(store.bytes_stored(), store.live_bytes(&[root])?);
+        }
+
+        store.restore(&base);
+        assert_eq!(store.bytes_stored(), base_bytes);
+        let map: Hamt<_, String, usize> = Hamt::load_with_bit_width(&base_root, &store, 2)?;
+        assert_eq!(map.get(&0)?.map(String::as_str), Some("old"));
+        Ok(())
+    }
+
+    #[test]
+    fn shared_counts_blocks_of_both_roots() -> Result<()> {
+        let store = MemoryDB::default();
+        let mut map: Hamt<_, String, usize> = Hamt::new_with_bit_width(&store, 2);
+        for key in 0..200 {
+            map.set(key, "old".to_string())?;
+        }
+        let old_root = map.flush()?;
+        map.set(0, "new".to_string())?;
+        let new_root = map.flush()?;
+
+        let live = store.live_bytes(&[new_root])?;
+        let (blocks, bytes) = store.shared(&[old_root], &[new_root])?;
+        assert!(blocks > 0);
+        assert_eq!(bytes, live - store.delta_bytes(&[old_root], &[new_root])?);
+        assert_eq!(store.shared(&[new_root], &[new_root])?.1, live);
+        assert_eq!(store.shared(&[], &[new_root])?, (0, 0));
         Ok(())
     }
 }
@@ -0,0 +1,161 @@
+use anyhow::Result;
+use cid::Cid;
+use fvm_ipld_blockstore::Blockstore;
+use serde::Serialize;
+use std::ops::Sub;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Wraps a blockstore and counts the traffic going through it.
+///
+/// Unlike the final size of the store, this also sees reads and blocks that
+/// are written again although they're already present.
+#[derive(Debug, Default)]
+pub struct MeteredStore<S> {
+    inner: S,
+    gets: AtomicU64,
+    get_misses: AtomicU64,
+    bytes_read: AtomicU64,
+    puts: AtomicU64,
+    put_hits: AtomicU64,
+    bytes_written: AtomicU64,
+}
+
+/// Counters of a [`MeteredStore`] at one point in time.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct StoreStats {
+    pub gets: u64,
+    /// Gets of blocks that weren't in the store.
+    pub get_misses: u64,
+    pub bytes_read: u64,
+    pub puts: u64,
+    /// Puts of blocks that were already in the store.
+    pub put_hits: u64,
+    /// Bytes of all puts, including hits.
+    pub bytes_written: u64,
+}
+
+impl<S> MeteredStore<S> {
+    pub fn new(inner: S) -> Self {
+        MeteredStore {
+            inner,
+            gets: AtomicU64::new(0),
+            get_misses: AtomicU64::new(0),
+            bytes_read: AtomicU64::new(0),
+            puts: AtomicU64::new(0),
+            put_hits: AtomicU64::new(0),
+            bytes_written: AtomicU64::new(0),
+        }
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    pub fn snapshot(&self) -> StoreStats {
+        StoreStats {
+            gets: self.gets.load(Ordering::Relaxed),
+            get_misses: self.get_misses.load(Ordering::Relaxed),
+            bytes_read: self.bytes_read.load(Ordering::Relaxed),
+            puts: self.puts.load(Ordering::Relaxed),
+            put_hits: self.put_hits.load(Ordering::Relaxed),
+            bytes_written: self.bytes_written.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Sets all counters back to zero.
+    pub fn reset(&self) {
+        for counter in [
+            &self.gets,
+            &self.get_misses,
+            &self.bytes_read,
+            &self.puts,
+            &self.put_hits,
+            &self.bytes_written,
+        ] {
+            counter.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+impl<S: Blockstore> Blockstore for MeteredStore<S> {
+    fn has(&self, k: &Cid) -> Result<bool> {
+        self.inner.has(k)
+    }
+
+    fn get(&self, k: &Cid) -> Result<Option<Vec<u8>>> {
+        let block = self.inner.get(k)?;
+        self.gets.fetch_add(1, Ordering::Relaxed);
+        match &block {
+            Some(block) => self
+                .bytes_read
+                .fetch_add(block.len() as u64, Ordering::Relaxed),
+            None => self.get_misses.fetch_add(1, Ordering::Relaxed),
+        };
+        Ok(block)
+    }
+
+    fn put_keyed(&self, k: &Cid, block: &[u8]) -> Result<()> {
+        if self.inner.has(k)? {
+            self.put_hits.fetch_add(1, Ordering::Relaxed);
+        }
+        self.inner.put_keyed(k, block)?;
+        self.puts.fetch_add(1, Ordering::Relaxed);
+        self.bytes_written
+            .fetch_add(block.len() as u64, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+/// Traffic between two snapshots.
+impl Sub for StoreStats {
+    type Output = StoreStats;
+
+    fn sub(self, earlier: StoreStats) -> StoreStats {
+        StoreStats {
+            gets: self.gets - earlier.gets,
+            get_misses: self.get_misses - earlier.get_misses,
+            bytes_read: self.bytes_read - earlier.bytes_read,
+            puts: self.puts - earlier.puts,
+            put_hits: self.put_hits - earlier.put_hits,
+            bytes_written: self.bytes_written - earlier.bytes_written,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memorydb::MemoryDB;
+    use fvm_ipld_hamt::{Hamt, Sha256};
+
+    #[test]
+    fn counts_traffic_and_rewrites() -> Result<()> {
+        let store = MeteredStore::new(MemoryDB::default());
+        let mut map: Hamt<_, String, usize, Sha256, 3> = Hamt::new_with_bit_width(&store, 4);
+        for key in 0..1000 {
+            map.set(key, "F".to_string())?;
+        }
+        let root = map.flush()?;
+        let written = store.snapshot();
+        assert_eq!(written.bytes_written, store.inner().bytes_stored());
+        assert_eq!(written.put_hits, 0);
+        assert_eq!(written.gets, 0);
+
+        // Flushing again rewrites the root, which is already there.
+        map.flush()?;
+        let rewrite = store.snapshot() - written;
+        assert_eq!((rewrite.puts, rewrite.put_hits), (1, 1));
+
+        store.reset();
+        let map: Hamt<_, String, usize, Sha256, 3> = Hamt::load_with_bit_width(&root, &store, 4)?;
+        map.get(&1)?;
+        let read = store.snapshot();
+        assert!(read.gets > 1);
+        assert_eq!(read.puts, 0);
+        Ok(())
+    }
+}
@@ -0,0 +1,755 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail, Context, Error, Result};
+use serde::de::{self, MapAccess};
+use serde::ser::SerializeMap;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::value::RawValue;
+use serde_json::Value;
+
+use crate::plot::Record;
+
+/// How result records are written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// Delimiter separated values with a header line. Nested structs are
+    /// flattened into `parent.field` columns.
+    Csv(Delimiter),
+    /// A single JSON array of records.
+    Json,
+    /// One JSON record per line.
+    Ndjson,
+}
+
+impl FromStr for Format {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s {
+            "csv" => Format::Csv(Delimiter::Semicolon),
+            "tsv" => Format::Csv(Delimiter::Tab),
+            "json" => Format::Json,
+            "ndjson" | "jsonl" => Format::Ndjson,
+            other => bail!("unknown format `{other}`, expected `csv`, `tsv`, `json` or `ndjson`"),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Delimiter {
+    Comma,
+    Semicolon,
+    Tab,
+}
+
+impl Delimiter {
+    pub fn as_char(self) -> char {
+        match self {
+            Delimiter::Comma => ',',
+            Delimiter::Semicolon => ';',
+            Delimiter::Tab => '\t',
+        }
+    }
+}
+
+impl FromStr for Delimiter {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s {
+            "," | "comma" => Delimiter::Comma,
+            ";" | "semicolon" => Delimiter::Semicolon,
+            "\t" | "tab" | "tsv" => Delimiter::Tab,
+            other => bail!("unknown delimiter `{other}`, expected `,`, `;` or `tab`"),
+        })
+    }
+}
+
+/// Writes experiment result records to a file or stdout.
+pub struct ResultsWriter {
+    out: Box<dyn Write>,
+    format: Format,
+    /// Header already present in the output, if any.
+    existing_header: Option<String>,
+    write_header: bool,
+    records: usize,
+    /// Flattened copies of the records written so far, if they're kept.
+    kept: Option<Vec<Record>>,
+    /// Leading columns added to every record.
+    columns: Vec<(String, Box<RawValue>)>,
+    /// Points of the sweep written so far, if they're tracked.
+    manifest: Option<Manifest>,
+}
+
+impl ResultsWriter {
+    pub fn stdout(format: Format) -> Self {
+        Self::new(Box::new(io::stdout().lock()), format)
+    }
+
+    /// Discards the output and only keeps the records.
+    pub fn in_memory() -> Self {
+        Self::new(Box::new(io::sink()), Format::Ndjson).keeping_records()
+    }
+
+    /// Truncates or creates the file at `path`.
+    pub fn create(path: &Path, format: Format) -> Result<Self> {
+        let file = File::create(path)?;
+        Ok(Self::new(Box::new(BufWriter::new(file)), format))
+    }
+
+    /// Appends to the file at `path`, creating it if necessary.
+    ///
+    /// For CSV the header is only written if the file was empty. Otherwise the
+    /// header of the first written record has to match the existing one.
+    /// JSON arrays can't be appended to, use NDJSON instead.
+    pub fn append(path: &Path, format: Format) -> Result<Self> {
+        if format == Format::Json {
+            bail!("can't append to a JSON array, use the ndjson format instead");
+        }
+
+        let existing_header = match File::open(path) {
+            Ok(file) => BufReader::new(file)
+                .lines()
+                .next()
+                .transpose()?
+                .filter(|line| !line.is_empty()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e.into()),
+        };
+
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let mut writer = Self::new(Box::new(BufWriter::new(file)), format);
+        writer.existing_header = existing_header;
+        Ok(writer)
+    }
+
+    /// Continues the results at `path` after the last point recorded in
+    /// `manifest`, dropping any records of points that weren't finished.
+    pub fn resume(path: &Path, format: Format, manifest: Manifest) -> Result<Self> {
+        match fs::metadata(path) {
+            Ok(metadata) if metadata.len() < manifest.results_len => bail!(
+                "`{}` is shorter than recorded in `{}`",
+                path.display(),
+                manifest.path.display()
+            ),
+            Ok(_) => OpenOptions::new()
+                .write(true)
+                .open(path)?
+                .set_len(manifest.results_len)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+        Ok(Self::append(path, format)?.with_manifest(manifest))
+    }
+
+    fn new(out: Box<dyn Write>, format: Format) -> Self {
+        ResultsWriter {
+            out,
+            format,
+            existing_header: None,
+            write_header: true,
+            records: 0,
+            kept: None,
+            columns: Vec::new(),
+            manifest: None,
+        }
+    }
+
+    /// Whether a CSV header line is emitted before the first record.
+    pub fn with_header(mut self, write_header: bool) -> Self {
+        self.write_header = write_header;
+        self
+    }
+
+    /// Adds a column with the same `value` in front of every record, e.g. the
+    /// seed of the run.
+    pub fn with_column(mut self, name: &str, value: &impl Serialize) -> Result<Self> {
+        self.columns
+            .push((name.to_string(), serde_json::value::to_raw_value(value)?));
+        Ok(self)
+    }
+
+    /// Records finished points in `manifest`, see [`ResultsWriter::checkpoint`].
+    pub fn with_manifest(mut self, manifest: Manifest) -> Self {
+        self.manifest = Some(manifest);
+        self
+    }
+
+    /// Whether the results of a point were written by an earlier run.
+    pub fn is_done(&self, bit_width: u32, bucket_size: usize) -> bool {
+        self.manifest
+            .as_ref()
+            .is_some_and(|manifest| manifest.is_done(bit_width, bucket_size))
+    }
+
+    /// Flushes the records written so far, which complete the results of a
+    /// point, and records the point in the manifest, if any.
+    pub fn checkpoint(&mut self, bit_width: u32, bucket_size: usize) -> Result<()> {
+        self.out.flush()?;
+        if let Some(manifest) = &mut self.manifest {
+            manifest.record(bit_width, bucket_size)?;
+        }
+        Ok(())
+    }
+
+    /// Keeps a copy of every record, e.g. to plot them afterwards.
+    pub fn keeping_records(mut self) -> Self {
+        self.kept = Some(Vec::new());
+        self
+    }
+
+    /// The records written so far, empty unless they're kept.
+    pub fn kept_records(&self) -> &[Record] {
+        self.kept.as_deref().unwrap_or_default()
+    }
+
+    pub fn into_kept_records(self) -> Vec<Record> {
+        self.kept.unwrap_or_default()
+    }
+
+    pub fn write<R: Serialize>(&mut self, record: &R) -> Result<()> {
+        let mut json = serde_json::to_string(record)?;
+        if !self.columns.is_empty() {
+            let Fields(fields) = serde_json::from_str::<Fields<Box<RawValue>>>(&json)?;
+            let fields = self.columns.iter().cloned().chain(fields).collect();
+            json = serde_json::to_string(&Fields(fields))?;
+        }
+        if let Some(kept) = &mut self.kept {
+            kept.push(self::record(&json)?);
+        }
+
+        match self.format {
+            Format::Csv(delimiter) => {
+                let (names, fields): (Vec<_>, Vec<_>) = self::record(&json)?.into_iter().unzip();
+
+                if self.records == 0 {
+                    let header = line(&names, delimiter);
+                    match self.existing_header.take() {
+                        Some(existing) if existing != header => {
+                            bail!("existing header `{existing}` does not match `{header}`")
+                        }
+                        Some(_) => {}
+                        None if self.write_header => writeln!(self.out, "{header}")?,
+                        None => {}
+                    }
+                }
+                writeln!(self.out, "{}", line(&fields, delimiter))?;
+            }
+            Format::Json => {
+                let separator = if self.records == 0 { "[" } else { "," };
+                write!(self.out, "{separator}\n{json}")?;
+            }
+            Format::Ndjson => writeln!(self.out, "{json}")?,
+        }
+
+        self.records += 1;
+        Ok(())
+    }
+
+    /// Closes the JSON array, if any, and flushes the output.
+    pub fn finish(mut self) -> Result<()> {
+        if self.format == Format::Json {
+            let close = if self.records == 0 { "[]" } else { "\n]" };
+            writeln!(self.out, "{close}")?;
+        }
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+/// The fields of a JSON object in the order they were serialized, which
+/// `serde_json::Map` sorts unless built with `preserve_order`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fields<V = Value>(pub Vec<(String, V)>);
+
+impl<V> Fields<V> {
+    pub fn get(&self, key: &str) -> Option<&V> {
+        self.0.iter().find(|(k, _)| k == key).map(|(_, v)| v)
+    }
+}
+
+impl<V: Serialize> Serialize for Fields<V> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.0.len()))?;
+        for (key, value) in &self.0 {
+            map.serialize_entry(key, value)?;
+        }
+        map.end()
+    }
+}
+
+impl<'de, V: Deserialize<'de>> Deserialize<'de> for Fields<V> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Visitor<V>(PhantomData<V>);
+
+        impl<'de, V: Deserialize<'de>> de::Visitor<'de> for Visitor<V> {
+            type Value = Fields<V>;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("a JSON object")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Fields<V>, A::Error> {
+                let mut fields = Vec::new();
+                while let Some(field) = map.next_entry()? {
+                    fields.push(field);
+                }
+                Ok(Fields(fields))
+            }
+        }
+
+        deserializer.deserialize_map(Visitor(PhantomData))
+    }
+}
+
+/// The points of a sweep whose results were completely written, kept next to
+/// the results as `<results>.manifest`.
+///
+/// The first line identifies the run, so results of other parameters aren't
+/// resumed. Every other line is a finished bit width and bucket size with the
+/// length of the results file after it:
+///
+/// ```text
+/// run sizes bit-width=[4] bucket-size=[1, 2, 3] n=100000 ...
+/// done 4 1 3512
+/// done 4 2 3581
+/// ```
+pub struct Manifest {
+    path: PathBuf,
+    results: PathBuf,
+    out: File,
+    done: HashSet<(u32, usize)>,
+    /// Length of the results up to the last finished point.
+    results_len: u64,
+}
+
+impl Manifest {
+    pub fn path_for(results: &Path) -> PathBuf {
+        let mut path = results.as_os_str().to_owned();
+        path.push(".manifest");
+        PathBuf::from(path)
+    }
+
+    /// Starts a new manifest for the results at `results`.
+    pub fn create(results: &Path, run: &str) -> Result<Self> {
+        let path = Self::path_for(results);
+        let mut out = File::create(&path)?;
+        writeln!(out, "run {run}")?;
+        Ok(Manifest {
+            path,
+            results: results.to_path_buf(),
+            out,
+            done: HashSet::new(),
+            results_len: 0,
+        })
+    }
+
+    /// Reads the manifest of the results at `results`, which has to be from
+    /// the same `run`. Starts a new one if there are no results yet.
+    pub fn resume(results: &Path, run: &str) -> Result<Self> {
+        let path = Self::path_for(results);
+        let file = match File::open(&path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                if fs::metadata(results).is_ok_and(|metadata| metadata.len() > 0) {
+                    bail!("no manifest `{}` to resume from", path.display());
+                }
+                return Self::create(results, run);
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut lines = BufReader::new(file).lines();
+        match lines.next().transpose()? {
+            Some(line) if line.strip_prefix("run ") == Some(run) => {}
+            _ => bail!(
+                "`{}` was written by a different run, use other parameters or another \
+                 `--output`",
+                path.display()
+            ),
+        }
+        let mut done = HashSet::new();
+        let mut results_len = 0;
+        for line in lines {
+            let line = line?;
+            let invalid = || anyhow!("invalid line `{line}` in `{}`", path.display());
+            let fields: Vec<&str> = line.split(' ').collect();
+            match fields.as_slice() {
+                ["done", bit_width, bucket_size, len] => {
+                    done.insert((
+                        bit_width.parse().map_err(|_| invalid())?,
+                        bucket_size.parse().map_err(|_| invalid())?,
+                    ));
+                    results_len = len.parse().map_err(|_| invalid())?;
+                }
+                _ => return Err(invalid()),
+            }
+        }
+
+        let out = OpenOptions::new().append(true).open(&path)?;
+        Ok(Manifest {
+            path,
+            results: results.to_path_buf(),
+            out,
+            done,
+            results_len,
+        })
+    }
+
+    pub fn is_done(&self, bit_width: u32, bucket_size: usize) -> bool {
+        self.done.contains(&(bit_width, bucket_size))
+    }
+
+    /// Records a finished point, after its results were flushed.
+    fn record(&mut self, bit_width: u32, bucket_size: usize) -> Result<()> {
+        self.results_len = fs::metadata(&self.results)?.len();
+        self.done.insert((bit_width, bucket_size));
+        writeln!(
+            self.out,
+            "done {bit_width} {bucket_size} {}",
+            self.results_len
+        )?;
+        self.out.sync_data()?;
+        Ok(())
+    }
+}
+
+/// Reads back the flattened records of results in any of the formats, told
+/// apart by their first character: `[` for a JSON array, `{` for NDJSON and
+/// anything else for CSV, whose delimiter is the one in its header.
+pub fn read_records(text: &str) -> Result<Vec<Record>> {
+    let records = match text.trim_start().chars().next() {
+        None => Vec::new(),
+        Some('[') => serde_json::from_str::<Vec<Box<RawValue>>>(text)?
+            .iter()
+            .map(|value| record(value.get()))
+            .collect::<Result<_>>()?,
+        Some('{') => text
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(i, line)| record(line).with_context(|| format!("line {}", i + 1)))
+            .collect::<Result<_>>()?,
+        Some(_) => read_csv(text)?,
+    };
+    Ok(records)
+}
+
+/// The flattened columns of the JSON object `json`.
+fn record(json: &str) -> Result<Record> {
+    let mut columns = Vec::new();
+    flatten("", json, &mut columns)?;
+    Ok(columns)
+}
+
+/// Records of CSV with a header line, undoing [`escape`].
+fn read_csv(text: &str) -> Result<Vec<Record>> {
+    let header = text.lines().next().unwrap_or_default();
+    let delimiter = [Delimiter::Tab, Delimiter::Semicolon, Delimiter::Comma]
+        .into_iter()
+        .find(|delimiter| header.contains(delimiter.as_char()))
+        .unwrap_or(Delimiter::Semicolon);
+    let mut rows = split_rows(text, delimiter.as_char())?.into_iter();
+    let names = rows.next().unwrap_or_default();
+    rows.enumerate()
+        .map(|(i, fields)| {
+            if fields.len() != names.len() {
+                bail!(
+                    "row {} has {} fields, the header {}",
+                    i + 1,
+                    fields.len(),
+                    names.len()
+                );
+            }
+            Ok(names.iter().cloned().zip(fields).collect())
+        })
+        .collect()
+}
+
+/// Splits CSV into rows of fields, keeping delimiters and line breaks inside
+/// quoted fields.
+fn split_rows(text: &str, delimiter: char) -> Result<Vec<Vec<String>>> {
+    let mut rows = Vec::new();
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            '"' if quoted => quoted = false,
+            '"' if field.is_empty() => quoted = true,
+            c if quoted => field.push(c),
+            c if c == delimiter => fields.push(std::mem::take(&mut field)),
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\n' => {
+                fields.push(std::mem::take(&mut field));
+                rows.push(std::mem::take(&mut fields));
+            }
+            c => field.push(c),
+        }
+    }
+    if quoted {
+        bail!("unterminated quoted field");
+    }
+    if !field.is_empty() || !fields.is_empty() {
+        fields.push(field);
+        rows.push(fields);
+    }
+    Ok(rows)
+}
+
+/// Collects the leaves of the JSON `value` as `(column, field)` pairs, in
+/// the order of their fields.
+fn flatten(prefix: &str, value: &str, columns: &mut Record) -> Result<()> {
+    if value.trim_start().starts_with('{') {
+        let Fields(fields) = serde_json::from_str::<Fields<Box<RawValue>>>(value)?;
+        for (key, value) in fields {
+            let name = if prefix.is_empty() {
+                key
+            } else {
+                format!("{prefix}.{key}")
+            };
+            flatten(&name, value.get(), columns)?;
+        }
+        return Ok(());
+    }
+    let field = match serde_json::from_str(value)? {
+        Value::Null => String::new(),
+        Value::String(s) => s,
+        // Whole floats like integers, as Rust prints them.
+        Value::Number(n) if n.is_f64() => n.as_f64().expect("a float").to_string(),
+        other => other.to_string(),
+    };
+    columns.push((prefix.to_string(), field));
+    Ok(())
+}
+
+fn line(fields: &[String], delimiter: Delimiter) -> String {
+    let delimiter = delimiter.as_char();
+    let fields: Vec<String> = fields
+        .iter()
+        .map(|field| escape(field, delimiter))
+        .collect();
+    fields.join(&delimiter.to_string())
+}
+
+/// Quotes a field if it contains the delimiter, quotes or line breaks.
+fn escape(field: &str, delimiter: char) -> String {
+    if field.contains([delimiter, '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_fields() {
+        assert_eq!(escape("plain", ','), "plain");
+        assert_eq!(escape("a,b", ','), "\"a,b\"");
+        assert_eq!(escape("a,b", ';'), "a,b");
+        assert_eq!(escape("say \"hi\"", '\t'), "\"say \"\"hi\"\"\"");
+        assert_eq!(escape("two\nlines", ';'), "\"two\nlines\"");
+    }
+
+    #[derive(Serialize)]
+    struct Row {
+        name: &'static str,
+        value: u32,
+    }
+
+    fn write_rows(path: &Path, format: Format, rows: &[(&'static str, u32)]) -> Result<String> {
+        let _ = std::fs::remove_file(path);
+        for &(name, value) in rows {
+            let mut writer = ResultsWriter::append(path, format)?;
+            writer.write(&Row { name, value })?;
+            writer.finish()?;
+        }
+        let contents = std::fs::read_to_string(path)?;
+        std::fs::remove_file(path)?;
+        Ok(contents)
+    }
+
+    #[test]
+    fn appends_without_repeating_header() -> Result<()> {
+        let path = std::env::temp_dir().join(format!("results-{}.csv", std::process::id()));
+        let contents = write_rows(
+            &path,
+            Format::Csv(Delimiter::Comma),
+            &[("a,b", 1), ("c", 2)],
+        )?;
+        assert_eq!(contents, "name,value\n\"a,b\",1\nc,2\n");
+        Ok(())
+    }
+
+    #[test]
+    fn writes_json_records() -> Result<()> {
+        let path = std::env::temp_dir().join(format!("results-{}.ndjson", std::process::id()));
+        let contents = write_rows(&path, Format::Ndjson, &[("a", 1), ("b", 2)])?;
+        assert_eq!(
+            contents,
+            "{\"name\":\"a\",\"value\":1}\n{\"name\":\"b\",\"value\":2}\n"
+        );
+
+        let path = std::env::temp_dir().join(format!("results-{}.json", std::process::id()));
+        let mut writer = ResultsWriter::create(&path, Format::Json)?;
+        writer.write(&Row {
+            name: "a",
+            value: 1,
+        })?;
+        writer.write(&Row {
+            name: "b",
+            value: 2,
+        })?;
+        writer.finish()?;
+        let contents = std::fs::read_to_string(&path)?;
+        std::fs::remove_file(&path)?;
+        assert_eq!(
+            contents,
+            "[\n{\"name\":\"a\",\"value\":1},\n{\"name\":\"b\",\"value\":2}\n]\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn resumes_after_the_last_checkpoint() -> Result<()> {
+        let path = std::env::temp_dir().join(format!("results-{}-resume.csv", std::process::id()));
+        let format = Format::Csv(Delimiter::Comma);
+        let row = |name| Row { name, value: 1 };
+
+        let manifest = Manifest::create(&path, "sizes n=1")?;
+        let mut writer = ResultsWriter::create(&path, format)?.with_manifest(manifest);
+        writer.write(&row("a"))?;
+        writer.checkpoint(4, 1)?;
+        // Interrupted before the second point is finished.
+        writer.write(&row("b"))?;
+        writer.finish()?;
+
+        assert!(Manifest::resume(&path, "sizes n=2").is_err());
+        let manifest = Manifest::resume(&path, "sizes n=1")?;
+        let mut writer = ResultsWriter::resume(&path, format, manifest)?;
+        assert!(writer.is_done(4, 1));
+        assert!(!writer.is_done(4, 2));
+        writer.write(&row("c"))?;
+        writer.checkpoint(4, 2)?;
+        writer.finish()?;
+
+        let contents = std::fs::read_to_string(&path)?;
+        let manifest = std::fs::read_to_string(Manifest::path_for(&path))?;
+        std::fs::remove_file(&path)?;
+        std::fs::remove_file(Manifest::path_for(&path))?;
+        assert_eq!(contents, "name,value\na,1\nc,1\n");
+        assert_eq!(manifest, "run sizes n=1\ndone 4 1 15\ndone 4 2 19\n");
+        Ok(())
+    }
+
+    #[test]
+    fn prepends_columns() -> Result<()> {
+        let mut writer = ResultsWriter::in_memory().with_column("seed", &7)?;
+        writer.write(&Row {
+            name: "a",
+            value: 1,
+        })?;
+        assert_eq!(
+            writer.kept_records(),
+            [vec![
+                ("seed".to_string(), "7".to_string()),
+                ("name".to_string(), "a".to_string()),
+                ("value".to_string(), "1".to_string()),
+            ]]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn keeps_the_field_order_of_records() -> Result<()> {
+        #[derive(Serialize)]
+        struct Outer {
+            z: u32,
+            #[serde(flatten)]
+            row: Row,
+            nested: Row,
+            ratio: f64,
+        }
+
+        let mut writer = ResultsWriter::in_memory();
+        writer.write(&Outer {
+            z: 3,
+            row: Row {
+                name: "a",
+                value: 1,
+            },
+            nested: Row {
+                name: "b",
+                value: 2,
+            },
+            ratio: 2.0,
+        })?;
+        let columns: Vec<_> = writer.kept_records()[0]
+            .iter()
+            .map(|(name, field)| format!("{name}={field}"))
+            .collect();
+        assert_eq!(
+            columns,
+            [
+                "z=3",
+                "name=a",
+                "value=1",
+                "nested.name=b",
+                "nested.value=2",
+                "ratio=2"
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn reads_back_written_records() -> Result<()> {
+        let rows = [("a;b", 1), ("say \"hi\"\n", 2)];
+        let expected: Vec<Record> = rows
+            .iter()
+            .map(|&(name, value)| {
+                vec![
+                    ("name".to_string(), name.to_string()),
+                    ("value".to_string(), value.to_string()),
+                ]
+            })
+            .collect();
+        for (i, format) in [
+            Format::Csv(Delimiter::Semicolon),
+            Format::Csv(Delimiter::Tab),
+            Format::Json,
+            Format::Ndjson,
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let path =
+                std::env::temp_dir().join(format!("results-{}-read-{i}", std::process::id()));
+            let mut writer = ResultsWriter::create(&path, format)?;
+            for &(name, value) in &rows {
+                writer.write(&Row { name, value })?;
+            }
+            writer.finish()?;
+            let contents = std::fs::read_to_string(&path)?;
+            std::fs::remove_file(&path)?;
+            assert_eq!(read_records(&contents)?, expected, "{format:?}");
+        }
+        assert!(read_records("")?.is_empty());
+        assert!(read_records("a,b\n1\n").is_err());
+        Ok(())
+    }
+}
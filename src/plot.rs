@@ -0,0 +1,553 @@
+//! Heatmaps and line charts of experiment results, drawn as SVG with
+//! plotters.
+//!
+//! Both chart a single numeric column over bucket size and bit width, the
+//! dimensions every experiment is swept over: heatmaps with bucket sizes
+//! across and bit widths down, line charts with one line per bit width.
+//! [`Distribution`]s chart one column over another instead, for results with
+//! many records per combination.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail, Error, Result};
+use plotters::coord::Shift;
+use plotters::prelude::*;
+use plotters::style::text_anchor::{HPos, Pos, VPos};
+
+pub(crate) const BUCKET_SIZE: &str = "bucket_size";
+pub(crate) const BIT_WIDTH: &str = "bit_width";
+
+const FONT: &str = "Helvetica";
+const MARGIN: u32 = 60;
+const CELL_WIDTH: u32 = 48;
+const CELL_HEIGHT: u32 = 32;
+const CHART_WIDTH: u32 = 560;
+const CHART_HEIGHT: u32 = 300;
+
+/// A chart of one result column, given as `heatmap:<column>` or
+/// `line:<column>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Chart {
+    Heatmap(String),
+    Line(String),
+}
+
+impl Chart {
+    pub fn column(&self) -> &str {
+        match self {
+            Chart::Heatmap(column) | Chart::Line(column) => column,
+        }
+    }
+
+    /// `<column>.svg`, used unless another path is given.
+    pub fn default_path(&self) -> PathBuf {
+        PathBuf::from(format!("{}.svg", self.column()))
+    }
+
+    /// Truncates or creates the file at `path`.
+    pub fn render_to_file(&self, records: &[Record], path: &Path) -> Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        self.render(records, &mut out)?;
+        out.flush()?;
+        Ok(())
+    }
+
+    pub fn render(&self, records: &[Record], out: &mut impl Write) -> Result<()> {
+        let points = Points::new(records, self.column())?;
+        match self {
+            Chart::Heatmap(column) => heatmap(&points, column, out),
+            Chart::Line(column) => line_chart(&points, column, out),
+        }
+    }
+}
+
+impl FromStr for Chart {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.split_once(':') {
+            Some(("heatmap", column)) if !column.is_empty() => Ok(Chart::Heatmap(column.into())),
+            Some(("line", column)) if !column.is_empty() => Ok(Chart::Line(column.into())),
+            _ => bail!("expected `heatmap:<column>` or `line:<column>`, got `{s}`"),
+        }
+    }
+}
+
+/// A result record flattened into `(column, field)` pairs, as in CSV output.
+pub type Record = Vec<(String, String)>;
+
+/// `field` of `record`, or an error naming the missing column.
+fn field<'a>(record: &'a Record, name: &str) -> Result<&'a str> {
+    record
+        .iter()
+        .find(|(column, _)| column == name)
+        .map(|(_, field)| field.as_str())
+        .ok_or_else(|| anyhow!("results have no `{name}` column"))
+}
+
+fn numeric(record: &Record, name: &str) -> Result<f64> {
+    let value = field(record, name)?;
+    value
+        .parse()
+        .map_err(|_| anyhow!("`{name}` isn't numeric: `{value}`"))
+}
+
+/// A line chart of column `y` over column `x`, with a line per value of
+/// column `series`, like the share of nodes by their number of set bits
+/// with a line per level.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Distribution {
+    pub x: String,
+    pub y: String,
+    pub series: String,
+}
+
+impl Distribution {
+    pub fn new(x: impl Into<String>, y: impl Into<String>, series: impl Into<String>) -> Self {
+        Distribution {
+            x: x.into(),
+            y: y.into(),
+            series: series.into(),
+        }
+    }
+
+    pub fn render(&self, records: &[Record], heading: &str, out: &mut impl Write) -> Result<()> {
+        // `(series, [(x, y)])`, in the order the series first appear.
+        let mut lines: Vec<(String, Vec<(f64, f64)>)> = Vec::new();
+        for record in records {
+            let series = field(record, &self.series)?;
+            let point = (numeric(record, &self.x)?, numeric(record, &self.y)?);
+            match lines.iter_mut().find(|(name, _)| name == series) {
+                Some((_, points)) => points.push(point),
+                None => lines.push((series.to_string(), vec![point])),
+            }
+        }
+        if lines.is_empty() {
+            bail!("no results to chart");
+        }
+        for (_, points) in &mut lines {
+            points.sort_by(|a, b| a.0.total_cmp(&b.0));
+        }
+        let (min, max) = range(lines.iter().flat_map(|(_, points)| points).map(|&(x, _)| x));
+        let (left, right, step) = nice_range(min, max);
+        Lines {
+            heading,
+            x_desc: &self.x,
+            x_range: left..right,
+            x_labels: ((right - left) / step).round() as usize + 1,
+            x_label: &format_value,
+            lines: lines
+                .into_iter()
+                .map(|(series, points)| (format!("{} {series}", self.series), points))
+                .collect(),
+            point_size: 0,
+        }
+        .render(out)
+    }
+}
+
+/// Values of the charted column by bit width and bucket size.
+struct Points {
+    bit_widths: Vec<u32>,
+    bucket_sizes: Vec<usize>,
+    /// `(bit_width, bucket_size, value)`
+    values: Vec<(u32, usize, f64)>,
+}
+
+impl Points {
+    fn new(records: &[Record], column: &str) -> Result<Self> {
+        let mut values: Vec<(u32, usize, f64)> = Vec::new();
+        for record in records {
+            let bit_width = field(record, BIT_WIDTH)?.parse()?;
+            let bucket_size = field(record, BUCKET_SIZE)?.parse()?;
+            let value = field(record, column)?;
+            // Empty fields are missing values, like a maximum of no keys.
+            if value.is_empty() {
+                continue;
+            }
+            let value: f64 = value
+                .parse()
+                .map_err(|_| anyhow!("`{column}` isn't numeric: `{value}`"))?;
+            if values
+                .iter()
+                .any(|&(b, s, _)| (b, s) == (bit_width, bucket_size))
+            {
+                bail!(
+                    "several results for bit width {bit_width} and bucket size {bucket_size}, \
+                     only sweeps with one result per combination can be charted"
+                );
+            }
+            values.push((bit_width, bucket_size, value));
+        }
+        if values.is_empty() {
+            bail!("no results to chart");
+        }
+
+        let mut bit_widths: Vec<u32> = values.iter().map(|&(b, _, _)| b).collect();
+        bit_widths.sort_unstable();
+        bit_widths.dedup();
+        let mut bucket_sizes: Vec<usize> = values.iter().map(|&(_, s, _)| s).collect();
+        bucket_sizes.sort_unstable();
+        bucket_sizes.dedup();
+        Ok(Points {
+            bit_widths,
+            bucket_sizes,
+            values,
+        })
+    }
+
+    fn get(&self, bit_width: u32, bucket_size: usize) -> Option<f64> {
+        self.values
+            .iter()
+            .find(|&&(b, s, _)| (b, s) == (bit_width, bucket_size))
+            .map(|&(_, _, value)| value)
+    }
+
+    fn range(&self) -> (f64, f64) {
+        range(self.values.iter().map(|&(_, _, value)| value))
+    }
+}
+
+fn heatmap(points: &Points, column: &str, out: &mut impl Write) -> Result<()> {
+    let columns = points.bucket_sizes.len();
+    let rows = points.bit_widths.len();
+    let size = (
+        MARGIN * 2 + CELL_WIDTH * columns as u32,
+        MARGIN * 2 + CELL_HEIGHT * rows as u32,
+    );
+    let (min, max) = points.range();
+    // Rows count up from the bottom, the first bit width is in the last one.
+    let row = |bit_width| {
+        let index = points.bit_widths.iter().position(|&b| b == bit_width);
+        rows - 1 - index.expect("collected from the values")
+    };
+    let column_of = |bucket_size| {
+        let index = points.bucket_sizes.iter().position(|&s| s == bucket_size);
+        index.expect("collected from the values")
+    };
+
+    render_svg(out, size, |area| {
+        let mut chart = ChartBuilder::on(area)
+            .caption(column, (FONT, 18))
+            .margin(MARGIN / 4)
+            .x_label_area_size(MARGIN * 3 / 4)
+            .y_label_area_size(MARGIN * 3 / 4)
+            .build_cartesian_2d(0..columns, 0..rows)?;
+        let (width, height) = chart.plotting_area().dim_in_pixel();
+        let cell = (
+            (width / columns as u32) as i32,
+            (height / rows as u32) as i32,
+        );
+        // Cell `(x, y)` spans `x..x + 1` and `y..y + 1`, its labels are moved
+        // from the lower left corner to the middle.
+        chart
+            .configure_mesh()
+            .disable_mesh()
+            .x_labels(columns + 1)
+            .y_labels(rows + 1)
+            .x_label_offset(cell.0 / 2)
+            .y_label_offset(-cell.1 / 2)
+            .x_label_formatter(&|&x| label(&points.bucket_sizes, x))
+            .y_label_formatter(&|&y| match y {
+                y if y < rows => label(&points.bit_widths, rows - 1 - y),
+                _ => String::new(),
+            })
+            .x_desc(BUCKET_SIZE)
+            .y_desc(BIT_WIDTH)
+            .label_style((FONT, 14))
+            .draw()?;
+
+        for &(bit_width, bucket_size, value) in &points.values {
+            let (x, y) = (column_of(bucket_size), row(bit_width));
+            let t = if max > min {
+                (value - min) / (max - min)
+            } else {
+                0.5
+            };
+            let text = if t < 0.6 { WHITE } else { BLACK };
+            chart.draw_series([Rectangle::new([(x, y), (x + 1, y + 1)], heat(t).filled())])?;
+            chart.draw_series([EmptyElement::at((x, y))
+                + Text::new(
+                    format_value(value),
+                    (cell.0 / 2, -cell.1 / 2),
+                    (FONT, 14)
+                        .into_font()
+                        .color(&text)
+                        .pos(Pos::new(HPos::Center, VPos::Center)),
+                )])?;
+        }
+        Ok(())
+    })
+}
+
+fn line_chart(points: &Points, column: &str, out: &mut impl Write) -> Result<()> {
+    let columns = points.bucket_sizes.len();
+    let lines = points
+        .bit_widths
+        .iter()
+        .map(|&bit_width| {
+            let line = points
+                .bucket_sizes
+                .iter()
+                .enumerate()
+                .filter_map(|(col, &bucket_size)| {
+                    let value = points.get(bit_width, bucket_size)?;
+                    Some((col as f64, value))
+                })
+                .collect();
+            (format!("{BIT_WIDTH} {bit_width}"), line)
+        })
+        .collect();
+    Lines {
+        heading: column,
+        x_desc: BUCKET_SIZE,
+        // Bucket sizes are spaced evenly, half a step in from the sides.
+        x_range: -0.5..columns as f64 - 0.5,
+        x_labels: columns,
+        x_label: &|x| match x.fract() {
+            0.0 if x >= 0.0 => label(&points.bucket_sizes, x as usize),
+            _ => String::new(),
+        },
+        lines,
+        point_size: 3,
+    }
+    .render(out)
+}
+
+/// A line chart, of a result column over bucket sizes or of a
+/// [`Distribution`].
+struct Lines<'a> {
+    heading: &'a str,
+    x_desc: &'a str,
+    x_range: Range<f64>,
+    /// About how many values along the x axis are labelled, by `x_label`.
+    x_labels: usize,
+    x_label: &'a dyn Fn(f64) -> String,
+    /// `(legend, [(x, y)])`
+    lines: Vec<(String, Vec<(f64, f64)>)>,
+    /// Radius of the circles marking the points, 0 for none.
+    point_size: u32,
+}
+
+impl Lines<'_> {
+    fn render(self, out: &mut impl Write) -> Result<()> {
+        let (min, max) = range(
+            self.lines
+                .iter()
+                .flat_map(|(_, line)| line)
+                .map(|&(_, y)| y),
+        );
+        let (low, high, step) = nice_range(min.min(0.0), max);
+        let size = (MARGIN * 2 + CHART_WIDTH, MARGIN * 2 + CHART_HEIGHT);
+
+        render_svg(out, size, |area| {
+            let mut chart = ChartBuilder::on(area)
+                .caption(self.heading, (FONT, 18))
+                .margin(MARGIN / 4)
+                .x_label_area_size(MARGIN * 3 / 4)
+                .y_label_area_size(MARGIN * 3 / 4)
+                .build_cartesian_2d(self.x_range.clone(), low..high)?;
+            chart
+                .configure_mesh()
+                .disable_x_mesh()
+                .x_labels(self.x_labels)
+                .y_labels(((high - low) / step).round() as usize + 1)
+                .x_label_formatter(&|&x| (self.x_label)(x))
+                .y_label_formatter(&|&y| format_value(y))
+                .x_desc(self.x_desc)
+                .label_style((FONT, 14))
+                .draw()?;
+
+            for (i, (legend, line)) in self.lines.iter().enumerate() {
+                let color = Palette99::pick(i).to_rgba();
+                chart
+                    .draw_series(
+                        LineSeries::new(line.iter().copied(), color.stroke_width(2))
+                            .point_size(self.point_size),
+                    )?
+                    .label(legend)
+                    .legend(move |(x, y)| {
+                        PathElement::new([(x, y), (x + 16, y)], color.stroke_width(2))
+                    });
+            }
+            chart
+                .configure_series_labels()
+                .background_style(WHITE)
+                .border_style(BLACK)
+                .label_font((FONT, 14))
+                .draw()?;
+            Ok(())
+        })
+    }
+}
+
+/// Draws on an SVG of `size` and writes it to `out`.
+fn render_svg(
+    out: &mut impl Write,
+    size: (u32, u32),
+    draw: impl FnOnce(&DrawingArea<SVGBackend, Shift>) -> Result<()>,
+) -> Result<()> {
+    let mut svg = String::new();
+    {
+        let area = SVGBackend::with_string(&mut svg, size).into_drawing_area();
+        area.fill(&WHITE)?;
+        draw(&area)?;
+        area.present()?;
+    }
+    out.write_all(svg.as_bytes())?;
+    Ok(())
+}
+
+/// Color for `t` between 0 and 1.
+fn heat(t: f64) -> RGBColor {
+    ViridisRGB::get_color(t.clamp(0.0, 1.0))
+}
+
+/// The label of row or column `index`, empty past the last.
+fn label(values: &[impl ToString], index: usize) -> String {
+    values.get(index).map_or(String::new(), ToString::to_string)
+}
+
+fn range(values: impl Iterator<Item = f64>) -> (f64, f64) {
+    values.fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), v| {
+        (min.min(v), max.max(v))
+    })
+}
+
+/// Three significant digits, without a fraction for large values.
+fn format_value(value: f64) -> String {
+    if value == 0.0 || value.abs() >= 100.0 {
+        format!("{value:.0}")
+    } else {
+        let decimals = 2 - value.abs().log10().floor() as i32;
+        format!("{value:.*}", decimals.max(0) as usize)
+    }
+}
+
+/// Rounds `min..max` outwards to about five ticks of 1, 2 or 5 times a
+/// power of ten, returning `(low, high, step)`.
+fn nice_range(min: f64, max: f64) -> (f64, f64, f64) {
+    if max <= min {
+        return (min - 1.0, min + 1.0, 1.0);
+    }
+    let rough = (max - min) / 5.0;
+    let magnitude = 10f64.powf(rough.log10().floor());
+    let step = [1.0, 2.0, 5.0, 10.0]
+        .iter()
+        .map(|factor| factor * magnitude)
+        .find(|&step| step >= rough)
+        .expect("10 times the magnitude is at least rough");
+    (
+        (min / step).floor() * step,
+        (max / step).ceil() * step,
+        step,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(bit_width: u32, bucket_size: usize, bytes: f64) -> Record {
+        vec![
+            ("bit_width".to_string(), bit_width.to_string()),
+            ("bucket_size".to_string(), bucket_size.to_string()),
+            ("bytes".to_string(), bytes.to_string()),
+        ]
+    }
+
+    /// The fill attribute plotters writes for `color`.
+    fn svg_color(color: RGBColor) -> String {
+        let (r, g, b) = color.rgb();
+        format!("fill=\"#{r:02X}{g:02X}{b:02X}\"")
+    }
+
+    /// Whether plotters wrote `text` as the whole content of a text element.
+    fn has_text(svg: &str, text: &str) -> bool {
+        svg.contains(&format!(">\n{text}\n</text>"))
+    }
+
+    /// Lines drawn 2 pixels wide, for data and their legend.
+    fn wide_lines(svg: &str) -> usize {
+        svg.matches("stroke-width=\"2\" points=").count()
+    }
+
+    #[test]
+    fn draws_a_cell_per_combination() -> Result<()> {
+        let records: Vec<Record> = [1, 2]
+            .into_iter()
+            .flat_map(|b| [1, 3, 5].map(|s| record(b, s, (b * 100 + s as u32) as f64)))
+            .collect();
+        let mut svg = Vec::new();
+        "heatmap:bytes"
+            .parse::<Chart>()?
+            .render(&records, &mut svg)?;
+        let svg = String::from_utf8(svg)?;
+        assert_eq!(svg.matches("<rect ").count(), 1 + 6);
+        assert!(svg.contains(&svg_color(heat(0.0))));
+        assert!(svg.contains(&svg_color(heat(1.0))));
+        assert!(has_text(&svg, "205"));
+
+        let mut svg = Vec::new();
+        Chart::Line("bytes".into()).render(&records, &mut svg)?;
+        let svg = String::from_utf8(svg)?;
+        assert_eq!(wide_lines(&svg), 2 * 2);
+        assert_eq!(svg.matches("<circle ").count(), 6);
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_unchartable_results() {
+        let chart = Chart::Heatmap("bytes".into());
+        let mut out = Vec::new();
+        assert!(chart.render(&[], &mut out).is_err());
+        let twice = [record(1, 3, 1.0), record(1, 3, 2.0)];
+        assert!(chart.render(&twice, &mut out).is_err());
+        let missing = Chart::Heatmap("nope".into());
+        assert!(missing.render(&[record(1, 3, 1.0)], &mut out).is_err());
+        assert!("pie:bytes".parse::<Chart>().is_err());
+        assert!("line:".parse::<Chart>().is_err());
+    }
+
+    #[test]
+    fn draws_a_line_per_series() -> Result<()> {
+        let records: Vec<Record> = [0, 1]
+            .into_iter()
+            .flat_map(|depth| {
+                (0..=4).map(move |set_bits| {
+                    vec![
+                        ("depth".to_string(), depth.to_string()),
+                        ("set_bits".to_string(), set_bits.to_string()),
+                        ("share".to_string(), (0.2 * (depth + 1) as f64).to_string()),
+                    ]
+                })
+            })
+            .collect();
+        let distribution = Distribution::new("set_bits", "share", "depth");
+        let mut svg = Vec::new();
+        distribution.render(&records, "occupancy", &mut svg)?;
+        let svg = String::from_utf8(svg)?;
+        assert_eq!(wide_lines(&svg), 2 * 2);
+        assert!(has_text(&svg, "depth 1"));
+        assert!(has_text(&svg, "occupancy"));
+
+        let mut out = Vec::new();
+        assert!(distribution.render(&[], "none", &mut out).is_err());
+        let missing = Distribution::new("set_bits", "share", "level");
+        assert!(missing.render(&records, "missing", &mut out).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn formats_three_significant_digits() {
+        assert_eq!(format_value(0.0), "0");
+        assert_eq!(format_value(1.23456), "1.23");
+        assert_eq!(format_value(12.3456), "12.3");
+        assert_eq!(format_value(1234.6), "1235");
+        assert_eq!(nice_range(0.0, 93.0), (0.0, 100.0, 20.0));
+    }
+}
@@ -0,0 +1,494 @@
+//! Where building a HAMT spends its time, as flame graphs: hashing keys,
+//! allocating, hashing blocks for their CIDs and writing them to the store.
+//!
+//! The parts are measured by instrumenting them rather than by sampling
+//! stacks: the global allocator times every allocation while a profile is
+//! recorded and [`MemoryDB`] its puts. Hashing keys and blocks happens deep
+//! in the HAMT, so it isn't measured but estimated, by hashing every key and
+//! written block once more; its frames say so. Whatever a phase doesn't
+//! spend in those is its own time, updating the tree when inserting and
+//! encoding nodes when flushing. Timing every allocation makes it look
+//! somewhat more expensive than it is.
+//!
+//! Only built with the `profile` feature, since the allocator checks whether
+//! to time an allocation on every one, profiled or not.
+//!
+//! Profiles are written as folded stacks, which `flamegraph.pl` and
+//! `inferno` read too, and drawn as SVG flame graphs.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::{Cell, RefCell};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+use anyhow::Result;
+use cid::multihash::{Code, MultihashDigest};
+use cid::Cid;
+use fvm_ipld_hamt::{Hamt, HashAlgorithm, Sha256};
+
+use crate::memorydb::MemoryDB;
+use crate::workload::Key;
+
+/// Frame of the time a frame spent allocating, on top of it.
+const ALLOC: &str = "alloc";
+/// Frames of the estimated time spent hashing, by hashing once more.
+const HASH_KEYS: &str = "hash keys (re-hashed estimate)";
+const HASH_BLOCKS: &str = "hash blocks (re-hashed estimate)";
+
+const WIDTH: f64 = 1200.0;
+const FRAME_HEIGHT: f64 = 16.0;
+const MARGIN: f64 = 10.0;
+const HEADING: f64 = 24.0;
+const FONT: &str = "Helvetica";
+/// Approximate width of a character of the labels.
+const CHAR_WIDTH: f64 = 6.5;
+
+/// Profiles being recorded, on any thread. Allocations are only timed while
+/// there are some.
+static PROFILING: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    /// Nanoseconds this thread spent allocating while profiling.
+    static ALLOC_NANOS: Cell<u64> = const { Cell::new(0) };
+    static RECORDING: RefCell<Option<Recording>> = const { RefCell::new(None) };
+}
+
+/// The system allocator, timing allocations while a profile is recorded.
+pub struct TimedAllocator;
+
+#[global_allocator]
+static ALLOCATOR: TimedAllocator = TimedAllocator;
+
+unsafe impl GlobalAlloc for TimedAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        timed(|| System.alloc(layout))
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        timed(|| System.alloc_zeroed(layout))
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        timed(|| System.dealloc(ptr, layout))
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        timed(|| System.realloc(ptr, layout, new_size))
+    }
+}
+
+fn timed<T>(f: impl FnOnce() -> T) -> T {
+    if PROFILING.load(Ordering::Relaxed) == 0 {
+        return f();
+    }
+    let start = Instant::now();
+    let result = f();
+    let nanos = start.elapsed().as_nanos() as u64;
+    // Fails once the thread is being torn down, its allocations don't matter.
+    let _ = ALLOC_NANOS.try_with(|total| total.set(total.get() + nanos));
+    result
+}
+
+fn alloc_nanos() -> u64 {
+    ALLOC_NANOS.with(Cell::get)
+}
+
+/// The frames of a profile as a tree, with the time spent in each frame
+/// itself, not counting its children.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Profile {
+    frames: Vec<Frame>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Frame {
+    name: &'static str,
+    children: Vec<usize>,
+    /// Nanoseconds spent in the frame itself.
+    nanos: u64,
+}
+
+/// A span that's still running.
+struct Open {
+    frame: usize,
+    start: Instant,
+    alloc_start: u64,
+    /// Time spent in the spans below, including their allocations.
+    children: u64,
+    /// Time spent allocating in the spans below.
+    child_alloc: u64,
+    /// Time that's not part of the span, like repeated work.
+    excluded: u64,
+}
+
+struct Recording {
+    profile: Profile,
+    stack: Vec<Open>,
+}
+
+impl Profile {
+    /// Runs `f` as the phase `name` and returns its result with where it
+    /// spent its time.
+    pub fn record<T>(name: &'static str, f: impl FnOnce() -> T) -> (T, Profile) {
+        let recording = Recording {
+            profile: Profile {
+                frames: vec![Frame::new(name)],
+            },
+            stack: Vec::with_capacity(16),
+        };
+        RECORDING.with(|cell| *cell.borrow_mut() = Some(recording));
+        PROFILING.fetch_add(1, Ordering::Relaxed);
+        enter(Some(0), name);
+        let result = f();
+        exit();
+        PROFILING.fetch_sub(1, Ordering::Relaxed);
+        let recording = RECORDING.with(|cell| cell.borrow_mut().take());
+        (result, recording.expect("recording").profile)
+    }
+
+    /// Nanoseconds spent in the frame at `index` and its children.
+    fn total(&self, index: usize) -> u64 {
+        let frame = &self.frames[index];
+        frame.nanos
+            + frame
+                .children
+                .iter()
+                .map(|&child| self.total(child))
+                .sum::<u64>()
+    }
+
+    /// Lines of `<frame>;<frame>;... <microseconds>`, one per frame with
+    /// time of its own, like `flamegraph.pl` reads them.
+    pub fn folded(&self) -> String {
+        let mut out = String::new();
+        self.fold(0, "", &mut out);
+        out
+    }
+
+    fn fold(&self, index: usize, prefix: &str, out: &mut String) {
+        let frame = &self.frames[index];
+        let stack = if prefix.is_empty() {
+            frame.name.to_string()
+        } else {
+            format!("{prefix};{}", frame.name)
+        };
+        let micros = frame.nanos / 1000;
+        if micros > 0 {
+            out.push_str(&format!("{stack} {micros}\n"));
+        }
+        for &child in &frame.children {
+            self.fold(child, &stack, out);
+        }
+    }
+
+    /// Writes `<path>.folded` and `<path>.svg`.
+    pub fn write_to_files(&self, path: &Path, title: &str) -> Result<()> {
+        std::fs::write(path.with_extension("folded"), self.folded())?;
+        let mut out = BufWriter::new(File::create(path.with_extension("svg"))?);
+        self.render(title, &mut out)?;
+        out.flush()?;
+        Ok(())
+    }
+
+    /// Draws the profile as a flame graph, the phase at the bottom and the
+    /// frames each one spent its time in on top of it.
+    pub fn render(&self, title: &str, out: &mut impl Write) -> Result<()> {
+        let depth = self.depth(0);
+        let height = 2.0 * MARGIN + HEADING + depth as f64 * FRAME_HEIGHT;
+        let total = self.total(0).max(1);
+        writeln!(
+            out,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{WIDTH}" height="{height}" font-family="{FONT}" font-size="11">"#
+        )?;
+        writeln!(
+            out,
+            r#"<text x="{}" y="{}" text-anchor="middle" font-size="14">{} ({} ms)</text>"#,
+            WIDTH / 2.0,
+            MARGIN + 14.0,
+            escape(title),
+            total / 1_000_000
+        )?;
+        let scale = (WIDTH - 2.0 * MARGIN) / total as f64;
+        self.render_frame(0, MARGIN, 0, height - MARGIN, scale, total, out)?;
+        writeln!(out, "</svg>")?;
+        Ok(())
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn render_frame(
+        &self,
+        index: usize,
+        x: f64,
+        level: usize,
+        bottom: f64,
+        scale: f64,
+        total: u64,
+        out: &mut impl Write,
+    ) -> Result<()> {
+        let frame = &self.frames[index];
+        let nanos = self.total(index);
+        let width = nanos as f64 * scale;
+        let y = bottom - (level + 1) as f64 * FRAME_HEIGHT;
+        let label = format!(
+            "{} ({:.1} ms, {:.1}%)",
+            frame.name,
+            nanos as f64 / 1e6,
+            nanos as f64 / total as f64 * 100.0
+        );
+        writeln!(
+            out,
+            r#"<g><title>{}</title><rect x="{x:.1}" y="{y:.1}" width="{width:.1}" height="{}" fill="{}" rx="2"/>"#,
+            escape(&label),
+            FRAME_HEIGHT - 1.0,
+            color(frame.name)
+        )?;
+        let chars = ((width - 6.0) / CHAR_WIDTH).floor() as usize;
+        if chars >= 3 {
+            let text: String = if frame.name.len() <= chars {
+                frame.name.to_string()
+            } else {
+                format!("{}..", &frame.name[..chars - 2])
+            };
+            writeln!(
+                out,
+                r#"<text x="{:.1}" y="{:.1}">{}</text>"#,
+                x + 3.0,
+                y + FRAME_HEIGHT - 4.5,
+                escape(&text)
+            )?;
+        }
+        writeln!(out, "</g>")?;
+
+        // Children left to right, the frame's own time to the right of them.
+        let mut child_x = x;
+        for &child in &frame.children {
+            self.render_frame(child, child_x, level + 1, bottom, scale, total, out)?;
+            child_x += self.total(child) as f64 * scale;
+        }
+        Ok(())
+    }
+
+    fn depth(&self, index: usize) -> usize {
+        1 + self.frames[index]
+            .children
+            .iter()
+            .map(|&child| self.depth(child))
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+impl Frame {
+    fn new(name: &'static str) -> Self {
+        Frame {
+            name,
+            children: Vec::new(),
+            nanos: 0,
+        }
+    }
+}
+
+impl Recording {
+    /// The child `name` of the frame `parent`, added if it's new.
+    fn child(&mut self, parent: usize, name: &'static str) -> usize {
+        let frames = &mut self.profile.frames;
+        let existing = frames[parent]
+            .children
+            .iter()
+            .copied()
+            .find(|&child| frames[child].name == name);
+        existing.unwrap_or_else(|| {
+            frames.push(Frame::new(name));
+            let child = frames.len() - 1;
+            frames[parent].children.push(child);
+            child
+        })
+    }
+}
+
+/// Opens the span `name`, at `frame` or below the innermost open one. Does
+/// nothing unless a profile is recorded.
+fn enter(frame: Option<usize>, name: &'static str) -> bool {
+    RECORDING.with(|cell| {
+        let mut cell = cell.borrow_mut();
+        let Some(recording) = cell.as_mut() else {
+            return false;
+        };
+        let frame = frame.unwrap_or_else(|| {
+            let parent = recording.stack.last().expect("inside a phase").frame;
+            recording.child(parent, name)
+        });
+        recording.stack.push(Open {
+            frame,
+            start: Instant::now(),
+            alloc_start: alloc_nanos(),
+            children: 0,
+            child_alloc: 0,
+            excluded: 0,
+        });
+        true
+    })
+}
+
+/// Closes the innermost span, adding its own time and allocations to its
+/// frame and the whole of it to its parent.
+fn exit() {
+    let end = Instant::now();
+    let alloc_end = alloc_nanos();
+    RECORDING.with(|cell| {
+        let mut cell = cell.borrow_mut();
+        let recording = cell.as_mut().expect("recording");
+        let open = recording.stack.pop().expect("an open span");
+        let elapsed = end.duration_since(open.start).as_nanos() as u64;
+        let alloc = alloc_end - open.alloc_start;
+        let own_alloc = alloc.saturating_sub(open.child_alloc);
+        let own = elapsed
+            .saturating_sub(open.children)
+            .saturating_sub(own_alloc)
+            .saturating_sub(open.excluded);
+        recording.profile.frames[open.frame].nanos += own;
+        if own_alloc > 0 {
+            let alloc_frame = recording.child(open.frame, ALLOC);
+            recording.profile.frames[alloc_frame].nanos += own_alloc;
+        }
+        if let Some(parent) = recording.stack.last_mut() {
+            parent.children += elapsed.saturating_sub(open.excluded);
+            parent.child_alloc += alloc;
+        }
+    });
+}
+
+fn recording() -> bool {
+    RECORDING.with(|cell| cell.borrow().is_some())
+}
+
+/// Runs `f` in the span `name` below the current one, if a profile is
+/// recorded.
+pub fn span<T>(name: &'static str, f: impl FnOnce() -> T) -> T {
+    if !enter(None, name) {
+        return f();
+    }
+    let result = f();
+    exit();
+    result
+}
+
+/// Runs `f`, which repeats work already done in the current span to time it,
+/// and records its time as the frame `name`. The time is taken off the
+/// span's own twice, for `f` and for the work it repeated.
+fn repeated<T>(name: &'static str, f: impl FnOnce() -> T) -> T {
+    let start = Instant::now();
+    let result = f();
+    let nanos = start.elapsed().as_nanos() as u64;
+    RECORDING.with(|cell| {
+        if let Some(recording) = cell.borrow_mut().as_mut() {
+            let parent = recording.stack.last().expect("inside a phase").frame;
+            let frame = recording.child(parent, name);
+            recording.profile.frames[frame].nanos += nanos;
+            let open = recording.stack.last_mut().expect("inside a phase");
+            open.children += nanos;
+            open.excluded += nanos;
+        }
+    });
+    result
+}
+
+/// Runs `put`, the store writing the block `cid`, in the span `store put`,
+/// and estimates hashing the block for its CID before. Does nothing else
+/// unless a profile is recorded.
+pub fn put<T>(cid: &Cid, block: &[u8], put: impl FnOnce() -> T) -> T {
+    if !recording() {
+        return put();
+    }
+    if let Ok(code) = Code::try_from(cid.hash().code()) {
+        repeated(HASH_BLOCKS, || std::hint::black_box(code.digest(block)));
+    }
+    span("store put", put)
+}
+
+/// Builds a HAMT of `keys`, hashed with sha256 like the other experiments,
+/// and returns where inserting them and flushing the HAMT spent their time.
+pub fn build<const BUCKET_SIZE: usize>(
+    keys: Vec<Key>,
+    bit_width: u32,
+) -> Result<(Profile, Profile)> {
+    let store = MemoryDB::default();
+    let mut map: Hamt<_, _, Key, Sha256, BUCKET_SIZE> = Hamt::new_with_bit_width(&store, bit_width);
+    let (inserted, insert) = Profile::record("insert", || {
+        keys.into_iter().try_for_each(|key| {
+            repeated(HASH_KEYS, || std::hint::black_box(Sha256::hash(&key)));
+            map.set(key, "F".to_string()).map(drop)
+        })
+    });
+    inserted?;
+    let (flushed, flush) = Profile::record("flush", || map.flush());
+    flushed?;
+    Ok((insert, flush))
+}
+
+/// Color of a frame, from a warm palette by its name, so the same frame has
+/// the same color in every graph.
+fn color(name: &str) -> String {
+    if name == ALLOC {
+        return "rgb(120,160,220)".to_string();
+    }
+    let hash = name
+        .bytes()
+        .fold(0u32, |hash, b| hash.wrapping_mul(31).wrapping_add(b as u32));
+    let red = 205 + hash % 50;
+    let green = 80 + (hash / 50) % 130;
+    let blue = 40 + (hash / 6500) % 40;
+    format!("rgb({red},{green},{blue})")
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn busy(micros: u64) {
+        let start = Instant::now();
+        while start.elapsed().as_micros() < micros as u128 {
+            std::hint::spin_loop();
+        }
+    }
+
+    #[test]
+    fn splits_time_between_spans() {
+        let ((), profile) = Profile::record("insert", || {
+            busy(2000);
+            span("hash keys", || busy(3000));
+            span("hash keys", || {
+                busy(1000);
+                std::hint::black_box(vec![0u8; 1 << 20]);
+            });
+        });
+        let names: Vec<_> = profile.frames.iter().map(|frame| frame.name).collect();
+        assert_eq!(names[..2], ["insert", "hash keys"]);
+        let hash_keys = profile.total(1);
+        assert!(hash_keys >= 4_000_000, "{hash_keys}");
+        assert!(profile.frames[0].nanos >= 2_000_000);
+        assert!(profile.total(0) >= profile.frames[0].nanos + hash_keys);
+        // Not recorded outside of a profile.
+        assert_eq!(span("hash keys", || 1), 1);
+
+        let folded = profile.folded();
+        assert!(folded.starts_with("insert "), "{folded}");
+        assert!(folded.contains("\ninsert;hash keys "), "{folded}");
+
+        let mut svg = Vec::new();
+        profile.render("insert", &mut svg).unwrap();
+        let svg = String::from_utf8(svg).unwrap();
+        assert!(svg.starts_with("<svg"));
+        assert!(svg.contains(">hash keys</text>"));
+    }
+}
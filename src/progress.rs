@@ -0,0 +1,182 @@
+//! Progress of a sweep on stderr, so that long runs aren't silent.
+//!
+//! Every finished point is logged with how long it took and an estimate of
+//! the time left. If stderr is a terminal, a bar of the running point is
+//! redrawn below the log as well.
+
+use std::io::{self, IsTerminal, Write};
+use std::time::{Duration, Instant};
+
+const BAR_WIDTH: usize = 30;
+
+pub struct Progress {
+    name: &'static str,
+    total: usize,
+    done: usize,
+    /// Points finished by an earlier run, which don't count for the ETA.
+    skipped: usize,
+    enabled: bool,
+    terminal: bool,
+    started: Instant,
+    /// Label and start of the running point.
+    point: Option<(String, Instant)>,
+}
+
+impl Progress {
+    /// Progress of `total` points of the experiment `name`, nothing is shown
+    /// unless `enabled`.
+    pub fn new(name: &'static str, total: usize, enabled: bool) -> Self {
+        Progress {
+            name,
+            total,
+            done: 0,
+            skipped: 0,
+            enabled,
+            terminal: io::stderr().is_terminal(),
+            started: Instant::now(),
+            point: None,
+        }
+    }
+
+    pub fn start(&mut self, label: String) {
+        self.point = Some((label, Instant::now()));
+        if self.enabled && self.terminal {
+            let line = self.bar();
+            self.draw(&line, false);
+        }
+    }
+
+    pub fn finish_point(&mut self) {
+        let Some((label, started)) = self.point.take() else {
+            return;
+        };
+        self.done += 1;
+        if self.enabled {
+            let mut line = format!(
+                "{} {}/{} {label}: {}",
+                self.name,
+                self.done,
+                self.total,
+                format_duration(started.elapsed())
+            );
+            if let Some(eta) = self.eta() {
+                line.push_str(&format!(", {} left", format_duration(eta)));
+            }
+            self.draw(&line, true);
+        }
+    }
+
+    /// Like [`Progress::finish_point`] for a point whose results are there
+    /// already.
+    pub fn skip_point(&mut self) {
+        let Some((label, _)) = self.point.take() else {
+            return;
+        };
+        self.done += 1;
+        self.skipped += 1;
+        if self.enabled {
+            let line = format!(
+                "{} {}/{} {label}: done before",
+                self.name, self.done, self.total
+            );
+            self.draw(&line, true);
+        }
+    }
+
+    /// Logs the total time taken.
+    pub fn finish(&mut self) {
+        if self.enabled && self.total > 1 {
+            let line = format!(
+                "{} finished {} points in {}",
+                self.name,
+                self.done,
+                format_duration(self.started.elapsed())
+            );
+            self.draw(&line, true);
+        }
+    }
+
+    /// Remaining time if the points left take as long as the finished ones
+    /// on average.
+    fn eta(&self) -> Option<Duration> {
+        let run = self.done - self.skipped;
+        if run == 0 || self.done >= self.total {
+            return None;
+        }
+        let per_point = self.started.elapsed() / run as u32;
+        Some(per_point * (self.total - self.done) as u32)
+    }
+
+    fn bar(&self) -> String {
+        let filled = BAR_WIDTH * self.done / self.total.max(1);
+        let label = self.point.as_ref().map_or("", |(label, _)| label.as_str());
+        let eta = match self.eta() {
+            Some(eta) => format!(" ETA {}", format_duration(eta)),
+            None => String::new(),
+        };
+        format!(
+            "{} [{}{}] {}/{} {label}{eta}",
+            self.name,
+            "=".repeat(filled),
+            " ".repeat(BAR_WIDTH - filled),
+            self.done,
+            self.total
+        )
+    }
+
+    /// Writes `line`, replacing the bar on a terminal. Lines that are `kept`
+    /// stay in the log, others are overwritten by the next one.
+    fn draw(&self, line: &str, kept: bool) {
+        let mut err = io::stderr().lock();
+        // Progress is best effort, a closed stderr mustn't fail the run.
+        let _ = if !self.terminal {
+            writeln!(err, "{line}")
+        } else if kept {
+            writeln!(err, "\r\x1b[2K{line}")
+        } else {
+            write!(err, "\r\x1b[2K{line}")
+        };
+        let _ = err.flush();
+    }
+}
+
+/// `850ms`, `12.3s`, `4m05s` or `2h03m`.
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    if secs >= 3600 {
+        format!("{}h{:02}m", secs / 3600, secs % 3600 / 60)
+    } else if secs >= 60 {
+        format!("{}m{:02}s", secs / 60, secs % 60)
+    } else if secs >= 1 {
+        format!("{:.1}s", duration.as_secs_f64())
+    } else {
+        format!("{}ms", duration.as_millis())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_durations() {
+        assert_eq!(format_duration(Duration::from_millis(850)), "850ms");
+        assert_eq!(format_duration(Duration::from_millis(12_340)), "12.3s");
+        assert_eq!(format_duration(Duration::from_secs(245)), "4m05s");
+        assert_eq!(format_duration(Duration::from_secs(7380)), "2h03m");
+    }
+
+    #[test]
+    fn estimates_time_left_from_finished_points() {
+        let mut progress = Progress::new("sizes", 4, false);
+        assert_eq!(progress.eta(), None);
+        progress.started = Instant::now() - Duration::from_secs(10);
+        for point in 0..2 {
+            progress.start(format!("point {point}"));
+            progress.finish_point();
+        }
+        assert_eq!(progress.eta().map(|eta| eta.as_secs()), Some(10));
+        progress.done = 4;
+        assert_eq!(progress.eta(), None);
+    }
+}
@@ -0,0 +1,547 @@
+//! A prolly tree (probabilistic B-tree), an ordered map whose shape only
+//! depends on its entries, not on the order they were set in.
+//!
+//! Like in the [`BTree`](crate::btree::BTree), entries are sorted by key and
+//! live in the leaves, and internal nodes link to children by their smallest
+//! key. But instead of splitting nodes once they're full, the entries of a
+//! level are cut into chunks after every key whose hash, together with the
+//! level, is `0` modulo `fanout`, so chunks hold `fanout` entries on average.
+//! Every chunk is a block of its own, encoded like the B-tree's nodes:
+//!
+//! ```text
+//! [level, [[key, value | cid], ...]]
+//! ```
+//!
+//! Nodes are loaded from the store as they're needed. Changes go into the
+//! loaded leaves and are only cut into chunks again when the tree is
+//! flushed: the entries of changed nodes are chunked again, together with
+//! those of their unchanged neighbours up to the next chunk that stays the
+//! same, and all other nodes keep their blocks.
+
+use std::cell::OnceCell;
+use std::mem;
+
+use anyhow::{anyhow, Result};
+use cid::multihash::{Code, MultihashDigest};
+use cid::Cid;
+use fvm_ipld_blockstore::{Block, Blockstore};
+use fvm_ipld_encoding::de::{DeserializeOwned, IgnoredAny};
+use fvm_ipld_encoding::{from_slice, to_vec, DAG_CBOR};
+use serde::Serialize;
+
+use crate::map::{IpldMap, MapStats};
+
+pub struct ProllyTree<'a, BS, K, V> {
+    store: &'a BS,
+    fanout: u32,
+    root: Link<K, V>,
+    /// Root as of the last flush, which the stats are taken of.
+    flushed: Option<Cid>,
+}
+
+enum Node<K, V> {
+    Leaf(Vec<(K, V)>),
+    /// Children at `level - 1` by their smallest key.
+    Internal {
+        level: u32,
+        children: Vec<(K, Link<K, V>)>,
+    },
+}
+
+struct Link<K, V> {
+    /// CID of the node, unless it changed since it was last flushed.
+    cid: Option<Cid>,
+    node: OnceCell<Node<K, V>>,
+}
+
+#[derive(Serialize)]
+#[serde(untagged)]
+enum Slot<'n, V> {
+    Value(&'n V),
+    Link(Cid),
+}
+
+impl<'a, BS, K, V> ProllyTree<'a, BS, K, V>
+where
+    BS: Blockstore,
+    K: Ord + Clone + Serialize + DeserializeOwned,
+    V: Serialize + DeserializeOwned,
+{
+    pub fn new_with_fanout(store: &'a BS, fanout: u32) -> Self {
+        assert!(fanout >= 2, "unsupported fanout {fanout}");
+        ProllyTree {
+            store,
+            fanout,
+            root: Link::changed(Node::Leaf(Vec::new())),
+            flushed: None,
+        }
+    }
+
+    /// Loads the tree with root `cid`, which has to be chunked with the same
+    /// `fanout`.
+    pub fn load_with_fanout(cid: &Cid, store: &'a BS, fanout: u32) -> Result<Self> {
+        let mut tree = Self::new_with_fanout(store, fanout);
+        tree.root = Link::stored(*cid);
+        tree.root.node(store)?;
+        tree.flushed = Some(*cid);
+        Ok(tree)
+    }
+
+    /// Sets the value of `key`, returning the previous one.
+    pub fn set(&mut self, key: K, value: V) -> Result<Option<V>> {
+        let mut node = self.root.node_mut(self.store)?;
+        loop {
+            match node {
+                Node::Leaf(entries) => {
+                    return Ok(match entries.binary_search_by(|(k, _)| k.cmp(&key)) {
+                        Ok(i) => Some(mem::replace(&mut entries[i].1, value)),
+                        Err(i) => {
+                            entries.insert(i, (key, value));
+                            None
+                        }
+                    });
+                }
+                Node::Internal { children, .. } => {
+                    let i = child_index(children, &key);
+                    if key < children[i].0 {
+                        children[i].0 = key.clone();
+                    }
+                    node = children[i].1.node_mut(self.store)?;
+                }
+            }
+        }
+    }
+
+    pub fn get(&self, key: &K) -> Result<Option<&V>> {
+        let mut node = self.root.node(self.store)?;
+        loop {
+            match node {
+                Node::Leaf(entries) => {
+                    let found = entries.binary_search_by(|(k, _)| k.cmp(key));
+                    return Ok(found.ok().map(|i| &entries[i].1));
+                }
+                Node::Internal { children, .. } => {
+                    node = children[child_index(children, key)].1.node(self.store)?;
+                }
+            }
+        }
+    }
+
+    /// Deletes `key`, returning its value. Nodes that end up empty are kept
+    /// until the next flush.
+    pub fn delete(&mut self, key: &K) -> Result<Option<V>> {
+        if self.get(key)?.is_none() {
+            return Ok(None);
+        }
+        let mut node = self.root.node_mut(self.store)?;
+        loop {
+            match node {
+                Node::Leaf(entries) => {
+                    let i = entries.binary_search_by(|(k, _)| k.cmp(key));
+                    return Ok(Some(entries.remove(i.expect("found above")).1));
+                }
+                Node::Internal { children, .. } => {
+                    let i = child_index(children, key);
+                    node = children[i].1.node_mut(self.store)?;
+                }
+            }
+        }
+    }
+
+    /// Chunks the changed nodes again, puts the new chunks and returns the
+    /// CID of the root, the lowest level that fits into a single chunk.
+    /// Afterwards, nodes are loaded from the store again.
+    pub fn flush(&mut self) -> Result<Cid> {
+        if let Some(cid) = self.root.cid {
+            return Ok(cid);
+        }
+        let mut chunker = Chunker {
+            store: self.store,
+            fanout: self.fanout,
+            levels: Vec::new(),
+        };
+        chunker.node(self.root.node(self.store)?)?;
+        let cid = chunker.finish()?;
+        self.root = Link::stored(cid);
+        self.flushed = Some(cid);
+        Ok(cid)
+    }
+
+    /// Shape of the tree as of the last flush, read from the store.
+    pub fn stats(&self) -> Result<MapStats> {
+        let Some(root) = self.flushed else {
+            return Ok(MapStats::default());
+        };
+        let mut stats = MapStats::default();
+        let mut cids = vec![root];
+        while let Some(cid) = cids.pop() {
+            stats.nodes += 1;
+            match load::<_, K, V>(self.store, &cid)? {
+                Node::Leaf(entries) => stats.values += entries.len() as u64,
+                Node::Internal { level, children } => {
+                    stats.height = stats.height.max(level);
+                    cids.extend(children.iter().filter_map(|(_, child)| child.cid));
+                }
+            }
+        }
+        Ok(stats)
+    }
+}
+
+impl<K, V> Link<K, V> {
+    fn stored(cid: Cid) -> Self {
+        Link {
+            cid: Some(cid),
+            node: OnceCell::new(),
+        }
+    }
+
+    fn changed(node: Node<K, V>) -> Self {
+        Link {
+            cid: None,
+            node: OnceCell::from(node),
+        }
+    }
+
+    /// The node, loaded from the store the first time.
+    fn node(&self, store: &impl Blockstore) -> Result<&Node<K, V>>
+    where
+        K: DeserializeOwned,
+        V: DeserializeOwned,
+    {
+        if let Some(node) = self.node.get() {
+            return Ok(node);
+        }
+        let cid = self.cid.expect("nodes that aren't loaded are stored");
+        let node = load(store, &cid)?;
+        Ok(self.node.get_or_init(|| node))
+    }
+
+    /// The node to change, which is chunked again by the next flush.
+    fn node_mut(&mut self, store: &impl Blockstore) -> Result<&mut Node<K, V>>
+    where
+        K: DeserializeOwned,
+        V: DeserializeOwned,
+    {
+        self.node(store)?;
+        self.cid = None;
+        Ok(self.node.get_mut().expect("loaded above"))
+    }
+}
+
+/// Cuts the entries of every level into chunks and puts them, each chunk
+/// once the entry after it arrives, so the root isn't put before it's known
+/// to be one.
+struct Chunker<'t, BS, K, V> {
+    store: &'t BS,
+    fanout: u32,
+    levels: Vec<Level<'t, K, V>>,
+}
+
+struct Level<'t, K, V> {
+    chunk: Vec<(&'t K, Slot<'t, V>)>,
+    /// Whether the chunk ends after its last entry.
+    complete: bool,
+    /// Whether any entries were pushed to the level.
+    used: bool,
+}
+
+impl<'t, BS, K, V> Chunker<'t, BS, K, V>
+where
+    BS: Blockstore,
+    K: Serialize + DeserializeOwned,
+    V: Serialize + DeserializeOwned,
+{
+    /// Pushes the entries of `node`, or of its children where they can't be
+    /// pushed as they are.
+    fn node(&mut self, node: &'t Node<K, V>) -> Result<()> {
+        match node {
+            Node::Leaf(entries) => {
+                for (key, value) in entries {
+                    self.push(0, key, Slot::Value(value))?;
+                }
+            }
+            Node::Internal { level, children } => {
+                for (key, child) in children {
+                    self.link(*level - 1, key, child)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Pushes an unchanged node at `level` as a whole if the chunks before it
+    /// are complete, as it's then chunked just like before. Otherwise its
+    /// entries join the chunks before it.
+    fn link(&mut self, level: u32, key: &'t K, link: &'t Link<K, V>) -> Result<()> {
+        if let Some(cid) = link.cid {
+            if self.complete(level)? {
+                return self.push(level + 1, key, Slot::Link(cid));
+            }
+        }
+        self.node(link.node(self.store)?)
+    }
+
+    fn push(&mut self, level: u32, key: &'t K, slot: Slot<'t, V>) -> Result<()> {
+        while self.levels.len() <= level as usize {
+            self.levels.push(Level {
+                chunk: Vec::new(),
+                complete: false,
+                used: false,
+            });
+        }
+        if self.levels[level as usize].complete {
+            self.put(level)?;
+        }
+        let complete = is_boundary(level, key, self.fanout)?;
+        let chunks = &mut self.levels[level as usize];
+        chunks.chunk.push((key, slot));
+        chunks.complete = complete;
+        chunks.used = true;
+        Ok(())
+    }
+
+    /// Puts the chunks up to `level` that are complete and returns whether
+    /// nothing is left to chunk on these levels.
+    fn complete(&mut self, level: u32) -> Result<bool> {
+        for l in 0..=level {
+            if self.levels.get(l as usize).is_some_and(|l| l.complete) {
+                self.put(l)?;
+            }
+        }
+        Ok(self
+            .levels
+            .iter()
+            .take(level as usize + 1)
+            .all(|l| l.chunk.is_empty()))
+    }
+
+    /// Puts the chunk at `level` and pushes a link to it to the level above.
+    fn put(&mut self, level: u32) -> Result<()> {
+        let chunks = &mut self.levels[level as usize];
+        chunks.complete = false;
+        let chunk = mem::take(&mut chunks.chunk);
+        if let Some(&(first, _)) = chunk.first() {
+            let cid = put(self.store, level, &chunk)?;
+            self.push(level + 1, first, Slot::Link(cid))?;
+        }
+        Ok(())
+    }
+
+    /// Puts the last chunks of every level up to the first one that nothing
+    /// was pushed above, and returns the root.
+    fn finish(mut self) -> Result<Cid> {
+        let mut level = 0;
+        while self.levels.iter().skip(level + 1).any(|l| l.used) {
+            self.put(level as u32)?;
+            level += 1;
+        }
+        let chunk = self.levels.get(level).map_or(&[][..], |l| &l.chunk);
+        let [(_, Slot::Link(cid))] = chunk else {
+            return put(self.store, level as u32, chunk);
+        };
+        // A single link makes the node it links to the root, which can
+        // itself be an unchanged node with a single child.
+        let mut cid = *cid;
+        while let Node::Internal { children, .. } = load::<_, K, V>(self.store, &cid)? {
+            let [(_, child)] = &children[..] else {
+                break;
+            };
+            cid = child.cid.expect("loaded nodes are stored");
+        }
+        Ok(cid)
+    }
+}
+
+impl<BS, K, V> IpldMap<K, V> for ProllyTree<'_, BS, K, V>
+where
+    BS: Blockstore,
+    K: Ord + Clone + Serialize + DeserializeOwned,
+    V: Serialize + DeserializeOwned,
+{
+    fn set(&mut self, key: K, value: V) -> Result<Option<V>> {
+        ProllyTree::set(self, key, value)
+    }
+
+    fn get(&self, key: &K) -> Result<Option<&V>> {
+        ProllyTree::get(self, key)
+    }
+
+    fn delete(&mut self, key: &K) -> Result<Option<V>> {
+        ProllyTree::delete(self, key)
+    }
+
+    fn flush(&mut self) -> Result<Cid> {
+        ProllyTree::flush(self)
+    }
+
+    fn stats(&self) -> Result<MapStats> {
+        ProllyTree::stats(self)
+    }
+}
+
+fn child_index<K: Ord, C>(children: &[(K, C)], key: &K) -> usize {
+    children
+        .partition_point(|(k, _)| k <= key)
+        .saturating_sub(1)
+}
+
+fn load<BS, K, V>(store: &BS, cid: &Cid) -> Result<Node<K, V>>
+where
+    BS: Blockstore,
+    K: DeserializeOwned,
+    V: DeserializeOwned,
+{
+    let block = store
+        .get(cid)?
+        .ok_or_else(|| anyhow!("block {cid} not found"))?;
+    let (level, _): (u32, IgnoredAny) = from_slice(&block)?;
+    Ok(if level == 0 {
+        let (_, entries): (u32, _) = from_slice(&block)?;
+        Node::Leaf(entries)
+    } else {
+        let (_, links): (u32, Vec<(K, Cid)>) = from_slice(&block)?;
+        let children = links
+            .into_iter()
+            .map(|(key, cid)| (key, Link::stored(cid)))
+            .collect();
+        Node::Internal { level, children }
+    })
+}
+
+fn put<K: Serialize, V: Serialize>(
+    store: &impl Blockstore,
+    level: u32,
+    entries: &[(&K, Slot<'_, V>)],
+) -> Result<Cid> {
+    let bytes = to_vec(&(level, entries))?;
+    store.put(Code::Blake2b256, &Block::new(DAG_CBOR, &bytes))
+}
+
+/// Whether a chunk at `level` ends after `key`.
+fn is_boundary<K: Serialize>(level: u32, key: &K, fanout: u32) -> Result<bool> {
+    let digest = Code::Sha2_256.digest(&to_vec(&(level, key))?);
+    let bytes: [u8; 4] = digest.digest()[..4].try_into()?;
+    Ok(u32::from_be_bytes(bytes).is_multiple_of(fanout))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memorydb::MemoryDB;
+    use crate::rng::Rng;
+    use std::collections::BTreeSet;
+
+    #[test]
+    fn sets_and_gets_values() -> Result<()> {
+        let store = MemoryDB::default();
+        let mut tree = ProllyTree::new_with_fanout(&store, 4);
+        for key in 0..1000u64 {
+            assert_eq!(tree.set(key, key * 2)?, None);
+        }
+        assert_eq!(tree.set(7, 0)?, Some(14));
+        assert_eq!(tree.get(&7)?, Some(&0));
+        assert_eq!(tree.get(&1000)?, None);
+
+        let root = tree.flush()?;
+        assert_eq!(tree.stats()?.values, 1000);
+        // About 250 leaves, 63 nodes above them and so on.
+        let blocks = store.blocks();
+        assert!((200..500).contains(&blocks), "{blocks} blocks");
+        tree.set(500, 0)?;
+        tree.flush()?;
+        // Only the path to key 500 is new.
+        assert!(store.blocks() - blocks < 10);
+
+        let loaded = ProllyTree::load_with_fanout(&root, &store, 4)?;
+        assert_eq!(loaded.get(&500)?, Some(&1000));
+        assert_eq!(loaded.get(&7)?, Some(&0));
+        Ok(())
+    }
+
+    #[test]
+    fn is_independent_of_insertion_order() -> Result<()> {
+        let store = MemoryDB::default();
+        let mut keys: Vec<u64> = (0..500).collect();
+        let mut sorted = ProllyTree::new_with_fanout(&store, 4);
+        for &key in &keys {
+            sorted.set(key, ())?;
+        }
+        let root = sorted.flush()?;
+
+        Rng::new(1).shuffle(&mut keys);
+        let mut shuffled = ProllyTree::new_with_fanout(&store, 4);
+        for &key in &keys[..250] {
+            shuffled.set(key, ())?;
+        }
+        shuffled.set(1000, ())?;
+        shuffled.flush()?;
+        shuffled.delete(&1000)?;
+        for &key in &keys[250..] {
+            shuffled.set(key, ())?;
+        }
+        assert_eq!(shuffled.flush()?, root);
+        Ok(())
+    }
+
+    #[test]
+    fn rechunks_after_deletes() -> Result<()> {
+        let store = MemoryDB::default();
+        let mut tree = ProllyTree::new_with_fanout(&store, 4);
+        for key in 0..500u64 {
+            tree.set(key, ())?;
+        }
+        tree.flush()?;
+        for key in (0..500).filter(|key| key % 7 != 0) {
+            tree.delete(&key)?;
+        }
+        assert_eq!(tree.delete(&1)?, None);
+        let root = tree.flush()?;
+
+        let mut fresh = ProllyTree::new_with_fanout(&store, 4);
+        for key in (0..500u64).step_by(7) {
+            fresh.set(key, ())?;
+        }
+        assert_eq!(fresh.flush()?, root);
+        assert_eq!(tree.stats()?, fresh.stats()?);
+        Ok(())
+    }
+
+    /// Sets and deletes random keys, with small chunks to have many levels
+    /// that change between flushes.
+    #[test]
+    fn flushes_changes_like_a_new_tree() -> Result<()> {
+        let store = MemoryDB::default();
+        let mut rng = Rng::new(7);
+        let mut tree = ProllyTree::new_with_fanout(&store, 2);
+        let mut keys = BTreeSet::new();
+        for round in 0..500 {
+            for _ in 0..rng.below(20) {
+                let key = rng.below(60);
+                if rng.below(2) == 0 {
+                    tree.set(key, ())?;
+                    keys.insert(key);
+                } else {
+                    tree.delete(&key)?;
+                    keys.remove(&key);
+                }
+            }
+            let mut fresh = ProllyTree::new_with_fanout(&store, 2);
+            for &key in &keys {
+                fresh.set(key, ())?;
+            }
+            assert_eq!(tree.flush()?, fresh.flush()?, "round {round}");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn flushes_an_empty_tree() -> Result<()> {
+        let store = MemoryDB::default();
+        let mut tree: ProllyTree<_, u8, u8> = ProllyTree::new_with_fanout(&store, 2);
+        tree.flush()?;
+        // [0, []]
+        assert_eq!(store.bytes_stored(), 3);
+        Ok(())
+    }
+}
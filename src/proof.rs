@@ -0,0 +1,497 @@
+//! Merkle proofs for single keys.
+//!
+//! A proof is the list of blocks on the path from the root to the slot a key
+//! hashes to. The verifier follows the same path, so a proof shows absence
+//! just as well as it shows which value a key maps to.
+//!
+//! A [`MultiProof`] covers several keys and stores the blocks shared by their
+//! paths, at least the root, only once.
+
+use std::collections::{HashMap, HashSet};
+use std::marker::PhantomData;
+
+use anyhow::{anyhow, bail, Result};
+use cid::Cid;
+use fvm_ipld_blockstore::Blockstore;
+use fvm_ipld_encoding::{de::DeserializeOwned, ser::Serialize};
+use fvm_ipld_hamt::{
+    cid_config, hamt::Root, hash_bits::HashBits, node::Node, pointer::Pointer, CidConfig, Envelope,
+    Hamt, Hash, HashAlgorithm,
+};
+
+#[derive(Debug, Clone)]
+pub struct Proof<K, V, H, const BUCKET_SIZE: usize> {
+    params: Params,
+    /// Encoded blocks, starting at the envelope or root the HAMT was flushed
+    /// to.
+    blocks: Vec<Vec<u8>>,
+    entry: PhantomData<(K, V, H)>,
+}
+
+#[derive(Debug, Clone)]
+pub struct MultiProof<K, V, H, const BUCKET_SIZE: usize> {
+    params: Params,
+    /// Distinct encoded blocks, in the order they were first visited.
+    blocks: Vec<Vec<u8>>,
+    entry: PhantomData<(K, V, H)>,
+}
+
+/// Parameters of the HAMT a proof was made from, which verifying it needs.
+#[derive(Debug, Clone)]
+struct Params {
+    bit_width: u32,
+    /// The [salt](Hamt::with_salt) keys are hashed with.
+    salt: Vec<u8>,
+    /// How the blocks are encoded and addressed.
+    cid_config: CidConfig,
+    /// Whether the first block is an [`Envelope`] linking to the root.
+    envelope: bool,
+}
+
+/// Collects the blocks on the path from the root of `hamt` to `key`.
+///
+/// The HAMT has to be flushed, since the path is read back from the store.
+/// The first block is the one [`flush`](Hamt::flush) returned the CID of, the
+/// envelope if the HAMT has one and the root with its number of entries if it
+/// keeps them there.
+pub fn generate_proof<S, K, V, H, const BUCKET_SIZE: usize>(
+    hamt: &Hamt<S, V, K, H, BUCKET_SIZE>,
+    key: &K,
+) -> Result<Proof<K, V, H, BUCKET_SIZE>>
+where
+    K: Hash + Eq + PartialOrd + Serialize + DeserializeOwned,
+    V: Serialize + DeserializeOwned,
+    H: HashAlgorithm,
+    S: Blockstore,
+{
+    let hash = H::hash_salted(key, &hamt.salt);
+    let mut bits = HashBits::new(&hash);
+
+    let root = hamt
+        .root_block()
+        .map_err(|_| anyhow!("the HAMT has to be flushed before generating proofs"))?;
+    let mut blocks = Vec::new();
+    if hamt.envelope {
+        let envelope = hamt.envelope(hamt.cid_config.cid(&root)?);
+        blocks.push(hamt.cid_config.encode(&envelope)?);
+    }
+    blocks.push(root);
+    let mut next = next_link(slot(&hamt.root, &mut bits, hamt.bit_width)?)?;
+    while let Some(cid) = next {
+        let block = hamt
+            .store()
+            .get(&cid)?
+            .ok_or_else(|| anyhow!("block {cid} not found"))?;
+        let node: Node<K, V, H, BUCKET_SIZE> = cid_config::decode(cid.codec(), &block)?;
+        next = next_link(slot(&node, &mut bits, hamt.bit_width)?)?;
+        blocks.push(block);
+    }
+
+    Ok(Proof {
+        params: Params::of(hamt),
+        blocks,
+        entry: PhantomData,
+    })
+}
+
+/// Collects the blocks on the paths from the root of `hamt` to all `keys`.
+pub fn generate_multi_proof<'a, S, K, V, H, const BUCKET_SIZE: usize>(
+    hamt: &Hamt<S, V, K, H, BUCKET_SIZE>,
+    keys: impl IntoIterator<Item = &'a K>,
+) -> Result<MultiProof<K, V, H, BUCKET_SIZE>>
+where
+    K: Hash + Eq + PartialOrd + Serialize + DeserializeOwned + 'a,
+    V: Serialize + DeserializeOwned,
+    H: HashAlgorithm,
+    S: Blockstore,
+{
+    let mut seen = HashSet::new();
+    let mut blocks = Vec::new();
+    for key in keys {
+        for block in generate_proof(hamt, key)?.blocks {
+            if seen.insert(hamt.cid_config.cid(&block)?) {
+                blocks.push(block);
+            }
+        }
+    }
+
+    Ok(MultiProof {
+        params: Params::of(hamt),
+        blocks,
+        entry: PhantomData,
+    })
+}
+
+impl Params {
+    fn of<S, K, V, H, const BUCKET_SIZE: usize>(hamt: &Hamt<S, V, K, H, BUCKET_SIZE>) -> Self {
+        Params {
+            bit_width: hamt.bit_width,
+            salt: hamt.salt.clone(),
+            cid_config: hamt.cid_config,
+            envelope: hamt.envelope,
+        }
+    }
+}
+
+impl<K, V, H, const BUCKET_SIZE: usize> Proof<K, V, H, BUCKET_SIZE> {
+    pub fn blocks(&self) -> &[Vec<u8>] {
+        &self.blocks
+    }
+
+    /// Total size of all blocks in the proof.
+    pub fn bytes(&self) -> u64 {
+        self.blocks.iter().map(|block| block.len() as u64).sum()
+    }
+
+    /// Checks that `key` maps to `value` in the HAMT flushed to `root`.
+    ///
+    /// Returns `Ok(false)` if the proof shows that `key` is absent or maps to
+    /// a different value, and an error if the proof doesn't belong to `root`
+    /// or doesn't lead to `key`.
+    pub fn verify(&self, root: &Cid, key: &K, value: &V) -> Result<bool>
+    where
+        K: Hash + Eq + PartialOrd + Serialize + DeserializeOwned,
+        V: Serialize + DeserializeOwned + PartialEq,
+        H: HashAlgorithm,
+    {
+        let (valid, used) =
+            walk_path::<K, V, H, BUCKET_SIZE>(root, key, value, &self.params, |depth, cid| {
+                let block = self
+                    .blocks
+                    .get(depth)
+                    .ok_or_else(|| anyhow!("proof ends before reaching the slot of the key"))?;
+                check_block(cid, block, &self.params.cid_config)?;
+                Ok(block)
+            })?;
+        if used != self.blocks.len() {
+            bail!("proof has blocks past the slot of the key");
+        }
+        Ok(valid)
+    }
+}
+
+impl<K, V, H, const BUCKET_SIZE: usize> MultiProof<K, V, H, BUCKET_SIZE> {
+    pub fn blocks(&self) -> &[Vec<u8>] {
+        &self.blocks
+    }
+
+    /// Total size of all blocks in the proof.
+    pub fn bytes(&self) -> u64 {
+        self.blocks.iter().map(|block| block.len() as u64).sum()
+    }
+
+    /// Checks that every key maps to its value in the HAMT flushed to `root`.
+    ///
+    /// Like [`Proof::verify`], returns `Ok(false)` if any key is absent or
+    /// maps to a different value, and an error if a block on the path of a
+    /// key is missing.
+    pub fn verify<'a>(
+        &self,
+        root: &Cid,
+        entries: impl IntoIterator<Item = (&'a K, &'a V)>,
+    ) -> Result<bool>
+    where
+        K: Hash + Eq + PartialOrd + Serialize + DeserializeOwned + 'a,
+        V: Serialize + DeserializeOwned + PartialEq + 'a,
+        H: HashAlgorithm,
+    {
+        // Blocks are looked up by their content, so they don't need to be
+        // checked against the links pointing to them.
+        let blocks: HashMap<Cid, &[u8]> = self
+            .blocks
+            .iter()
+            .map(|block| Ok((self.params.cid_config.cid(block)?, block.as_slice())))
+            .collect::<Result<_>>()?;
+
+        for (key, value) in entries {
+            let (valid, _) =
+                walk_path::<K, V, H, BUCKET_SIZE>(root, key, value, &self.params, |_, cid| {
+                    blocks
+                        .get(cid)
+                        .copied()
+                        .ok_or_else(|| anyhow!("proof is missing block {cid}"))
+                })?;
+            if !valid {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// Follows the path of `key` from `root`, the envelope or root block of a
+/// HAMT of `params`, through the blocks returned by `block`, which is called
+/// with the depth and CID of each one.
+///
+/// Returns whether `key` maps to `value` and the number of blocks visited.
+fn walk_path<'a, K, V, H, const BUCKET_SIZE: usize>(
+    root: &Cid,
+    key: &K,
+    value: &V,
+    params: &Params,
+    mut block: impl FnMut(usize, &Cid) -> Result<&'a [u8]>,
+) -> Result<(bool, usize)>
+where
+    K: Hash + Eq + PartialOrd + Serialize + DeserializeOwned,
+    V: Serialize + DeserializeOwned + PartialEq,
+    H: HashAlgorithm,
+{
+    let Params {
+        bit_width,
+        salt,
+        cid_config,
+        envelope,
+    } = params;
+    let hash = H::hash_salted(key, salt);
+    let mut bits = HashBits::new(&hash);
+    let mut expected = *root;
+    let mut depth = 0;
+
+    if *envelope {
+        let envelope: Envelope = cid_config::decode(expected.codec(), block(depth, &expected)?)?;
+        expected = envelope.root;
+        depth += 1;
+    }
+    // The root block may end with the number of entries.
+    let root: Root<K, V, H, BUCKET_SIZE> =
+        cid_config::decode(expected.codec(), block(depth, &expected)?)?;
+    let mut node = root.node;
+    loop {
+        let found = match slot(&node, &mut bits, *bit_width)? {
+            Some(Pointer::Link { cid, .. }) => {
+                expected = *cid;
+                depth += 1;
+                node = cid_config::decode(expected.codec(), block(depth, &expected)?)?;
+                continue;
+            }
+            Some(Pointer::Values(values)) => match values.iter().find(|kv| kv.key() == key) {
+                // External values are checked against their CID, so proofs
+                // don't need the value block.
+                Some(kv) => match kv.value_block() {
+                    Some(external) => {
+                        Some(external.cid == cid_config.cid(&cid_config.encode(value)?)?)
+                    }
+                    None => Some(kv.loaded_value() == Some(value)),
+                },
+                None => None,
+            },
+            Some(Pointer::Dirty(_)) => unreachable!("decoded nodes are never dirty"),
+            None => None,
+        };
+        return Ok((found.unwrap_or(false), depth + 1));
+    }
+}
+
+/// The pointer in `node` the next bits of a key hash lead to, if any.
+fn slot<'a, K, V, H, const BUCKET_SIZE: usize>(
+    node: &'a Node<K, V, H, BUCKET_SIZE>,
+    bits: &mut HashBits,
+    bit_width: u32,
+) -> Result<Option<&'a Pointer<K, V, H, BUCKET_SIZE>>> {
+    // Keys of another slot at a level the node skips aren't below it.
+    for &skipped in &node.skip {
+        if bits.next(bit_width)? != skipped as u32 {
+            return Ok(None);
+        }
+    }
+    let idx = bits.next(bit_width)?;
+    if !node.bitfield.test_bit(idx) {
+        return Ok(None);
+    }
+    let index = (0..idx).filter(|&i| node.bitfield.test_bit(i)).count();
+    Ok(node.pointers.get(index))
+}
+
+fn next_link<K, V, H, const BUCKET_SIZE: usize>(
+    pointer: Option<&Pointer<K, V, H, BUCKET_SIZE>>,
+) -> Result<Option<Cid>> {
+    match pointer {
+        Some(Pointer::Link { cid, .. }) => Ok(Some(*cid)),
+        Some(Pointer::Dirty(_)) => bail!("the HAMT has to be flushed before generating proofs"),
+        Some(Pointer::Values(_)) | None => Ok(None),
+    }
+}
+
+/// Checks that `block` is stored under `cid` in a HAMT of `cid_config`.
+fn check_block(cid: &Cid, block: &[u8], cid_config: &CidConfig) -> Result<()> {
+    if cid_config.cid(block)? != *cid {
+        bail!("block doesn't match {cid}");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::invariants::verify_invariants;
+    use crate::memorydb::MemoryDB;
+    use cid::multihash::Code;
+    use fvm_ipld_hamt::dag_json::DAG_JSON;
+    use fvm_ipld_hamt::Sha256;
+
+    #[test]
+    fn proves_inclusion_and_absence() -> Result<()> {
+        let store = MemoryDB::default();
+        let mut map: Hamt<_, String, usize, Sha256, 3> = Hamt::new_with_bit_width(&store, 4);
+        for key in 0..1000 {
+            map.set(key, "F".to_string())?;
+        }
+        assert!(generate_proof(&map, &0).is_err());
+        let root = map.flush()?;
+
+        for key in 0..1000 {
+            let proof = generate_proof(&map, &key)?;
+            assert!(proof.verify(&root, &key, &"F".to_string())?);
+            assert!(!proof.verify(&root, &key, &".".to_string())?);
+            assert!(proof.bytes() < store.bytes_stored());
+        }
+        let proof = generate_proof(&map, &1000)?;
+        assert!(!proof.verify(&root, &1000, &"F".to_string())?);
+        Ok(())
+    }
+
+    #[test]
+    fn proves_keys_of_salted_hamts() -> Result<()> {
+        let store = MemoryDB::default();
+        let mut map: Hamt<_, String, usize, Sha256, 3> =
+            Hamt::new_with_bit_width(&store, 4).with_salt(b"salt".to_vec());
+        for key in 0..1000 {
+            map.set(key, "F".to_string())?;
+        }
+        let root = map.flush()?;
+        verify_invariants(&map)?;
+
+        let value = "F".to_string();
+        for key in 0..1000 {
+            assert!(generate_proof(&map, &key)?.verify(&root, &key, &value)?);
+        }
+        let keys: Vec<usize> = (0..50).collect();
+        let proof = generate_multi_proof(&map, &keys)?;
+        assert!(proof.verify(&root, keys.iter().map(|key| (key, &value)))?);
+        Ok(())
+    }
+
+    #[test]
+    fn proves_keys_against_the_flushed_root() -> Result<()> {
+        let store = MemoryDB::default();
+        let value = "F".to_string();
+        for envelope in [false, true] {
+            let mut map: Hamt<_, String, usize, Sha256, 3> =
+                Hamt::new_with_bit_width(&store, 4).with_len_in_root();
+            map.envelope = envelope;
+            for key in 0..1000 {
+                map.set(key, value.clone())?;
+            }
+            let root = map.flush()?;
+
+            let proof = generate_proof(&map, &7)?;
+            assert!(proof.verify(&root, &7, &value)?);
+            let proof = generate_proof(&map, &1000)?;
+            assert!(!proof.verify(&root, &1000, &value)?);
+            let keys: Vec<usize> = (0..50).collect();
+            let proof = generate_multi_proof(&map, &keys)?;
+            assert!(proof.verify(&root, keys.iter().map(|key| (key, &value)))?);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_proofs_for_other_roots() -> Result<()> {
+        let store = MemoryDB::default();
+        let mut map: Hamt<_, String, usize, Sha256, 3> = Hamt::new_with_bit_width(&store, 4);
+        for key in 0..1000 {
+            map.set(key, "F".to_string())?;
+        }
+        map.flush()?;
+        let proof = generate_proof(&map, &0)?;
+
+        map.set(0, ".".to_string())?;
+        let root = map.flush()?;
+        assert!(proof.verify(&root, &0, &"F".to_string()).is_err());
+
+        let mut truncated = generate_proof(&map, &0)?;
+        truncated.blocks.pop();
+        assert!(truncated.verify(&root, &0, &".".to_string()).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn proves_external_values_by_cid() -> Result<()> {
+        let store = MemoryDB::default();
+        let mut map: Hamt<_, String, usize, Sha256, 3> =
+            Hamt::new_with_bit_width(&store, 4).with_value_threshold(16);
+        let large = "F".repeat(100);
+        for key in 0..1000 {
+            map.set(key, large.clone())?;
+        }
+        let root = map.flush()?;
+
+        let proof = generate_proof(&map, &7)?;
+        assert!(proof.verify(&root, &7, &large)?);
+        assert!(!proof.verify(&root, &7, &"F".repeat(99))?);
+
+        // Proofs only contain the CIDs of the values.
+        let mut inline: Hamt<_, String, usize, Sha256, 3> = Hamt::new_with_bit_width(&store, 4);
+        for key in 0..1000 {
+            inline.set(key, large.clone())?;
+        }
+        inline.flush()?;
+        assert!(generate_proof(&inline, &7)?.bytes() > proof.bytes() + large.len() as u64);
+        Ok(())
+    }
+
+    #[test]
+    fn proves_keys_of_hamts_with_other_cids() -> Result<()> {
+        let store = MemoryDB::default();
+        let cid_config = CidConfig {
+            mh_code: Code::Sha2_256,
+            codec: DAG_JSON,
+            ..CidConfig::default()
+        };
+        let mut map: Hamt<_, String, usize, Sha256, 3> = Hamt::new_with_bit_width(&store, 4)
+            .with_cid_config(cid_config)
+            .with_value_threshold(16);
+        let large = "F".repeat(100);
+        for key in 0..1000 {
+            map.set(key, large.clone())?;
+        }
+        let root = map.flush()?;
+
+        let proof = generate_proof(&map, &7)?;
+        assert!(proof.verify(&root, &7, &large)?);
+        assert!(!proof.verify(&root, &7, &"F".repeat(99))?);
+        let keys: Vec<usize> = (0..50).collect();
+        let proof = generate_multi_proof(&map, &keys)?;
+        assert!(proof.verify(&root, keys.iter().map(|key| (key, &large)))?);
+        Ok(())
+    }
+
+    #[test]
+    fn multi_proofs_share_blocks() -> Result<()> {
+        let store = MemoryDB::default();
+        let mut map: Hamt<_, String, usize, Sha256, 3> = Hamt::new_with_bit_width(&store, 4);
+        for key in 0..1000 {
+            map.set(key, "F".to_string())?;
+        }
+        let root = map.flush()?;
+        let keys: Vec<usize> = (0..50).collect();
+        let value = "F".to_string();
+
+        let proof = generate_multi_proof(&map, &keys)?;
+        assert!(proof.verify(&root, keys.iter().map(|key| (key, &value)))?);
+        assert!(!proof.verify(&root, [(&0, &".".to_string())])?);
+
+        let separate: u64 = keys
+            .iter()
+            .map(|key| Ok(generate_proof(&map, key)?.bytes()))
+            .sum::<Result<_>>()?;
+        assert!(proof.bytes() < separate);
+
+        let mut truncated = generate_multi_proof(&map, &keys)?;
+        truncated.blocks.pop();
+        assert!(truncated
+            .verify(&root, keys.iter().map(|key| (key, &value)))
+            .is_err());
+        Ok(())
+    }
+}
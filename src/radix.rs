@@ -0,0 +1,477 @@
+//! A Merkle radix trie keyed on the raw bytes of the keys, to compare the
+//! hashed HAMT against an ordered trie.
+//!
+//! Keys are split into digits of `bit_width` bits, most significant first,
+//! and every level of the trie branches on one digit. Runs of digits without
+//! a branch are compressed into the prefix of a node. Nodes without children
+//! are stored inline in their parent, others in blocks of their own:
+//!
+//! ```text
+//! node  = [prefix_digits, prefix, value | null, [child, ...]]
+//! child = [digit, cid] | [digit, prefix_digits, prefix, value]
+//! ```
+//!
+//! Prefixes are packed into bytes. Unlike hashed keys, similar keys share
+//! paths, so the shape of the trie depends on the workload. Deleting a key
+//! merges a node left with neither a value nor a branch into its only child,
+//! so a trie only depends on its keys. Trees are only built and flushed to
+//! measure them.
+
+use std::cmp;
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use cid::multihash::Code;
+use cid::Cid;
+use fvm_ipld_blockstore::{Block, Blockstore};
+use fvm_ipld_encoding::serde_bytes::ByteBuf;
+use fvm_ipld_encoding::{to_vec, DAG_CBOR};
+use fvm_ipld_hamt::{Hamt, Sha256};
+use serde::{Serialize, Serializer};
+
+use crate::experiments::delete::delete_in_batches;
+use crate::experiments::depth::depth_experiment;
+use crate::experiments::sizes::experiment;
+use crate::experiments::ExperimentContext;
+use crate::map::{map_sizes, IpldMap, MapStats};
+use crate::memorydb::MemoryDB;
+use crate::metered::MeteredStore;
+use crate::workload::{Key, Workload};
+
+pub struct RadixTrie<'a, BS, V> {
+    store: &'a BS,
+    bit_width: u32,
+    root: Node<V>,
+}
+
+struct Node<V> {
+    /// Digits after the one the node is reached by.
+    prefix: Vec<u8>,
+    value: Option<V>,
+    children: BTreeMap<u8, Child<V>>,
+}
+
+struct Child<V> {
+    node: Node<V>,
+    /// Unless the node changed since it was last flushed, or is inline.
+    cid: Option<Cid>,
+}
+
+impl<'a, BS: Blockstore, V: Serialize> RadixTrie<'a, BS, V> {
+    pub fn new_with_bit_width(store: &'a BS, bit_width: u32) -> Self {
+        assert!(
+            (1..=8).contains(&bit_width),
+            "unsupported bit width {bit_width}"
+        );
+        RadixTrie {
+            store,
+            bit_width,
+            root: Node::leaf(Vec::new(), None),
+        }
+    }
+
+    /// Sets the value of `key`, returning the previous one.
+    pub fn set(&mut self, key: &[u8], value: V) -> Option<V> {
+        let digits = digits(key, self.bit_width);
+        self.root.set(&digits, value)
+    }
+
+    /// Deletes `key`, returning its value.
+    pub fn delete(&mut self, key: &[u8]) -> Option<V> {
+        let digits = digits(key, self.bit_width);
+        self.root.delete(&digits)
+    }
+
+    pub fn get(&self, key: &[u8]) -> Option<&V> {
+        let digits = digits(key, self.bit_width);
+        self.root.find(&digits).map(|(value, _)| value)
+    }
+
+    /// Number of blocks from the root to the node holding `key`, the root
+    /// included.
+    pub fn blocks_on_path(&self, key: &[u8]) -> Option<usize> {
+        let digits = digits(key, self.bit_width);
+        self.root.find(&digits).map(|(_, blocks)| blocks)
+    }
+
+    /// Writes every changed node and the root to the store.
+    pub fn flush(&mut self) -> Result<Cid> {
+        self.root.flush(self.store, self.bit_width)
+    }
+}
+
+impl<V: Serialize> Node<V> {
+    fn leaf(prefix: Vec<u8>, value: Option<V>) -> Self {
+        Node {
+            prefix,
+            value,
+            children: BTreeMap::new(),
+        }
+    }
+
+    fn set(&mut self, digits: &[u8], value: V) -> Option<V> {
+        let common = self
+            .prefix
+            .iter()
+            .zip(digits)
+            .take_while(|(a, b)| a == b)
+            .count();
+        if common < self.prefix.len() {
+            // Split the prefix where `digits` leaves it.
+            let mut rest = self.prefix.split_off(common);
+            let digit = rest.remove(0);
+            let split = Node {
+                prefix: rest,
+                value: self.value.take(),
+                children: std::mem::take(&mut self.children),
+            };
+            self.children.insert(
+                digit,
+                Child {
+                    node: split,
+                    cid: None,
+                },
+            );
+        }
+
+        match digits[common..].split_first() {
+            None => self.value.replace(value),
+            Some((digit, rest)) => match self.children.get_mut(digit) {
+                Some(child) => {
+                    child.cid = None;
+                    child.node.set(rest, value)
+                }
+                None => {
+                    let node = Node::leaf(rest.to_vec(), Some(value));
+                    self.children.insert(*digit, Child { node, cid: None });
+                    None
+                }
+            },
+        }
+    }
+
+    /// Deletes the value of `digits`. A child left without a value is
+    /// dropped if it has no children either and merged with its only child
+    /// if it has one, undoing the split that created it.
+    fn delete(&mut self, digits: &[u8]) -> Option<V> {
+        let digits = digits.strip_prefix(self.prefix.as_slice())?;
+        let Some((digit, rest)) = digits.split_first() else {
+            return self.value.take();
+        };
+        let child = self.children.get_mut(digit)?;
+        let value = child.node.delete(rest)?;
+        child.cid = None;
+        if child.node.value.is_none() {
+            match child.node.children.len() {
+                0 => {
+                    self.children.remove(digit);
+                }
+                1 => child.node.merge_child(),
+                _ => {}
+            }
+        }
+        Some(value)
+    }
+
+    /// Appends the only child to this node, which has no value.
+    fn merge_child(&mut self) {
+        let (digit, child) = self.children.pop_first().expect("one child");
+        self.prefix.push(digit);
+        self.prefix.extend(child.node.prefix);
+        self.value = child.node.value;
+        self.children = child.node.children;
+    }
+
+    /// The value of `digits` and the number of blocks on the way to it.
+    fn find(&self, digits: &[u8]) -> Option<(&V, usize)> {
+        let mut node = self;
+        let mut digits = digits;
+        let mut blocks = 1;
+        loop {
+            digits = digits.strip_prefix(node.prefix.as_slice())?;
+            match digits.split_first() {
+                None => return node.value.as_ref().map(|value| (value, blocks)),
+                Some((digit, rest)) => {
+                    node = &node.children.get(digit)?.node;
+                    if !node.is_inline() {
+                        blocks += 1;
+                    }
+                    digits = rest;
+                }
+            }
+        }
+    }
+
+    /// Counts the values below and including this node and the blocks below
+    /// it, given that it's `depth` blocks below the root.
+    fn add_stats(&self, depth: u32, stats: &mut MapStats) {
+        stats.values += u64::from(self.value.is_some());
+        for child in self.children.values() {
+            let mut depth = depth;
+            if !child.node.is_inline() {
+                depth += 1;
+                stats.nodes += 1;
+                stats.height = stats.height.max(depth);
+            }
+            child.node.add_stats(depth, stats);
+        }
+    }
+
+    fn is_inline(&self) -> bool {
+        self.children.is_empty()
+    }
+
+    fn flush(&mut self, store: &impl Blockstore, bit_width: u32) -> Result<Cid> {
+        for child in self.children.values_mut() {
+            if child.cid.is_none() && !child.node.is_inline() {
+                child.cid = Some(child.node.flush(store, bit_width)?);
+            }
+        }
+        let encoding = (
+            self.prefix.len(),
+            pack(&self.prefix, bit_width),
+            &self.value,
+            self.children
+                .iter()
+                .map(|(&digit, child)| ChildEncoding {
+                    digit,
+                    child,
+                    bit_width,
+                })
+                .collect::<Vec<_>>(),
+        );
+        let bytes = to_vec(&encoding)?;
+        store.put(Code::Blake2b256, &Block::new(DAG_CBOR, &bytes))
+    }
+}
+
+impl<BS: Blockstore, V: Serialize> IpldMap<Vec<u8>, V> for RadixTrie<'_, BS, V> {
+    fn set(&mut self, key: Vec<u8>, value: V) -> Result<Option<V>> {
+        Ok(RadixTrie::set(self, &key, value))
+    }
+
+    fn get(&self, key: &Vec<u8>) -> Result<Option<&V>> {
+        Ok(RadixTrie::get(self, key))
+    }
+
+    fn delete(&mut self, key: &Vec<u8>) -> Result<Option<V>> {
+        Ok(RadixTrie::delete(self, key))
+    }
+
+    fn flush(&mut self) -> Result<Cid> {
+        RadixTrie::flush(self)
+    }
+
+    fn stats(&self) -> Result<MapStats> {
+        let mut stats = MapStats {
+            nodes: 1,
+            ..MapStats::default()
+        };
+        self.root.add_stats(0, &mut stats);
+        Ok(stats)
+    }
+}
+
+struct ChildEncoding<'c, V> {
+    digit: u8,
+    child: &'c Child<V>,
+    bit_width: u32,
+}
+
+impl<V: Serialize> Serialize for ChildEncoding<'_, V> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let node = &self.child.node;
+        match self.child.cid {
+            Some(cid) => (self.digit, cid).serialize(serializer),
+            None => (
+                self.digit,
+                node.prefix.len(),
+                pack(&node.prefix, self.bit_width),
+                &node.value,
+            )
+                .serialize(serializer),
+        }
+    }
+}
+
+/// `key` as digits of `bit_width` bits, the last one padded with zeros.
+fn digits(key: &[u8], bit_width: u32) -> Vec<u8> {
+    let bit_width = bit_width as usize;
+    let bits = key.len() * 8;
+    (0..bits.div_ceil(bit_width))
+        .map(|digit| {
+            (0..bit_width).fold(0u8, |acc, i| {
+                let bit = digit * bit_width + i;
+                let set = bit < bits && key[bit / 8] >> (7 - bit % 8) & 1 == 1;
+                acc << 1 | u8::from(set)
+            })
+        })
+        .collect()
+}
+
+/// The inverse of [`digits`], given the number of digits.
+fn pack(digits: &[u8], bit_width: u32) -> ByteBuf {
+    let bit_width = bit_width as usize;
+    let mut bytes = vec![0u8; (digits.len() * bit_width).div_ceil(8)];
+    for (d, digit) in digits.iter().enumerate() {
+        for i in 0..bit_width {
+            if digit >> (bit_width - 1 - i) & 1 == 1 {
+                let bit = d * bit_width + i;
+                bytes[bit / 8] |= 1 << (7 - bit % 8);
+            }
+        }
+    }
+    ByteBuf::from(bytes)
+}
+
+#[derive(Debug, Serialize)]
+pub struct RadixResult {
+    n: usize,
+    m: usize,
+    bucket_size: usize,
+    bit_width: u32,
+    hamt_total_bytes: u64,
+    radix_total_bytes: u64,
+    hamt_avg_node_bytes: f64,
+    radix_avg_node_bytes: f64,
+    hamt_max_node_bytes: usize,
+    radix_max_node_bytes: usize,
+    hamt_byte_diff: u64,
+    radix_byte_diff: u64,
+    /// Blocks from the root to a key, on average over all keys.
+    hamt_mean_blocks: f64,
+    radix_mean_blocks: f64,
+    /// Bytes written by deleting `m` of the keys in batches of `batch_size`.
+    hamt_delete_byte_diff: u64,
+    radix_delete_byte_diff: u64,
+    hamt_nodes_after_delete: u64,
+    radix_nodes_after_delete: u64,
+}
+
+/// The measurements of [`experiment`] and of deleting `m` of the `n` keys,
+/// like the `delete` experiment, in a HAMT and in a radix trie with the same
+/// keys, and the mean number of blocks on the path to one of the first `n`
+/// keys in each.
+pub fn radix_experiment<const BUCKET_SIZE: usize>(
+    ctx: &ExperimentContext,
+    bit_width: u32,
+    n: usize,
+    m: usize,
+    batch_size: usize,
+    workload: &Workload,
+) -> Result<RadixResult> {
+    let hamt = experiment::<BUCKET_SIZE>(ctx, bit_width, n, m, workload);
+    let depths = depth_experiment::<BUCKET_SIZE>(ctx, bit_width, n, workload);
+
+    let store = MeteredStore::new(MemoryDB::default());
+    let mut trie = RadixTrie::new_with_bit_width(&store, bit_width);
+    let workload_keys = workload.keys(cmp::max(n, m), &mut ctx.rng());
+    let keys: Vec<Vec<u8>> = workload_keys.iter().map(Key::to_bytes).collect();
+    let radix = map_sizes(&mut trie, &store, &keys, n, m)?;
+    let blocks: usize = keys[..n]
+        .iter()
+        .map(|key| trie.blocks_on_path(key).expect("key was set"))
+        .sum();
+
+    let store = MemoryDB::default();
+    let mut map: Hamt<_, _, Key, Sha256, BUCKET_SIZE> = Hamt::new_with_bit_width(&store, bit_width);
+    let hamt_deletes =
+        delete_in_batches(ctx, &mut map, &store, &workload_keys[..n], m, batch_size)?;
+    let store = MemoryDB::default();
+    let mut trie = RadixTrie::new_with_bit_width(&store, bit_width);
+    let radix_deletes = delete_in_batches(ctx, &mut trie, &store, &keys[..n], m, batch_size)?;
+    Ok(RadixResult {
+        n,
+        m,
+        bucket_size: BUCKET_SIZE,
+        bit_width,
+        hamt_total_bytes: hamt.total_bytes,
+        radix_total_bytes: radix.total_bytes,
+        hamt_avg_node_bytes: hamt.avg_node_bytes,
+        radix_avg_node_bytes: radix.avg_node_bytes,
+        hamt_max_node_bytes: hamt.max_node_bytes,
+        radix_max_node_bytes: radix.max_node_bytes,
+        hamt_byte_diff: hamt.byte_difference,
+        radix_byte_diff: radix.byte_difference,
+        hamt_mean_blocks: depths.mean_depth + 1.0,
+        radix_mean_blocks: blocks as f64 / cmp::max(n, 1) as f64,
+        hamt_delete_byte_diff: hamt_deletes.byte_diff,
+        radix_delete_byte_diff: radix_deletes.byte_diff,
+        hamt_nodes_after_delete: hamt_deletes.nodes_after,
+        radix_nodes_after_delete: radix_deletes.nodes_after,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memorydb::MemoryDB;
+
+    #[test]
+    fn splits_keys_into_digits() {
+        assert_eq!(digits(&[0b1011_0110], 3), [0b101, 0b101, 0b100]);
+        assert_eq!(digits(&[0xab, 0xcd], 4), [0xa, 0xb, 0xc, 0xd]);
+        assert_eq!(pack(&[0xa, 0xb, 0xc], 4).into_vec(), [0xab, 0xc0]);
+        assert_eq!(
+            pack(&digits(b"key", 5), 5).into_vec(),
+            [b'k', b'e', b'y', 0]
+        );
+    }
+
+    #[test]
+    fn sets_and_gets_values() -> Result<()> {
+        let store = MemoryDB::default();
+        let mut trie = RadixTrie::new_with_bit_width(&store, 4);
+        let keys: [&[u8]; 5] = [b"/photos/a", b"/photos/b", b"/music", b"/photos", b"/"];
+        for (i, key) in keys.iter().enumerate() {
+            assert_eq!(trie.set(key, i), None);
+        }
+        assert_eq!(trie.set(b"/music", 7), Some(2));
+        assert_eq!(trie.get(b"/photos"), Some(&3));
+        assert_eq!(trie.get(b"/photos/a"), Some(&0));
+        assert_eq!(trie.get(b"/music"), Some(&7));
+        assert_eq!(trie.get(b"/photo"), None);
+        assert_eq!(trie.get(b"/photos/c"), None);
+
+        trie.flush()?;
+        // The root and the nodes of `/`, `/photos` and `/photos/`, the other
+        // keys have no children and are inline.
+        assert_eq!(store.blocks(), 4);
+        assert_eq!(trie.blocks_on_path(b"/photos/b"), Some(4));
+        assert_eq!(trie.blocks_on_path(b"/music"), Some(2));
+        let stats = IpldMap::stats(&trie)?;
+        assert_eq!((stats.nodes, stats.values, stats.height), (4, 5, 3));
+        Ok(())
+    }
+    #[test]
+    fn deletes_to_the_trie_without_the_keys() -> Result<()> {
+        let store = MemoryDB::default();
+        let mut trie = RadixTrie::new_with_bit_width(&store, 4);
+        let mut fresh = RadixTrie::new_with_bit_width(&store, 4);
+        let keys: Vec<Vec<u8>> = (0..200)
+            .map(|i| format!("/photos/{i}").into_bytes())
+            .chain([b"/".to_vec(), b"/photos".to_vec()])
+            .collect();
+        for (i, key) in keys.iter().enumerate() {
+            trie.set(key, i);
+            if i % 3 != 0 {
+                fresh.set(key, i);
+            }
+        }
+        trie.flush()?;
+        for (i, key) in keys.iter().enumerate().step_by(3) {
+            assert_eq!(trie.delete(key), Some(i));
+        }
+        assert_eq!(trie.delete(&keys[0]), None);
+        assert_eq!(trie.delete(b"/photo"), None);
+        assert_eq!(trie.get(&keys[3]), None);
+        assert_eq!(trie.get(&keys[4]), Some(&4));
+        assert_eq!(trie.flush()?, fresh.flush()?);
+
+        for key in &keys {
+            trie.delete(key);
+        }
+        assert!(trie.root.value.is_none() && trie.root.children.is_empty());
+        Ok(())
+    }
+}
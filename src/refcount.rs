@@ -0,0 +1,181 @@
+//! A blockstore counting how many versions of a HAMT reference each block,
+//! so that dropping a version removes exactly the blocks no other version
+//! still uses.
+
+use std::collections::{HashMap, HashSet};
+
+use anyhow::{anyhow, Result};
+use cid::Cid;
+use fvm_ipld_blockstore::Blockstore;
+use parking_lot::RwLock;
+use serde::Serialize;
+
+use crate::memorydb::MemoryDB;
+use crate::sync::walk;
+
+/// Blockstores blocks can be removed from again.
+pub trait RemoveBlocks: Blockstore {
+    /// Removes the blocks `cids` and returns the number of bytes freed.
+    fn remove(&self, cids: &[Cid]) -> Result<u64>;
+}
+
+impl RemoveBlocks for MemoryDB {
+    fn remove(&self, cids: &[Cid]) -> Result<u64> {
+        Ok(MemoryDB::remove(self, cids))
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Refs {
+    versions: usize,
+    bytes: usize,
+}
+
+/// Wraps a blockstore and counts, for every block, the
+/// [retained](RefCountedStore::retain) versions it's reachable from.
+///
+/// Blocks that were put but aren't part of any retained version, like those
+/// of versions that were never flushed, aren't tracked and stay in the store.
+#[derive(Debug, Default)]
+pub struct RefCountedStore<S> {
+    inner: S,
+    refs: RwLock<HashMap<Cid, Refs>>,
+}
+
+/// Blocks of the retained versions of a [`RefCountedStore`] and how many of
+/// them are shared.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct Sharing {
+    pub blocks: usize,
+    pub bytes: u64,
+    /// Blocks reachable from more than one version.
+    pub shared_blocks: usize,
+    pub shared_bytes: u64,
+    /// Sum of the number of versions referencing each block.
+    pub references: usize,
+}
+
+impl<S> RefCountedStore<S> {
+    pub fn new(inner: S) -> Self {
+        RefCountedStore {
+            inner,
+            refs: RwLock::default(),
+        }
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    /// Number of retained versions `cid` is reachable from.
+    pub fn refs(&self, cid: &Cid) -> usize {
+        self.refs.read().get(cid).map_or(0, |refs| refs.versions)
+    }
+
+    pub fn sharing(&self) -> Sharing {
+        let mut sharing = Sharing::default();
+        for refs in self.refs.read().values() {
+            sharing.blocks += 1;
+            sharing.bytes += refs.bytes as u64;
+            sharing.references += refs.versions;
+            if refs.versions > 1 {
+                sharing.shared_blocks += 1;
+                sharing.shared_bytes += refs.bytes as u64;
+            }
+        }
+        sharing
+    }
+}
+
+impl<S: RemoveBlocks> RefCountedStore<S> {
+    /// Counts every block reachable from `root` as referenced by one more
+    /// version. Retaining the same root twice counts it twice.
+    pub fn retain(&self, root: &Cid) -> Result<()> {
+        let mut refs = self.refs.write();
+        walk(&self.inner, root, &mut HashSet::new(), |cid, block| {
+            refs.entry(*cid)
+                .or_insert(Refs {
+                    versions: 0,
+                    bytes: block.len(),
+                })
+                .versions += 1;
+        })
+    }
+
+    /// Drops the version at `root`, removing the blocks no other retained
+    /// version references, and returns the number of bytes freed.
+    pub fn release(&self, root: &Cid) -> Result<u64> {
+        let mut blocks = Vec::new();
+        walk(&self.inner, root, &mut HashSet::new(), |cid, _| {
+            blocks.push(*cid)
+        })?;
+
+        let mut refs = self.refs.write();
+        if let Some(cid) = blocks.iter().find(|cid| !refs.contains_key(cid)) {
+            return Err(anyhow!("block {cid} isn't part of a retained version"));
+        }
+        let mut unused = Vec::new();
+        for cid in blocks {
+            let block = refs.get_mut(&cid).expect("checked above");
+            block.versions -= 1;
+            if block.versions == 0 {
+                refs.remove(&cid);
+                unused.push(cid);
+            }
+        }
+        self.inner.remove(&unused)
+    }
+}
+
+impl<S: Blockstore> Blockstore for RefCountedStore<S> {
+    fn has(&self, k: &Cid) -> Result<bool> {
+        self.inner.has(k)
+    }
+
+    fn get(&self, k: &Cid) -> Result<Option<Vec<u8>>> {
+        self.inner.get(k)
+    }
+
+    fn put_keyed(&self, k: &Cid, block: &[u8]) -> Result<()> {
+        self.inner.put_keyed(k, block)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fvm_ipld_hamt::{Hamt, Sha256};
+
+    #[test]
+    fn releasing_removes_only_unshared_blocks() -> Result<()> {
+        let store = RefCountedStore::new(MemoryDB::default());
+        let mut map: Hamt<_, String, usize, Sha256, 3> = Hamt::new_with_bit_width(&store, 4);
+        for key in 0..1000 {
+            map.set(key, "old".to_string())?;
+        }
+        let old = map.flush()?;
+        store.retain(&old)?;
+        map.set(0, "new".to_string())?;
+        let new = map.flush()?;
+        store.retain(&new)?;
+
+        let sharing = store.sharing();
+        assert_eq!(sharing.bytes, store.inner().bytes_stored());
+        assert!(sharing.shared_blocks > 0);
+        assert_eq!(store.refs(&old), 1);
+
+        let only_old = store.inner().delta_bytes(&[new], &[old])?;
+        assert_eq!(store.release(&old)?, only_old);
+        assert_eq!(store.sharing().shared_blocks, 0);
+        assert_eq!(
+            store.inner().bytes_stored(),
+            store.inner().live_bytes(&[new])?
+        );
+        assert!(store.release(&old).is_err());
+
+        let map: Hamt<_, String, usize, Sha256, 3> = Hamt::load_with_bit_width(&new, &store, 4)?;
+        assert_eq!(map.get(&0)?.map(String::as_str), Some("new"));
+        assert_eq!(map.get(&999)?.map(String::as_str), Some("old"));
+        Ok(())
+    }
+}
@@ -0,0 +1,167 @@
+//! Regression data: root CIDs of HAMTs with known contents, generated by
+//! this implementation with `rust-ipld-hamt regression`, so changes to the
+//! encoding show up as changed roots.
+//!
+//! No roots from go-hamt-ipld or any other implementation are bundled, so the
+//! vectors say nothing about compatibility. The only root checked against Go
+//! is the empty one, in `empty_root_matches_go`.
+//!
+//! A vector is a line `<name> <bit_width> <bucket_size> <n> <root>`. Its HAMT
+//! maps the keys `0` to `n - 1`, as the bytes of their decimal strings, to the
+//! same integers. Lines starting with `#` are comments.
+
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{anyhow, Context, Error, Result};
+use cid::Cid;
+use fvm_ipld_hamt::{BytesKey, Hamt, Sha256};
+
+use crate::bucket::with_bucket_size;
+use crate::memorydb::MemoryDB;
+
+/// The vectors checked by the tests.
+pub const FIXTURES: &str = include_str!("../regression/roots.txt");
+
+/// Name, bit width, bucket size and number of entries of the vectors
+/// `rust-ipld-hamt regression` generates.
+pub const CASES: &[(&str, u32, usize, usize)] = &[
+    ("empty", 8, 3, 0),
+    ("single", 8, 3, 1),
+    ("bucket", 8, 3, 3),
+    ("small", 8, 3, 100),
+    ("large", 8, 3, 10_000),
+    ("filecoin-small", 5, 3, 100),
+    ("filecoin-large", 5, 3, 10_000),
+    ("narrow", 1, 3, 1000),
+    ("medium-width", 3, 3, 1000),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Vector {
+    pub name: String,
+    pub bit_width: u32,
+    pub bucket_size: usize,
+    pub n: usize,
+    pub root: Cid,
+}
+
+impl Vector {
+    /// The vector with the root this implementation builds.
+    pub fn generate(name: &str, bit_width: u32, bucket_size: usize, n: usize) -> Result<Self> {
+        Ok(Vector {
+            name: name.to_string(),
+            bit_width,
+            bucket_size,
+            n,
+            root: build_root(bit_width, bucket_size, n)?,
+        })
+    }
+
+    /// Root this implementation builds for the vector, which should be
+    /// [`Vector::root`].
+    pub fn build(&self) -> Result<Cid> {
+        build_root(self.bit_width, self.bucket_size, self.n)
+    }
+}
+
+impl fmt::Display for Vector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {} {} {}",
+            self.name, self.bit_width, self.bucket_size, self.n, self.root
+        )
+    }
+}
+
+impl FromStr for Vector {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let fields: Vec<&str> = s.split_whitespace().collect();
+        let [name, bit_width, bucket_size, n, root] = fields[..] else {
+            return Err(anyhow!(
+                "expected `<name> <bit_width> <bucket_size> <n> <root>`, got `{s}`"
+            ));
+        };
+        Ok(Vector {
+            name: name.to_string(),
+            bit_width: bit_width.parse().context("invalid bit width")?,
+            bucket_size: bucket_size.parse().context("invalid bucket size")?,
+            n: n.parse().context("invalid number of entries")?,
+            root: root.parse().context("invalid root")?,
+        })
+    }
+}
+
+/// The vectors of a fixture file, skipping comments and empty lines.
+pub fn parse(text: &str) -> Result<Vec<Vector>> {
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.starts_with('#'))
+        .map(|(i, line)| line.parse().with_context(|| format!("line {}", i + 1)))
+        .collect()
+}
+
+/// A fixture file of [`CASES`], with a header describing the format.
+pub fn generate() -> Result<String> {
+    let mut text = String::from(
+        "# Self-generated HAMT roots written by `rust-ipld-hamt regression`, see\n\
+         # src/regression.rs for the entries. They are checked against the roots\n\
+         # built now with `rust-ipld-hamt regression --check <file>`.\n\
+         # <name> <bit_width> <bucket_size> <n> <root>\n",
+    );
+    for &(name, bit_width, bucket_size, n) in CASES {
+        let vector = Vector::generate(name, bit_width, bucket_size, n)?;
+        text.push_str(&format!("{vector}\n"));
+    }
+    Ok(text)
+}
+
+fn build_root(bit_width: u32, bucket_size: usize, n: usize) -> Result<Cid> {
+    let store = MemoryDB::default();
+    with_bucket_size!(bucket_size, B => {
+        let mut map: Hamt<_, u64, BytesKey, Sha256, B> =
+            Hamt::new_with_bit_width(&store, bit_width);
+        for i in 0..n as u64 {
+            map.set(BytesKey(i.to_string().into_bytes()), i)?;
+        }
+        Ok(map.flush()?)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_the_fixture_roots() -> Result<()> {
+        let vectors = parse(FIXTURES)?;
+        assert_eq!(vectors.len(), CASES.len());
+        for vector in vectors {
+            assert_eq!(vector.build()?, vector.root, "{}", vector.name);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn empty_root_matches_go() -> Result<()> {
+        // The empty map of Filecoin actor state, as go-hamt-ipld writes it.
+        let root: Cid = "bafy2bzaceamp42wmmgr2g2ymg46euououzfyck7szknvfacqscohrvaikwfay".parse()?;
+        assert_eq!(build_root(5, 3, 0)?, root);
+        assert_eq!(build_root(8, 3, 0)?, root);
+        Ok(())
+    }
+
+    #[test]
+    fn parses_vectors() -> Result<()> {
+        let vector = Vector::generate("single", 8, 3, 1)?;
+        let text = format!("# comment\n\n{vector}\n");
+        assert_eq!(parse(&text)?, [vector]);
+        let err = parse("# comment\nsingle 8 3 1\n").unwrap_err();
+        assert_eq!(err.to_string(), "line 2");
+        assert!(parse("single 8 x 1 bafy").is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,160 @@
+//! Summaries of the repetitions of an experiment point run with different
+//! seeds, since a single run of a random workload is mostly noise.
+//!
+//! Every numeric column becomes the mean over the repetitions, followed by
+//! `<column>_stddev`, `<column>_min` and `<column>_max`. Records are
+//! summarized flattened, so nested fields stay `parent.field` columns in
+//! JSON output too.
+
+use serde_json::Value;
+
+use crate::output::Fields;
+use crate::plot::Record;
+
+/// Columns that are parameters of the point rather than results, the same in
+/// every repetition, which are kept as they are.
+pub(crate) const PARAMETERS: &[&str] = &["n", "m", "bucket_size", "bit_width"];
+
+/// Summarizes the records of `runs` row by row. A row only some runs have
+/// is summarized over those, and columns that aren't a number in each of
+/// them are taken from the first.
+pub fn aggregate(runs: &[Vec<Record>]) -> Vec<Fields> {
+    let rows = runs.iter().map(Vec::len).max().unwrap_or(0);
+    (0..rows)
+        .map(|row| {
+            let records: Vec<&Record> = runs.iter().filter_map(|run| run.get(row)).collect();
+            let mut columns = Vec::new();
+            for (i, (name, first)) in records[0].iter().enumerate() {
+                let fields: Vec<&str> = records
+                    .iter()
+                    .filter_map(|record| record.get(i).map(|(_, field)| field.as_str()))
+                    .collect();
+                let numbers: Option<Vec<f64>> = fields
+                    .iter()
+                    .map(|field| field.parse().ok().filter(|x: &f64| x.is_finite()))
+                    .collect();
+                match numbers {
+                    Some(numbers) if !PARAMETERS.contains(&name.as_str()) => {
+                        let summary = Summary::of(&numbers);
+                        let (min, max) = summary.extremes(&fields);
+                        columns.push((name.clone(), summary.mean.into()));
+                        columns.push((format!("{name}_stddev"), summary.stddev.into()));
+                        columns.push((format!("{name}_min"), number(min)));
+                        columns.push((format!("{name}_max"), number(max)));
+                    }
+                    Some(_) => columns.push((name.clone(), number(first))),
+                    None if first.is_empty() => columns.push((name.clone(), Value::Null)),
+                    None => columns.push((name.clone(), Value::String(first.clone()))),
+                }
+            }
+            Fields(columns)
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Summary {
+    mean: f64,
+    /// Sample standard deviation, 0 for a single value.
+    stddev: f64,
+    min: usize,
+    max: usize,
+}
+
+impl Summary {
+    /// Of at least one value.
+    fn of(values: &[f64]) -> Self {
+        let n = values.len() as f64;
+        let mean = values.iter().sum::<f64>() / n;
+        let stddev = if values.len() > 1 {
+            let variance = values.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (n - 1.0);
+            variance.sqrt()
+        } else {
+            0.0
+        };
+        let (mut min, mut max) = (0, 0);
+        for (i, &x) in values.iter().enumerate() {
+            if x < values[min] {
+                min = i;
+            }
+            if x > values[max] {
+                max = i;
+            }
+        }
+        Summary {
+            mean,
+            stddev,
+            min,
+            max,
+        }
+    }
+
+    /// The smallest and largest of `fields`, as they were written.
+    fn extremes<'a>(&self, fields: &[&'a str]) -> (&'a str, &'a str) {
+        (fields[self.min], fields[self.max])
+    }
+}
+
+/// A field of a flattened record that parses as a number, as the integer it
+/// was if it was one.
+fn number(field: &str) -> Value {
+    if let Ok(x) = field.parse::<u64>() {
+        x.into()
+    } else if let Ok(x) = field.parse::<i64>() {
+        x.into()
+    } else {
+        field.parse().unwrap_or(f64::NAN).into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(fields: &[(&str, &str)]) -> Record {
+        fields
+            .iter()
+            .map(|&(name, field)| (name.to_string(), field.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn summarizes_numeric_columns() {
+        let run = |bytes: &str, ratio: &str| {
+            vec![record(&[
+                ("n", "10"),
+                ("hash", "sha256"),
+                ("bytes", bytes),
+                ("ratio", ratio),
+                ("median", ""),
+            ])]
+        };
+        let runs = [run("10", "0.5"), run("20", "1.5"), run("30", "1")];
+        let rows = aggregate(&runs);
+        assert_eq!(rows.len(), 1);
+        let row = &rows[0];
+        assert_eq!(row.get("n"), Some(&Value::from(10u64)));
+        assert_eq!(row.get("hash"), Some(&Value::String("sha256".to_string())));
+        assert_eq!(row.get("median"), Some(&Value::Null));
+        assert_eq!(row.get("bytes"), Some(&Value::from(20.0)));
+        assert_eq!(row.get("bytes_stddev"), Some(&Value::from(10.0)));
+        assert_eq!(row.get("bytes_min"), Some(&Value::from(10u64)));
+        assert_eq!(row.get("bytes_max"), Some(&Value::from(30u64)));
+        assert_eq!(row.get("ratio"), Some(&Value::from(1.0)));
+        assert_eq!(row.get("ratio_min"), Some(&Value::from(0.5)));
+        assert_eq!(row.get("ratio_max"), Some(&Value::from(1.5)));
+    }
+
+    #[test]
+    fn summarizes_rows_only_some_runs_have() {
+        let runs = [
+            vec![record(&[("bytes", "1")]), record(&[("bytes", "4")])],
+            vec![record(&[("bytes", "3")])],
+        ];
+        let rows = aggregate(&runs);
+        assert_eq!(rows[0].get("bytes"), Some(&Value::from(2.0)));
+        assert_eq!(rows[1].get("bytes"), Some(&Value::from(4.0)));
+        assert_eq!(rows[1].get("bytes_stddev"), Some(&Value::from(0.0)));
+        assert!(aggregate(&[]).is_empty());
+    }
+}
@@ -0,0 +1,212 @@
+//! Traces of the operations on Filecoin actor state HAMTs, captured from a
+//! node, which the `replay` experiment runs instead of a generated workload.
+//!
+//! A trace is newline delimited DAG-JSON, one operation per line:
+//!
+//! ```text
+//! {"op": "set", "hamt": "power/claims", "key": {"/": {"bytes": "AGQ"}}, "value": [0, 0]}
+//! {"op": "get", "hamt": "power/claims", "key": {"/": {"bytes": "AGQ"}}}
+//! {"op": "delete", "hamt": "power/claims", "key": {"/": {"bytes": "AGQ"}}}
+//! {"op": "flush"}
+//! ```
+//!
+//! `hamt` names the actor HAMT an operation applies to, all of them kept in
+//! the same store, and defaults to the empty name. A `flush` with a `hamt`
+//! flushes only that HAMT, one without flushes all of them, like the end of
+//! an epoch. Keys are bytes, or strings taken as their UTF-8 bytes, and are
+//! stored as [`Key::Bytes`] so they hash and encode like the `BytesKey`s of
+//! the actors. Empty lines and lines starting with `#` are skipped.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use cid::Cid;
+use fvm_ipld_blockstore::Blockstore;
+use fvm_ipld_hamt::dag_json;
+use fvm_ipld_hamt::{Hamt, Sha256};
+use libipld_core::ipld::Ipld;
+use serde::Deserialize;
+
+use crate::workload::Key;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Op {
+    Set(String, Key, Ipld),
+    Delete(String, Key),
+    Get(String, Key),
+    /// Flushes the named HAMT, or all of them.
+    Flush(Option<String>),
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Trace {
+    pub ops: Vec<Op>,
+}
+
+#[derive(Deserialize)]
+struct RawOp {
+    op: String,
+    hamt: Option<String>,
+    key: Option<Ipld>,
+    value: Option<Ipld>,
+}
+
+impl TryFrom<RawOp> for Op {
+    type Error = anyhow::Error;
+
+    fn try_from(raw: RawOp) -> Result<Self> {
+        let key = raw.key.map(key).transpose()?;
+        let hamt = raw.hamt;
+        Ok(match (raw.op.as_str(), key, raw.value) {
+            ("set", Some(key), Some(value)) => Op::Set(hamt.unwrap_or_default(), key, value),
+            ("delete", Some(key), _) => Op::Delete(hamt.unwrap_or_default(), key),
+            ("get", Some(key), _) => Op::Get(hamt.unwrap_or_default(), key),
+            ("flush", None, None) => Op::Flush(hamt),
+            ("set", Some(_), None) => bail!("`set` needs a value"),
+            ("set" | "delete" | "get", ..) => bail!("`{}` needs a key", raw.op),
+            ("flush", ..) => bail!("`flush` takes no key or value"),
+            (other, ..) => bail!("unknown operation `{other}`"),
+        })
+    }
+}
+
+fn key(key: Ipld) -> Result<Key> {
+    match key {
+        Ipld::String(key) => Ok(Key::Bytes(key.into_bytes())),
+        Ipld::Bytes(key) => Ok(Key::Bytes(key)),
+        other => bail!("keys are strings or bytes, not {other:?}"),
+    }
+}
+
+/// What replaying a trace left behind.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Replayed {
+    /// The last root of every HAMT, by name.
+    pub roots: BTreeMap<String, Cid>,
+    /// Flushes of single HAMTs, including the final one of every HAMT.
+    pub flushes: usize,
+    /// `get`s of keys that weren't set.
+    pub missing: usize,
+}
+
+impl Trace {
+    pub fn parse(text: &str) -> Result<Self> {
+        let ops = text
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty() && !line.starts_with('#'))
+            .map(|(i, line)| {
+                dag_json::from_slice::<RawOp>(line.as_bytes())
+                    .map_err(anyhow::Error::from)
+                    .and_then(Op::try_from)
+                    .with_context(|| format!("line {}", i + 1))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Trace { ops })
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        Trace::parse(&fs::read_to_string(path)?)
+            .with_context(|| format!("reading {}", path.display()))
+    }
+
+    /// Runs the operations against empty HAMTs in `store`, then flushes
+    /// every HAMT once more.
+    pub fn replay<BS: Blockstore, const BUCKET_SIZE: usize>(
+        &self,
+        store: &BS,
+        bit_width: u32,
+    ) -> Result<Replayed> {
+        let mut maps: BTreeMap<&str, Hamt<&BS, Ipld, Key, Sha256, BUCKET_SIZE>> = BTreeMap::new();
+        let empty = || Hamt::new_with_bit_width(store, bit_width);
+        let mut replayed = Replayed::default();
+        for (i, op) in self.ops.iter().enumerate() {
+            let context = || format!("op {}", i + 1);
+            match op {
+                Op::Set(name, key, value) => {
+                    let map = maps.entry(name.as_str()).or_insert_with(empty);
+                    map.set(key.clone(), value.clone()).with_context(context)?;
+                }
+                Op::Delete(name, key) => {
+                    let map = maps.entry(name.as_str()).or_insert_with(empty);
+                    map.delete(key).with_context(context)?;
+                }
+                Op::Get(name, key) => {
+                    let map = maps.entry(name.as_str()).or_insert_with(empty);
+                    if map.get(key).with_context(context)?.is_none() {
+                        replayed.missing += 1;
+                    }
+                }
+                Op::Flush(Some(name)) => {
+                    let map = maps.entry(name.as_str()).or_insert_with(empty);
+                    map.flush().with_context(context)?;
+                    replayed.flushes += 1;
+                }
+                Op::Flush(None) => {
+                    for map in maps.values_mut() {
+                        map.flush().with_context(context)?;
+                        replayed.flushes += 1;
+                    }
+                }
+            }
+        }
+        for (name, map) in &mut maps {
+            replayed.roots.insert(name.to_string(), map.flush()?);
+            replayed.flushes += 1;
+        }
+        Ok(replayed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memorydb::MemoryDB;
+    use fvm_ipld_hamt::BytesKey;
+
+    // `AGQ` and `AGU` are the ID addresses of the actors 100 and 101.
+    const TRACE: &str = r#"# two epochs
+{"op": "set", "hamt": "claims", "key": {"/": {"bytes": "AGQ"}}, "value": 1}
+{"op": "set", "hamt": "claims", "key": {"/": {"bytes": "AGU"}}, "value": 2}
+{"op": "set", "key": "a", "value": "x"}
+{"op": "flush"}
+
+{"op": "delete", "hamt": "claims", "key": {"/": {"bytes": "AGU"}}}
+{"op": "get", "hamt": "claims", "key": {"/": {"bytes": "AGU"}}}
+{"op": "flush", "hamt": "claims"}
+"#;
+
+    #[test]
+    fn replays_into_the_hamts_actors_build() -> Result<()> {
+        let trace = Trace::parse(TRACE)?;
+        assert_eq!(trace.ops.len(), 7);
+        assert_eq!(trace.ops[6], Op::Flush(Some("claims".to_string())));
+
+        let store = MemoryDB::default();
+        let replayed = trace.replay::<_, 3>(&store, 5)?;
+        assert_eq!(replayed.flushes, 2 + 1 + 2);
+        assert_eq!(replayed.missing, 1);
+
+        let mut claims: Hamt<_, u64, BytesKey, Sha256, 3> = Hamt::new_with_bit_width(&store, 5);
+        claims.set(BytesKey(vec![0, 100]), 1)?;
+        assert_eq!(replayed.roots["claims"], claims.flush()?);
+        let mut other: Hamt<_, String, BytesKey, Sha256, 3> = Hamt::new_with_bit_width(&store, 5);
+        other.set(BytesKey(b"a".to_vec()), "x".to_string())?;
+        assert_eq!(replayed.roots[""], other.flush()?);
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_malformed_lines() {
+        let err = |text: &str| format!("{:#}", Trace::parse(text).unwrap_err());
+        assert_eq!(
+            err("{\"op\": \"flush\"}\n{\"op\": \"set\", \"key\": \"a\"}"),
+            "line 2: `set` needs a value"
+        );
+        assert!(err(r#"{"op": "get", "key": 1}"#).contains("keys are strings or bytes"));
+        assert!(err(r#"{"op": "flush", "key": "a"}"#).contains("takes no key"));
+        assert!(err("not json").starts_with("line 1"));
+    }
+}
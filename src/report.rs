@@ -0,0 +1,349 @@
+//! Standalone HTML reports of a parameter study.
+//!
+//! A report lists the parameters of the run, then one section per experiment
+//! with its results as a table and charts of them, and optionally snapshots
+//! of a HAMT. Charts and snapshots are embedded, so the file can be shared
+//! on its own.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use anyhow::Result;
+
+use crate::plot::{Chart, Distribution, Record, BIT_WIDTH, BUCKET_SIZE};
+
+const STYLE: &str = "\
+body { font-family: Helvetica, Arial, sans-serif; margin: 2em auto; max-width: 80em; color: #222; }
+h1 { font-size: 1.6em; }
+h2 { font-size: 1.3em; margin-top: 2em; border-bottom: 1px solid #ddd; }
+table { border-collapse: collapse; font-size: 0.85em; margin: 1em 0; }
+th, td { border: 1px solid #ddd; padding: 0.2em 0.6em; text-align: right; }
+th { background: #f4f4f4; }
+.table { overflow-x: auto; }
+.charts { display: flex; flex-wrap: wrap; gap: 1em; }
+.charts svg { width: 30em; height: auto; }
+pre { background: #f8f8f8; padding: 1em; overflow: auto; max-height: 30em; font-size: 0.8em; }
+";
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Report {
+    title: String,
+    parameters: Vec<(String, String)>,
+    sections: Vec<Section>,
+}
+
+/// Results of one experiment.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Section {
+    pub title: String,
+    pub records: Vec<Record>,
+    /// SVG images.
+    pub charts: Vec<String>,
+    pub snapshots: Vec<Snapshot>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Snapshot {
+    /// Drawn as is.
+    Svg(String),
+    /// DOT or other source code, shown as text.
+    Source(String),
+}
+
+impl Report {
+    pub fn new(title: impl Into<String>) -> Self {
+        Report {
+            title: title.into(),
+            ..Report::default()
+        }
+    }
+
+    pub fn with_parameters<N: ToString, V: ToString>(
+        mut self,
+        parameters: impl IntoIterator<Item = (N, V)>,
+    ) -> Self {
+        self.parameters = parameters
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        self
+    }
+
+    pub fn add_section(&mut self, section: Section) {
+        self.sections.push(section);
+    }
+
+    /// Truncates or creates the file at `path`.
+    pub fn render_to_file(&self, path: &Path) -> Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        self.render(&mut out)?;
+        out.flush()?;
+        Ok(())
+    }
+
+    pub fn render(&self, out: &mut impl Write) -> Result<()> {
+        let title = escape(&self.title);
+        writeln!(out, "<!DOCTYPE html>")?;
+        writeln!(out, "<html>\n<head>\n<meta charset=\"utf-8\">")?;
+        writeln!(out, "<title>{title}</title>\n<style>\n{STYLE}</style>")?;
+        writeln!(out, "</head>\n<body>\n<h1>{title}</h1>")?;
+
+        if !self.parameters.is_empty() {
+            writeln!(out, "<h2>Parameters</h2>\n<table>")?;
+            for (name, value) in &self.parameters {
+                writeln!(
+                    out,
+                    "<tr><th>{}</th><td>{}</td></tr>",
+                    escape(name),
+                    escape(value)
+                )?;
+            }
+            writeln!(out, "</table>")?;
+        }
+        if self.sections.len() > 1 {
+            writeln!(out, "<ul>")?;
+            for (i, section) in self.sections.iter().enumerate() {
+                writeln!(
+                    out,
+                    "<li><a href=\"#section-{i}\">{}</a></li>",
+                    escape(&section.title)
+                )?;
+            }
+            writeln!(out, "</ul>")?;
+        }
+        for (i, section) in self.sections.iter().enumerate() {
+            writeln!(
+                out,
+                "<h2 id=\"section-{i}\">{}</h2>",
+                escape(&section.title)
+            )?;
+            section.render(out)?;
+        }
+
+        writeln!(out, "</body>\n</html>")?;
+        Ok(())
+    }
+}
+
+impl Section {
+    /// A section with `records` and a chart of each of their columns that
+    /// can be charted: heatmaps if the results sweep several bit widths,
+    /// line charts if they only sweep bucket sizes. Columns with the same
+    /// value in every record, like parameters, aren't charted.
+    pub fn new(title: impl Into<String>, records: Vec<Record>) -> Self {
+        let distinct = |name: &str| -> usize {
+            let mut fields: Vec<&str> = records
+                .iter()
+                .filter_map(|record| record.iter().find(|(column, _)| column == name))
+                .map(|(_, field)| field.as_str())
+                .collect();
+            fields.sort_unstable();
+            fields.dedup();
+            fields.len()
+        };
+        let chart: Option<fn(String) -> Chart> = match (distinct(BIT_WIDTH), distinct(BUCKET_SIZE))
+        {
+            (bit_widths, bucket_sizes) if bit_widths > 1 && bucket_sizes > 1 => {
+                Some(Chart::Heatmap)
+            }
+            (_, bucket_sizes) if bucket_sizes > 1 => Some(Chart::Line),
+            _ => None,
+        };
+
+        let mut charts = Vec::new();
+        if let (Some(chart), Some(first)) = (chart, records.first()) {
+            for (column, _) in first {
+                if column == BIT_WIDTH || column == BUCKET_SIZE || distinct(column) < 2 {
+                    continue;
+                }
+                // Columns that aren't numeric or have several results per
+                // combination are only listed in the table.
+                let mut svg = Vec::new();
+                if chart(column.clone()).render(&records, &mut svg).is_ok() {
+                    charts.push(String::from_utf8(svg).expect("SVG is UTF-8"));
+                }
+            }
+        }
+
+        Section {
+            title: title.into(),
+            records,
+            charts,
+            snapshots: Vec::new(),
+        }
+    }
+
+    /// Adds a chart of `distribution` for each combination of bit width and
+    /// bucket size in the records, for results with several records per
+    /// combination.
+    pub fn with_distribution(mut self, distribution: &Distribution) -> Result<Self> {
+        let mut groups: Vec<((&str, &str), Vec<Record>)> = Vec::new();
+        for record in &self.records {
+            let field = |name: &str| {
+                record
+                    .iter()
+                    .find(|(column, _)| column == name)
+                    .map_or("", |(_, field)| field.as_str())
+            };
+            let key = (field(BIT_WIDTH), field(BUCKET_SIZE));
+            match groups.iter_mut().find(|(k, _)| *k == key) {
+                Some((_, group)) => group.push(record.clone()),
+                None => groups.push((key, vec![record.clone()])),
+            }
+        }
+        let mut charts = Vec::new();
+        for ((bit_width, bucket_size), group) in &groups {
+            let heading = format!(
+                "{} by {}, {BIT_WIDTH} {bit_width}, {BUCKET_SIZE} {bucket_size}",
+                distribution.y, distribution.x
+            );
+            let mut svg = Vec::new();
+            distribution.render(group, &heading, &mut svg)?;
+            charts.push(String::from_utf8(svg).expect("SVG is UTF-8"));
+        }
+        self.charts.extend(charts);
+        Ok(self)
+    }
+
+    fn render(&self, out: &mut impl Write) -> Result<()> {
+        match self.records.first() {
+            None if self.snapshots.is_empty() => writeln!(out, "<p>No results.</p>")?,
+            None => {}
+            Some(first) => {
+                writeln!(out, "<div class=\"table\"><table>\n<tr>")?;
+                for (column, _) in first {
+                    writeln!(out, "<th>{}</th>", escape(column))?;
+                }
+                writeln!(out, "</tr>")?;
+                for record in &self.records {
+                    write!(out, "<tr>")?;
+                    for (_, field) in record {
+                        write!(out, "<td>{}</td>", escape(field))?;
+                    }
+                    writeln!(out, "</tr>")?;
+                }
+                writeln!(out, "</table></div>")?;
+            }
+        }
+        if !self.charts.is_empty() {
+            writeln!(out, "<div class=\"charts\">")?;
+            for chart in &self.charts {
+                writeln!(out, "{chart}")?;
+            }
+            writeln!(out, "</div>")?;
+        }
+        for snapshot in &self.snapshots {
+            match snapshot {
+                Snapshot::Svg(svg) => writeln!(out, "<div class=\"charts\">\n{svg}\n</div>")?,
+                Snapshot::Source(source) => writeln!(out, "<pre>{}</pre>", escape(source))?,
+            }
+        }
+        Ok(())
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_tables_charts_and_snapshots() -> Result<()> {
+        let mut report = Report::new("Study").with_parameters([("n", 10), ("m", 2)]);
+        report.add_section(Section {
+            title: "sizes".to_string(),
+            records: vec![
+                vec![
+                    ("n".to_string(), "10".to_string()),
+                    ("name".to_string(), "<a>".to_string()),
+                ],
+                vec![
+                    ("n".to_string(), "20".to_string()),
+                    ("name".to_string(), "b".to_string()),
+                ],
+            ],
+            charts: vec!["<svg id=\"chart\"></svg>".to_string()],
+            snapshots: vec![Snapshot::Source("digraph { a -> b }".to_string())],
+        });
+        report.add_section(Section {
+            title: "empty".to_string(),
+            ..Section::default()
+        });
+
+        let mut html = Vec::new();
+        report.render(&mut html)?;
+        let html = String::from_utf8(html)?;
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("<tr><th>m</th><td>2</td></tr>"));
+        assert_eq!(html.matches("<tr><td>").count(), 2);
+        assert!(html.contains("<td>&lt;a&gt;</td>"));
+        assert!(html.contains("<svg id=\"chart\"></svg>"));
+        assert!(html.contains("<pre>digraph { a -&gt; b }</pre>"));
+        assert!(html.contains("<a href=\"#section-1\">empty</a>"));
+        assert!(html.contains("No results."));
+        Ok(())
+    }
+
+    #[test]
+    fn charts_numeric_columns_of_sweeps() {
+        let record = |bit_width: u32, bucket_size: usize| {
+            vec![
+                ("n".to_string(), "10".to_string()),
+                ("bit_width".to_string(), bit_width.to_string()),
+                ("bucket_size".to_string(), bucket_size.to_string()),
+                (
+                    "bytes".to_string(),
+                    (bit_width as usize * bucket_size).to_string(),
+                ),
+                ("hash".to_string(), "sha256".to_string()),
+            ]
+        };
+
+        let sweep = Section::new(
+            "sweep",
+            vec![record(1, 1), record(1, 2), record(2, 1), record(2, 2)],
+        );
+        // Only the lines of line charts are drawn 2 pixels wide, not the axes.
+        let line_width = "stroke-width=\"2\" points=";
+        assert_eq!(sweep.charts.len(), 1);
+        assert!(!sweep.charts[0].contains(line_width));
+
+        let line = Section::new("line", vec![record(4, 1), record(4, 2)]);
+        assert_eq!(line.charts.len(), 1);
+        assert!(line.charts[0].contains(line_width));
+
+        assert!(Section::new("single", vec![record(4, 3)]).charts.is_empty());
+    }
+
+    #[test]
+    fn charts_a_distribution_per_combination() -> Result<()> {
+        let record = |bit_width: u32, depth: usize, set_bits: usize| {
+            vec![
+                ("bit_width".to_string(), bit_width.to_string()),
+                ("bucket_size".to_string(), "3".to_string()),
+                ("depth".to_string(), depth.to_string()),
+                ("set_bits".to_string(), set_bits.to_string()),
+                ("share".to_string(), "0.5".to_string()),
+            ]
+        };
+        let records = [1, 2]
+            .into_iter()
+            .flat_map(|b| [(0, 1), (0, 2), (1, 1), (1, 2)].map(|(d, s)| record(b, d, s)))
+            .collect();
+        let section = Section::new("occupancy", records)
+            .with_distribution(&Distribution::new("set_bits", "share", "depth"))?;
+        assert_eq!(section.charts.len(), 2);
+        assert!(section.charts[1].contains("bit_width 2, bucket_size 3"));
+        // A line per depth, and one in the legend for each.
+        let lines = section.charts[0].matches("stroke-width=\"2\" points=");
+        assert_eq!(lines.count(), 2 * 2);
+        Ok(())
+    }
+}
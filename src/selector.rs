@@ -0,0 +1,243 @@
+//! A small selector language in the spirit of GraphSync, picking the part
+//! of a HAMT a peer asks for, and a traversal extracting it into a store of
+//! its own.
+//!
+//! Selectors see the HAMT only as IPLD: nodes are `[bitfield, pointers]`,
+//! followed by the skipped slots of compressed nodes, pointers either links
+//! or buckets of `[key, value]` pairs. That's all a remote peer answering the
+//! request knows, too.
+
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail, Result};
+use cid::Cid;
+use fvm_ipld_blockstore::Blockstore;
+use fvm_ipld_encoding::{from_slice, DAG_CBOR};
+use libipld_core::ipld::Ipld;
+
+use crate::rng::Rng;
+
+/// Which blocks below a root to select.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Selector {
+    /// Every block at most this many links below the root, which is at
+    /// depth 0.
+    Depth(usize),
+    /// The blocks on the paths to entries whose key starts with this, integer
+    /// keys compared by their decimal digits. Hashing spreads such keys over
+    /// the whole HAMT, so every block has to be visited to find them.
+    KeyPrefix(String),
+    /// Every link followed with this probability.
+    Sample(f64),
+}
+
+/// Selectors the `selectors` experiment compares unless `--selector` is
+/// given.
+pub fn selectors() -> Vec<Selector> {
+    vec![
+        Selector::Depth(1),
+        Selector::Depth(2),
+        Selector::KeyPrefix("1".to_string()),
+        Selector::KeyPrefix("42".to_string()),
+        Selector::Sample(0.1),
+        Selector::Sample(0.01),
+    ]
+}
+
+/// Blocks and bytes a selection visited and selected.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Selection {
+    /// Blocks read to find the selected ones.
+    pub visited_blocks: u64,
+    pub visited_bytes: u64,
+    /// Blocks copied into the target store.
+    pub selected_blocks: u64,
+    pub selected_bytes: u64,
+    /// Entries in the selected blocks, or only the matching ones for
+    /// [`Selector::KeyPrefix`].
+    pub entries: u64,
+}
+
+/// Copies the blocks below `root` that `selector` selects from `store` into
+/// `target`. `rng` decides which links a [`Selector::Sample`] follows.
+pub fn select<S: Blockstore, T: Blockstore>(
+    store: &S,
+    root: &Cid,
+    selector: &Selector,
+    rng: &mut Rng,
+    target: &T,
+) -> Result<Selection> {
+    let mut selection = Selection::default();
+    visit(store, root, 0, selector, rng, target, &mut selection)?;
+    Ok(selection)
+}
+
+/// Visits the block `cid` at `depth` and returns whether it was selected.
+fn visit<S: Blockstore, T: Blockstore>(
+    store: &S,
+    cid: &Cid,
+    depth: usize,
+    selector: &Selector,
+    rng: &mut Rng,
+    target: &T,
+    selection: &mut Selection,
+) -> Result<bool> {
+    let block = store
+        .get(cid)?
+        .ok_or_else(|| anyhow!("block {cid} not found"))?;
+    selection.visited_blocks += 1;
+    selection.visited_bytes += block.len() as u64;
+    let ipld: Ipld = if cid.codec() == DAG_CBOR {
+        from_slice(&block)?
+    } else {
+        Ipld::Null
+    };
+    let mut links = Vec::new();
+    ipld.references(&mut links);
+
+    let keys = bucket_keys(&ipld);
+    let entries = match selector {
+        Selector::KeyPrefix(prefix) => keys.iter().filter(|key| key_matches(key, prefix)).count(),
+        _ => keys.len(),
+    };
+    // Only prefix selections leave out blocks they visit.
+    let mut selected = !matches!(selector, Selector::KeyPrefix(_)) || entries > 0;
+    for link in &links {
+        let follow = match selector {
+            Selector::Depth(max_depth) => depth < *max_depth,
+            Selector::KeyPrefix(_) => true,
+            Selector::Sample(rate) => rng.next_f64() < *rate,
+        };
+        if follow {
+            selected |= visit(store, link, depth + 1, selector, rng, target, selection)?;
+        }
+    }
+
+    if selected {
+        target.put_keyed(cid, &block)?;
+        selection.selected_blocks += 1;
+        selection.selected_bytes += block.len() as u64;
+        selection.entries += entries as u64;
+    }
+    Ok(selected)
+}
+
+/// Keys in the buckets of a HAMT node, none for anything else.
+fn bucket_keys(node: &Ipld) -> Vec<&Ipld> {
+    let pointers = match node {
+        Ipld::List(fields) => match fields.as_slice() {
+            [Ipld::Bytes(_), Ipld::List(pointers), ..] => pointers,
+            _ => return Vec::new(),
+        },
+        _ => return Vec::new(),
+    };
+    pointers
+        .iter()
+        .filter_map(|pointer| match pointer {
+            Ipld::List(bucket) => Some(bucket),
+            _ => None,
+        })
+        .flatten()
+        .filter_map(|entry| match entry {
+            Ipld::List(pair) if pair.len() == 2 => Some(&pair[0]),
+            _ => None,
+        })
+        .collect()
+}
+
+fn key_matches(key: &Ipld, prefix: &str) -> bool {
+    match key {
+        Ipld::String(key) => key.starts_with(prefix),
+        Ipld::Bytes(key) => key.starts_with(prefix.as_bytes()),
+        Ipld::Integer(key) => key.to_string().starts_with(prefix),
+        _ => false,
+    }
+}
+
+impl fmt::Display for Selector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Selector::Depth(depth) => write!(f, "depth:{depth}"),
+            Selector::KeyPrefix(prefix) => write!(f, "prefix:{prefix}"),
+            Selector::Sample(rate) => write!(f, "sample:{rate}"),
+        }
+    }
+}
+
+impl FromStr for Selector {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let selector = match s.split_once(':') {
+            Some(("depth", depth)) => Selector::Depth(
+                depth
+                    .parse()
+                    .map_err(|_| anyhow!("invalid depth `{depth}`"))?,
+            ),
+            Some(("prefix", prefix)) => Selector::KeyPrefix(prefix.to_string()),
+            Some(("sample", rate)) => match rate.parse() {
+                Ok(rate) if (0.0..=1.0).contains(&rate) => Selector::Sample(rate),
+                _ => bail!("invalid sample rate `{rate}`, expected a fraction from 0 to 1"),
+            },
+            _ => bail!(
+                "unknown selector `{s}`, expected `depth:<d>`, `prefix:<key>` or `sample:<rate>`"
+            ),
+        };
+        Ok(selector)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memorydb::MemoryDB;
+    use fvm_ipld_hamt::{Hamt, Sha256};
+
+    #[test]
+    fn parses_and_prints_selectors() {
+        for selector in selectors() {
+            assert_eq!(selector.to_string().parse::<Selector>().unwrap(), selector);
+        }
+        assert!("depth:-1".parse::<Selector>().is_err());
+        assert!("sample:2".parse::<Selector>().is_err());
+        assert!("everything".parse::<Selector>().is_err());
+    }
+
+    #[test]
+    fn selects_subgraphs() -> Result<()> {
+        let store = MemoryDB::default();
+        let mut map: Hamt<_, String, usize, Sha256, 3> = Hamt::new_with_bit_width(&store, 4);
+        for key in 0..1000 {
+            map.set(key, "F".to_string())?;
+        }
+        let root = map.flush()?;
+        let total_bytes = store.live_bytes(&[root])?;
+        let mut rng = Rng::new(1);
+
+        let target = MemoryDB::default();
+        let root_only = select(&store, &root, &Selector::Depth(0), &mut rng, &target)?;
+        assert_eq!(root_only.selected_blocks, 1);
+        assert_eq!(target.bytes_stored(), root_only.selected_bytes);
+
+        let target = MemoryDB::default();
+        let everything = Selector::Depth(usize::MAX);
+        let all = select(&store, &root, &everything, &mut rng, &target)?;
+        assert_eq!(all.entries, 1000);
+        assert_eq!(all.selected_bytes, total_bytes);
+        assert_eq!(target.live_bytes(&[root])?, total_bytes);
+
+        // 1, 10..=19 and 100..=199.
+        let target = MemoryDB::default();
+        let selector = Selector::KeyPrefix("1".to_string());
+        let prefixed = select(&store, &root, &selector, &mut rng, &target)?;
+        assert_eq!(prefixed.entries, 111);
+        assert_eq!(prefixed.visited_bytes, total_bytes);
+        assert!(prefixed.selected_bytes < total_bytes);
+
+        let target = MemoryDB::default();
+        let none = select(&store, &root, &Selector::Sample(0.0), &mut rng, &target)?;
+        assert_eq!(none, root_only);
+        Ok(())
+    }
+}
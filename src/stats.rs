@@ -0,0 +1,907 @@
+//! Distributions over the structure of a HAMT.
+
+use std::fmt;
+use std::ops::{AddAssign, Range};
+
+use anyhow::{bail, Result};
+use fvm_ipld_blockstore::Blockstore;
+use fvm_ipld_encoding::{de::DeserializeOwned, serde_bytes, to_vec};
+use fvm_ipld_hamt::{
+    node::Node, pointer::Pointer, Hamt, HamtView, Hash, HashAlgorithm, KeyValuePair,
+};
+use rayon::prelude::*;
+use serde::Serialize;
+
+use crate::traverse::{resolved, visit_node, Control, NodeVisitor, Resolved, Visit, Walk};
+
+/// Counts of small non-negative integers, like depths or degrees.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+#[serde(transparent)]
+pub struct Histogram {
+    /// `counts[v]` is how often `v` was added.
+    counts: Vec<u64>,
+}
+
+impl Histogram {
+    pub fn add(&mut self, value: usize) {
+        if self.counts.len() <= value {
+            self.counts.resize(value + 1, 0);
+        }
+        self.counts[value] += 1;
+    }
+
+    pub fn merge(&mut self, other: &Histogram) {
+        if self.counts.len() < other.counts.len() {
+            self.counts.resize(other.counts.len(), 0);
+        }
+        for (count, other) in self.counts.iter_mut().zip(&other.counts) {
+            *count += other;
+        }
+    }
+
+    pub fn counts(&self) -> &[u64] {
+        &self.counts
+    }
+
+    /// Number of values added.
+    pub fn len(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn min(&self) -> Option<usize> {
+        self.counts.iter().position(|&count| count > 0)
+    }
+
+    pub fn max(&self) -> Option<usize> {
+        self.counts.iter().rposition(|&count| count > 0)
+    }
+
+    /// Sum of all values.
+    pub fn sum(&self) -> u64 {
+        self.counts
+            .iter()
+            .enumerate()
+            .map(|(value, &count)| value as u64 * count)
+            .sum()
+    }
+
+    /// Mean of all values, `NaN` if empty.
+    pub fn mean(&self) -> f64 {
+        self.sum() as f64 / self.len() as f64
+    }
+
+    /// Smallest value such that at least `p` percent of all values are less
+    /// than or equal to it (nearest rank), `None` if empty.
+    pub fn percentile(&self, p: f64) -> Option<usize> {
+        assert!((0.0..=100.0).contains(&p), "percentile out of range");
+        let rank = ((p / 100.0 * self.len() as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (value, &count) in self.counts.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return Some(value);
+            }
+        }
+        None
+    }
+
+    pub fn median(&self) -> Option<usize> {
+        self.percentile(50.0)
+    }
+}
+
+/// Counts of block sizes in power of two buckets, `[2^k, 2^(k+1))`. Empty
+/// blocks are counted in the first bucket.
+///
+/// `Display` draws a horizontal bar chart, [`rows`](Self::rows) yields one
+/// record per bucket for a [`ResultsWriter`](crate::output::ResultsWriter).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BlockSizeHistogram {
+    counts: Histogram,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BlockSizeRow {
+    pub min_bytes: usize,
+    /// Exclusive.
+    pub max_bytes: usize,
+    pub blocks: u64,
+}
+
+impl BlockSizeHistogram {
+    pub fn add(&mut self, size: usize) {
+        self.counts.add(size.max(1).ilog2() as usize);
+    }
+
+    pub fn merge(&mut self, other: &BlockSizeHistogram) {
+        self.counts.merge(&other.counts);
+    }
+
+    /// Number of blocks added.
+    pub fn len(&self) -> u64 {
+        self.counts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.counts.is_empty()
+    }
+
+    /// Size range and block count of every bucket from the smallest to the
+    /// largest non-empty one.
+    pub fn buckets(&self) -> impl Iterator<Item = (Range<usize>, u64)> + '_ {
+        let first = self.counts.min().unwrap_or(0);
+        self.counts.counts()[first..]
+            .iter()
+            .enumerate()
+            .map(move |(i, &count)| {
+                let k = first + i;
+                let start = if k == 0 { 0 } else { 1 << k };
+                (start..1 << (k + 1), count)
+            })
+    }
+
+    pub fn rows(&self) -> impl Iterator<Item = BlockSizeRow> + '_ {
+        self.buckets().map(|(range, blocks)| BlockSizeRow {
+            min_bytes: range.start,
+            max_bytes: range.end,
+            blocks,
+        })
+    }
+}
+
+impl fmt::Display for BlockSizeHistogram {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const WIDTH: u64 = 40;
+
+        let buckets: Vec<_> = self.buckets().collect();
+        let most = buckets.iter().map(|(_, count)| *count).max().unwrap_or(0);
+        let labels: Vec<String> = buckets
+            .iter()
+            .map(|(range, _)| format!("{}..{}", range.start, range.end))
+            .collect();
+        let label_width = labels.iter().map(String::len).max().unwrap_or(0);
+
+        for ((_, count), label) in buckets.iter().zip(labels) {
+            let bar = (count * WIDTH).div_ceil(most) as usize;
+            writeln!(
+                f,
+                "{label:>label_width$} |{:<width$} {count}",
+                "#".repeat(bar),
+                width = WIDTH as usize
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Shape of a HAMT: node, link and value counts for the whole tree and for
+/// each of its levels.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct TreeStats {
+    pub nodes: u64,
+    pub links: u64,
+    pub values: u64,
+    /// Number of child links of every node, nodes without children count as 0.
+    pub degrees: Histogram,
+    /// Indexed by depth, starting at the root.
+    pub levels: Vec<LevelStats>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct LevelStats {
+    pub nodes: u64,
+    pub links: u64,
+    pub values: u64,
+}
+
+impl TreeStats {
+    /// Stats of every node of a [`Hamt`] or [`HamtView`].
+    pub fn new<K, V, H, const BUCKET_SIZE: usize>(
+        hamt: &impl Visit<K, V, H, BUCKET_SIZE>,
+    ) -> Result<Self> {
+        let mut stats = TreeStats::default();
+        hamt.visit(&mut stats)?;
+        Ok(stats)
+    }
+
+    fn add_node(&mut self, depth: usize, node: &LevelStats) {
+        self.nodes += node.nodes;
+        self.links += node.links;
+        self.values += node.values;
+        self.degrees.add(node.links as usize);
+        if self.levels.len() <= depth {
+            self.levels.resize(depth + 1, LevelStats::default());
+        }
+        self.levels[depth] += node;
+    }
+
+    pub fn links_per_node(&self) -> f64 {
+        self.links as f64 / self.nodes as f64
+    }
+
+    pub fn values_per_node(&self) -> f64 {
+        self.values as f64 / self.nodes as f64
+    }
+
+    pub fn min_degree(&self) -> Option<usize> {
+        self.degrees.min()
+    }
+
+    pub fn max_degree(&self) -> Option<usize> {
+        self.degrees.max()
+    }
+
+    pub fn degree_percentile(&self, p: f64) -> Option<usize> {
+        self.degrees.percentile(p)
+    }
+}
+
+/// [`TreeStats::new`], collecting the subtrees below the root on the rayon
+/// pool and merging them. Nodes that weren't loaded yet are loaded by the
+/// threads walking their subtree, and stay cached in the view.
+pub fn stats_parallel<S, K, V, H, const BUCKET_SIZE: usize>(
+    hamt: &HamtView<S, V, K, H, BUCKET_SIZE>,
+) -> Result<TreeStats>
+where
+    K: Hash + Eq + PartialOrd + Serialize + DeserializeOwned + Send + Sync,
+    V: Serialize + DeserializeOwned + Send + Sync,
+    H: HashAlgorithm + Send + Sync,
+    S: Blockstore + Sync,
+{
+    let store = hamt.store();
+    let mut stats = TreeStats::default();
+    stats.add_node(0, &LevelStats::of(hamt.root()));
+    let subtrees = hamt
+        .root()
+        .pointers
+        .par_iter()
+        .map(|pointer| -> Result<TreeStats> {
+            let mut stats = TreeStats::default();
+            if let Resolved::Link(child) = resolved(pointer, store)? {
+                visit_node(child, store, 1, &mut stats)?;
+            }
+            Ok(stats)
+        })
+        .try_reduce(TreeStats::default, |mut left, right| {
+            left += &right;
+            Ok(left)
+        })?;
+    stats += &subtrees;
+    Ok(stats)
+}
+
+/// Adds up the stats of two trees, level by level.
+impl AddAssign<&TreeStats> for TreeStats {
+    fn add_assign(&mut self, rhs: &TreeStats) {
+        self.nodes += rhs.nodes;
+        self.links += rhs.links;
+        self.values += rhs.values;
+        self.degrees.merge(&rhs.degrees);
+        if self.levels.len() < rhs.levels.len() {
+            self.levels.resize(rhs.levels.len(), LevelStats::default());
+        }
+        for (level, rhs) in self.levels.iter_mut().zip(&rhs.levels) {
+            *level += rhs;
+        }
+    }
+}
+
+impl<K, V, H, const BUCKET_SIZE: usize> NodeVisitor<K, V, H, BUCKET_SIZE> for TreeStats {
+    fn enter_node(&mut self, depth: usize, node: &Node<K, V, H, BUCKET_SIZE>) -> Result<Control> {
+        self.add_node(depth, &LevelStats::of(node));
+        Ok(Control::Descend)
+    }
+}
+
+impl LevelStats {
+    /// Counts for a single node.
+    fn of<K, V, H, const BUCKET_SIZE: usize>(node: &Node<K, V, H, BUCKET_SIZE>) -> Self {
+        let mut stats = LevelStats {
+            nodes: 1,
+            ..LevelStats::default()
+        };
+        for pointer in node.pointers.iter() {
+            match pointer {
+                Pointer::Values(values) => stats.values += values.len() as u64,
+                Pointer::Link { .. } | Pointer::Dirty(_) => stats.links += 1,
+            }
+        }
+        stats
+    }
+}
+
+impl AddAssign<&LevelStats> for LevelStats {
+    fn add_assign(&mut self, rhs: &LevelStats) {
+        self.nodes += rhs.nodes;
+        self.links += rhs.links;
+        self.values += rhs.values;
+    }
+}
+
+/// Depth of the node holding each key, where the root is at depth 0. Looking
+/// up a key at depth `d` loads `d + 1` nodes.
+pub fn key_depths<S, K, V, H, const BUCKET_SIZE: usize>(
+    hamt: &Hamt<S, V, K, H, BUCKET_SIZE>,
+) -> Result<Histogram>
+where
+    K: Hash + Eq + PartialOrd + Serialize + DeserializeOwned,
+    V: Serialize + DeserializeOwned,
+    H: HashAlgorithm,
+    S: Blockstore,
+{
+    struct KeyDepths(Histogram);
+
+    impl<K, V, H, const BUCKET_SIZE: usize> NodeVisitor<K, V, H, BUCKET_SIZE> for KeyDepths {
+        fn bucket(&mut self, depth: usize, bucket: &[KeyValuePair<K, V>]) -> Result<()> {
+            for _ in bucket {
+                self.0.add(depth);
+            }
+            Ok(())
+        }
+    }
+
+    let mut depths = KeyDepths(Histogram::default());
+    hamt.visit(&mut depths)?;
+    Ok(depths.0)
+}
+
+/// Number of entries in each bucket. Only buckets of colliding keys at the
+/// deepest level hold more than `BUCKET_SIZE`.
+pub fn bucket_sizes<S, K, V, H, const BUCKET_SIZE: usize>(
+    hamt: &Hamt<S, V, K, H, BUCKET_SIZE>,
+) -> Result<Histogram>
+where
+    K: Hash + Eq + PartialOrd + Serialize + DeserializeOwned,
+    V: Serialize + DeserializeOwned,
+    H: HashAlgorithm,
+    S: Blockstore,
+{
+    struct BucketSizes(Histogram);
+
+    impl<K, V, H, const BUCKET_SIZE: usize> NodeVisitor<K, V, H, BUCKET_SIZE> for BucketSizes {
+        fn bucket(&mut self, _: usize, bucket: &[KeyValuePair<K, V>]) -> Result<()> {
+            self.0.add(bucket.len());
+            Ok(())
+        }
+    }
+
+    let mut sizes = BucketSizes(Histogram::default());
+    hamt.visit(&mut sizes)?;
+    Ok(sizes.0)
+}
+
+/// A total estimated from a sample, with a 95% confidence interval.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct Estimate {
+    pub value: f64,
+    pub low: f64,
+    pub high: f64,
+}
+
+impl Estimate {
+    /// `exact` plus the total over `population` units, of which `sample` are
+    /// a random sample, using the normal approximation. With fewer than two
+    /// units sampled out of more, the interval is unbounded above.
+    fn from_sample(exact: f64, population: u64, sample: &[f64]) -> Self {
+        let (n, big_n) = (sample.len() as f64, population as f64);
+        let mean = if sample.is_empty() {
+            0.0
+        } else {
+            sample.iter().sum::<f64>() / n
+        };
+        let value = exact + big_n * mean;
+        let margin = if sample.len() as u64 >= population {
+            0.0
+        } else if sample.len() < 2 {
+            f64::INFINITY
+        } else {
+            let variance = sample.iter().map(|y| (y - mean).powi(2)).sum::<f64>() / (n - 1.0);
+            1.96 * big_n * ((1.0 - n / big_n) * variance / n).sqrt()
+        };
+        Estimate {
+            value,
+            low: (value - margin).max(exact + sample.iter().sum::<f64>()),
+            high: value + margin,
+        }
+    }
+
+    pub fn contains(&self, value: f64) -> bool {
+        (self.low..=self.high).contains(&value)
+    }
+}
+
+/// Totals of [`TreeStats`] estimated from a [`Walk`] that only visits a
+/// sample of the subtrees below its sampling depth. Without sampling, the
+/// estimates are the exact totals of the nodes walked.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct SampledStats {
+    pub nodes: Estimate,
+    pub links: Estimate,
+    pub values: Estimate,
+    /// Nodes the walk loaded or found cached.
+    pub visited_nodes: u64,
+    /// Subtrees below the sampling depth, and how many of them were walked.
+    pub subtrees: u64,
+    pub sampled_subtrees: u64,
+}
+
+pub fn sampled_stats<S, K, V, H, const BUCKET_SIZE: usize>(
+    hamt: &Hamt<S, V, K, H, BUCKET_SIZE>,
+    walk: &Walk,
+) -> Result<SampledStats>
+where
+    K: Hash + Eq + PartialOrd + Serialize + DeserializeOwned,
+    V: Serialize + DeserializeOwned,
+    H: HashAlgorithm,
+    S: Blockstore,
+{
+    /// Counts of the nodes down to the sampling depth, and of each sampled
+    /// subtree.
+    struct Subtrees {
+        depth: usize,
+        exact: LevelStats,
+        links: u64,
+        sampled: Vec<LevelStats>,
+    }
+
+    impl<K, V, H, const BUCKET_SIZE: usize> NodeVisitor<K, V, H, BUCKET_SIZE> for Subtrees {
+        fn enter_node(
+            &mut self,
+            depth: usize,
+            node: &Node<K, V, H, BUCKET_SIZE>,
+        ) -> Result<Control> {
+            let stats = LevelStats::of(node);
+            if depth <= self.depth {
+                if depth == self.depth {
+                    self.links += stats.links;
+                }
+                self.exact += &stats;
+            } else {
+                if depth == self.depth + 1 {
+                    self.sampled.push(LevelStats::default());
+                }
+                *self
+                    .sampled
+                    .last_mut()
+                    .expect("subtrees start below the sampling depth") += &stats;
+            }
+            Ok(Control::Descend)
+        }
+    }
+
+    let mut subtrees = Subtrees {
+        depth: walk.sample.map_or(usize::MAX, |sampling| sampling.depth),
+        exact: LevelStats::default(),
+        links: 0,
+        sampled: Vec::new(),
+    };
+    hamt.visit_with(walk, &mut subtrees)?;
+    // Links at the deepest level walked lead to subtrees nobody visits.
+    if walk
+        .max_depth
+        .is_some_and(|max_depth| max_depth <= subtrees.depth)
+    {
+        subtrees.links = 0;
+    }
+
+    let estimate = |count: fn(&LevelStats) -> u64| {
+        let sample: Vec<f64> = subtrees.sampled.iter().map(|s| count(s) as f64).collect();
+        Estimate::from_sample(count(&subtrees.exact) as f64, subtrees.links, &sample)
+    };
+    Ok(SampledStats {
+        nodes: estimate(|stats| stats.nodes),
+        links: estimate(|stats| stats.links),
+        values: estimate(|stats| stats.values),
+        visited_nodes: subtrees.exact.nodes + subtrees.sampled.iter().map(|s| s.nodes).sum::<u64>(),
+        subtrees: subtrees.links,
+        sampled_subtrees: subtrees.sampled.len() as u64,
+    })
+}
+
+/// Node count, serialized size, values and fanout of every level of a
+/// flushed HAMT, starting at the root.
+pub fn stats_per_level<S, K, V, H, const BUCKET_SIZE: usize>(
+    hamt: &Hamt<S, V, K, H, BUCKET_SIZE>,
+) -> Result<Vec<LevelSummary>>
+where
+    K: Hash + Eq + PartialOrd + Serialize + DeserializeOwned,
+    V: Serialize + DeserializeOwned,
+    H: HashAlgorithm,
+    S: Blockstore,
+{
+    /// Stats and serialized size of each level.
+    struct Levels(Vec<(LevelStats, u64)>);
+
+    impl<K, V, H, const BUCKET_SIZE: usize> NodeVisitor<K, V, H, BUCKET_SIZE> for Levels
+    where
+        K: Serialize,
+        V: Serialize,
+    {
+        fn enter_node(
+            &mut self,
+            depth: usize,
+            node: &Node<K, V, H, BUCKET_SIZE>,
+        ) -> Result<Control> {
+            let block = match to_vec(node) {
+                Ok(block) => block,
+                Err(_) => {
+                    bail!("the HAMT has to be flushed before its node sizes can be measured")
+                }
+            };
+            if self.0.len() <= depth {
+                self.0.resize(depth + 1, Default::default());
+            }
+            let (stats, bytes) = &mut self.0[depth];
+            *stats += &LevelStats::of(node);
+            *bytes += block.len() as u64;
+            Ok(Control::Descend)
+        }
+    }
+
+    let mut levels = Levels(Vec::new());
+    hamt.visit(&mut levels)?;
+    Ok(levels
+        .0
+        .into_iter()
+        .enumerate()
+        .map(|(depth, (stats, bytes))| LevelSummary {
+            depth,
+            nodes: stats.nodes,
+            bytes,
+            values: stats.values,
+            avg_fanout: stats.links as f64 / stats.nodes as f64,
+        })
+        .collect())
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct LevelSummary {
+    pub depth: usize,
+    pub nodes: u64,
+    /// Serialized size of all nodes on this level.
+    pub bytes: u64,
+    pub values: u64,
+    /// Child links per node.
+    pub avg_fanout: f64,
+}
+
+/// Bytes the bitfield of each node takes in its block, with the CBOR header,
+/// in the encoding it's written in.
+pub fn bitfield_sizes<S, K, V, H, const BUCKET_SIZE: usize>(
+    hamt: &Hamt<S, V, K, H, BUCKET_SIZE>,
+) -> Result<Histogram>
+where
+    K: Hash + Eq + PartialOrd + Serialize + DeserializeOwned,
+    V: Serialize + DeserializeOwned,
+    H: HashAlgorithm,
+    S: Blockstore,
+{
+    struct BitfieldSizes(Histogram);
+
+    impl<K, V, H, const BUCKET_SIZE: usize> NodeVisitor<K, V, H, BUCKET_SIZE> for BitfieldSizes {
+        fn enter_node(&mut self, _: usize, node: &Node<K, V, H, BUCKET_SIZE>) -> Result<Control> {
+            let bytes = match node.compact_bitfield {
+                true => node.bitfield.to_compact_bytes(),
+                false => node.bitfield.to_bytes(),
+            };
+            self.0.add(to_vec(&serde_bytes::Bytes::new(&bytes))?.len());
+            Ok(Control::Descend)
+        }
+    }
+
+    let mut sizes = BitfieldSizes(Histogram::default());
+    hamt.visit(&mut sizes)?;
+    Ok(sizes.0)
+}
+
+/// Nodes of one level by the number of bits set in their bitfield, which is
+/// their number of pointers and so most of what their size depends on.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct Occupancy {
+    pub set_bits: Histogram,
+    /// Serialized size of the nodes, summed by their number of set bits.
+    pub bytes: Vec<u64>,
+}
+
+impl Occupancy {
+    /// Mean serialized size of the nodes with `set_bits` bits set.
+    pub fn avg_bytes(&self, set_bits: usize) -> Option<f64> {
+        let nodes = *self.set_bits.counts().get(set_bits)?;
+        (nodes > 0).then(|| self.bytes[set_bits] as f64 / nodes as f64)
+    }
+}
+
+/// [`Occupancy`] of every level of a flushed HAMT, starting at the root.
+pub fn occupancy_per_level<S, K, V, H, const BUCKET_SIZE: usize>(
+    hamt: &Hamt<S, V, K, H, BUCKET_SIZE>,
+) -> Result<Vec<Occupancy>>
+where
+    K: Hash + Eq + PartialOrd + Serialize + DeserializeOwned,
+    V: Serialize + DeserializeOwned,
+    H: HashAlgorithm,
+    S: Blockstore,
+{
+    struct Levels(Vec<Occupancy>);
+
+    impl<K, V, H, const BUCKET_SIZE: usize> NodeVisitor<K, V, H, BUCKET_SIZE> for Levels
+    where
+        K: Serialize,
+        V: Serialize,
+    {
+        fn enter_node(
+            &mut self,
+            depth: usize,
+            node: &Node<K, V, H, BUCKET_SIZE>,
+        ) -> Result<Control> {
+            let block = match to_vec(node) {
+                Ok(block) => block,
+                Err(_) => {
+                    bail!("the HAMT has to be flushed before its node sizes can be measured")
+                }
+            };
+            if self.0.len() <= depth {
+                self.0.resize(depth + 1, Occupancy::default());
+            }
+            let level = &mut self.0[depth];
+            let set_bits = node.bitfield.count_ones();
+            level.set_bits.add(set_bits);
+            if level.bytes.len() <= set_bits {
+                level.bytes.resize(set_bits + 1, 0);
+            }
+            level.bytes[set_bits] += block.len() as u64;
+            Ok(Control::Descend)
+        }
+    }
+
+    let mut levels = Levels(Vec::new());
+    hamt.visit(&mut levels)?;
+    Ok(levels.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memorydb::MemoryDB;
+    use fvm_ipld_hamt::Sha256;
+
+    #[test]
+    fn percentiles_use_nearest_rank() {
+        let mut histogram = Histogram::default();
+        assert_eq!(histogram.median(), None);
+        for value in [3, 1, 2, 2, 5, 2, 1, 4, 2, 3] {
+            histogram.add(value);
+        }
+        assert_eq!(histogram.counts(), &[0, 2, 4, 2, 1, 1]);
+        assert_eq!(histogram.len(), 10);
+        assert_eq!(histogram.mean(), 2.5);
+        assert_eq!(histogram.percentile(0.0), Some(1));
+        assert_eq!(histogram.median(), Some(2));
+        assert_eq!(histogram.percentile(80.0), Some(3));
+        assert_eq!(histogram.percentile(99.0), Some(5));
+        assert_eq!((histogram.min(), histogram.max()), (Some(1), Some(5)));
+
+        let mut merged = Histogram::default();
+        merged.add(7);
+        merged.merge(&histogram);
+        assert_eq!(merged.len(), 11);
+        assert_eq!(merged.max(), Some(7));
+    }
+
+    #[test]
+    fn buckets_block_sizes_by_powers_of_two() {
+        let mut histogram = BlockSizeHistogram::default();
+        for size in [0, 1, 5, 6, 7, 8, 100, 127] {
+            histogram.add(size);
+        }
+        let buckets: Vec<_> = histogram.buckets().collect();
+        assert_eq!(
+            buckets,
+            vec![
+                (0..2, 2),
+                (2..4, 0),
+                (4..8, 3),
+                (8..16, 1),
+                (16..32, 0),
+                (32..64, 0),
+                (64..128, 2)
+            ]
+        );
+
+        let text = histogram.to_string();
+        assert_eq!(text.lines().count(), buckets.len());
+        assert!(text.lines().nth(2).unwrap().starts_with("   4..8 |"));
+        assert!(text
+            .lines()
+            .nth(2)
+            .unwrap()
+            .ends_with(&format!("{} 3", "#".repeat(40))));
+    }
+
+    #[test]
+    fn tree_stats_add_up() -> anyhow::Result<()> {
+        let store = MemoryDB::default();
+        let mut map: Hamt<_, String, usize, Sha256, 1> = Hamt::new_with_bit_width(&store, 3);
+        for key in 0..2000 {
+            map.set(key, "F".to_string())?;
+        }
+
+        let stats = TreeStats::new(&map)?;
+        assert_eq!(stats.values, 2000);
+        // Every node but the root is linked exactly once.
+        assert_eq!(stats.links, stats.nodes - 1);
+        assert_eq!(stats.degrees.len(), stats.nodes);
+        assert_eq!(stats.levels[0].nodes, 1);
+        assert_eq!(stats.min_degree(), Some(0));
+        assert!(stats.max_degree() <= Some(8));
+        let levels = stats
+            .levels
+            .iter()
+            .fold(LevelStats::default(), |mut sum, level| {
+                sum += level;
+                sum
+            });
+        assert_eq!(
+            (levels.nodes, levels.links, levels.values),
+            (stats.nodes, stats.links, stats.values)
+        );
+
+        let mut twice = stats.clone();
+        twice += &stats;
+        assert_eq!(twice.links, 2 * stats.links);
+        assert_eq!(twice.levels[1].nodes, 2 * stats.levels[1].nodes);
+        assert_eq!(twice.degree_percentile(50.0), stats.degree_percentile(50.0));
+        Ok(())
+    }
+
+    #[test]
+    fn parallel_stats_match_sequential_ones() -> anyhow::Result<()> {
+        let store = MemoryDB::default();
+        let mut map: Hamt<_, String, usize, Sha256, 3> = Hamt::new_with_bit_width(&store, 4);
+        for key in 0..5000 {
+            map.set(key, "F".to_string())?;
+        }
+        let root = map.flush()?;
+        let expected = TreeStats::new(&map)?;
+
+        let map: HamtView<_, String, usize, Sha256, 3> =
+            HamtView::load_with_bit_width(&root, &store, 4)?;
+        assert_eq!(stats_parallel(&map)?, expected);
+        assert_eq!(TreeStats::new(&map)?, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn level_sizes_add_up_to_the_store() -> anyhow::Result<()> {
+        let store = MemoryDB::default();
+        let mut map: Hamt<_, String, usize, Sha256, 3> = Hamt::new_with_bit_width(&store, 4);
+        for key in 0..5000 {
+            map.set(key, "F".to_string())?;
+        }
+        assert!(stats_per_level(&map).is_err());
+        map.flush()?;
+
+        let levels = stats_per_level(&map)?;
+        assert_eq!(levels[0].nodes, 1);
+        assert_eq!(
+            levels.iter().map(|l| l.bytes).sum::<u64>(),
+            store.bytes_stored()
+        );
+        assert_eq!(levels.iter().map(|l| l.values).sum::<u64>(), 5000);
+        assert_eq!(levels.last().unwrap().avg_fanout, 0.0);
+        Ok(())
+    }
+
+    #[test]
+    fn every_key_has_a_depth() -> anyhow::Result<()> {
+        let store = MemoryDB::default();
+        let mut map: Hamt<_, String, usize, Sha256, 3> = Hamt::new_with_bit_width(&store, 4);
+        for key in 0..10_000 {
+            map.set(key, "F".to_string())?;
+        }
+        let root = map.flush()?;
+        // Reload so every node has to come from the store.
+        let map: Hamt<_, String, usize, Sha256, 3> = Hamt::load_with_bit_width(&root, &store, 4)?;
+
+        let depths = key_depths(&map)?;
+        assert_eq!(depths.len(), 10_000);
+        // Two levels of 16 slots hold at most 16 * 16 * 3 keys.
+        assert!(depths.max() >= Some(2));
+        Ok(())
+    }
+
+    #[test]
+    fn sampled_stats_bracket_the_exact_ones() -> anyhow::Result<()> {
+        use crate::traverse::Sampling;
+
+        let store = MemoryDB::default();
+        let mut map: Hamt<_, String, usize, Sha256, 3> = Hamt::new_with_bit_width(&store, 4);
+        for key in 0..20_000 {
+            map.set(key, "F".to_string())?;
+        }
+        map.flush()?;
+        let exact = TreeStats::new(&map)?;
+
+        let full = sampled_stats(&map, &Walk::default())?;
+        assert_eq!(full.nodes.value, exact.nodes as f64);
+        assert_eq!((full.values.low, full.values.high), (20_000.0, 20_000.0));
+
+        let walk = Walk {
+            sample: Some(Sampling {
+                rate: 0.25,
+                depth: 1,
+            }),
+            seed: 42,
+            ..Walk::default()
+        };
+        let sampled = sampled_stats(&map, &walk)?;
+        assert_eq!(sampled.subtrees, exact.levels[2].nodes);
+        assert!(sampled.sampled_subtrees < sampled.subtrees);
+        assert!(sampled.visited_nodes < exact.nodes);
+        assert!(sampled.nodes.contains(exact.nodes as f64));
+        assert!(sampled.values.contains(20_000.0));
+        assert!(sampled.values.low < sampled.values.high);
+
+        let shallow = Walk {
+            max_depth: Some(1),
+            ..walk
+        };
+        let shallow = sampled_stats(&map, &shallow)?;
+        let top = exact.levels[0].nodes + exact.levels[1].nodes;
+        assert_eq!(shallow.visited_nodes, top);
+        assert_eq!(shallow.nodes.high, top as f64);
+        Ok(())
+    }
+
+    #[test]
+    fn compact_bitfields_are_smaller_for_wide_nodes() -> anyhow::Result<()> {
+        let store = MemoryDB::default();
+        let build = |compact: bool| -> anyhow::Result<_> {
+            let mut map: Hamt<_, String, usize, Sha256, 3> = Hamt::new_with_bit_width(&store, 8);
+            map.compact_bitfields = compact;
+            for key in 0..1000 {
+                map.set(key, "F".to_string())?;
+            }
+            map.flush()?;
+            bitfield_sizes(&map)
+        };
+        let (dense, compact) = (build(false)?, build(true)?);
+        assert_eq!(dense.len(), compact.len());
+        // A wide root has most of its 256 bits set, a header and 32 bytes.
+        assert_eq!(dense.max(), Some(34));
+        assert!(compact.mean() < dense.mean());
+        Ok(())
+    }
+
+    #[test]
+    fn occupancy_counts_every_node_by_its_pointers() -> anyhow::Result<()> {
+        let store = MemoryDB::default();
+        let mut map: Hamt<_, String, usize, Sha256, 3> = Hamt::new_with_bit_width(&store, 4);
+        for key in 0..5000 {
+            map.set(key, "F".to_string())?;
+        }
+        assert!(occupancy_per_level(&map).is_err());
+        map.flush()?;
+
+        let stats = TreeStats::new(&map)?;
+        let levels = occupancy_per_level(&map)?;
+        assert_eq!(levels.len(), stats.levels.len());
+        assert_eq!(levels[0].set_bits.counts().len(), 17);
+        for (occupancy, level) in levels.iter().zip(&stats.levels) {
+            assert_eq!(occupancy.set_bits.len(), level.nodes);
+            let pointers: u64 = (occupancy.set_bits.mean() * level.nodes as f64).round() as u64;
+            // Every pointer is either a link or a bucket of up to 3 values.
+            assert!(pointers >= level.links + level.values.div_ceil(3));
+        }
+        assert_eq!(
+            levels.iter().flat_map(|level| &level.bytes).sum::<u64>(),
+            store.bytes_stored()
+        );
+        assert!(levels[0].avg_bytes(16).is_some());
+        assert_eq!(levels[0].avg_bytes(1), None);
+        Ok(())
+    }
+}
@@ -0,0 +1,238 @@
+//! Studies: the matrix of experiments to run described in a TOML file, so the
+//! runs behind a set of results can be repeated from the file alone.
+//!
+//! Every table in `runs` is one or more experiment runs, with the options of
+//! the command line as keys. An array runs the experiment once per value, and
+//! several arrays once per combination of their values. `{<option>}` in a
+//! string is replaced by the value of that option in the run, so each
+//! combination can write its own results. `defaults` holds options every run
+//! shares unless it sets them itself:
+//!
+//! ```toml
+//! [defaults]
+//! seed = 7845
+//! format = "ndjson"
+//! quiet = true
+//!
+//! [[runs]]
+//! experiment = ["sizes", "amt", "champ"]
+//! bit-width = "1..=8"
+//! bucket-size = [1, 3, 8]
+//! workload = ["sequential", "uniform"]
+//! n = [1000, 100000]
+//! output = "results/{experiment}-{workload}-{n}-{bucket-size}.ndjson"
+//! ```
+//!
+//! `true` passes a switch like `--quiet`, `false` leaves it out. Paths are
+//! relative to the working directory.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde::Deserialize;
+use toml::value::{Table, Value};
+
+/// A single run of an experiment, as its command line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Run {
+    pub args: Vec<String>,
+}
+
+impl fmt::Display for Run {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.args.join(" "))
+    }
+}
+
+#[derive(Deserialize)]
+struct Manifest {
+    #[serde(default)]
+    defaults: Table,
+    runs: Vec<Table>,
+}
+
+/// The runs of a study, in the order of its tables and, within a table, with
+/// the options sorted by name varying slowest.
+pub fn parse(text: &str) -> Result<Vec<Run>> {
+    let manifest: Manifest = toml::from_str(text)?;
+    let mut runs = Vec::new();
+    for (i, table) in manifest.runs.into_iter().enumerate() {
+        let mut options = manifest.defaults.clone();
+        options.extend(table);
+        let expanded = expand(&options).with_context(|| format!("run {}", i + 1))?;
+        runs.extend(expanded);
+    }
+
+    let mut outputs = HashMap::new();
+    for run in &runs {
+        let output = run
+            .args
+            .windows(2)
+            .find(|pair| pair[0] == "--output")
+            .map(|pair| &pair[1]);
+        if let Some(output) = output {
+            if let Some(other) = outputs.insert(output, run) {
+                bail!("`{other}` and `{run}` both write to `{output}`, add a `{{<option>}}` to it");
+            }
+        }
+    }
+    Ok(runs)
+}
+
+/// The combinations of the values of `options`.
+fn expand(options: &Table) -> Result<Vec<Run>> {
+    let mut combinations = vec![Vec::new()];
+    for (name, value) in options {
+        let values = match value {
+            Value::Array(values) if values.is_empty() => bail!("`{name}` has no values"),
+            Value::Array(values) => values.iter().map(|value| arg(name, value)).collect(),
+            value => vec![arg(name, value)],
+        };
+        let values = values.into_iter().collect::<Result<Vec<_>>>()?;
+        combinations = combinations
+            .into_iter()
+            .flat_map(|combination: Vec<(&String, Arg)>| {
+                values.iter().map(move |value| {
+                    let mut combination = combination.clone();
+                    combination.push((name, value.clone()));
+                    combination
+                })
+            })
+            .collect();
+    }
+
+    combinations
+        .into_iter()
+        .map(|combination| {
+            let lookup: HashMap<&str, &str> = combination
+                .iter()
+                .map(|(name, value)| (name.as_str(), value.as_str()))
+                .collect();
+            let experiment = lookup
+                .get("experiment")
+                .ok_or_else(|| anyhow!("missing `experiment`"))?;
+            let mut args = vec!["experiment".to_string(), experiment.to_string()];
+            for (name, value) in &combination {
+                match value {
+                    _ if *name == "experiment" => {}
+                    Arg::Value(value) => {
+                        args.push(format!("--{name}"));
+                        args.push(substitute(value, &lookup)?);
+                    }
+                    Arg::Switch(true) => args.push(format!("--{name}")),
+                    Arg::Switch(false) => {}
+                }
+            }
+            Ok(Run { args })
+        })
+        .collect()
+}
+
+/// The value of an option as it's given on the command line.
+#[derive(Debug, Clone)]
+enum Arg {
+    Value(String),
+    /// Whether a switch is passed.
+    Switch(bool),
+}
+
+impl Arg {
+    fn as_str(&self) -> &str {
+        match self {
+            Arg::Value(value) => value,
+            Arg::Switch(true) => "true",
+            Arg::Switch(false) => "false",
+        }
+    }
+}
+
+fn arg(name: &str, value: &Value) -> Result<Arg> {
+    Ok(match value {
+        Value::String(s) => Arg::Value(s.clone()),
+        Value::Integer(i) => Arg::Value(i.to_string()),
+        Value::Float(x) => Arg::Value(x.to_string()),
+        Value::Boolean(on) => Arg::Switch(*on),
+        _ => bail!("`{name}` has to be a string, number, boolean or array of those"),
+    })
+}
+
+/// Replaces every `{<option>}` in `value` by the value of the option.
+fn substitute(value: &str, options: &HashMap<&str, &str>) -> Result<String> {
+    let mut out = String::new();
+    let mut rest = value;
+    while let Some(start) = rest.find('{') {
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| anyhow!("unclosed `{{` in `{value}`"))?;
+        let name = &rest[start + 1..start + end];
+        let replacement = options
+            .get(name)
+            .ok_or_else(|| anyhow!("`{{{name}}}` in `{value}` isn't an option of the run"))?;
+        out.push_str(&rest[..start]);
+        out.push_str(replacement);
+        rest = &rest[start + end + 1..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(runs: &[Run]) -> Vec<String> {
+        runs.iter().map(Run::to_string).collect()
+    }
+
+    #[test]
+    fn expands_every_combination() -> Result<()> {
+        let runs = parse(
+            r#"
+            [defaults]
+            n = 10
+            quiet = true
+
+            [[runs]]
+            experiment = ["sizes", "amt"]
+            bucket-size = [1, 3]
+            output = "{experiment}-{bucket-size}.csv"
+
+            [[runs]]
+            experiment = "proof"
+            n = 20
+            quiet = false
+            "#,
+        )?;
+        assert_eq!(
+            lines(&runs),
+            [
+                "experiment sizes --bucket-size 1 --n 10 --output sizes-1.csv --quiet",
+                "experiment amt --bucket-size 1 --n 10 --output amt-1.csv --quiet",
+                "experiment sizes --bucket-size 3 --n 10 --output sizes-3.csv --quiet",
+                "experiment amt --bucket-size 3 --n 10 --output amt-3.csv --quiet",
+                "experiment proof --n 20",
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_runs_writing_to_the_same_file() {
+        let study = |run| parse(&format!("[[runs]]\nexperiment = \"sizes\"\n{run}"));
+        assert!(study("n = [1, 2]\noutput = \"a.csv\"").is_err());
+        assert!(study("output = \"{m}.csv\"").is_err());
+        assert!(study("n = [[1]]").is_err());
+        assert!(parse("[[runs]]\nn = 1").is_err());
+    }
+
+    #[test]
+    fn parses_the_bundled_study() -> Result<()> {
+        let runs = parse(include_str!("../studies/structures.toml"))?;
+        assert_eq!(runs.len(), 4 * 3 * 2 + 3);
+        for run in &runs {
+            crate::cli::parse(run.args.clone())?;
+        }
+        Ok(())
+    }
+}
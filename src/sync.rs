@@ -0,0 +1,99 @@
+//! Blocks to transfer to bring a peer from one version of a DAG to another.
+//!
+//! Blocks are addressed by their content, so a block the peer already has
+//! comes with everything below it, and walking the new version can stop
+//! there.
+
+use std::collections::HashSet;
+
+use anyhow::{anyhow, Result};
+use cid::Cid;
+use fvm_ipld_blockstore::Blockstore;
+use fvm_ipld_encoding::{from_slice, DAG_CBOR};
+use libipld_core::ipld::Ipld;
+
+/// Blocks reachable from `to` but not from `from`, parents first: what a
+/// peer holding `from` has to fetch to get `to`. Only DAG-CBOR blocks are
+/// followed.
+pub fn sync_delta<S: Blockstore>(store: &S, from: &Cid, to: &Cid) -> Result<Vec<Cid>> {
+    let mut have = HashSet::new();
+    walk(store, from, &mut have, |_, _| {})?;
+
+    let mut delta = Vec::new();
+    walk(store, to, &mut have, |cid, _| delta.push(*cid))?;
+    Ok(delta)
+}
+
+/// Number of blocks and bytes of the [`sync_delta`] from `from` to `to`.
+pub fn delta_size<S: Blockstore>(store: &S, from: &Cid, to: &Cid) -> Result<(u64, u64)> {
+    let mut have = HashSet::new();
+    walk(store, from, &mut have, |_, _| {})?;
+
+    let (mut blocks, mut bytes) = (0, 0);
+    walk(store, to, &mut have, |_, block| {
+        blocks += 1;
+        bytes += block.len() as u64;
+    })?;
+    Ok((blocks, bytes))
+}
+
+/// Calls `f` with every block reachable from `root` that's not in `seen`
+/// yet, parents first, and adds them to `seen`.
+pub(crate) fn walk<S: Blockstore>(
+    store: &S,
+    root: &Cid,
+    seen: &mut HashSet<Cid>,
+    mut f: impl FnMut(&Cid, &[u8]),
+) -> Result<()> {
+    let mut stack = vec![*root];
+    while let Some(cid) = stack.pop() {
+        if !seen.insert(cid) {
+            continue;
+        }
+        let block = store
+            .get(&cid)?
+            .ok_or_else(|| anyhow!("block {cid} not found"))?;
+        f(&cid, &block);
+        if cid.codec() == DAG_CBOR {
+            let ipld: Ipld = from_slice(&block)?;
+            let mut links = Vec::new();
+            ipld.references(&mut links);
+            stack.extend(links.into_iter().rev());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memorydb::MemoryDB;
+    use fvm_ipld_hamt::{Hamt, Sha256};
+
+    #[test]
+    fn delta_is_what_the_new_version_adds() -> Result<()> {
+        let store = MemoryDB::default();
+        let mut map: Hamt<_, String, usize, Sha256, 3> = Hamt::new_with_bit_width(&store, 4);
+        for key in 0..1000 {
+            map.set(key, "old".to_string())?;
+        }
+        let old = map.flush()?;
+        for key in 0..5 {
+            map.set(key, "new".to_string())?;
+        }
+        let new = map.flush()?;
+
+        let delta = sync_delta(&store, &old, &new)?;
+        assert_eq!(delta[0], new);
+        assert!(!delta.contains(&old));
+        let bytes: u64 = delta
+            .iter()
+            .map(|cid| store.get(cid).unwrap().unwrap().len() as u64)
+            .sum();
+        assert_eq!(delta_size(&store, &old, &new)?, (delta.len() as u64, bytes));
+        assert_eq!(bytes, store.delta_bytes(&[old], &[new])?);
+
+        assert!(sync_delta(&store, &new, &new)?.is_empty());
+        Ok(())
+    }
+}
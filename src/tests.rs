@@ -1,24 +1,50 @@
+//! Property tests of the HAMT and the structures it's compared against.
+//!
+//! The HAMT, [`FlatMap`], [`ProllyTree`], [`Champ`] and [`RadixTrie`] are
+//! history independent: the same entries give the same root, whatever the
+//! operations leading to them. The [`BTree`] isn't.
+//!
+//! The HAMT is also checked against a [`BTreeMap`] as a model of a map, and
+//! has to keep its [invariants](crate::invariants) along the way.
+
 use anyhow::Result;
-use fvm_ipld_hamt::Hash;
+use std::collections::BTreeMap;
 use std::fmt::Debug;
 
+use crate::btree::BTree;
+use crate::champ::Champ;
+use crate::flat::FlatMap;
+use crate::invariants::verify_invariants;
+use crate::map::IpldMap;
 use crate::memorydb::MemoryDB;
+use crate::prolly::ProllyTree;
+use crate::radix::RadixTrie;
+use cid::Cid;
 use fvm_ipld_hamt::Hamt;
 use fvm_ipld_hamt::Sha256;
+use fvm_ipld_hamt::{BytesKey, HashOnly};
 use proptest::collection::*;
 use proptest::prelude::*;
 use proptest::strategy::Shuffleable;
-use serde::de::DeserializeOwned;
-use serde::Serialize;
 use test_strategy::proptest;
 
 #[derive(Debug, Clone)]
 enum Operation<K, V> {
     Insert(K, V),
+    /// Insert unless the key has a value already.
+    InsertIfAbsent(K, V),
     Remove(K),
 }
 
 impl<K, V> Operation<K, V> {
+    fn key(&self) -> &K {
+        match self {
+            Operation::Insert(key, _)
+            | Operation::InsertIfAbsent(key, _)
+            | Operation::Remove(key) => key,
+        }
+    }
+
     pub fn can_be_swapped_with(&self, other: &Operation<K, V>) -> bool
     where
         K: PartialEq,
@@ -47,6 +73,15 @@ impl<K, V> Operation<K, V> {
                 // Removes can always be swapped
                 true
             }
+            (Operation::InsertIfAbsent(key_a, val_a), Operation::InsertIfAbsent(key_b, val_b)) => {
+                // Like inserts, except that the first one wins.
+                key_a != key_b || val_a == val_b
+            }
+            (a, b) => {
+                // Whether an insert if absent does anything depends on the
+                // operations on the same key before it.
+                a.key() != b.key()
+            }
         }
     }
 }
@@ -102,28 +137,42 @@ impl<K: PartialEq, V: PartialEq> Shuffleable for Operations<K, V> {
     }
 }
 
-fn node_from_operations<K, V>(
+/// Applies `operations` to `map` and flushes it.
+fn root_after_operations<K, V>(
+    mut map: impl IpldMap<K, V>,
     operations: Operations<K, V>,
-    store: &MemoryDB,
-) -> Result<Hamt<&MemoryDB, V, K, Sha256, 3>>
-where
-    K: Hash + Eq + PartialOrd + Serialize + DeserializeOwned,
-    V: Serialize + DeserializeOwned + Eq,
-{
-    let mut map: Hamt<&MemoryDB, V, K, Sha256, 3> = Hamt::new_with_bit_width(&store, 4);
-
+) -> Result<Cid> {
     for op in operations.0 {
         match op {
             Operation::Insert(key, value) => {
                 map.set(key, value)?;
             }
+            Operation::InsertIfAbsent(key, value) => {
+                map.set_if_absent(key, value)?;
+            }
             Operation::Remove(key) => {
                 map.delete(&key)?;
             }
         };
     }
 
-    Ok(map)
+    map.flush()
+}
+
+/// Asserts that maps created by `new` have the same root after both
+/// sequences of operations.
+fn assert_history_independent<K, V, M: IpldMap<K, V>>(
+    (original, shuffled): (Operations<K, V>, Operations<K, V>),
+    new: impl Fn() -> M,
+) {
+    let cid1 = root_after_operations(new(), original).unwrap();
+    let cid2 = root_after_operations(new(), shuffled).unwrap();
+
+    assert_eq!(cid1, cid2);
+}
+
+fn hamt(store: &MemoryDB) -> Hamt<&MemoryDB, u64, String, Sha256, 3> {
+    Hamt::new_with_bit_width(store, 4)
 }
 
 fn small_key() -> impl Strategy<Value = String> {
@@ -134,13 +183,13 @@ fn operation<K: Debug, V: Debug>(
     key: impl Strategy<Value = K>,
     value: impl Strategy<Value = V>,
 ) -> impl Strategy<Value = Operation<K, V>> {
-    (any::<bool>(), key, value).prop_map(|(is_insert, key, value)| {
-        if is_insert {
-            Operation::Insert(key, value)
-        } else {
-            Operation::Remove(key)
-        }
-    })
+    (any::<bool>(), any::<bool>(), key, value).prop_map(
+        |(is_insert, if_absent, key, value)| match (is_insert, if_absent) {
+            (true, false) => Operation::Insert(key, value),
+            (true, true) => Operation::InsertIfAbsent(key, value),
+            (false, _) => Operation::Remove(key),
+        },
+    )
 }
 
 fn operations<K: Debug, V: Debug>(
@@ -167,15 +216,265 @@ fn node_operations_are_history_independent(
         Operations<String, u64>,
     ),
 ) {
+    let store = MemoryDB::default();
+    assert_history_independent(pair, || hamt(&store));
+}
+
+#[proptest(cases = 256)]
+fn flat_map_is_history_independent(
+    #[strategy(operations_and_shuffled(small_key(), 0u64..1000, 0..1000))] pair: (
+        Operations<String, u64>,
+        Operations<String, u64>,
+    ),
+) {
+    let store = MemoryDB::default();
+    assert_history_independent(pair, || FlatMap::new(&store));
+}
+
+#[proptest(cases = 256)]
+fn prolly_tree_is_history_independent(
+    #[strategy(operations_and_shuffled(small_key(), 0u64..1000, 0..1000))] pair: (
+        Operations<String, u64>,
+        Operations<String, u64>,
+    ),
+) {
+    let store = MemoryDB::default();
+    assert_history_independent(pair, || ProllyTree::new_with_fanout(&store, 16));
+}
+
+#[proptest(cases = 256)]
+fn champ_is_history_independent(
+    #[strategy(operations_and_shuffled(small_key(), 0u64..1000, 0..1000))] pair: (
+        Operations<String, u64>,
+        Operations<String, u64>,
+    ),
+) {
+    let store = MemoryDB::default();
+    assert_history_independent(pair, || {
+        Champ::<_, _, _, Sha256>::new_with_bit_width(&store, 4)
+    });
+}
+
+#[proptest(cases = 256)]
+fn radix_trie_is_history_independent(
+    #[strategy(operations_and_shuffled(small_key().prop_map(String::into_bytes), 0u64..1000, 0..1000))]
+    pair: (Operations<Vec<u8>, u64>, Operations<Vec<u8>, u64>),
+) {
+    let store = MemoryDB::default();
+    assert_history_independent(pair, || RadixTrie::new_with_bit_width(&store, 4));
+}
+
+/// Applies `operations` to a HAMT and to a [`BTreeMap`] as the model. Both
+/// have to return the same previous values and end up with the same
+/// contents, before and after flushing and loading the HAMT again, and the
+/// HAMT has to keep its invariants. Measuring the loaded HAMT in parallel
+/// has to see the same blocks as measuring it sequentially.
+#[proptest(cases = 256)]
+fn hamt_behaves_like_a_btree_map(
+    #[strategy(operations(small_key(), 0u64..1000, 0..1000))] operations: Operations<String, u64>,
+    #[strategy(1u32..=8)] bit_width: u32,
+) {
+    let store = MemoryDB::default();
+    let mut map: Hamt<&MemoryDB, u64, String, Sha256, 3> =
+        Hamt::new_with_bit_width(&store, bit_width);
+    let mut model = BTreeMap::new();
+
+    for op in operations.0 {
+        apply_to_both(&mut map, &mut model, op);
+    }
+
+    assert_matches_model(&map, &model);
+    verify_invariants(&map).unwrap();
+    let cid = map.flush().unwrap();
+    assert_matches_model(&map, &model);
+    verify_invariants(&map).unwrap();
+    let loaded = Hamt::load_with_bit_width(&cid, &store, bit_width).unwrap();
+    assert_eq!(
+        loaded.par_reachable_size().unwrap(),
+        map.reachable_size().unwrap()
+    );
+    assert_matches_model(&loaded, &model);
+    verify_invariants(&loaded).unwrap();
+}
+
+/// With path compression the root has to be the same whether the HAMT was
+/// flushed in between, merging nodes that later operations split or collapse
+/// again, or only at the end. Small bit widths make chains of nodes with a
+/// single child common.
+#[proptest(cases = 256)]
+fn compressed_hamt_is_history_independent(
+    #[strategy(operations_and_shuffled(small_key(), 0u64..1000, 0..1000))] pair: (
+        Operations<String, u64>,
+        Operations<String, u64>,
+    ),
+    #[strategy(1u32..=3)] bit_width: u32,
+    #[strategy(1usize..100)] flush_every: usize,
+) {
+    let store = MemoryDB::default();
+    let new = || {
+        Hamt::<_, u64, String, Sha256, 3>::new_with_bit_width(&store, bit_width)
+            .with_path_compression()
+    };
     let (original, shuffled) = pair;
+    let mut map = new();
+    let mut model = BTreeMap::new();
 
-    let store = &mut MemoryDB::default();
+    for (i, op) in original.0.into_iter().enumerate() {
+        apply_to_both(&mut map, &mut model, op);
+        if i % flush_every == 0 {
+            map.flush().unwrap();
+            verify_invariants(&map).unwrap();
+        }
+    }
+
+    let cid = map.flush().unwrap();
+    assert_eq!(root_after_operations(new(), shuffled).unwrap(), cid);
+    let loaded = Hamt::load_with_bit_width(&cid, &store, bit_width)
+        .unwrap()
+        .with_path_compression();
+    assert_matches_model(&loaded, &model);
+    verify_invariants(&loaded).unwrap();
+}
 
-    let mut node1 = node_from_operations(original, store).unwrap();
-    let mut node2 = node_from_operations(shuffled, store).unwrap();
+/// A compressed root skipping the levels all keys share has no parent to
+/// collapse into, so deletes have to undo the skip for the bucket left over.
+#[test]
+fn compressed_root_collapses_after_deletes() -> Result<()> {
+    let store = MemoryDB::default();
+    let new = || {
+        Hamt::<_, u64, BytesKey, HashOnly<Sha256, 32>, 3>::new_with_bit_width(&store, 4)
+            .with_path_compression()
+    };
+    // Every key starts with the same byte, so the root skips two levels.
+    let keys: Vec<BytesKey> = (0..5u8)
+        .map(|i| BytesKey([0xab, i << 4].into_iter().chain([0; 30]).collect()))
+        .collect();
 
-    let cid1 = node1.flush().unwrap();
-    let cid2 = node2.flush().unwrap();
+    let mut map = new();
+    for (i, key) in keys.iter().enumerate() {
+        map.set(key.clone(), i as u64)?;
+    }
+    map.flush()?;
+    assert_eq!(map.root.skip, vec![0xa, 0xb]);
+    verify_invariants(&map)?;
 
-    assert_eq!(cid1, cid2);
+    map.delete(&keys[0])?;
+    map.delete(&keys[1])?;
+    let cid = map.flush()?;
+    assert!(map.root.skip.is_empty());
+    verify_invariants(&map)?;
+
+    let mut fresh = new();
+    for (i, key) in keys.iter().enumerate().skip(2) {
+        fresh.set(key.clone(), i as u64)?;
+    }
+    assert_eq!(fresh.flush()?, cid);
+    Ok(())
+}
+
+/// With a maximum depth, buckets at the bottom take any number of entries.
+/// The HAMT still has to behave like a map and keep its invariants, and the
+/// root has to be independent of the order of operations, and of whether the
+/// entries were inserted one by one or in a batch.
+#[proptest(cases = 256)]
+fn capped_hamt_is_history_independent(
+    #[strategy(operations_and_shuffled(small_key(), 0u64..1000, 0..1000))] pair: (
+        Operations<String, u64>,
+        Operations<String, u64>,
+    ),
+    #[strategy(1u32..=3)] bit_width: u32,
+    #[strategy(0u32..=2)] max_depth: u32,
+) {
+    let store = MemoryDB::default();
+    let new = || {
+        Hamt::<_, u64, String, Sha256, 3>::new_with_bit_width(&store, bit_width)
+            .with_max_depth(max_depth)
+    };
+    let (original, shuffled) = pair;
+    let mut map = new();
+    let mut model = BTreeMap::new();
+
+    for op in original.0 {
+        apply_to_both(&mut map, &mut model, op);
+    }
+
+    assert_matches_model(&map, &model);
+    verify_invariants(&map).unwrap();
+    let cid = map.flush().unwrap();
+    assert_eq!(root_after_operations(new(), shuffled).unwrap(), cid);
+    let mut batch = new();
+    batch.set_many(model.clone()).unwrap();
+    assert_eq!(batch.flush().unwrap(), cid);
+    let loaded = Hamt::load_with_bit_width(&cid, &store, bit_width)
+        .unwrap()
+        .with_max_depth(max_depth);
+    assert_matches_model(&loaded, &model);
+    verify_invariants(&loaded).unwrap();
+}
+
+/// Applies `op` to the HAMT and the model, which have to return the same.
+fn apply_to_both(
+    map: &mut Hamt<&MemoryDB, u64, String, Sha256, 3>,
+    model: &mut BTreeMap<String, u64>,
+    op: Operation<String, u64>,
+) {
+    match op {
+        Operation::Insert(key, value) => {
+            let old = map.set(key.clone(), value).unwrap();
+            assert_eq!(old, model.insert(key, value));
+        }
+        Operation::InsertIfAbsent(key, value) => {
+            let set = map.set_if_absent(key.clone(), value).unwrap();
+            assert_eq!(set, !model.contains_key(&key));
+            model.entry(key).or_insert(value);
+        }
+        Operation::Remove(key) => {
+            let old = map.delete(&key).unwrap().map(|(_, value)| value);
+            assert_eq!(old, model.remove(&key));
+        }
+    }
+}
+
+/// Compares lookups of every key [`small_key`] generates, the iterated
+/// entries and their count.
+fn assert_matches_model(
+    map: &Hamt<&MemoryDB, u64, String, Sha256, 3>,
+    model: &BTreeMap<String, u64>,
+) {
+    for i in 0..1000 {
+        let key = format!("key {i}");
+        assert_eq!(map.get(&key).unwrap(), model.get(&key), "{key}");
+    }
+    let mut entries: Vec<(String, u64)> = map
+        .iter()
+        .map(|entry| entry.map(|(key, value)| (key.clone(), *value)).unwrap())
+        .collect();
+    assert_eq!(entries.len(), model.len());
+    entries.sort();
+    let expected: Vec<(String, u64)> = model.clone().into_iter().collect();
+    assert_eq!(entries, expected);
+    assert_eq!(map.len().unwrap(), model.len() as u64);
+}
+
+/// Unlike the other maps, the nodes of a B-tree depend on the order of
+/// inserts: which keys end up together depends on when nodes were split.
+#[test]
+fn btree_is_not_history_independent() {
+    let store = MemoryDB::default();
+    let inserts = |keys: [&str; 4]| {
+        Operations(
+            keys.iter()
+                .map(|key| Operation::Insert(key.to_string(), 0u64))
+                .collect(),
+        )
+    };
+    let ascending = root_after_operations(
+        BTree::new_with_fanout(&store, 2),
+        inserts(["a", "b", "c", "d"]),
+    );
+    let descending = root_after_operations(
+        BTree::new_with_fanout(&store, 2),
+        inserts(["d", "c", "b", "a"]),
+    );
+    assert_ne!(ascending.unwrap(), descending.unwrap());
 }
@@ -0,0 +1,430 @@
+//! Walking the nodes of a HAMT, loading the ones that aren't cached yet.
+//!
+//! Unlike the HAMT's own methods, these don't assume every linked block is
+//! in the store, so they can run against partial stores and report which
+//! block is missing or broken.
+//!
+//! Analyses implement [`NodeVisitor`] and are driven by [`Visit::visit`]:
+//!
+//! ```ignore
+//! struct Leaves(usize);
+//!
+//! impl<K, V, H, const BUCKET_SIZE: usize> NodeVisitor<K, V, H, BUCKET_SIZE> for Leaves {
+//!     fn bucket(&mut self, _: usize, bucket: &[KeyValuePair<K, V>]) -> Result<()> {
+//!         self.0 += bucket.len();
+//!         Ok(())
+//!     }
+//! }
+//!
+//! let mut leaves = Leaves(0);
+//! hamt.visit(&mut leaves)?;
+//! ```
+
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail, Result};
+use cid::Cid;
+use fvm_ipld_blockstore::Blockstore;
+use fvm_ipld_encoding::de::DeserializeOwned;
+use fvm_ipld_hamt::node::Node;
+use fvm_ipld_hamt::pointer::Pointer;
+use fvm_ipld_hamt::{cid_config, Hamt, HamtView, Hash, HashAlgorithm, KeyValuePair};
+use once_cell::sync::OnceCell;
+use serde::Serialize;
+
+use crate::rng::Rng;
+
+#[derive(Debug)]
+pub enum TraverseError {
+    /// A node links to a block the store doesn't have.
+    MissingBlock(Cid),
+    /// A linked block isn't a node of this kind of HAMT.
+    Decode(Cid, anyhow::Error),
+    /// The store failed to read a block.
+    Store(Cid, anyhow::Error),
+}
+
+impl fmt::Display for TraverseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TraverseError::MissingBlock(cid) => write!(f, "block {cid} is missing"),
+            TraverseError::Decode(cid, e) => write!(f, "block {cid} isn't a node: {e}"),
+            TraverseError::Store(cid, e) => write!(f, "reading block {cid} failed: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for TraverseError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TraverseError::MissingBlock(_) => None,
+            TraverseError::Decode(_, e) | TraverseError::Store(_, e) => Some(e.as_ref()),
+        }
+    }
+}
+
+/// The node `cid` links to, from `cache` or else loaded from `store` and
+/// cached.
+pub fn resolve_link<'a, S, K, V, H, const BUCKET_SIZE: usize>(
+    cid: &Cid,
+    cache: &'a OnceCell<Box<Node<K, V, H, BUCKET_SIZE>>>,
+    store: &S,
+) -> Result<&'a Node<K, V, H, BUCKET_SIZE>, TraverseError>
+where
+    K: Hash + Eq + PartialOrd + DeserializeOwned,
+    H: HashAlgorithm,
+    V: DeserializeOwned,
+    S: Blockstore,
+{
+    if let Some(cached_node) = cache.get() {
+        return Ok(cached_node);
+    }
+    let block = store
+        .get(cid)
+        .map_err(|e| TraverseError::Store(*cid, e))?
+        .ok_or(TraverseError::MissingBlock(*cid))?;
+    let node = cid_config::decode(cid.codec(), &block)
+        .map_err(|e| TraverseError::Decode(*cid, e.into()))?;
+
+    // Another thread may have loaded it first, into the same node.
+    Ok(cache.get_or_init(|| node))
+}
+
+pub enum Resolved<'a, K, V, H, const BUCKET_SIZE: usize> {
+    Link(&'a Node<K, V, H, BUCKET_SIZE>),
+    Bucket(&'a Vec<KeyValuePair<K, V>>),
+}
+
+/// What `pointer` points to, loading linked nodes as needed.
+pub fn resolved<'a, S, K, V, H, const BUCKET_SIZE: usize>(
+    pointer: &'a Pointer<K, V, H, BUCKET_SIZE>,
+    store: &S,
+) -> Result<Resolved<'a, K, V, H, BUCKET_SIZE>, TraverseError>
+where
+    K: Hash + Eq + PartialOrd + DeserializeOwned,
+    H: HashAlgorithm,
+    V: DeserializeOwned,
+    S: Blockstore,
+{
+    Ok(match pointer {
+        Pointer::Values(v) => Resolved::Bucket(v),
+        Pointer::Link { cid, cache } => Resolved::Link(resolve_link(cid, cache, store)?),
+        Pointer::Dirty(node) => Resolved::Link(node),
+    })
+}
+
+/// Whether to go on into the pointers of a node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Control {
+    Descend,
+    /// Skips the node's pointers, and [`NodeVisitor::leave_node`] for it.
+    Skip,
+}
+
+/// Hooks called while walking a HAMT depth first, in pointer order. `depth`
+/// is that of the node, 0 for the root, and buckets have the depth of the
+/// node holding them.
+///
+/// Errors returned by a hook stop the walk and are passed on.
+pub trait NodeVisitor<K, V, H, const BUCKET_SIZE: usize> {
+    /// Called before the pointers of `node`.
+    fn enter_node(&mut self, depth: usize, node: &Node<K, V, H, BUCKET_SIZE>) -> Result<Control> {
+        let _ = (depth, node);
+        Ok(Control::Descend)
+    }
+
+    /// Called after the pointers of `node`, so after all of its children.
+    fn leave_node(&mut self, depth: usize, node: &Node<K, V, H, BUCKET_SIZE>) -> Result<()> {
+        let _ = (depth, node);
+        Ok(())
+    }
+
+    fn bucket(&mut self, depth: usize, bucket: &[KeyValuePair<K, V>]) -> Result<()> {
+        let _ = (depth, bucket);
+        Ok(())
+    }
+}
+
+/// Which nodes a [`Visit::visit_with`] walks, to estimate statistics of
+/// trees too large to walk completely.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Walk {
+    /// Depth of the deepest nodes visited. The buckets of nodes at this depth
+    /// are still visited, their links aren't followed.
+    pub max_depth: Option<usize>,
+    pub sample: Option<Sampling>,
+    /// Seed deciding which links a sampled walk follows.
+    pub seed: u64,
+}
+
+/// Follows each link out of the nodes at `depth` with probability `rate`,
+/// so a walk visits that share of the subtrees below them completely. Nodes
+/// above are always visited.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sampling {
+    pub rate: f64,
+    pub depth: usize,
+}
+
+/// Samplings the `sample` experiment compares unless `--sample` is given.
+pub fn samplings() -> Vec<Sampling> {
+    [(0.5, 1), (0.1, 1), (0.1, 2), (0.01, 2)]
+        .into_iter()
+        .map(|(rate, depth)| Sampling { rate, depth })
+        .collect()
+}
+
+impl fmt::Display for Sampling {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.rate, self.depth)
+    }
+}
+
+impl FromStr for Sampling {
+    type Err = anyhow::Error;
+
+    /// `<rate>[:<depth>]`, sampling the links of the root's children unless
+    /// the depth is given.
+    fn from_str(s: &str) -> Result<Self> {
+        let (rate, depth) = s.split_once(':').unwrap_or((s, "1"));
+        let rate = match rate.parse() {
+            Ok(rate) if (0.0..=1.0).contains(&rate) => rate,
+            _ => bail!("invalid sample rate `{rate}`, expected a fraction from 0 to 1"),
+        };
+        let depth = depth
+            .parse()
+            .map_err(|_| anyhow!("invalid sampling depth `{depth}`"))?;
+        Ok(Sampling { rate, depth })
+    }
+}
+
+/// [`NodeVisitor`]s walking a whole HAMT.
+pub trait Visit<K, V, H, const BUCKET_SIZE: usize> {
+    /// Walks every node from the root down, loading those that aren't cached
+    /// yet. Blocks that can't be loaded fail with a [`TraverseError`].
+    fn visit(&self, visitor: &mut impl NodeVisitor<K, V, H, BUCKET_SIZE>) -> Result<()> {
+        self.visit_with(&Walk::default(), visitor)
+    }
+
+    /// [`Visit::visit`] limited to the nodes `walk` selects. Links that
+    /// aren't followed aren't loaded either.
+    fn visit_with(
+        &self,
+        walk: &Walk,
+        visitor: &mut impl NodeVisitor<K, V, H, BUCKET_SIZE>,
+    ) -> Result<()>;
+}
+
+impl<S, K, V, H, const BUCKET_SIZE: usize> Visit<K, V, H, BUCKET_SIZE>
+    for Hamt<S, V, K, H, BUCKET_SIZE>
+where
+    K: Hash + Eq + PartialOrd + Serialize + DeserializeOwned,
+    V: Serialize + DeserializeOwned,
+    H: HashAlgorithm,
+    S: Blockstore,
+{
+    fn visit_with(
+        &self,
+        walk: &Walk,
+        visitor: &mut impl NodeVisitor<K, V, H, BUCKET_SIZE>,
+    ) -> Result<()> {
+        let mut rng = Rng::new(walk.seed);
+        walk_node(&self.root, self.store(), 0, walk, &mut rng, visitor)
+    }
+}
+
+impl<S, K, V, H, const BUCKET_SIZE: usize> Visit<K, V, H, BUCKET_SIZE>
+    for HamtView<S, V, K, H, BUCKET_SIZE>
+where
+    K: Hash + Eq + PartialOrd + Serialize + DeserializeOwned,
+    V: Serialize + DeserializeOwned,
+    H: HashAlgorithm,
+    S: Blockstore,
+{
+    fn visit_with(
+        &self,
+        walk: &Walk,
+        visitor: &mut impl NodeVisitor<K, V, H, BUCKET_SIZE>,
+    ) -> Result<()> {
+        let mut rng = Rng::new(walk.seed);
+        walk_node(self.root(), self.store(), 0, walk, &mut rng, visitor)
+    }
+}
+
+/// [`Visit::visit`] of the subtree at `node`, which is at `depth`.
+pub fn visit_node<S, K, V, H, const BUCKET_SIZE: usize>(
+    node: &Node<K, V, H, BUCKET_SIZE>,
+    store: &S,
+    depth: usize,
+    visitor: &mut impl NodeVisitor<K, V, H, BUCKET_SIZE>,
+) -> Result<()>
+where
+    K: Hash + Eq + PartialOrd + DeserializeOwned,
+    V: DeserializeOwned,
+    H: HashAlgorithm,
+    S: Blockstore,
+{
+    let mut rng = Rng::new(0);
+    walk_node(node, store, depth, &Walk::default(), &mut rng, visitor)
+}
+
+fn walk_node<S, K, V, H, const BUCKET_SIZE: usize>(
+    node: &Node<K, V, H, BUCKET_SIZE>,
+    store: &S,
+    depth: usize,
+    walk: &Walk,
+    rng: &mut Rng,
+    visitor: &mut impl NodeVisitor<K, V, H, BUCKET_SIZE>,
+) -> Result<()>
+where
+    K: Hash + Eq + PartialOrd + DeserializeOwned,
+    V: DeserializeOwned,
+    H: HashAlgorithm,
+    S: Blockstore,
+{
+    if visitor.enter_node(depth, node)? == Control::Skip {
+        return Ok(());
+    }
+    let descend = walk.max_depth.is_none_or(|max_depth| depth < max_depth);
+    let rate = match walk.sample {
+        Some(sampling) if sampling.depth == depth => sampling.rate,
+        _ => 1.0,
+    };
+    for pointer in node.pointers.iter() {
+        let child = match pointer {
+            Pointer::Values(bucket) => {
+                visitor.bucket(depth, bucket)?;
+                continue;
+            }
+            _ if !descend || (rate < 1.0 && rng.next_f64() >= rate) => continue,
+            Pointer::Link { cid, cache } => resolve_link(cid, cache, store)?,
+            Pointer::Dirty(child) => child,
+        };
+        walk_node(child, store, depth + 1, walk, rng, visitor)?;
+    }
+    visitor.leave_node(depth, node)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memorydb::MemoryDB;
+    use fvm_ipld_hamt::{Hamt, Sha256};
+
+    type Map<'a> = Hamt<&'a MemoryDB, String, usize, Sha256, 3>;
+
+    /// Checks that nodes are left in the reverse order they're entered in.
+    #[derive(Default)]
+    struct Counts {
+        open: Vec<usize>,
+        nodes: usize,
+        entries: usize,
+    }
+
+    impl<K, V, H, const BUCKET_SIZE: usize> NodeVisitor<K, V, H, BUCKET_SIZE> for Counts {
+        fn enter_node(&mut self, depth: usize, _: &Node<K, V, H, BUCKET_SIZE>) -> Result<Control> {
+            self.open.push(depth);
+            self.nodes += 1;
+            Ok(Control::Descend)
+        }
+
+        fn leave_node(&mut self, depth: usize, _: &Node<K, V, H, BUCKET_SIZE>) -> Result<()> {
+            assert_eq!(self.open.pop(), Some(depth));
+            Ok(())
+        }
+
+        fn bucket(&mut self, depth: usize, bucket: &[KeyValuePair<K, V>]) -> Result<()> {
+            assert_eq!(self.open.last(), Some(&depth));
+            self.entries += bucket.len();
+            Ok(())
+        }
+    }
+
+    fn count_nodes(map: &Map) -> Result<usize> {
+        let mut counts = Counts::default();
+        map.visit(&mut counts)?;
+        assert!(counts.open.is_empty());
+        Ok(counts.nodes)
+    }
+
+    #[test]
+    fn visits_every_node_and_entry() -> Result<()> {
+        let store = MemoryDB::default();
+        let mut map: Map = Hamt::new_with_bit_width(&store, 4);
+        for key in 0..1000 {
+            map.set(key, "F".to_string())?;
+        }
+        // Unflushed nodes are visited too.
+        let mut counts = Counts::default();
+        map.visit(&mut counts)?;
+        assert_eq!(counts.entries, 1000);
+        let root = map.flush()?;
+        let map: Map = Hamt::load_with_bit_width(&root, &store, 4)?;
+        assert_eq!(count_nodes(&map)?, counts.nodes);
+
+        let mut view_counts = Counts::default();
+        HamtView::<_, String, usize, Sha256, 3>::load_with_bit_width(&root, &store, 4)?
+            .visit(&mut view_counts)?;
+        assert_eq!(
+            (view_counts.nodes, view_counts.entries),
+            (counts.nodes, 1000)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn broken_blocks_surface_as_errors() -> Result<()> {
+        let store = MemoryDB::default();
+        let mut map: Map = Hamt::new_with_bit_width(&store, 4);
+        for key in 0..1000 {
+            map.set(key, "F".to_string())?;
+        }
+        let root = map.flush()?;
+        let nodes = count_nodes(&Hamt::load_with_bit_width(&root, &store, 4)?)?;
+        assert!(nodes > 1);
+
+        let child = map
+            .root
+            .pointers
+            .iter()
+            .find_map(|pointer| match pointer {
+                Pointer::Link { cid, .. } => Some(*cid),
+                _ => None,
+            })
+            .expect("the root has children");
+        store.remove(&[child]);
+        let partial: Map = Hamt::load_with_bit_width(&root, &store, 4)?;
+        let error = count_nodes(&partial).unwrap_err();
+        assert!(matches!(
+            error.downcast_ref(),
+            Some(TraverseError::MissingBlock(cid)) if *cid == child
+        ));
+
+        store.put_keyed(&child, &fvm_ipld_encoding::to_vec(&"not a node")?)?;
+        let corrupt: Map = Hamt::load_with_bit_width(&root, &store, 4)?;
+        let error = count_nodes(&corrupt).unwrap_err();
+        assert!(matches!(
+            error.downcast_ref(),
+            Some(TraverseError::Decode(cid, _)) if *cid == child
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn parses_and_prints_samplings() -> Result<()> {
+        let sampling: Sampling = "0.1".parse()?;
+        assert_eq!(
+            sampling,
+            Sampling {
+                rate: 0.1,
+                depth: 1
+            }
+        );
+        assert_eq!(sampling.to_string(), "0.1:1");
+        assert_eq!("0.5:3".parse::<Sampling>()?.depth, 3);
+        assert!("2".parse::<Sampling>().is_err());
+        assert!("0.5:x".parse::<Sampling>().is_err());
+        Ok(())
+    }
+}
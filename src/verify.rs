@@ -0,0 +1,139 @@
+//! A blockstore checking that the blocks it returns are the ones their CIDs
+//! address, to catch blocks put under a CID of another multihash or codec
+//! than they were hashed and encoded with.
+
+use std::convert::TryFrom;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use anyhow::{anyhow, bail, Result};
+use cid::multihash::{Code, MultihashDigest};
+use cid::Cid;
+use fvm_ipld_blockstore::Blockstore;
+use fvm_ipld_encoding::DAG_CBOR;
+use fvm_ipld_hamt::cid_config;
+use fvm_ipld_hamt::dag_json::DAG_JSON;
+use libipld_core::ipld::Ipld;
+
+/// Wraps a blockstore and, on every `get`, hashes the block again with the
+/// multihash of its CID and decodes it with the codec, failing if either
+/// doesn't match. Blocks of codecs other than DAG-CBOR and DAG-JSON are only
+/// hashed.
+#[derive(Debug, Default)]
+pub struct VerifyingStore<S> {
+    inner: S,
+    enabled: bool,
+    verified: AtomicU64,
+}
+
+impl<S> VerifyingStore<S> {
+    pub fn new(inner: S) -> Self {
+        VerifyingStore {
+            inner,
+            enabled: true,
+            verified: AtomicU64::new(0),
+        }
+    }
+
+    /// A store passing blocks through unchecked, so callers can turn
+    /// verification off without changing the store's type.
+    pub fn passthrough(inner: S) -> Self {
+        VerifyingStore {
+            enabled: false,
+            ..VerifyingStore::new(inner)
+        }
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    /// Number of blocks checked so far.
+    pub fn verified(&self) -> u64 {
+        self.verified.load(Ordering::Relaxed)
+    }
+}
+
+/// Checks that `block` hashes to the multihash of `cid` and decodes with its
+/// codec.
+pub fn verify_block(cid: &Cid, block: &[u8]) -> Result<()> {
+    let code = Code::try_from(cid.hash().code())
+        .map_err(|_| anyhow!("block {cid} has an unsupported multihash"))?;
+    if code.digest(block) != *cid.hash() {
+        bail!("block {cid} doesn't hash to its CID");
+    }
+    if matches!(cid.codec(), DAG_CBOR | DAG_JSON) {
+        cid_config::decode::<Ipld>(cid.codec(), block)
+            .map_err(|e| anyhow!("block {cid} doesn't decode with its codec: {e}"))?;
+    }
+    Ok(())
+}
+
+impl<S: Blockstore> Blockstore for VerifyingStore<S> {
+    fn has(&self, k: &Cid) -> Result<bool> {
+        self.inner.has(k)
+    }
+
+    fn get(&self, k: &Cid) -> Result<Option<Vec<u8>>> {
+        let block = self.inner.get(k)?;
+        if let (true, Some(block)) = (self.enabled, &block) {
+            verify_block(k, block)?;
+            self.verified.fetch_add(1, Ordering::Relaxed);
+        }
+        Ok(block)
+    }
+
+    fn put_keyed(&self, k: &Cid, block: &[u8]) -> Result<()> {
+        self.inner.put_keyed(k, block)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memorydb::MemoryDB;
+    use fvm_ipld_hamt::{CidConfig, Hamt, Sha256};
+
+    #[test]
+    fn accepts_every_cid_config_and_rejects_mismatches() -> Result<()> {
+        for cid_config in [
+            CidConfig::default(),
+            CidConfig {
+                mh_code: Code::Sha2_256,
+                ..CidConfig::default()
+            },
+            CidConfig {
+                codec: DAG_JSON,
+                ..CidConfig::default()
+            },
+        ] {
+            let store = VerifyingStore::new(MemoryDB::default());
+            let mut map: Hamt<_, String, usize, Sha256, 3> =
+                Hamt::new_with_bit_width(&store, 4).with_cid_config(cid_config);
+            for key in 0..500 {
+                map.set(key, "F".to_string())?;
+            }
+            let root = map.flush()?;
+            let map: Hamt<_, String, usize, Sha256, 3> =
+                Hamt::load_with_bit_width(&root, &store, 4)?;
+            assert_eq!(map.iter().collect::<Result<Vec<_>, _>>()?.len(), 500);
+            assert!(store.verified() > 1);
+        }
+
+        let store = VerifyingStore::new(MemoryDB::default());
+        let block = fvm_ipld_encoding::to_vec(&"F")?;
+        let cbor = Cid::new_v1(DAG_CBOR, Code::Blake2b256.digest(&block));
+        store.put_keyed(&cbor, &block)?;
+        assert!(store.get(&cbor)?.is_some());
+        // The right hash, but DAG-CBOR bytes under a DAG-JSON CID.
+        let json = Cid::new_v1(DAG_JSON, Code::Blake2b256.digest(&block));
+        store.put_keyed(&json, &block)?;
+        assert!(store.get(&json).is_err());
+        // The bytes of another block.
+        let other = Cid::new_v1(DAG_CBOR, Code::Sha2_256.digest(b"other"));
+        store.put_keyed(&other, &block)?;
+        assert!(store.get(&other).is_err());
+        let unchecked = VerifyingStore::passthrough(store.inner());
+        assert!(unchecked.get(&other)?.is_some());
+        Ok(())
+    }
+}
@@ -0,0 +1,517 @@
+//! Graph output for HAMTs.
+//!
+//! [`hamt_to_graph`] and [`hamt_diff_to_graph`] collect the nodes of a HAMT,
+//! which are then written out as graphviz DOT by [`DotRenderer`], as a Mermaid
+//! flowchart by [`MermaidRenderer`] or, with the `svg` feature, drawn
+//! directly by [`SvgRenderer`].
+
+mod mermaid;
+#[cfg(feature = "svg")]
+mod svg;
+
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+use anyhow::{bail, Error, Result};
+use cid::Cid;
+use fvm_ipld_blockstore::Blockstore;
+use fvm_ipld_encoding::{de::DeserializeOwned, to_vec};
+use fvm_ipld_hamt::{
+    bitfield::Bitfield, node::Node, CidConfig, HamtView, Hash, HashAlgorithm, KeyValuePair,
+};
+use serde::Serialize;
+
+use crate::diff;
+use crate::traverse::{Control, NodeVisitor, Visit};
+
+pub use mermaid::MermaidRenderer;
+#[cfg(feature = "svg")]
+pub use svg::SvgRenderer;
+
+/// Nodes and edges of one or two versions of a HAMT.
+///
+/// Nodes are stored children first, so roots come after everything they link
+/// to.
+pub struct Graph {
+    pub nodes: Vec<GraphNode>,
+    pub edges: Vec<Edge>,
+    seen: HashSet<Cid>,
+}
+
+impl Graph {
+    fn new() -> Self {
+        Graph {
+            nodes: Vec::new(),
+            edges: Vec::new(),
+            seen: HashSet::new(),
+        }
+    }
+}
+
+pub struct GraphNode {
+    /// Last characters of the node's CID.
+    pub id: String,
+    pub bitfield: String,
+    /// `(key hash prefix, key)` for the entries of each bucket.
+    pub buckets: Vec<Vec<(String, String)>>,
+    pub bucket_size: usize,
+    pub status: Option<Status>,
+}
+
+pub struct Edge {
+    pub from: String,
+    pub to: String,
+    pub status: Option<Status>,
+}
+
+/// How a node changed between two versions of a HAMT. Edges take the status
+/// of the node they start from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Status {
+    Unchanged,
+    New,
+    Removed,
+}
+
+impl Status {
+    fn node_attributes(self) -> &'static str {
+        match self {
+            Status::Unchanged => {
+                "color = \"/x11/gray50\" fontcolor = \"/x11/gray30\" fillcolor = 6"
+            }
+            Status::New => "color = 10 fontcolor = 11 fillcolor = 8",
+            Status::Removed => "color = 2 fontcolor = 1 fillcolor = 4 style = \"filled,dashed\"",
+        }
+    }
+
+    fn edge_attributes(self) -> &'static str {
+        match self {
+            Status::Unchanged => "color = \"/x11/gray50\"",
+            Status::New => "color = 10 penwidth = 2",
+            Status::Removed => "color = 2 style = dashed",
+        }
+    }
+}
+
+/// `(stroke, fill)` for renderers that don't use graphviz color schemes,
+/// matching the default `piyg11` DOT colors.
+fn colors(status: Option<Status>) -> (&'static str, &'static str) {
+    match status {
+        None => ("#c51b7d", "#fde0ef"),
+        Some(Status::Unchanged) => ("#7f7f7f", "#f7f7f7"),
+        Some(Status::New) => ("#4d9221", "#b8e186"),
+        Some(Status::Removed) => ("#c51b7d", "#f1b6da"),
+    }
+}
+
+fn cidstr(cid: &Cid) -> String {
+    let str = cid.to_string();
+    str[str.len() - 8..str.len()].to_string()
+}
+
+fn bitfieldstr(bitfield: Bitfield, len: u32) -> String {
+    let mut str = String::new();
+    for i in 0..len {
+        if bitfield.test_bit(i) {
+            str += "1";
+        } else {
+            str += "0";
+        }
+    }
+    str
+}
+
+pub fn hamt_to_graph<S, K, V, H, const BUCKET_SIZE: usize>(
+    hamt: &HamtView<S, K, V, H, BUCKET_SIZE>,
+) -> Result<Graph>
+where
+    K: Hash + Eq + PartialOrd + Serialize + DeserializeOwned + ToString,
+    H: HashAlgorithm,
+    V: Serialize + DeserializeOwned + Hash + Eq + PartialOrd + ToString,
+    S: Blockstore,
+{
+    let mut graph = Graph::new();
+    hamt.visit(&mut GraphVisitor::new(
+        hamt.store(),
+        hamt.bit_width,
+        &hamt.cid_config,
+        &|_| None,
+        &mut graph,
+    ))?;
+    Ok(graph)
+}
+
+/// Both versions of a HAMT in one graph, with nodes marked by whether they
+/// are shared, only part of `new` or only part of `old`.
+pub fn hamt_diff_to_graph<S, K, V, H, const BUCKET_SIZE: usize>(
+    store: &S,
+    bit_width: u32,
+    old: &Cid,
+    new: &Cid,
+) -> Result<Graph>
+where
+    K: Hash + Eq + PartialOrd + Serialize + DeserializeOwned + ToString + Clone,
+    H: HashAlgorithm,
+    V: Serialize + DeserializeOwned + PartialEq + ToString + Clone,
+    S: Blockstore,
+{
+    let diff = diff::diff::<_, K, V, BUCKET_SIZE>(store, old, new)?;
+    let status = |cid: &Cid| {
+        Some(if diff.right_blocks.contains(cid) {
+            Status::New
+        } else if diff.left_blocks.contains(cid) {
+            Status::Removed
+        } else {
+            Status::Unchanged
+        })
+    };
+
+    let mut graph = Graph::new();
+    for root in [new, old] {
+        let hamt: HamtView<&S, V, K, H, BUCKET_SIZE> =
+            HamtView::load_with_bit_width(root, store, bit_width)?;
+        hamt.visit(&mut GraphVisitor::new(
+            store,
+            bit_width,
+            &hamt.cid_config,
+            &status,
+            &mut graph,
+        ))?;
+    }
+    Ok(graph)
+}
+
+/// `(key hash prefix, key)` for the entries of a bucket.
+type BucketEntries = Vec<(String, String)>;
+
+/// Adds the nodes it visits to a [`Graph`], putting them into `store` to get
+/// their CIDs. Nodes already in the graph are linked to, but not visited
+/// again.
+struct GraphVisitor<'a, S> {
+    store: &'a S,
+    bit_width: u32,
+    cid_config: &'a CidConfig,
+    status: &'a dyn Fn(&Cid) -> Option<Status>,
+    graph: &'a mut Graph,
+    /// The nodes entered but not left yet, with their buckets so far.
+    open: Vec<(Cid, Vec<BucketEntries>)>,
+}
+
+impl<'a, S> GraphVisitor<'a, S> {
+    fn new(
+        store: &'a S,
+        bit_width: u32,
+        cid_config: &'a CidConfig,
+        status: &'a dyn Fn(&Cid) -> Option<Status>,
+        graph: &'a mut Graph,
+    ) -> Self {
+        GraphVisitor {
+            store,
+            bit_width,
+            cid_config,
+            status,
+            graph,
+            open: Vec::new(),
+        }
+    }
+
+    /// Adds an edge from the node being visited to `child`.
+    fn link_to(&mut self, child: &Cid) {
+        if let Some((parent, _)) = self.open.last() {
+            self.graph.edges.push(Edge {
+                from: cidstr(parent),
+                to: cidstr(child),
+                status: (self.status)(parent),
+            });
+        }
+    }
+}
+
+impl<S, K, V, H, const BUCKET_SIZE: usize> NodeVisitor<K, V, H, BUCKET_SIZE> for GraphVisitor<'_, S>
+where
+    K: Hash + Serialize + ToString,
+    V: Serialize,
+    H: HashAlgorithm,
+    S: Blockstore,
+{
+    fn enter_node(&mut self, _: usize, node: &Node<K, V, H, BUCKET_SIZE>) -> Result<Control> {
+        let cid = self.cid_config.put(self.store, &to_vec(node)?)?;
+        if self.graph.seen.insert(cid) {
+            self.open.push((cid, Vec::new()));
+            Ok(Control::Descend)
+        } else {
+            self.link_to(&cid);
+            Ok(Control::Skip)
+        }
+    }
+
+    fn leave_node(&mut self, _: usize, node: &Node<K, V, H, BUCKET_SIZE>) -> Result<()> {
+        let (cid, buckets) = self.open.pop().expect("nodes are entered first");
+        self.graph.nodes.push(GraphNode {
+            id: cidstr(&cid),
+            bitfield: bitfieldstr(node.bitfield, 1 << self.bit_width),
+            buckets,
+            bucket_size: BUCKET_SIZE,
+            status: (self.status)(&cid),
+        });
+        self.link_to(&cid);
+        Ok(())
+    }
+
+    fn bucket(&mut self, _: usize, bucket: &[KeyValuePair<K, V>]) -> Result<()> {
+        let (_, buckets) = self.open.last_mut().expect("buckets are in a node");
+        buckets.push(
+            bucket
+                .iter()
+                .map(|kv| {
+                    (
+                        hex::encode(H::hash(kv.key()))[..8].to_string(),
+                        kv.key().to_string(),
+                    )
+                })
+                .collect(),
+        );
+        Ok(())
+    }
+}
+
+/// Writes a [`Graph`] as graphviz DOT with a common set of styles.
+///
+/// Node and edge colors are indices into `color_scheme`, so it has to be one
+/// of graphviz' 11 class diverging brewer schemes, like `piyg11` or `rdbu11`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DotRenderer {
+    rankdir: RankDir,
+    font: String,
+    color_scheme: String,
+}
+
+impl Default for DotRenderer {
+    fn default() -> Self {
+        DotRenderer {
+            rankdir: RankDir::TopBottom,
+            font: "Helvetica".to_string(),
+            color_scheme: "piyg11".to_string(),
+        }
+    }
+}
+
+impl DotRenderer {
+    pub fn with_rankdir(mut self, rankdir: RankDir) -> Self {
+        self.rankdir = rankdir;
+        self
+    }
+
+    pub fn with_font(mut self, font: impl Into<String>) -> Self {
+        self.font = font.into();
+        self
+    }
+
+    pub fn with_color_scheme(mut self, color_scheme: impl Into<String>) -> Self {
+        self.color_scheme = color_scheme.into();
+        self
+    }
+
+    /// Truncates or creates the file at `path`.
+    pub fn render_to_file(&self, graph: &Graph, path: &Path) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        self.render(graph, &mut out)?;
+        out.flush()
+    }
+
+    pub fn render(&self, graph: &Graph, out: &mut impl Write) -> io::Result<()> {
+        let DotRenderer {
+            rankdir,
+            font,
+            color_scheme,
+        } = self;
+
+        writeln!(out, "digraph G {{")?;
+        writeln!(
+            out,
+            "\n  compound = true
+  rankdir = {rankdir}
+  fontname = \"{font}\"
+
+  edge [
+    colorscheme = \"{color_scheme}\"
+    fontname = \"{font}\"
+  ];
+
+  node [
+    shape = plaintext
+    style = filled
+    colorscheme = \"{color_scheme}\"
+    fontname = \"{font}\"
+
+    color = 2
+    fontcolor = 2
+    fillcolor = 5
+  ];
+
+  graph [
+    colorscheme = \"{color_scheme}\"
+    color = 10
+    style = \"rounded,filled\"
+    fontcolor = 7
+  ];\n",
+            rankdir = rankdir.as_str(),
+        )?;
+        for node in &graph.nodes {
+            write_dot_node(node, out)?;
+        }
+        for Edge { from, to, status } in &graph.edges {
+            match status {
+                Some(status) => writeln!(
+                    out,
+                    "  \"{from}\" -> \"{to}\" [{}]",
+                    status.edge_attributes()
+                )?,
+                None => writeln!(out, "  \"{from}\" -> \"{to}\"")?,
+            }
+        }
+        writeln!(out, "}}")
+    }
+}
+
+fn write_dot_node(node: &GraphNode, out: &mut impl Write) -> io::Result<()> {
+    let GraphNode {
+        id,
+        bitfield,
+        buckets,
+        bucket_size,
+        status,
+    } = node;
+
+    write!(
+        out,
+        "\"{id}\" [
+    label=<
+        <table border=\"0\" cellborder=\"1\" cellspacing=\"0\">
+            <tr><td colspan=\"{bucket_size}\">{id}</td></tr>
+            <tr><td colspan=\"{bucket_size}\">{bitfield}</td></tr>\n"
+    )?;
+    for bucket in buckets {
+        let cells: Vec<String> = bucket
+            .iter()
+            .map(|(hash, key)| {
+                format!("<td align=\"left\"><font face=\"mono\">{hash}:</font> {key}</td>")
+            })
+            .collect();
+        write!(out, "<tr>{}</tr>", cells.join(", "))?;
+    }
+    writeln!(
+        out,
+        "        </table>
+    >"
+    )?;
+    if let Some(status) = status {
+        writeln!(out, "    {}", status.node_attributes())?;
+    }
+    writeln!(out, "]")
+}
+
+/// Direction of the graph layout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RankDir {
+    TopBottom,
+    LeftRight,
+    BottomTop,
+    RightLeft,
+}
+
+impl RankDir {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RankDir::TopBottom => "TB",
+            RankDir::LeftRight => "LR",
+            RankDir::BottomTop => "BT",
+            RankDir::RightLeft => "RL",
+        }
+    }
+}
+
+impl FromStr for RankDir {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s.to_ascii_uppercase().as_str() {
+            "TB" => RankDir::TopBottom,
+            "LR" => RankDir::LeftRight,
+            "BT" => RankDir::BottomTop,
+            "RL" => RankDir::RightLeft,
+            _ => bail!("unknown rankdir `{s}`, expected `TB`, `LR`, `BT` or `RL`"),
+        })
+    }
+}
+
+/// One of the supported output formats for a [`Graph`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Renderer {
+    Dot(DotRenderer),
+    Mermaid(MermaidRenderer),
+    #[cfg(feature = "svg")]
+    Svg(SvgRenderer),
+}
+
+impl Renderer {
+    pub fn render_to_file(&self, graph: &Graph, path: &Path) -> io::Result<()> {
+        match self {
+            Renderer::Dot(renderer) => renderer.render_to_file(graph, path),
+            Renderer::Mermaid(renderer) => renderer.render_to_file(graph, path),
+            #[cfg(feature = "svg")]
+            Renderer::Svg(renderer) => renderer.render_to_file(graph, path),
+        }
+    }
+
+    pub fn render(&self, graph: &Graph, out: &mut impl Write) -> io::Result<()> {
+        match self {
+            Renderer::Dot(renderer) => renderer.render(graph, out),
+            Renderer::Mermaid(renderer) => renderer.render(graph, out),
+            #[cfg(feature = "svg")]
+            Renderer::Svg(renderer) => renderer.render(graph, out),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memorydb::MemoryDB;
+    use fvm_ipld_hamt::{Hamt, Sha256};
+
+    pub(super) fn small_graph() -> Result<Graph> {
+        let store = MemoryDB::default();
+        let mut map: Hamt<_, String, usize, Sha256, 1> = Hamt::new_with_bit_width(&store, 2);
+        for key in 0..20 {
+            map.set(key, "F".to_string())?;
+        }
+        map.flush()?;
+        hamt_to_graph(&map.into_view())
+    }
+
+    #[test]
+    fn renders_to_file_with_options() -> Result<()> {
+        let graph = small_graph()?;
+
+        let path = std::env::temp_dir().join(format!("hamt-{}.dot", std::process::id()));
+        DotRenderer::default()
+            .with_rankdir("lr".parse()?)
+            .with_font("Fira Sans")
+            .with_color_scheme("rdbu11")
+            .render_to_file(&graph, &path)?;
+        let contents = std::fs::read_to_string(&path)?;
+        std::fs::remove_file(&path)?;
+
+        assert!(contents.starts_with("digraph G {"));
+        assert!(contents.contains("rankdir = LR"));
+        assert!(contents.contains("fontname = \"Fira Sans\""));
+        assert!(contents.contains("colorscheme = \"rdbu11\""));
+        assert_eq!(contents.matches(" -> ").count(), graph.edges.len());
+        assert!(contents.trim_end().ends_with('}'));
+        Ok(())
+    }
+}
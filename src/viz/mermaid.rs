@@ -0,0 +1,135 @@
+//! Mermaid flowcharts, which render inline in GitHub issues and most
+//! markdown editors.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use super::{colors, Edge, Graph, GraphNode, RankDir, Status};
+
+/// Writes a [`Graph`] as a Mermaid `graph` definition.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MermaidRenderer {
+    rankdir: RankDir,
+    /// Left to the Mermaid theme if unset.
+    font: Option<String>,
+}
+
+impl Default for MermaidRenderer {
+    fn default() -> Self {
+        MermaidRenderer {
+            rankdir: RankDir::TopBottom,
+            font: None,
+        }
+    }
+}
+
+impl MermaidRenderer {
+    pub fn with_rankdir(mut self, rankdir: RankDir) -> Self {
+        self.rankdir = rankdir;
+        self
+    }
+
+    pub fn with_font(mut self, font: impl Into<String>) -> Self {
+        self.font = Some(font.into());
+        self
+    }
+
+    /// Truncates or creates the file at `path`.
+    pub fn render_to_file(&self, graph: &Graph, path: &Path) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        self.render(graph, &mut out)?;
+        out.flush()
+    }
+
+    pub fn render(&self, graph: &Graph, out: &mut impl Write) -> io::Result<()> {
+        if let Some(font) = &self.font {
+            writeln!(
+                out,
+                "%%{{init: {{\"themeVariables\": {{\"fontFamily\": \"{}\"}}}}}}%%",
+                font.replace('"', "'")
+            )?;
+        }
+        let direction = match self.rankdir {
+            RankDir::TopBottom => "TD",
+            other => other.as_str(),
+        };
+        writeln!(out, "graph {direction}")?;
+
+        for status in [
+            None,
+            Some(Status::Unchanged),
+            Some(Status::New),
+            Some(Status::Removed),
+        ] {
+            let (stroke, fill) = colors(status);
+            write!(
+                out,
+                "  classDef {} fill:{fill},stroke:{stroke},color:{stroke}",
+                class(status)
+            )?;
+            if status == Some(Status::Removed) {
+                write!(out, ",stroke-dasharray:4 2")?;
+            }
+            writeln!(out)?;
+        }
+
+        for node in &graph.nodes {
+            writeln!(
+                out,
+                "  n{}[\"{}\"]:::{}",
+                node.id,
+                label(node),
+                class(node.status)
+            )?;
+        }
+        for Edge { from, to, status } in &graph.edges {
+            let arrow = match status {
+                Some(Status::New) => "==>",
+                Some(Status::Removed) => "-.->",
+                _ => "-->",
+            };
+            writeln!(out, "  n{from} {arrow} n{to}")?;
+        }
+        Ok(())
+    }
+}
+
+fn class(status: Option<Status>) -> &'static str {
+    match status {
+        None => "hamt",
+        Some(Status::Unchanged) => "unchanged",
+        Some(Status::New) => "new",
+        Some(Status::Removed) => "removed",
+    }
+}
+
+fn label(node: &GraphNode) -> String {
+    let mut lines = vec![node.id.clone(), format!("<code>{}</code>", node.bitfield)];
+    for bucket in &node.buckets {
+        let keys: Vec<&str> = bucket.iter().map(|(_, key)| key.as_str()).collect();
+        lines.push(keys.join(", "));
+    }
+    lines.join("<br/>").replace('"', "#quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+
+    #[test]
+    fn writes_one_line_per_node_and_edge() -> Result<()> {
+        let graph = super::super::tests::small_graph()?;
+        let mut out = Vec::new();
+        MermaidRenderer::default()
+            .with_rankdir(RankDir::LeftRight)
+            .render(&graph, &mut out)?;
+        let mermaid = String::from_utf8(out)?;
+
+        assert!(mermaid.starts_with("graph LR\n"));
+        assert_eq!(mermaid.matches(":::hamt").count(), graph.nodes.len());
+        assert_eq!(mermaid.matches(" --> ").count(), graph.edges.len());
+        Ok(())
+    }
+}
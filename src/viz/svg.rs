@@ -0,0 +1,237 @@
+//! Radial SVG layout, for when graphviz isn't available.
+//!
+//! Roots sit in the center and every level of the tree is drawn on its own
+//! ring. Leaves are spread evenly around the outermost rings and parents are
+//! placed at the mean angle of their children.
+
+use std::collections::HashMap;
+use std::f64::consts::TAU;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use super::{colors, Edge, Graph, GraphNode, Status};
+
+const MARGIN: f64 = 20.0;
+const NODE_RADIUS: f64 = 6.0;
+
+/// Writes a [`Graph`] as a standalone SVG image.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SvgRenderer {
+    font: String,
+    ring_spacing: u32,
+}
+
+impl Default for SvgRenderer {
+    fn default() -> Self {
+        SvgRenderer {
+            font: "Helvetica".to_string(),
+            ring_spacing: 60,
+        }
+    }
+}
+
+impl SvgRenderer {
+    pub fn with_font(mut self, font: impl Into<String>) -> Self {
+        self.font = font.into();
+        self
+    }
+
+    /// Distance in pixels between the rings of two consecutive tree levels.
+    pub fn with_ring_spacing(mut self, ring_spacing: u32) -> Self {
+        self.ring_spacing = ring_spacing;
+        self
+    }
+
+    /// Truncates or creates the file at `path`.
+    pub fn render_to_file(&self, graph: &Graph, path: &Path) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        self.render(graph, &mut out)?;
+        out.flush()
+    }
+
+    pub fn render(&self, graph: &Graph, out: &mut impl Write) -> io::Result<()> {
+        let layout = Layout::new(graph);
+        let ring_spacing = self.ring_spacing as f64;
+        let radius = layout.max_depth as f64 * ring_spacing + NODE_RADIUS + MARGIN;
+        let position = |i: usize| {
+            let r = layout.depth[i] as f64 * ring_spacing;
+            let angle = TAU * layout.slot[i] / layout.leaves as f64;
+            (r * angle.cos(), r * angle.sin())
+        };
+
+        writeln!(
+            out,
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"{} {} {} {}\" font-family=\"{}\" font-size=\"8\">",
+            -radius,
+            -radius,
+            2.0 * radius,
+            2.0 * radius,
+            escape(&self.font),
+        )?;
+
+        for depth in 1..=layout.max_depth {
+            writeln!(
+                out,
+                "  <circle r=\"{:.1}\" fill=\"none\" stroke=\"#e0e0e0\" stroke-dasharray=\"2,4\"/>",
+                depth as f64 * ring_spacing
+            )?;
+        }
+
+        for Edge { from, to, status } in &graph.edges {
+            let (x1, y1) = position(layout.index[from.as_str()]);
+            let (x2, y2) = position(layout.index[to.as_str()]);
+            let (stroke, _) = colors(*status);
+            write!(
+                out,
+                "  <line x1=\"{x1:.1}\" y1=\"{y1:.1}\" x2=\"{x2:.1}\" y2=\"{y2:.1}\" stroke=\"{stroke}\""
+            )?;
+            match status {
+                Some(Status::New) => write!(out, " stroke-width=\"2\"")?,
+                Some(Status::Removed) => write!(out, " stroke-dasharray=\"4,2\"")?,
+                _ => {}
+            }
+            writeln!(out, "/>")?;
+        }
+
+        for (i, node) in graph.nodes.iter().enumerate() {
+            let (x, y) = position(i);
+            let (stroke, fill) = colors(node.status);
+            writeln!(
+                out,
+                "  <g transform=\"translate({x:.1} {y:.1})\">\n    <title>{}</title>",
+                escape(&title(node))
+            )?;
+            write!(
+                out,
+                "    <circle r=\"{NODE_RADIUS}\" fill=\"{fill}\" stroke=\"{stroke}\""
+            )?;
+            if node.status == Some(Status::Removed) {
+                write!(out, " stroke-dasharray=\"2,1\"")?;
+            }
+            writeln!(out, "/>")?;
+
+            let entries: usize = node.buckets.iter().map(Vec::len).sum();
+            if entries > 0 {
+                writeln!(
+                    out,
+                    "    <text y=\"3\" text-anchor=\"middle\" fill=\"{stroke}\">{entries}</text>"
+                )?;
+            }
+            writeln!(out, "  </g>")?;
+        }
+
+        writeln!(out, "</svg>")
+    }
+}
+
+/// Ring and angular position of every node, indexed like `Graph::nodes`.
+struct Layout<'a> {
+    index: HashMap<&'a str, usize>,
+    depth: Vec<usize>,
+    /// Position around the ring in units of leaves.
+    slot: Vec<f64>,
+    leaves: usize,
+    max_depth: usize,
+}
+
+impl<'a> Layout<'a> {
+    fn new(graph: &'a Graph) -> Self {
+        let index: HashMap<&str, usize> = graph
+            .nodes
+            .iter()
+            .enumerate()
+            .map(|(i, node)| (node.id.as_str(), i))
+            .collect();
+
+        let mut children = vec![Vec::new(); graph.nodes.len()];
+        let mut has_parent = vec![false; graph.nodes.len()];
+        for edge in &graph.edges {
+            let to = index[edge.to.as_str()];
+            children[index[edge.from.as_str()]].push(to);
+            has_parent[to] = true;
+        }
+
+        let roots: Vec<usize> = (0..graph.nodes.len()).filter(|&i| !has_parent[i]).collect();
+        // Two versions get their own rings around an empty center.
+        let root_depth = usize::from(roots.len() > 1);
+
+        let mut layout = Layout {
+            index,
+            depth: vec![0; graph.nodes.len()],
+            slot: vec![0.0; graph.nodes.len()],
+            leaves: 0,
+            max_depth: 0,
+        };
+        let mut placed = vec![false; graph.nodes.len()];
+        for root in roots {
+            layout.place(root, root_depth, &children, &mut placed);
+        }
+        layout.leaves = layout.leaves.max(1);
+        layout
+    }
+
+    /// Places `node` and its not yet placed descendants, returning its slot.
+    fn place(
+        &mut self,
+        node: usize,
+        depth: usize,
+        children: &[Vec<usize>],
+        placed: &mut [bool],
+    ) -> Option<f64> {
+        if placed[node] {
+            return None;
+        }
+        placed[node] = true;
+        self.depth[node] = depth;
+        self.max_depth = self.max_depth.max(depth);
+
+        let slots: Vec<f64> = children[node]
+            .iter()
+            .filter_map(|&child| self.place(child, depth + 1, children, placed))
+            .collect();
+        self.slot[node] = if slots.is_empty() {
+            self.leaves += 1;
+            self.leaves as f64 - 0.5
+        } else {
+            slots.iter().sum::<f64>() / slots.len() as f64
+        };
+        Some(self.slot[node])
+    }
+}
+
+fn title(node: &GraphNode) -> String {
+    let mut title = format!("{}\n{}", node.id, node.bitfield);
+    for bucket in &node.buckets {
+        let keys: Vec<&str> = bucket.iter().map(|(_, key)| key.as_str()).collect();
+        title += &format!("\n[{}]", keys.join(", "));
+    }
+    title
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+
+    #[test]
+    fn draws_every_node_and_edge() -> Result<()> {
+        let graph = super::super::tests::small_graph()?;
+        let mut out = Vec::new();
+        SvgRenderer::default().render(&graph, &mut out)?;
+        let svg = String::from_utf8(out)?;
+
+        assert!(svg.starts_with("<svg "));
+        assert_eq!(svg.matches("<g ").count(), graph.nodes.len());
+        assert_eq!(svg.matches("<line ").count(), graph.edges.len());
+        assert!(!svg.contains("NaN"));
+        Ok(())
+    }
+}
@@ -0,0 +1,497 @@
+//! A file system in the style of WNFS: every directory is a HAMT of its
+//! entries, with files stored inline and subdirectories linked by the root
+//! of their HAMT, so any change rewrites the directories up to the root.
+//!
+//! The `wnfs` experiment runs random operations on it, drawn from an
+//! [`OpMix`], to see what each kind of operation costs.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail, Result};
+use cid::Cid;
+use fvm_ipld_blockstore::Blockstore;
+use fvm_ipld_encoding::to_vec;
+use fvm_ipld_hamt::{Hamt, Sha256};
+use libipld_core::ipld::Ipld;
+use serde::Serialize;
+
+use crate::experiments::ExperimentContext;
+use crate::memorydb::MemoryDB;
+use crate::metered::MeteredStore;
+use crate::rng::Rng;
+use crate::workload::{Key, ValueSizes};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum FsOp {
+    /// Creates an empty directory.
+    Mkdir,
+    /// Creates a file, or overwrites one with new contents.
+    Write,
+    /// Renames a file or directory within its directory.
+    Rename,
+    /// Removes a file, or a directory with everything below it.
+    Rm,
+}
+
+impl FsOp {
+    pub const ALL: [FsOp; 4] = [FsOp::Mkdir, FsOp::Write, FsOp::Rename, FsOp::Rm];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            FsOp::Mkdir => "mkdir",
+            FsOp::Write => "write",
+            FsOp::Rename => "rename",
+            FsOp::Rm => "rm",
+        }
+    }
+}
+
+/// Relative weights of the operations, in the order of [`FsOp::ALL`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OpMix(pub [u32; 4]);
+
+/// Mostly writes, like a synced folder.
+impl Default for OpMix {
+    fn default() -> Self {
+        OpMix([1, 6, 1, 2])
+    }
+}
+
+impl OpMix {
+    pub fn sample(&self, rng: &mut Rng) -> FsOp {
+        let total: u32 = self.0.iter().sum();
+        let mut x = rng.below(total as u64) as u32;
+        for (op, &weight) in FsOp::ALL.iter().zip(&self.0) {
+            if x < weight {
+                return *op;
+            }
+            x -= weight;
+        }
+        unreachable!("below the total weight")
+    }
+}
+
+impl fmt::Display for OpMix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ops: Vec<String> = FsOp::ALL
+            .iter()
+            .zip(&self.0)
+            .map(|(op, weight)| format!("{}:{weight}", op.name()))
+            .collect();
+        f.write_str(&ops.join(","))
+    }
+}
+
+impl FromStr for OpMix {
+    type Err = anyhow::Error;
+
+    /// `mkdir:1,write:6,rename:1,rm:2`, operations not listed are never drawn.
+    fn from_str(s: &str) -> Result<Self> {
+        let mut weights = [0; 4];
+        for part in s.split(',') {
+            let (name, weight) = part
+                .split_once(':')
+                .ok_or_else(|| anyhow!("expected `<op>:<weight>`, got `{part}`"))?;
+            let i = FsOp::ALL
+                .iter()
+                .position(|op| op.name() == name.trim())
+                .ok_or_else(|| {
+                    anyhow!(
+                        "unknown operation `{name}`, expected `mkdir`, `write`, `rename` or `rm`"
+                    )
+                })?;
+            weights[i] = weight
+                .trim()
+                .parse()
+                .map_err(|_| anyhow!("invalid weight `{weight}`"))?;
+        }
+        if weights.iter().all(|&weight| weight == 0) {
+            bail!("at least one operation needs a weight");
+        }
+        Ok(OpMix(weights))
+    }
+}
+
+struct Dir<'s, BS, const BUCKET_SIZE: usize> {
+    map: Hamt<&'s BS, Ipld, Key, Sha256, BUCKET_SIZE>,
+    /// Directory this one is in and its name there, `None` for the root.
+    parent: Option<(usize, String)>,
+    files: Vec<String>,
+    dirs: BTreeMap<String, usize>,
+}
+
+impl<BS, const BUCKET_SIZE: usize> Dir<'_, BS, BUCKET_SIZE> {
+    fn entries(&self) -> usize {
+        self.files.len() + self.dirs.len()
+    }
+}
+
+/// An operation that was applied.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Change {
+    pub op: FsOp,
+    /// Encoded size of the name and value of the entry created, written,
+    /// renamed or removed.
+    pub entry_bytes: u64,
+}
+
+/// Directories kept as HAMTs in a store, indexed by the order they were
+/// created in. The root is the first.
+pub struct FileSystem<'s, BS, const BUCKET_SIZE: usize> {
+    store: &'s BS,
+    bit_width: u32,
+    /// `None` once removed.
+    dirs: Vec<Option<Dir<'s, BS, BUCKET_SIZE>>>,
+    /// Indices of the directories that weren't removed.
+    live: Vec<usize>,
+    /// Numbers new names so they never collide.
+    next_name: usize,
+    root: Cid,
+}
+
+impl<'s, BS: Blockstore, const BUCKET_SIZE: usize> FileSystem<'s, BS, BUCKET_SIZE> {
+    /// A file system with an empty root directory.
+    pub fn new(store: &'s BS, bit_width: u32) -> Result<Self> {
+        let mut map = Hamt::new_with_bit_width(store, bit_width);
+        let root = map.flush()?;
+        Ok(FileSystem {
+            store,
+            bit_width,
+            dirs: vec![Some(Dir {
+                map,
+                parent: None,
+                files: Vec::new(),
+                dirs: BTreeMap::new(),
+            })],
+            live: vec![0],
+            next_name: 0,
+            root,
+        })
+    }
+
+    /// Root of the root directory, as of the last operation.
+    pub fn root(&self) -> Cid {
+        self.root
+    }
+
+    /// Directories, the root included.
+    pub fn dirs(&self) -> usize {
+        self.live.len()
+    }
+
+    pub fn files(&self) -> usize {
+        self.live.iter().map(|&d| self.dir(d).files.len()).sum()
+    }
+
+    fn dir(&self, d: usize) -> &Dir<'s, BS, BUCKET_SIZE> {
+        self.dirs[d].as_ref().expect("live directory")
+    }
+
+    fn dir_mut(&mut self, d: usize) -> &mut Dir<'s, BS, BUCKET_SIZE> {
+        self.dirs[d].as_mut().expect("live directory")
+    }
+
+    /// Draws an operation from `mix` and applies it to random directories
+    /// and entries, then flushes the directories up to the root. Renames and
+    /// removals are drawn again while there's nothing to apply them to.
+    pub fn step(&mut self, mix: &OpMix, sizes: &ValueSizes, rng: &mut Rng) -> Result<Change> {
+        let non_empty: Vec<usize> = self
+            .live
+            .iter()
+            .copied()
+            .filter(|&d| self.dir(d).entries() > 0)
+            .collect();
+        let [mkdir, write, ..] = mix.0;
+        if non_empty.is_empty() && mkdir + write == 0 {
+            bail!("nothing to rename or remove in an empty file system");
+        }
+        let op = loop {
+            match mix.sample(rng) {
+                FsOp::Rename | FsOp::Rm if non_empty.is_empty() => continue,
+                op => break op,
+            }
+        };
+        let pick = |dirs: &[usize], rng: &mut Rng| dirs[rng.below(dirs.len() as u64) as usize];
+        let (d, entry_bytes) = match op {
+            FsOp::Mkdir => {
+                let parent = pick(&self.live, rng);
+                (parent, self.mkdir(parent)?)
+            }
+            FsOp::Write => {
+                let d = pick(&self.live, rng);
+                (d, self.write(d, sizes.value(rng), rng)?)
+            }
+            FsOp::Rename => {
+                let d = pick(&non_empty, rng);
+                (d, self.rename(d, rng)?)
+            }
+            FsOp::Rm => {
+                let d = pick(&non_empty, rng);
+                (d, self.rm(d, rng)?)
+            }
+        };
+        self.flush_up(d)?;
+        Ok(Change { op, entry_bytes })
+    }
+
+    fn new_name(&mut self, prefix: &str) -> String {
+        self.next_name += 1;
+        format!("{prefix}-{}", self.next_name)
+    }
+
+    fn set(&mut self, d: usize, name: &str, value: Ipld) -> Result<u64> {
+        let key = Key::Path(name.to_string());
+        let entry_bytes = to_vec(&(&key, &value))?.len() as u64;
+        self.dir_mut(d).map.set(key, value)?;
+        Ok(entry_bytes)
+    }
+
+    /// Removes an entry, returning its value and encoded size.
+    fn remove(&mut self, d: usize, name: &str) -> Result<(Ipld, u64)> {
+        let key = Key::Path(name.to_string());
+        let (_, value) = self
+            .dir_mut(d)
+            .map
+            .delete(&key)?
+            .ok_or_else(|| anyhow!("missing entry `{name}`"))?;
+        let entry_bytes = to_vec(&(&key, &value))?.len() as u64;
+        Ok((value, entry_bytes))
+    }
+
+    fn mkdir(&mut self, parent: usize) -> Result<u64> {
+        let name = self.new_name("dir");
+        let mut map = Hamt::new_with_bit_width(self.store, self.bit_width);
+        let root = map.flush()?;
+        let d = self.dirs.len();
+        self.dirs.push(Some(Dir {
+            map,
+            parent: Some((parent, name.clone())),
+            files: Vec::new(),
+            dirs: BTreeMap::new(),
+        }));
+        self.live.push(d);
+        self.dir_mut(parent).dirs.insert(name.clone(), d);
+        self.set(parent, &name, Ipld::Link(root))
+    }
+
+    /// Overwrites a random file of `d` half of the time, if it has any, and
+    /// creates a new one otherwise.
+    fn write(&mut self, d: usize, contents: String, rng: &mut Rng) -> Result<u64> {
+        let files = &self.dir(d).files;
+        let name = if !files.is_empty() && rng.below(2) == 0 {
+            files[rng.below(files.len() as u64) as usize].clone()
+        } else {
+            let name = self.new_name("file");
+            self.dir_mut(d).files.push(name.clone());
+            name
+        };
+        self.set(d, &name, Ipld::String(contents))
+    }
+
+    /// Picks a random entry of the non-empty `d`, and whether it's a file.
+    fn pick_entry(&self, d: usize, rng: &mut Rng) -> (String, bool) {
+        let dir = self.dir(d);
+        let i = rng.below(dir.entries() as u64) as usize;
+        match dir.files.get(i) {
+            Some(name) => (name.clone(), true),
+            None => {
+                let name = dir.dirs.keys().nth(i - dir.files.len());
+                (name.expect("entry in range").clone(), false)
+            }
+        }
+    }
+
+    fn rename(&mut self, d: usize, rng: &mut Rng) -> Result<u64> {
+        let (old, is_file) = self.pick_entry(d, rng);
+        let new = self.new_name(if is_file { "file" } else { "dir" });
+        let (value, _) = self.remove(d, &old)?;
+        let dir = self.dir_mut(d);
+        if is_file {
+            let file = dir.files.iter_mut().find(|name| **name == old);
+            *file.expect("renamed file") = new.clone();
+        } else {
+            let child = dir.dirs.remove(&old).expect("renamed directory");
+            dir.dirs.insert(new.clone(), child);
+            self.dir_mut(child).parent = Some((d, new.clone()));
+        }
+        self.set(d, &new, value)
+    }
+
+    fn rm(&mut self, d: usize, rng: &mut Rng) -> Result<u64> {
+        let (name, is_file) = self.pick_entry(d, rng);
+        let (_, entry_bytes) = self.remove(d, &name)?;
+        let dir = self.dir_mut(d);
+        if is_file {
+            dir.files.retain(|file| *file != name);
+        } else {
+            let child = dir.dirs.remove(&name).expect("removed directory");
+            let mut removed = vec![child];
+            while let Some(d) = removed.pop() {
+                let dir = self.dirs[d].take().expect("live directory");
+                removed.extend(dir.dirs.into_values());
+                self.live.retain(|&live| live != d);
+            }
+        }
+        Ok(entry_bytes)
+    }
+
+    /// Flushes `d` and links its new root from its parent, up to the root.
+    fn flush_up(&mut self, mut d: usize) -> Result<()> {
+        loop {
+            let dir = self.dir_mut(d);
+            let root = dir.map.flush()?;
+            match dir.parent.clone() {
+                Some((parent, name)) => {
+                    self.dir_mut(parent)
+                        .map
+                        .set(Key::Path(name), Ipld::Link(root))?;
+                    d = parent;
+                }
+                None => {
+                    self.root = root;
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+/// Sizes of the files `wnfs` writes unless `--value-size` is given.
+pub const FILE_SIZES: ValueSizes = ValueSizes::LogNormal {
+    median: 256.0,
+    sigma: 1.0,
+};
+
+#[derive(Debug, Serialize)]
+pub struct WnfsResult {
+    n: usize,
+    bucket_size: usize,
+    bit_width: u32,
+    op: &'static str,
+    ops: usize,
+    bytes_written_per_op: f64,
+    blocks_written_per_op: f64,
+    /// Bytes the store grew by per operation, not counting blocks it
+    /// already had.
+    growth_per_op: f64,
+    /// Bytes written over the encoded size of the entries changed.
+    amplification: f64,
+    /// After all operations, of every kind.
+    store_bytes: u64,
+    live_bytes: u64,
+    dirs: usize,
+    files: usize,
+}
+
+/// Runs `n` random operations drawn from `mix` on a file system of HAMTs,
+/// and sums up the writes of each kind of operation.
+pub fn wnfs_experiment<const BUCKET_SIZE: usize>(
+    ctx: &ExperimentContext,
+    bit_width: u32,
+    n: usize,
+    mix: &OpMix,
+    sizes: &ValueSizes,
+) -> Result<Vec<WnfsResult>> {
+    let store = MeteredStore::new(MemoryDB::default());
+    let mut fs = FileSystem::<_, BUCKET_SIZE>::new(&store, bit_width)?;
+    let mut rng = ctx.rng();
+    // Operations, bytes and blocks written, growth and entry bytes per kind.
+    let mut totals = [(0, 0, 0, 0, 0); FsOp::ALL.len()];
+    for _ in 0..n {
+        let before = store.snapshot();
+        let stored = store.inner().bytes_stored();
+        let change = fs.step(mix, sizes, &mut rng)?;
+        let traffic = store.snapshot() - before;
+        let total = &mut totals[change.op as usize];
+        total.0 += 1;
+        total.1 += traffic.bytes_written;
+        total.2 += traffic.puts;
+        total.3 += store.inner().bytes_stored() - stored;
+        total.4 += change.entry_bytes;
+    }
+    let store_bytes = store.inner().bytes_stored();
+    let live_bytes = store.inner().live_bytes(&[fs.root()])?;
+    Ok(FsOp::ALL
+        .iter()
+        .zip(totals)
+        .map(|(op, (ops, bytes, blocks, growth, entry_bytes))| {
+            let per_op = |total: u64| total as f64 / ops as f64;
+            WnfsResult {
+                n,
+                bucket_size: BUCKET_SIZE,
+                bit_width,
+                op: op.name(),
+                ops,
+                bytes_written_per_op: per_op(bytes),
+                blocks_written_per_op: per_op(blocks),
+                growth_per_op: per_op(growth),
+                amplification: bytes as f64 / entry_bytes as f64,
+                store_bytes,
+                live_bytes,
+                dirs: fs.dirs(),
+                files: fs.files(),
+            }
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memorydb::MemoryDB;
+
+    /// Directories and files reachable from the directory at `root`.
+    fn count(store: &MemoryDB, root: &Cid) -> Result<(usize, usize)> {
+        let map: Hamt<_, Ipld, Key, Sha256, 3> = Hamt::load_with_bit_width(root, store, 4)?;
+        let (mut dirs, mut files) = (1, 0);
+        for entry in map.iter() {
+            match entry?.1 {
+                Ipld::Link(child) => {
+                    let (child_dirs, child_files) = count(store, child)?;
+                    dirs += child_dirs;
+                    files += child_files;
+                }
+                _ => files += 1,
+            }
+        }
+        Ok((dirs, files))
+    }
+
+    #[test]
+    fn tree_matches_what_was_simulated() -> Result<()> {
+        let store = MemoryDB::default();
+        let mut fs = FileSystem::<_, 3>::new(&store, 4)?;
+        let mut rng = Rng::new(1);
+        let mix: OpMix = "mkdir:2,write:4,rename:1,rm:1".parse()?;
+        let mut seen = [0; 4];
+        for _ in 0..2000 {
+            let change = fs.step(&mix, &ValueSizes::Fixed(16), &mut rng)?;
+            seen[change.op as usize] += 1;
+            assert!(change.entry_bytes > 0);
+        }
+        assert!(seen.iter().all(|&ops| ops > 100), "{seen:?}");
+        assert!(fs.dirs() > 10);
+        assert_eq!(count(&store, &fs.root())?, (fs.dirs(), fs.files()));
+        Ok(())
+    }
+
+    #[test]
+    fn parses_op_mixes() {
+        let mix: OpMix = "write:3, rm:1".parse().unwrap();
+        assert_eq!(mix, OpMix([0, 3, 0, 1]));
+        assert_eq!(mix.to_string(), "mkdir:0,write:3,rename:0,rm:1");
+        assert_eq!(mix.to_string().parse::<OpMix>().unwrap(), mix);
+        let mut rng = Rng::new(1);
+        assert!((0..100).all(|_| matches!(mix.sample(&mut rng), FsOp::Write | FsOp::Rm)));
+        assert!("write:0".parse::<OpMix>().is_err());
+        assert!("copy:1".parse::<OpMix>().is_err());
+        assert!("write".parse::<OpMix>().is_err());
+
+        let store = MemoryDB::default();
+        let mut fs = FileSystem::<_, 3>::new(&store, 4).unwrap();
+        let only_rm: OpMix = "rm:1".parse().unwrap();
+        assert!(fs.step(&only_rm, &ValueSizes::Fixed(1), &mut rng).is_err());
+    }
+}
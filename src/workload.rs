@@ -0,0 +1,474 @@
+//! Key sets, access patterns and value sizes experiments can run on instead
+//! of the sequential keys `0..n` with one character values.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::hash::Hasher;
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail};
+use serde::de::{self, Deserializer, Visitor};
+use serde::{Deserialize, Serialize};
+
+use crate::rng::Rng;
+
+/// A key of any workload.
+///
+/// Integer keys hash and encode exactly like plain `usize` keys, so the
+/// sequential workload reproduces the results of experiments using `usize`.
+/// Byte keys do the same for `BytesKey`, the key of Filecoin actor state:
+/// they hash their raw bytes and encode as a CBOR byte string.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+#[serde(untagged)]
+pub enum Key {
+    Int(usize),
+    Path(String),
+    Bytes(#[serde(serialize_with = "serialize_bytes")] Vec<u8>),
+}
+
+fn serialize_bytes<S: serde::Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_bytes(bytes)
+}
+
+impl fvm_ipld_hamt::Hash for Key {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        match self {
+            Key::Int(key) => key.hash(state),
+            Key::Path(key) => key.hash(state),
+            Key::Bytes(key) => state.write(key),
+        }
+    }
+}
+
+// Not derived, since the CBOR deserializer hands all integers to
+// `deserialize_any` as `i128`, which untagged enums don't support.
+impl<'de> Deserialize<'de> for Key {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct KeyVisitor;
+
+        impl<'de> Visitor<'de> for KeyVisitor {
+            type Value = Key;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("an unsigned integer, a string or bytes")
+            }
+
+            fn visit_u64<E: de::Error>(self, key: u64) -> Result<Key, E> {
+                self.visit_i128(key.into())
+            }
+
+            fn visit_i128<E: de::Error>(self, key: i128) -> Result<Key, E> {
+                usize::try_from(key)
+                    .map(Key::Int)
+                    .map_err(|_| E::custom(format!("key {key} doesn't fit into a usize")))
+            }
+
+            fn visit_str<E: de::Error>(self, key: &str) -> Result<Key, E> {
+                Ok(Key::Path(key.to_string()))
+            }
+
+            fn visit_string<E: de::Error>(self, key: String) -> Result<Key, E> {
+                Ok(Key::Path(key))
+            }
+
+            fn visit_bytes<E: de::Error>(self, key: &[u8]) -> Result<Key, E> {
+                Ok(Key::Bytes(key.to_vec()))
+            }
+
+            fn visit_byte_buf<E: de::Error>(self, key: Vec<u8>) -> Result<Key, E> {
+                Ok(Key::Bytes(key))
+            }
+        }
+
+        deserializer.deserialize_any(KeyVisitor)
+    }
+}
+
+impl Key {
+    /// Big-endian integers, so that keys sort like their bytes, UTF-8, or
+    /// the raw bytes.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            Key::Int(key) => (*key as u64).to_be_bytes().to_vec(),
+            Key::Path(key) => key.as_bytes().to_vec(),
+            Key::Bytes(key) => key.clone(),
+        }
+    }
+
+    /// `n` as an unsigned varint, like the keys of actor HAMTs indexed by
+    /// number, e.g. sector numbers.
+    pub fn varint(n: u64) -> Self {
+        let mut buf = unsigned_varint::encode::u64_buffer();
+        Key::Bytes(unsigned_varint::encode::u64(n, &mut buf).to_vec())
+    }
+
+    /// The Filecoin ID address of an actor: protocol `0` followed by the ID
+    /// as an unsigned varint.
+    pub fn id_address(id: u64) -> Self {
+        let Key::Bytes(mut bytes) = Key::varint(id) else {
+            unreachable!()
+        };
+        bytes.insert(0, 0);
+        Key::Bytes(bytes)
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Key::Int(key) => key.fmt(f),
+            Key::Path(key) => key.fmt(f),
+            Key::Bytes(key) => f.write_str(&hex::encode(key)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Workload {
+    /// The keys `0..n`.
+    #[default]
+    Sequential,
+    /// Distinct random integers.
+    Uniform,
+    /// The keys `0..n`, but accessed with Zipf distributed popularity, key `0`
+    /// being the most popular.
+    Zipf(f64),
+    /// Integers sharing one of a few random high halves, with sequential low
+    /// halves.
+    Clustered,
+    /// File system like paths, e.g. `/photos/2019/img-42.jpg`.
+    Paths,
+    /// The integers `0..n` as unsigned varint byte keys.
+    Varint,
+    /// ID addresses of the actors from [`FIRST_ACTOR_ID`] on, the keys of
+    /// actor state HAMTs like the power actor's claims.
+    Addresses,
+}
+
+/// First ID Filecoin assigns to actors that aren't built in.
+pub const FIRST_ACTOR_ID: u64 = 100;
+
+/// Number of distinct prefixes used by [`Workload::Clustered`].
+const CLUSTERS: usize = 16;
+
+const DIRECTORIES: [&str; 12] = [
+    "home", "docs", "photos", "music", "src", "lib", "2019", "2020", "2021", "drafts", "archive",
+    "shared",
+];
+const NAMES: [&str; 6] = ["img", "notes", "track", "report", "main", "data"];
+const EXTENSIONS: [&str; 6] = ["jpg", "md", "mp3", "pdf", "rs", "json"];
+
+impl Workload {
+    /// `n` distinct keys, in insertion order.
+    pub fn keys(&self, n: usize, rng: &mut Rng) -> Vec<Key> {
+        match self {
+            Workload::Sequential | Workload::Zipf(_) => (0..n).map(Key::Int).collect(),
+            Workload::Uniform => distinct(n, || Key::Int(rng.next_u64() as usize)),
+            Workload::Clustered => {
+                let prefixes: Vec<usize> = (0..CLUSTERS)
+                    .map(|_| (rng.next_u64() as usize) & !0xffff_ffff)
+                    .collect();
+                (0..n)
+                    .map(|i| Key::Int(prefixes[i % CLUSTERS] | (i / CLUSTERS)))
+                    .collect()
+            }
+            Workload::Paths => distinct(n, || {
+                let mut path = String::new();
+                for _ in 0..=rng.below(4) {
+                    path.push('/');
+                    path.push_str(DIRECTORIES[rng.below(DIRECTORIES.len() as u64) as usize]);
+                }
+                let name = NAMES[rng.below(NAMES.len() as u64) as usize];
+                let extension = EXTENSIONS[rng.below(EXTENSIONS.len() as u64) as usize];
+                let number = rng.below(1000);
+                Key::Path(format!("{path}/{name}-{number}.{extension}"))
+            }),
+            Workload::Varint => (0..n as u64).map(Key::varint).collect(),
+            Workload::Addresses => (0..n as u64)
+                .map(|i| Key::id_address(FIRST_ACTOR_ID + i))
+                .collect(),
+        }
+    }
+
+    /// Picks which of `keys` is accessed next.
+    pub fn sampler(&self, keys: usize) -> Sampler {
+        match self {
+            Workload::Zipf(exponent) => Sampler::zipf(keys, *exponent),
+            _ => Sampler::Uniform(keys),
+        }
+    }
+}
+
+fn distinct(n: usize, mut generate: impl FnMut() -> Key) -> Vec<Key> {
+    let mut seen = HashSet::with_capacity(n);
+    let mut keys = Vec::with_capacity(n);
+    while keys.len() < n {
+        let key = generate();
+        if seen.insert(key.clone()) {
+            keys.push(key);
+        }
+    }
+    keys
+}
+
+impl FromStr for Workload {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let workload = match s.split_once(':') {
+            None if s == "sequential" => Workload::Sequential,
+            None if s == "uniform" => Workload::Uniform,
+            None if s == "zipf" => Workload::Zipf(1.0),
+            None if s == "clustered" => Workload::Clustered,
+            None if s == "paths" => Workload::Paths,
+            None if s == "varint" => Workload::Varint,
+            None if s == "addresses" => Workload::Addresses,
+            Some(("zipf", exponent)) => {
+                let exponent: f64 = exponent
+                    .parse()
+                    .map_err(|_| anyhow!("invalid Zipf exponent `{exponent}`"))?;
+                if exponent.is_nan() || exponent < 0.0 {
+                    bail!("Zipf exponent must not be negative");
+                }
+                Workload::Zipf(exponent)
+            }
+            _ => bail!(
+                "unknown workload `{s}`, expected `sequential`, `uniform`, `zipf[:<exponent>]`, \
+                 `clustered`, `paths`, `varint` or `addresses`"
+            ),
+        };
+        Ok(workload)
+    }
+}
+
+/// Indices into a list of keys, drawn with some popularity distribution.
+#[derive(Debug, Clone)]
+pub enum Sampler {
+    Uniform(usize),
+    /// Cumulative, normalized weights by rank.
+    Zipf(Vec<f64>),
+}
+
+impl Sampler {
+    fn zipf(keys: usize, exponent: f64) -> Self {
+        let mut total = 0.0;
+        let mut cdf: Vec<f64> = (1..=keys)
+            .map(|rank| {
+                total += (rank as f64).powf(-exponent);
+                total
+            })
+            .collect();
+        for weight in &mut cdf {
+            *weight /= total;
+        }
+        Sampler::Zipf(cdf)
+    }
+
+    pub fn sample(&self, rng: &mut Rng) -> usize {
+        match self {
+            Sampler::Uniform(keys) => rng.below(std::cmp::max(*keys, 1) as u64) as usize,
+            Sampler::Zipf(cdf) => {
+                let x = rng.next_f64();
+                cdf.partition_point(|&weight| weight < x)
+                    .min(cdf.len().saturating_sub(1))
+            }
+        }
+    }
+}
+
+/// Distribution of the lengths of generated values.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ValueSizes {
+    Fixed(usize),
+    /// Uniformly distributed in `min..=max`.
+    Uniform(usize, usize),
+    /// Log-normally distributed, with a long tail of large values.
+    LogNormal {
+        median: f64,
+        sigma: f64,
+    },
+}
+
+impl ValueSizes {
+    pub fn sample(&self, rng: &mut Rng) -> usize {
+        match *self {
+            ValueSizes::Fixed(size) => size,
+            ValueSizes::Uniform(min, max) => min + rng.below((max - min) as u64 + 1) as usize,
+            ValueSizes::LogNormal { median, sigma } => {
+                // Box-Muller; `1 - x` is in `(0, 1]`, so the logarithm is finite.
+                let radius = (-2.0 * (1.0 - rng.next_f64()).ln()).sqrt();
+                let normal = radius * (2.0 * std::f64::consts::PI * rng.next_f64()).cos();
+                (median * (sigma * normal).exp()).round() as usize
+            }
+        }
+    }
+
+    /// Random lowercase letters of a sampled length, so that values don't
+    /// deduplicate when stored as blocks.
+    pub fn value(&self, rng: &mut Rng) -> String {
+        (0..self.sample(rng))
+            .map(|_| (b'a' + rng.below(26) as u8) as char)
+            .collect()
+    }
+}
+
+impl fmt::Display for ValueSizes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValueSizes::Fixed(size) => write!(f, "{size}"),
+            ValueSizes::Uniform(min, max) => write!(f, "{min}..={max}"),
+            ValueSizes::LogNormal { median, sigma } => write!(f, "lognormal:{median}:{sigma}"),
+        }
+    }
+}
+
+impl FromStr for ValueSizes {
+    type Err = anyhow::Error;
+
+    /// `64`, `16..=256` or `lognormal:<median>[:<sigma>]`.
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let invalid = || anyhow!("invalid value size `{s}`");
+        if let Some(params) = s.strip_prefix("lognormal:") {
+            let (median, sigma) = params.split_once(':').unwrap_or((params, "1"));
+            let median: f64 = median.parse().map_err(|_| invalid())?;
+            let sigma: f64 = sigma.parse().map_err(|_| invalid())?;
+            if !(median.is_finite() && median >= 0.0 && sigma.is_finite() && sigma >= 0.0) {
+                bail!("log-normal median and sigma must not be negative");
+            }
+            return Ok(ValueSizes::LogNormal { median, sigma });
+        }
+        if let Some((min, max)) = s.split_once("..=") {
+            let min = min.parse().map_err(|_| invalid())?;
+            let max = max.parse().map_err(|_| invalid())?;
+            if min > max {
+                bail!("empty value size range `{s}`");
+            }
+            return Ok(ValueSizes::Uniform(min, max));
+        }
+        s.parse().map(ValueSizes::Fixed).map_err(|_| invalid())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memorydb::MemoryDB;
+    use crate::regression;
+    use fvm_ipld_hamt::{BytesKey, Hamt, Sha256};
+
+    #[test]
+    fn int_keys_build_the_same_hamt_as_usize() -> anyhow::Result<()> {
+        let store = MemoryDB::default();
+        let mut plain: Hamt<_, String, usize, Sha256, 3> = Hamt::new_with_bit_width(&store, 4);
+        let mut keyed: Hamt<_, String, Key, Sha256, 3> = Hamt::new_with_bit_width(&store, 4);
+        for key in Workload::Sequential.keys(1000, &mut Rng::new(1)) {
+            let Key::Int(int) = key else { unreachable!() };
+            plain.set(int, "F".to_string())?;
+            keyed.set(key, "F".to_string())?;
+        }
+        let root = keyed.flush()?;
+        assert_eq!(plain.flush()?, root);
+        let keyed: Hamt<_, String, Key, Sha256, 3> = Hamt::load_with_bit_width(&root, &store, 4)?;
+        assert_eq!(keyed.get(&Key::Int(999))?, Some(&"F".to_string()));
+
+        let mut paths: Hamt<_, String, Key, Sha256, 3> = Hamt::new_with_bit_width(&store, 4);
+        let keys = Workload::Paths.keys(1000, &mut Rng::new(1));
+        for key in &keys {
+            paths.set(key.clone(), "F".to_string())?;
+        }
+        let root = paths.flush()?;
+        let paths: Hamt<_, String, Key, Sha256, 3> = Hamt::load_with_bit_width(&root, &store, 4)?;
+        assert!(keys[0].to_string().starts_with('/'));
+        assert_eq!(paths.get(&keys[999])?, Some(&"F".to_string()));
+        Ok(())
+    }
+
+    #[test]
+    fn byte_keys_build_the_same_hamt_as_bytes_key() -> anyhow::Result<()> {
+        assert_eq!(Key::varint(300), Key::Bytes(vec![0xac, 0x02]));
+        assert_eq!(Key::id_address(1234), Key::Bytes(vec![0x00, 0xd2, 0x09]));
+        assert_eq!(Key::id_address(FIRST_ACTOR_ID).to_string(), "0064");
+
+        let store = MemoryDB::default();
+        let mut plain: Hamt<_, u64, BytesKey, Sha256, 3> = Hamt::new_with_bit_width(&store, 5);
+        let mut keyed: Hamt<_, u64, Key, Sha256, 3> = Hamt::new_with_bit_width(&store, 5);
+        let keys = Workload::Addresses.keys(1000, &mut Rng::new(1));
+        for (i, key) in keys.into_iter().enumerate() {
+            plain.set(BytesKey(key.to_bytes()), i as u64)?;
+            keyed.set(key, i as u64)?;
+        }
+        let root = keyed.flush()?;
+        assert_eq!(plain.flush()?, root);
+        let keyed: Hamt<_, u64, Key, Sha256, 3> = Hamt::load_with_bit_width(&root, &store, 5)?;
+        assert_eq!(
+            keyed.get(&Key::id_address(FIRST_ACTOR_ID + 999))?,
+            Some(&999)
+        );
+
+        // The regression vectors, built with string keys as bytes.
+        for vector in regression::parse(regression::FIXTURES)? {
+            if vector.bucket_size != 3 || vector.n > 1000 {
+                continue;
+            }
+            let mut map: Hamt<_, u64, Key, Sha256, 3> =
+                Hamt::new_with_bit_width(&store, vector.bit_width);
+            for i in 0..vector.n as u64 {
+                map.set(Key::Bytes(i.to_string().into_bytes()), i)?;
+            }
+            assert_eq!(map.flush()?, vector.root, "{}", vector.name);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn zipf_prefers_low_ranks() {
+        let sampler = Workload::Zipf(1.0).sampler(1000);
+        let mut rng = Rng::new(1);
+        let mut counts = [0; 1000];
+        for _ in 0..10_000 {
+            counts[sampler.sample(&mut rng)] += 1;
+        }
+        assert!(counts[0] > counts[1] && counts[1] > counts[100]);
+        // The top rank has a weight of 1 / H(1000), about 13%.
+        assert!((1100..1500).contains(&counts[0]));
+    }
+
+    #[test]
+    fn value_sizes_follow_their_distribution() {
+        let mut rng = Rng::new(1);
+        let sizes = |spec: &str, rng: &mut Rng| -> Vec<usize> {
+            let sizes: ValueSizes = spec.parse().unwrap();
+            assert_eq!(sizes.to_string().parse::<ValueSizes>().unwrap(), sizes);
+            (0..10_000).map(|_| sizes.sample(rng)).collect()
+        };
+
+        assert!(sizes("64", &mut rng).iter().all(|&size| size == 64));
+        let uniform = sizes("16..=256", &mut rng);
+        assert_eq!(uniform.iter().min(), Some(&16));
+        assert_eq!(uniform.iter().max(), Some(&256));
+
+        let mut lognormal = sizes("lognormal:100:1", &mut rng);
+        lognormal.sort_unstable();
+        assert!((90..110).contains(&lognormal[5000]));
+        // The mean of a log-normal distribution is `median * e^(sigma² / 2)`.
+        let mean = lognormal.iter().sum::<usize>() as f64 / 10_000.0;
+        assert!((150.0..180.0).contains(&mean), "{mean}");
+
+        assert!("lognormal:-1".parse::<ValueSizes>().is_err());
+        assert!("9..=3".parse::<ValueSizes>().is_err());
+        let value = ValueSizes::Fixed(3).value(&mut rng);
+        assert!(value.len() == 3 && value.chars().all(|c| c.is_ascii_lowercase()));
+    }
+
+    #[test]
+    fn keys_are_distinct() {
+        for workload in ["uniform", "clustered", "paths"] {
+            let keys = workload
+                .parse::<Workload>()
+                .unwrap()
+                .keys(5000, &mut Rng::new(1));
+            let distinct: HashSet<_> = keys.iter().collect();
+            assert_eq!(distinct.len(), 5000, "{workload}");
+        }
+    }
+}